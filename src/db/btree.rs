@@ -0,0 +1,753 @@
+use std::io;
+use std::mem;
+use std::ops::Bound;
+
+use super::pager::{
+    self, read_u16, read_u32, write_u16, write_u32, Page, PageId, Pager, PAGE_SIZE,
+};
+
+// The most a key and its value may take up together, so that a node that overflows always splits
+// into two that fit
+pub const MAX_ENTRY_SIZE: usize = PAGE_SIZE / 4;
+// Nodes that shrink below this after a delete take entries from a sibling or merge with it
+const MIN_FILL: usize = PAGE_SIZE / 4;
+// How full bulk loading packs pages, leaving room for later inserts
+const BULK_FILL: usize = PAGE_SIZE * 9 / 10;
+
+// A node page starts with its kind, its number of entries and, for a leaf, the next leaf or, for
+// an inner node, its first child. Leaf entries follow as key length, value length, key and value;
+// inner entries as key length, key and child.
+const LEAF: u8 = 1;
+const INNER: u8 = 2;
+const NODE_HEADER: usize = 7;
+
+#[derive(Debug)]
+enum Node {
+    // Entries in key order; `next` is 0 for the last leaf
+    Leaf {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        next: PageId,
+    },
+    // `first` has the keys below the first entry's, every other child the keys from its entry's on
+    Inner {
+        first: PageId,
+        entries: Vec<(Vec<u8>, PageId)>,
+    },
+}
+
+// A B+tree of byte string keys and values, compared bytewise. The root stays on the same page
+// for the life of the tree, so whatever refers to the tree never has to change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BTree {
+    pub root: PageId,
+}
+
+// Walks the leaves from where `BTree::seek` left it
+#[derive(Debug)]
+pub struct Cursor {
+    entries: std::vec::IntoIter<(Vec<u8>, Vec<u8>)>,
+    next: PageId,
+}
+
+impl BTree {
+    pub fn create(pager: &mut Pager) -> io::Result<BTree> {
+        let root = pager.allocate()?;
+        let leaf = Node::Leaf {
+            entries: Vec::new(),
+            next: 0,
+        };
+        pager.write(root, |page| leaf.encode(page))?;
+        Ok(BTree { root })
+    }
+
+    pub fn get(&self, pager: &mut Pager, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let leaf = self.leaf(pager, Some(key))?;
+        let Node::Leaf { entries, .. } = load(pager, leaf)? else {
+            unreachable!("`leaf` ends at a leaf");
+        };
+        Ok(entries
+            .binary_search_by(|(k, _)| k.as_slice().cmp(key))
+            .ok()
+            .map(|index| entries[index].1.clone()))
+    }
+
+    // Adds or replaces an entry, returning the value it replaced
+    pub fn insert(
+        &self,
+        pager: &mut Pager,
+        key: &[u8],
+        value: &[u8],
+    ) -> io::Result<Option<Vec<u8>>> {
+        check_size(key, value)?;
+        let (old, split) = insert_into(pager, self.root, key, value)?;
+        if let Some((left, separator, right)) = split {
+            // The root keeps its page: its left half moves out instead
+            let moved = pager.allocate()?;
+            pager.write(moved, |page| left.encode(page))?;
+            let root = Node::Inner {
+                first: moved,
+                entries: vec![(separator, right)],
+            };
+            pager.write(self.root, |page| root.encode(page))?;
+        }
+        Ok(old)
+    }
+
+    // Removes an entry, returning its value
+    pub fn delete(&self, pager: &mut Pager, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let (old, _) = delete_from(pager, self.root, key)?;
+        // A root left with a single child takes its place, so the tree gets one level shorter
+        if let Node::Inner { first, entries } = load(pager, self.root)? {
+            if entries.is_empty() {
+                let child = load(pager, first)?;
+                pager.write(self.root, |page| child.encode(page))?;
+                pager.free(first)?;
+            }
+        }
+        Ok(old)
+    }
+
+    // Builds a tree from entries in ascending key order, one page at a time, instead of inserting
+    // them one by one
+    pub fn bulk_load(
+        pager: &mut Pager,
+        entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> io::Result<BTree> {
+        let tree = BTree::create(pager)?;
+        // The first key and the page of every node on the level being built
+        let mut level: Vec<(Vec<u8>, PageId)> = Vec::new();
+        let mut leaf: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        let mut size = NODE_HEADER;
+        for (key, value) in entries {
+            check_size(&key, &value)?;
+            if let Some((previous, _)) = leaf.last() {
+                if *previous >= key {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "BTree: bulk loaded entries are not in ascending order",
+                    ));
+                }
+            }
+            if size + 4 + key.len() + value.len() > BULK_FILL {
+                // The next leaf's page is known before this one is written, to link them
+                let next = pager.allocate()?;
+                write_leaf(pager, &mut level, mem::take(&mut leaf), next)?;
+                level.push((key.clone(), next));
+                size = NODE_HEADER;
+            } else if level.is_empty() {
+                level.push((key.clone(), pager.allocate()?));
+            }
+            size += 4 + key.len() + value.len();
+            leaf.push((key, value));
+        }
+        if leaf.is_empty() {
+            return Ok(tree);
+        }
+        write_leaf(pager, &mut level, leaf, 0)?;
+
+        while level.len() > 1 {
+            let mut parents = Vec::new();
+            let mut children = level.into_iter().peekable();
+            while let Some((first_key, first)) = children.next() {
+                let mut entries = Vec::new();
+                let mut size = NODE_HEADER;
+                while let Some((key, _)) = children.peek() {
+                    if size + 6 + key.len() > BULK_FILL {
+                        break;
+                    }
+                    size += 6 + key.len();
+                    entries.extend(children.next());
+                }
+                let node = Node::Inner { first, entries };
+                let page_id = pager.allocate()?;
+                pager.write(page_id, |page| node.encode(page))?;
+                parents.push((first_key, page_id));
+            }
+            level = parents;
+        }
+        // The top node moves to the root page
+        let top = level[0].1;
+        let node = load(pager, top)?;
+        pager.write(tree.root, |page| node.encode(page))?;
+        pager.free(top)?;
+        Ok(tree)
+    }
+
+    // A cursor at the first entry within `start`
+    pub fn seek(&self, pager: &mut Pager, start: Bound<&[u8]>) -> io::Result<Cursor> {
+        let key = match start {
+            Bound::Included(key) | Bound::Excluded(key) => Some(key),
+            Bound::Unbounded => None,
+        };
+        let leaf = self.leaf(pager, key)?;
+        let Node::Leaf { mut entries, next } = load(pager, leaf)? else {
+            unreachable!("`leaf` ends at a leaf");
+        };
+        let skipped = match start {
+            Bound::Included(key) => entries.partition_point(|(k, _)| k.as_slice() < key),
+            Bound::Excluded(key) => entries.partition_point(|(k, _)| k.as_slice() <= key),
+            Bound::Unbounded => 0,
+        };
+        entries.drain(..skipped);
+        Ok(Cursor {
+            entries: entries.into_iter(),
+            next,
+        })
+    }
+
+    // Frees every page of the tree
+    pub fn destroy(self, pager: &mut Pager) -> io::Result<()> {
+        free_node(pager, self.root)
+    }
+
+    // The leaf that has or would have `key`, or the first leaf
+    fn leaf(&self, pager: &mut Pager, key: Option<&[u8]>) -> io::Result<PageId> {
+        let mut page_id = self.root;
+        loop {
+            match load(pager, page_id)? {
+                Node::Leaf { .. } => return Ok(page_id),
+                Node::Inner { first, entries } => {
+                    page_id = match key {
+                        Some(key) => child(first, &entries, key).1,
+                        None => first,
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl Cursor {
+    pub fn next(&mut self, pager: &mut Pager) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Ok(Some(entry));
+            }
+            if self.next == 0 {
+                return Ok(None);
+            }
+            let Node::Leaf { entries, next } = load(pager, self.next)? else {
+                return Err(pager::corrupt("leaf links to an inner node"));
+            };
+            self.entries = entries.into_iter();
+            self.next = next;
+        }
+    }
+}
+
+// Inserts into the subtree at `page_id`. If the node had to split, its left half is returned
+// unwritten along with the separator key and the page the right half went to.
+#[allow(clippy::type_complexity)]
+fn insert_into(
+    pager: &mut Pager,
+    page_id: PageId,
+    key: &[u8],
+    value: &[u8],
+) -> io::Result<(Option<Vec<u8>>, Option<(Node, Vec<u8>, PageId)>)> {
+    let mut node = load(pager, page_id)?;
+    let old = match &mut node {
+        Node::Leaf { entries, .. } => {
+            match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+                Ok(index) => Some(mem::replace(&mut entries[index].1, value.to_vec())),
+                Err(index) => {
+                    entries.insert(index, (key.to_vec(), value.to_vec()));
+                    None
+                }
+            }
+        }
+        Node::Inner { first, entries } => {
+            let (index, child) = child(*first, entries, key);
+            let (old, split) = insert_into(pager, child, key, value)?;
+            let Some((left, separator, right)) = split else {
+                return Ok((old, None));
+            };
+            pager.write(child, |page| left.encode(page))?;
+            entries.insert(index, (separator, right));
+            old
+        }
+    };
+    if node.size() <= PAGE_SIZE {
+        pager.write(page_id, |page| node.encode(page))?;
+        return Ok((old, None));
+    }
+    Ok((old, Some(split(pager, node)?)))
+}
+
+// Deletes from the subtree at `page_id`, returning the value and whether the node underflowed
+fn delete_from(
+    pager: &mut Pager,
+    page_id: PageId,
+    key: &[u8],
+) -> io::Result<(Option<Vec<u8>>, bool)> {
+    let mut node = load(pager, page_id)?;
+    let old = match &mut node {
+        Node::Leaf { entries, .. } => {
+            match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+                Ok(index) => Some(entries.remove(index).1),
+                Err(_) => return Ok((None, false)),
+            }
+        }
+        Node::Inner { first, entries } => {
+            let (index, child) = child(*first, entries, key);
+            let (old, underflow) = delete_from(pager, child, key)?;
+            if !underflow {
+                return Ok((old, false));
+            }
+            rebalance(pager, &mut node, index)?;
+            old
+        }
+    };
+    pager.write(page_id, |page| node.encode(page))?;
+    Ok((old, node.size() < MIN_FILL))
+}
+
+// Refills the underflowing child at `position` (0 for the first child, n for the child of entry
+// n - 1) from a sibling: merged into one page if both fit, otherwise split evenly again
+fn rebalance(pager: &mut Pager, parent: &mut Node, position: usize) -> io::Result<()> {
+    let Node::Inner { first, entries } = parent else {
+        unreachable!("only inner nodes have children");
+    };
+    if entries.is_empty() {
+        return Ok(());
+    }
+    // The separator between the pair is entry `right - 1`
+    let right = position.max(1);
+    let left_page = if right == 1 {
+        *first
+    } else {
+        entries[right - 2].1
+    };
+    let right_page = entries[right - 1].1;
+    let separator = entries[right - 1].0.clone();
+
+    let merged = match (load(pager, left_page)?, load(pager, right_page)?) {
+        (
+            Node::Leaf {
+                entries: mut left, ..
+            },
+            Node::Leaf {
+                entries: right_entries,
+                next,
+            },
+        ) => {
+            left.extend(right_entries);
+            Node::Leaf {
+                entries: left,
+                next,
+            }
+        }
+        (
+            Node::Inner {
+                first: left_first,
+                entries: mut left,
+            },
+            Node::Inner {
+                first: right_first,
+                entries: right_entries,
+            },
+        ) => {
+            // The separator comes down between the two
+            left.push((separator, right_first));
+            left.extend(right_entries);
+            Node::Inner {
+                first: left_first,
+                entries: left,
+            }
+        }
+        _ => return Err(pager::corrupt("siblings on different levels")),
+    };
+
+    if merged.size() <= PAGE_SIZE {
+        pager.write(left_page, |page| merged.encode(page))?;
+        pager.free(right_page)?;
+        entries.remove(right - 1);
+        return Ok(());
+    }
+    let (mut left, separator, right_node) = match merged {
+        Node::Leaf { mut entries, next } => {
+            let sizes: Vec<usize> = entries.iter().map(|(k, v)| 4 + k.len() + v.len()).collect();
+            let right_entries = entries.split_off(middle(&sizes));
+            let separator = right_entries[0].0.clone();
+            (
+                Node::Leaf { entries, next: 0 },
+                separator,
+                Node::Leaf {
+                    entries: right_entries,
+                    next,
+                },
+            )
+        }
+        Node::Inner { first, mut entries } => {
+            let sizes: Vec<usize> = entries.iter().map(|(k, _)| 6 + k.len()).collect();
+            let mut right_entries = entries.split_off(middle(&sizes));
+            let (separator, right_first) = right_entries.remove(0);
+            (
+                Node::Inner { first, entries },
+                separator,
+                Node::Inner {
+                    first: right_first,
+                    entries: right_entries,
+                },
+            )
+        }
+    };
+    if let Node::Leaf { next, .. } = &mut left {
+        *next = right_page;
+    }
+    pager.write(left_page, |page| left.encode(page))?;
+    pager.write(right_page, |page| right_node.encode(page))?;
+    entries[right - 1].0 = separator;
+    Ok(())
+}
+
+// Writes a bulk loaded leaf to the page `level` has for it
+fn write_leaf(
+    pager: &mut Pager,
+    level: &mut [(Vec<u8>, PageId)],
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    next: PageId,
+) -> io::Result<()> {
+    let page_id = level.last().expect("the leaf was added to the level").1;
+    let leaf = Node::Leaf { entries, next };
+    pager.write(page_id, |page| leaf.encode(page))
+}
+
+fn check_size(key: &[u8], value: &[u8]) -> io::Result<()> {
+    if key.len() + value.len() > MAX_ENTRY_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "BTree: entry of {} bytes exceeds the maximum of {}",
+                key.len() + value.len(),
+                MAX_ENTRY_SIZE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Moves the upper half of an overfull node to a new page
+fn split(pager: &mut Pager, node: Node) -> io::Result<(Node, Vec<u8>, PageId)> {
+    let right_page = pager.allocate()?;
+    let (left, separator, right) = match node {
+        Node::Leaf { mut entries, next } => {
+            let sizes: Vec<usize> = entries.iter().map(|(k, v)| 4 + k.len() + v.len()).collect();
+            let right = entries.split_off(middle(&sizes));
+            let separator = right[0].0.clone();
+            (
+                Node::Leaf {
+                    entries,
+                    next: right_page,
+                },
+                separator,
+                Node::Leaf {
+                    entries: right,
+                    next,
+                },
+            )
+        }
+        Node::Inner { first, mut entries } => {
+            let sizes: Vec<usize> = entries.iter().map(|(k, _)| 6 + k.len()).collect();
+            let mut right = entries.split_off(middle(&sizes));
+            // The middle entry moves up, its child becoming the right node's first
+            let (separator, right_first) = right.remove(0);
+            (
+                Node::Inner { first, entries },
+                separator,
+                Node::Inner {
+                    first: right_first,
+                    entries: right,
+                },
+            )
+        }
+    };
+    pager.write(right_page, |page| right.encode(page))?;
+    Ok((left, separator, right_page))
+}
+
+// Where to split entries of these sizes so that both halves take up about the same space
+fn middle(sizes: &[usize]) -> usize {
+    let half = sizes.iter().sum::<usize>() / 2;
+    let mut total = 0;
+    for (index, size) in sizes.iter().enumerate() {
+        total += size;
+        if total >= half {
+            return index.clamp(1, sizes.len() - 1);
+        }
+    }
+    sizes.len() - 1
+}
+
+// The position a new separator for `key` would take, and the child that has `key`
+fn child(first: PageId, entries: &[(Vec<u8>, PageId)], key: &[u8]) -> (usize, PageId) {
+    let index = entries.partition_point(|(k, _)| k.as_slice() <= key);
+    match index {
+        0 => (0, first),
+        _ => (index, entries[index - 1].1),
+    }
+}
+
+fn free_node(pager: &mut Pager, page_id: PageId) -> io::Result<()> {
+    if let Node::Inner { first, entries } = load(pager, page_id)? {
+        free_node(pager, first)?;
+        for (_, child) in entries {
+            free_node(pager, child)?;
+        }
+    }
+    pager.free(page_id)
+}
+
+fn load(pager: &mut Pager, page_id: PageId) -> io::Result<Node> {
+    pager.read(page_id, Node::decode)?
+}
+
+impl Node {
+    fn size(&self) -> usize {
+        let entries: usize = match self {
+            Node::Leaf { entries, .. } => entries.iter().map(|(k, v)| 4 + k.len() + v.len()).sum(),
+            Node::Inner { entries, .. } => entries.iter().map(|(k, _)| 6 + k.len()).sum(),
+        };
+        NODE_HEADER + entries
+    }
+
+    fn encode(&self, page: &mut Page) {
+        page.fill(0);
+        let mut offset = NODE_HEADER;
+        match self {
+            Node::Leaf { entries, next } => {
+                page[0] = LEAF;
+                write_u16(page, 1, entries.len() as u16);
+                write_u32(page, 3, *next);
+                for (key, value) in entries {
+                    write_u16(page, offset, key.len() as u16);
+                    write_u16(page, offset + 2, value.len() as u16);
+                    offset += 4;
+                    page[offset..offset + key.len()].copy_from_slice(key);
+                    offset += key.len();
+                    page[offset..offset + value.len()].copy_from_slice(value);
+                    offset += value.len();
+                }
+            }
+            Node::Inner { first, entries } => {
+                page[0] = INNER;
+                write_u16(page, 1, entries.len() as u16);
+                write_u32(page, 3, *first);
+                for (key, child) in entries {
+                    write_u16(page, offset, key.len() as u16);
+                    offset += 2;
+                    page[offset..offset + key.len()].copy_from_slice(key);
+                    offset += key.len();
+                    write_u32(page, offset, *child);
+                    offset += 4;
+                }
+            }
+        }
+    }
+
+    fn decode(page: &Page) -> io::Result<Node> {
+        let damaged = || pager::corrupt("damaged B+tree node");
+        let bytes = |offset: usize, len: usize| {
+            page.get(offset..offset + len)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(damaged)
+        };
+        let count = read_u16(page, 1) as usize;
+        let link = read_u32(page, 3);
+        let mut offset = NODE_HEADER;
+        match page[0] {
+            LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = read_u16(bytes(offset, 2)?.as_slice(), 0) as usize;
+                    let value_len = read_u16(bytes(offset + 2, 2)?.as_slice(), 0) as usize;
+                    offset += 4;
+                    let key = bytes(offset, key_len)?;
+                    let value = bytes(offset + key_len, value_len)?;
+                    offset += key_len + value_len;
+                    entries.push((key, value));
+                }
+                Ok(Node::Leaf {
+                    entries,
+                    next: link,
+                })
+            }
+            INNER => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = read_u16(bytes(offset, 2)?.as_slice(), 0) as usize;
+                    let key = bytes(offset + 2, key_len)?;
+                    let child = read_u32(bytes(offset + 2 + key_len, 4)?.as_slice(), 0);
+                    offset += 6 + key_len;
+                    entries.push((key, child));
+                }
+                Ok(Node::Inner {
+                    first: link,
+                    entries,
+                })
+            }
+            _ => Err(damaged()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pager::{journal_path, DEFAULT_MEMORY_BUDGET};
+    use crate::db::wal::Wal;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(Wal::path_for(&path));
+        let _ = fs::remove_file(journal_path(&path));
+        path
+    }
+
+    fn remove_files(path: &Path) {
+        fs::remove_file(path).unwrap();
+        fs::remove_file(Wal::path_for(path)).unwrap();
+        fs::remove_file(journal_path(path)).unwrap();
+    }
+
+    fn key(n: u64) -> [u8; 8] {
+        n.to_be_bytes()
+    }
+
+    fn collect(tree: &BTree, pager: &mut Pager, start: Bound<&[u8]>) -> Vec<u64> {
+        let mut cursor = tree.seek(pager, start).unwrap();
+        let mut keys = Vec::new();
+        while let Some((key, _)) = cursor.next(pager).unwrap() {
+            keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+        }
+        keys
+    }
+
+    #[test]
+    fn test_inserts_splits_and_scans() {
+        let path = temp_path("btree.pages");
+        let mut pager = Pager::open(&path, 16 * PAGE_SIZE).unwrap();
+        let tree = BTree::create(&mut pager).unwrap();
+        // Every number below 5000 once, in a scrambled order
+        let numbers: Vec<u64> = (0..5000).map(|n| n * 2833 % 5000).collect();
+        for n in &numbers {
+            let value = vec![*n as u8; (*n % 50) as usize];
+            assert_eq!(tree.insert(&mut pager, &key(*n), &value).unwrap(), None);
+        }
+        assert!(matches!(
+            load(&mut pager, tree.root).unwrap(),
+            Node::Inner { .. }
+        ));
+        for n in [0, 1, 2500, 4999] {
+            let value = vec![n as u8; (n % 50) as usize];
+            assert_eq!(tree.get(&mut pager, &key(n)).unwrap(), Some(value));
+        }
+        assert_eq!(tree.get(&mut pager, &key(5000)).unwrap(), None);
+        assert_eq!(
+            collect(&tree, &mut pager, Bound::Unbounded),
+            (0..5000).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            collect(&tree, &mut pager, Bound::Excluded(&key(4995))),
+            vec![4996, 4997, 4998, 4999]
+        );
+
+        assert_eq!(
+            tree.insert(&mut pager, &key(7), b"new").unwrap(),
+            Some(vec![7; 7])
+        );
+        for n in (0..5000).filter(|n| n % 3 != 0) {
+            assert!(tree.delete(&mut pager, &key(n)).unwrap().is_some());
+        }
+        assert_eq!(tree.delete(&mut pager, &key(1)).unwrap(), None);
+        assert_eq!(
+            collect(&tree, &mut pager, Bound::Included(&key(4990))),
+            vec![4992, 4995, 4998]
+        );
+        assert_eq!(collect(&tree, &mut pager, Bound::Unbounded).len(), 1667);
+
+        // Emptied leaves merge away until the root is a leaf again
+        for n in (0..5000).filter(|n| n % 3 == 0) {
+            assert!(tree.delete(&mut pager, &key(n)).unwrap().is_some());
+        }
+        assert!(collect(&tree, &mut pager, Bound::Unbounded).is_empty());
+        assert!(matches!(
+            load(&mut pager, tree.root).unwrap(),
+            Node::Leaf { .. }
+        ));
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_bulk_load() {
+        let path = temp_path("btree-bulk.pages");
+        let mut pager = Pager::open(&path, 16 * PAGE_SIZE).unwrap();
+        let tree = BTree::bulk_load(
+            &mut pager,
+            (0..20000u64).map(|n| (key(n * 2).to_vec(), key(n).to_vec())),
+        )
+        .unwrap();
+        // 20000 entries of 20 bytes, 90% to a page
+        let pages = pager.allocate().unwrap();
+        assert!(pages < 120, "{} pages", pages);
+        assert_eq!(
+            tree.get(&mut pager, &key(39998)).unwrap(),
+            Some(key(19999).to_vec())
+        );
+        assert_eq!(tree.get(&mut pager, &key(3)).unwrap(), None);
+        assert_eq!(
+            collect(&tree, &mut pager, Bound::Unbounded),
+            (0..20000).map(|n| n * 2).collect::<Vec<_>>()
+        );
+
+        // The loaded tree takes inserts and deletes like any other
+        for n in 0..2000 {
+            tree.insert(&mut pager, &key(n * 2 + 1), b"").unwrap();
+            tree.delete(&mut pager, &key(n * 20)).unwrap();
+        }
+        let keys = collect(&tree, &mut pager, Bound::Included(&key(39990)));
+        assert_eq!(keys, vec![39990, 39992, 39994, 39996, 39998]);
+        assert_eq!(collect_len(&tree, &mut pager), 20000);
+
+        let empty = BTree::bulk_load(&mut pager, Vec::new()).unwrap();
+        assert_eq!(collect_len(&empty, &mut pager), 0);
+        let unordered = vec![(key(2).to_vec(), vec![]), (key(1).to_vec(), vec![])];
+        assert!(BTree::bulk_load(&mut pager, unordered).is_err());
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_large_entries() {
+        let path = temp_path("btree-large.pages");
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let tree = BTree::create(&mut pager).unwrap();
+        for n in 0..200u64 {
+            let mut long_key = vec![b'k'; MAX_ENTRY_SIZE - 16];
+            long_key.extend_from_slice(&key(n));
+            tree.insert(&mut pager, &long_key, &key(n)).unwrap();
+        }
+        assert_eq!(collect_len(&tree, &mut pager), 200);
+        assert!(tree
+            .insert(&mut pager, &[0; MAX_ENTRY_SIZE + 1], b"")
+            .is_err());
+
+        let pages = pager.allocate().unwrap();
+        tree.destroy(&mut pager).unwrap();
+        assert!(pager.allocate().unwrap() < pages);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    fn collect_len(tree: &BTree, pager: &mut Pager) -> usize {
+        let mut cursor = tree.seek(pager, Bound::Unbounded).unwrap();
+        let mut count = 0;
+        while cursor.next(pager).unwrap().is_some() {
+            count += 1;
+        }
+        count
+    }
+}
@@ -1,15 +1,60 @@
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::fs;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use super::data_types::{CaseInsensitiveString, Column, Value};
-use super::table::{Row, Table};
+use super::data_types::{CaseInsensitiveString, Column, UniqueConstraint, Value};
+use super::datetime::{Clock, SystemClock, Timestamp};
+use super::engine::{RowIdIter, RowIter, StorageEngine};
+use super::fault;
+use super::file_format;
+use super::full_text::FullTextIndex;
+use super::statistics::TableStatistics;
+use super::table::{ColumnIndex, IndexRange, Row, RowRange, Table, TableSchema};
+use super::transaction::{Transaction, UndoEntry};
+use super::wal::{Redo, Wal};
+
+// Once the log has grown past this many bytes, the next commit checkpoints it
+const CHECKPOINT_SIZE: u64 = 1 << 20;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Database {
     tables: BTreeMap<CaseInsensitiveString, Table>,
     next_row_id: usize,
+    // Not persisted: a loaded database always starts out on the system clock
+    #[serde(skip, default = "default_clock")]
+    clock: Arc<dyn Clock>,
+    // Set between BEGIN and COMMIT / ROLLBACK. Changes are applied in place and undone from the
+    // log on rollback.
+    #[serde(skip)]
+    transaction: Option<Transaction>,
+    // Where each statement in progress started: whether it runs in a transaction of its own, and
+    // the length of the log when it began
+    #[serde(skip)]
+    statements: Vec<(bool, usize)>,
+    // Where committed changes go; None for a database that only lives in memory
+    #[serde(skip)]
+    storage: Option<Storage>,
+}
+
+// The file the database was opened from and its write-ahead log. Committed changes go to the log
+// straight away and only reach the file itself at a checkpoint.
+#[derive(Debug)]
+struct Storage {
+    path: PathBuf,
+    wal: Wal,
+}
+
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Database {
@@ -18,50 +63,980 @@ impl Database {
         Database {
             tables: BTreeMap::new(),
             next_row_id: 0,
+            clock: default_clock(),
+            transaction: None,
+            statements: Vec::new(),
+            storage: None,
         }
     }
 
-    pub fn create_table(&mut self, table_name: String, columns: Vec<Column>) {
-        let table = Table::new(columns);
-        self.tables.insert(table_name.into(), table);
+    // Replaces the clock behind CURRENT_DATE / CURRENT_TIMESTAMP, e.g. with a FixedClock in tests
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
     }
 
     pub fn insert_row(&mut self, table_name: &str, values: Vec<Value>) -> Result<(), String> {
-        let table = self
-            .tables
-            .get_mut(&CaseInsensitiveString(table_name.to_string()))
-            .ok_or_else(|| format!("Table '{}' not found", table_name))?;
+        self.insert_rows(table_name, vec![values]).map(|_| ())
+    }
 
-        let row_id = self.next_row_id;
-        self.next_row_id += 1;
+    pub fn insert_rows(
+        &mut self,
+        table_name: &str,
+        rows: Vec<Vec<Value>>,
+    ) -> Result<Vec<usize>, String> {
+        self.write_rows(table_name, rows, Vec::new())
+    }
 
-        table.insert_row(row_id, values)
+    // Runs `change` as a single statement, see `engine::atomically`
+    fn atomically<T>(
+        &mut self,
+        change: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.begin_statement();
+        let result = change(self);
+        let ended = self.end_statement(result.is_ok());
+        let value = result?;
+        ended?;
+        Ok(value)
     }
 
-    pub fn select(&self, table_name: &str, columns: &[String]) -> Result<Vec<Row>, String> {
-        let table = self
-            .tables
-            .get(&CaseInsensitiveString(table_name.to_string()))
-            .ok_or_else(|| format!("Table '{}' not found", table_name))?;
+    // Changes a table's indexes, keeping its schema as it was for rollback
+    fn alter_table(
+        &mut self,
+        table_name: &str,
+        change: impl FnOnce(&mut Table) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.atomically(|database| {
+            let table = database.get_table_mut(table_name)?;
+            let previous = table.schema();
+            change(table)?;
+            database.record(UndoEntry::Schema {
+                table: CaseInsensitiveString(table_name.to_string()),
+                previous,
+            });
+            Ok(())
+        })
+    }
 
-        table.select(columns)
+    fn transaction_mut(&mut self) -> Result<&mut Transaction, String> {
+        self.transaction
+            .as_mut()
+            .ok_or_else(|| "Savepoints can only be used inside a transaction".to_string())
     }
 
-    pub fn save_to_file(&self, path: &Path) -> std::io::Result<()> {
-        let serialized = serde_json::to_string(self)?;
-        fs::write(path, serialized)?;
+    // Writes what a committed log of changes left behind to the write-ahead log, if there is one
+    fn log_commit(&mut self, log: &[UndoEntry]) -> Result<(), String> {
+        if self.storage.is_none() || log.is_empty() {
+            return Ok(());
+        }
+        let mut changes = Vec::new();
+        for entry in log {
+            match entry {
+                UndoEntry::CreateTable { name, .. } => {
+                    changes.push(Redo::Table {
+                        name: name.clone(),
+                        table: self.tables.get(name).cloned(),
+                    });
+                }
+                UndoEntry::Schema { table: name, .. } => {
+                    if let Some(table) = self.tables.get(name) {
+                        changes.push(Redo::Schema {
+                            name: name.clone(),
+                            schema: table.schema(),
+                        });
+                    }
+                }
+                UndoEntry::Rows { table, rows } => {
+                    let stored = self.tables.get(table);
+                    changes.push(Redo::Rows {
+                        table: table.clone(),
+                        rows: rows
+                            .iter()
+                            .map(|(row_id, _)| {
+                                (*row_id, stored.and_then(|t| t.get_row(*row_id)).cloned())
+                            })
+                            .collect(),
+                    });
+                }
+                UndoEntry::NextRowId(_) => {}
+            }
+        }
+        changes.push(Redo::NextRowId(self.next_row_id));
+
+        let wal = &mut self.storage.as_mut().expect("checked above").wal;
+        wal.append(&changes)
+            .map_err(|e| format!("WAL: failed to write the log: {}", e))?;
+        if wal.size() >= CHECKPOINT_SIZE {
+            // The changes are safe in the log already; if this fails the next commit tries again
+            let _ = self.checkpoint();
+        }
         Ok(())
     }
 
-    pub fn load_from_file(path: &Path) -> std::io::Result<Self> {
-        let contents = fs::read_to_string(path)?;
-        let database: Database = serde_json::from_str(&contents)?;
+    fn redo(&mut self, changes: Vec<Redo>) {
+        for change in changes {
+            match change {
+                Redo::Table {
+                    name,
+                    table: Some(mut table),
+                } => {
+                    table.rebuild_indexes();
+                    self.tables.insert(name, table);
+                }
+                Redo::Table { name, table: None } => {
+                    self.tables.remove(&name);
+                }
+                Redo::Rows { table, rows } => {
+                    if let Some(table) = self.tables.get_mut(&table) {
+                        table.restore_rows(rows);
+                    }
+                }
+                Redo::NextRowId(next_row_id) => self.next_row_id = next_row_id,
+                Redo::Schema { name, schema } => {
+                    if let Some(table) = self.tables.get_mut(&name) {
+                        table.restore_schema(schema);
+                    }
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, entry: UndoEntry) {
+        if let Some(transaction) = self.transaction.as_mut() {
+            transaction.log.push(entry);
+        }
+    }
+
+    // Applies the entries newest first, restoring the exact earlier state
+    fn undo(&mut self, log: Vec<UndoEntry>) {
+        for entry in log.into_iter().rev() {
+            match entry {
+                UndoEntry::CreateTable { name, previous } => match previous {
+                    Some(table) => {
+                        self.tables.insert(name, table);
+                    }
+                    None => {
+                        self.tables.remove(&name);
+                    }
+                },
+                UndoEntry::Rows { table, rows } => {
+                    if let Some(table) = self.tables.get_mut(&table) {
+                        table.restore_rows(rows);
+                    }
+                }
+                UndoEntry::Schema { table, previous } => {
+                    if let Some(table) = self.tables.get_mut(&table) {
+                        table.restore_schema(previous);
+                    }
+                }
+                UndoEntry::NextRowId(next_row_id) => self.next_row_id = next_row_id,
+            }
+        }
+    }
+
+    // A database that only lives in memory, e.g. to export another engine's tables
+    pub fn from_tables(tables: BTreeMap<CaseInsensitiveString, Table>, next_row_id: usize) -> Self {
+        Database {
+            tables,
+            next_row_id,
+            ..Database::new()
+        }
+    }
+
+    // Writes the database to a temporary file first and then renames it over `path`, so a crash
+    // or a full disk leaves either the old or the new version, never a mix. The version being
+    // replaced is kept as `<path>.bak`.
+    pub fn save_to_file(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, &file_format::encode(self)?, true)
+    }
+
+    // Opens the database stored at `path`, or starts a new one there if there is nothing yet
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if path.exists() || Wal::path_for(path).exists() {
+            return Self::load_from_file(path);
+        }
+        let mut database = Database::new();
+        database.attach(path)?;
         Ok(database)
     }
 
+    // Loads the last checkpoint and replays the changes committed after it. A database that
+    // crashed before its first checkpoint only has the log.
+    pub fn load_from_file(path: &Path) -> io::Result<Self> {
+        let (mut database, legacy) = if path.exists() || !Wal::path_for(path).exists() {
+            read_snapshot(path)?
+        } else {
+            (Database::new(), false)
+        };
+        database.attach(path)?;
+        if legacy {
+            // Rewritten in the current format straight away; the JSON stays behind as the backup
+            database.checkpoint()?;
+        }
+        Ok(database)
+    }
+
+    fn attach(&mut self, path: &Path) -> io::Result<()> {
+        let (wal, records) = Wal::open(&Wal::path_for(path))?;
+        for changes in records {
+            self.redo(changes);
+        }
+        self.storage = Some(Storage {
+            path: path.to_path_buf(),
+            wal,
+        });
+        Ok(())
+    }
+
     pub fn get_table(&self, table_name: &str) -> Result<&Table, String> {
         self.tables
             .get(&CaseInsensitiveString(table_name.to_string()))
             .ok_or_else(|| format!("Table '{}' not found", table_name))
     }
+
+    // Private so that every change goes through a method that can record it for rollback
+    fn get_table_mut(&mut self, table_name: &str) -> Result<&mut Table, String> {
+        self.tables
+            .get_mut(&CaseInsensitiveString(table_name.to_string()))
+            .ok_or_else(|| format!("Table '{}' not found", table_name))
+    }
+}
+
+impl StorageEngine for Database {
+    fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    fn table_names(&self) -> Result<Vec<String>, String> {
+        Ok(self.tables.keys().map(|name| name.0.clone()).collect())
+    }
+
+    fn schema(&self, table_name: &str) -> Result<TableSchema, String> {
+        Ok(self.get_table(table_name)?.schema())
+    }
+
+    fn create_table(
+        &mut self,
+        table_name: String,
+        columns: Vec<Column>,
+        constraints: Vec<UniqueConstraint>,
+    ) -> Result<(), String> {
+        let mut table = Table::new(columns);
+        for constraint in constraints {
+            table.add_constraint(constraint)?;
+        }
+        let name: CaseInsensitiveString = table_name.into();
+        if self.tables.contains_key(&name) {
+            return Err(format!("Table '{}' already exists", name.0));
+        }
+        self.atomically(|database| {
+            let previous = database.tables.insert(name.clone(), table);
+            database.record(UndoEntry::CreateTable { name, previous });
+            Ok(())
+        })
+    }
+
+    fn drop_table(&mut self, table_name: &str) -> Result<(), String> {
+        self.get_table(table_name)?;
+        self.atomically(|database| {
+            let name = CaseInsensitiveString(table_name.to_string());
+            let previous = database.tables.remove(&name);
+            database.record(UndoEntry::CreateTable { name, previous });
+            Ok(())
+        })
+    }
+
+    fn create_index(&mut self, table_name: &str, index: ColumnIndex) -> Result<(), String> {
+        self.alter_table(table_name, |table| table.create_index(index))
+    }
+
+    fn create_full_text_index(
+        &mut self,
+        table_name: &str,
+        index: FullTextIndex,
+    ) -> Result<(), String> {
+        self.alter_table(table_name, |table| table.create_full_text_index(index))
+    }
+
+    fn drop_index(&mut self, table_name: &str, name: &str) -> Result<(), String> {
+        self.alter_table(table_name, |table| table.drop_index(name))
+    }
+
+    fn set_statistics(
+        &mut self,
+        table_name: &str,
+        statistics: TableStatistics,
+    ) -> Result<(), String> {
+        self.alter_table(table_name, |table| {
+            table.set_statistics(statistics);
+            Ok(())
+        })
+    }
+
+    // Inserts new rows and replaces existing ones in one all or nothing batch, returning the ids
+    // given to the inserted rows. Ids are only used up once the table has accepted the whole batch.
+    fn write_rows(
+        &mut self,
+        table_name: &str,
+        inserts: Vec<Vec<Value>>,
+        updates: Vec<(usize, Vec<Value>)>,
+    ) -> Result<Vec<usize>, String> {
+        self.atomically(|database| {
+            let next_row_id = database.next_row_id;
+            let table = database.get_table_mut(table_name)?;
+            let row_ids: Vec<usize> = (next_row_id..next_row_id + inserts.len()).collect();
+            let previous: Vec<(usize, Option<Row>)> = updates
+                .iter()
+                .map(|(row_id, _)| (*row_id, table.get_row(*row_id).cloned()))
+                .chain(row_ids.iter().map(|row_id| (*row_id, None)))
+                .collect();
+
+            if !row_ids.is_empty() {
+                database.record(UndoEntry::NextRowId(next_row_id));
+            }
+            database.record(UndoEntry::Rows {
+                table: CaseInsensitiveString(table_name.to_string()),
+                rows: previous,
+            });
+            database
+                .get_table_mut(table_name)?
+                .write_rows(row_ids.iter().copied().zip(inserts).collect(), updates)?;
+            database.next_row_id += row_ids.len();
+            Ok(row_ids)
+        })
+    }
+
+    fn delete_rows(&mut self, table_name: &str, row_ids: &[usize]) -> Result<(), String> {
+        self.atomically(|database| {
+            let table = database.get_table_mut(table_name)?;
+            let previous: Vec<(usize, Option<Row>)> = row_ids
+                .iter()
+                .map(|row_id| (*row_id, table.get_row(*row_id).cloned()))
+                .collect();
+
+            database.record(UndoEntry::Rows {
+                table: CaseInsensitiveString(table_name.to_string()),
+                rows: previous,
+            });
+            database.get_table_mut(table_name)?.delete_rows(row_ids)
+        })
+    }
+
+    fn get_row(&self, table_name: &str, row_id: usize) -> Result<Option<Row>, String> {
+        Ok(self.get_table(table_name)?.get_row(row_id).cloned())
+    }
+
+    fn scan(&self, table_name: &str, range: RowRange) -> Result<RowIter<'_>, String> {
+        let rows = self.get_table(table_name)?.scan_range(range);
+        Ok(Box::new(
+            rows.map(|(row_id, row)| Ok((row_id, row.clone()))),
+        ))
+    }
+
+    fn index_scan(
+        &self,
+        table_name: &str,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<RowIdIter<'_>, String> {
+        Ok(Box::new(
+            self.get_table(table_name)?.index_scan(index, range).map(Ok),
+        ))
+    }
+
+    fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    fn begin(&mut self) -> Result<(), String> {
+        if self.transaction.is_some() {
+            return Err("A transaction is already in progress".to_string());
+        }
+        self.transaction = Some(Transaction::default());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        let transaction = self
+            .transaction
+            .take()
+            .ok_or_else(|| "No transaction is in progress".to_string())?;
+        if let Err(e) = self.log_commit(&transaction.log) {
+            // Still open, so it can be committed again or rolled back
+            self.transaction = Some(transaction);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        let transaction = self
+            .transaction
+            .take()
+            .ok_or_else(|| "No transaction is in progress".to_string())?;
+        self.undo(transaction.log);
+        Ok(())
+    }
+
+    fn savepoint(&mut self, name: &str) -> Result<(), String> {
+        self.transaction_mut()?.savepoint(name);
+        Ok(())
+    }
+
+    // Keeps the changes made since the savepoint but forgets the savepoint itself
+    fn release_savepoint(&mut self, name: &str) -> Result<(), String> {
+        self.transaction_mut()?.release(name).map(|_| ())
+    }
+
+    // Undoes the changes made since the savepoint, which stays in place for another rollback
+    fn rollback_to_savepoint(&mut self, name: &str) -> Result<(), String> {
+        let transaction = self.transaction_mut()?;
+        let position = transaction.release(name)?;
+        let undone = transaction.log.split_off(position);
+        transaction.savepoint(name);
+        self.undo(undone);
+        Ok(())
+    }
+
+    // Changes are logged before they are applied, so a statement that fails halfway is undone
+    // just as well, leaving tables, indexes and row ids as they were
+    fn begin_statement(&mut self) {
+        let implicit = self.transaction.is_none();
+        let start = self
+            .transaction
+            .get_or_insert_with(Transaction::default)
+            .log
+            .len();
+        self.statements.push((implicit, start));
+    }
+
+    fn end_statement(&mut self, succeeded: bool) -> Result<(), String> {
+        let Some((implicit, start)) = self.statements.pop() else {
+            return Ok(());
+        };
+        if implicit {
+            // A statement outside of a transaction commits on its own
+            let log = self.transaction.take().map(|t| t.log).unwrap_or_default();
+            let mut result = Ok(());
+            if succeeded {
+                result = self.log_commit(&log);
+            }
+            if !succeeded || result.is_err() {
+                self.undo(log);
+            }
+            result
+        } else {
+            if !succeeded {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    let undone = transaction.log.split_off(start);
+                    self.undo(undone);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // Writes the committed state to the database file and empties the log. A crash in between
+    // is harmless: replaying the log onto the new file changes nothing.
+    fn checkpoint(&mut self) -> io::Result<()> {
+        if self.in_transaction() {
+            return Err(io::Error::other(
+                "Cannot checkpoint while a transaction is in progress",
+            ));
+        }
+        if let Some(storage) = &self.storage {
+            self.save_to_file(&storage.path)?;
+        }
+        if let Some(storage) = self.storage.as_mut() {
+            storage.wal.reset()?;
+        }
+        Ok(())
+    }
+
+    // Human readable copy of the database, in the JSON format earlier versions stored. Opening
+    // the exported file loads it like any other old database file.
+    fn export_json(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, &serde_json::to_vec_pretty(self)?, false)
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn write_atomically(path: &Path, contents: &[u8], backup: bool) -> io::Result<()> {
+    let temporary = with_suffix(path, ".tmp");
+    let written = write_synced(&temporary, contents).and_then(|_| {
+        fault::check("write_atomically").map_err(io::Error::other)?;
+        if backup && path.exists() {
+            keep_backup(path)?;
+        }
+        fs::rename(&temporary, path)?;
+        sync_directory(path)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    written
+}
+
+// Replaces the backup with the current file. A hard link keeps the current file in place the
+// whole time; filesystems without them get a copy.
+fn keep_backup(path: &Path) -> io::Result<()> {
+    let backup = with_suffix(path, ".bak");
+    let _ = fs::remove_file(&backup);
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(())
+}
+
+// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// Refuses files that were cut short or damaged, naming the file and the backup to fall back on.
+// Also says whether the file is in the old JSON format.
+fn read_snapshot(path: &Path) -> io::Result<(Database, bool)> {
+    let contents = fs::read(path)?;
+    let legacy = file_format::is_json(&contents);
+    let decoded = if legacy {
+        serde_json::from_slice(&contents).map_err(|e| {
+            let problem = if e.is_eof() {
+                "is truncated"
+            } else {
+                "is corrupt"
+            };
+            format!("{} ({})", problem, e)
+        })
+    } else {
+        file_format::decode(&contents)
+    };
+    let mut database: Database = decoded.map_err(|problem| {
+        let backup = with_suffix(path, ".bak");
+        let hint = if backup.exists() {
+            format!("; the previous version is in '{}'", backup.display())
+        } else {
+            String::new()
+        };
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Database file '{}' {}{}", path.display(), problem, hint),
+        )
+    })?;
+    for table in database.tables.values_mut() {
+        table.rebuild_indexes();
+    }
+    Ok((database, legacy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::conformance;
+    use crate::db::data_types::DataType;
+    use crate::db::fault;
+    use crate::db::table::ALL_ROWS;
+
+    fn users() -> Database {
+        let mut database = Database::new();
+        database
+            .create_table(
+                "users".to_string(),
+                vec![
+                    Column::new("id".to_string(), DataType::Integer),
+                    Column::new("doc".to_string(), DataType::Json),
+                ],
+                vec![UniqueConstraint {
+                    columns: vec!["id".to_string()],
+                    primary_key: true,
+                }],
+            )
+            .unwrap();
+        database
+            .insert_rows(
+                "users",
+                vec![
+                    vec![Value::Integer(1), Value::Text(r#"{"a": 1}"#.to_string())],
+                    vec![Value::Integer(2), Value::Text(r#"{"a": 2}"#.to_string())],
+                ],
+            )
+            .unwrap();
+        database
+    }
+
+    fn row(id: i64) -> Vec<Value> {
+        vec![Value::Integer(id), Value::Null]
+    }
+
+    #[test]
+    fn test_conformance() {
+        conformance::run(&|| Box::new(Database::new()));
+    }
+
+    #[test]
+    fn test_rollback_restores_everything() {
+        let mut database = users();
+        let tables = database.tables.clone();
+        let next_row_id = database.next_row_id;
+
+        database.begin().unwrap();
+        database.insert_rows("users", vec![row(3), row(4)]).unwrap();
+        database
+            .write_rows("users", vec![], vec![(0, row(10))])
+            .unwrap();
+        database.delete_rows("users", &[1, 2]).unwrap();
+        database
+            .create_index(
+                "users",
+                ColumnIndex {
+                    name: "doc_a".to_string(),
+                    columns: vec!["json_extract(doc, '$.a')".to_string()],
+                    unique: false,
+                    predicate: None,
+                },
+            )
+            .unwrap();
+        database.drop_table("users").unwrap();
+        database
+            .create_table("users".to_string(), vec![], vec![])
+            .unwrap();
+        database
+            .create_table("other".to_string(), vec![], vec![])
+            .unwrap();
+        assert_ne!(database.tables, tables);
+
+        database.rollback().unwrap();
+        assert_eq!(database.tables, tables);
+        assert_eq!(database.next_row_id, next_row_id);
+        assert!(!database.in_transaction());
+        assert!(database.rollback().is_err());
+    }
+
+    #[test]
+    fn test_nested_savepoints() {
+        let mut database = users();
+        assert!(database.savepoint("a").is_err());
+
+        database.begin().unwrap();
+        assert!(database.begin().is_err());
+        database.insert_rows("users", vec![row(3)]).unwrap();
+        database.savepoint("a").unwrap();
+        database.insert_rows("users", vec![row(4)]).unwrap();
+        database.savepoint("b").unwrap();
+        database.insert_rows("users", vec![row(5)]).unwrap();
+        let ids = |database: &Database| -> Vec<String> {
+            let table = database.get_table("users").unwrap();
+            table
+                .scan()
+                .map(|(_, row)| row.values[0].to_string())
+                .collect()
+        };
+
+        // Rolling back to `a` also discards `b`, but `a` itself can be used again
+        database.rollback_to_savepoint("a").unwrap();
+        assert_eq!(ids(&database), vec!["1", "2", "3"]);
+        assert!(database.rollback_to_savepoint("b").is_err());
+        database.insert_rows("users", vec![row(6)]).unwrap();
+        database.rollback_to_savepoint("A").unwrap();
+        assert_eq!(ids(&database), vec!["1", "2", "3"]);
+
+        // Released changes stay part of the transaction
+        database.insert_rows("users", vec![row(7)]).unwrap();
+        database.release_savepoint("a").unwrap();
+        assert!(database.release_savepoint("a").is_err());
+        database.commit().unwrap();
+        assert_eq!(ids(&database), vec!["1", "2", "3", "7"]);
+        assert!(database.commit().is_err());
+    }
+
+    // Fails the change at each point in turn, outside and inside a transaction, until it gets
+    // through: every failed attempt must leave the tables, their indexes and the row ids as they
+    // were, and keep the transaction going
+    fn assert_atomic(change: impl Fn(&mut Database) -> Result<(), String>) {
+        for in_transaction in [false, true] {
+            for failures in 0.. {
+                let mut database = users();
+                database
+                    .create_index(
+                        "users",
+                        ColumnIndex {
+                            name: "doc_a".to_string(),
+                            columns: vec!["json_extract(doc, '$.a')".to_string()],
+                            unique: false,
+                            predicate: None,
+                        },
+                    )
+                    .unwrap();
+                if in_transaction {
+                    database.begin().unwrap();
+                    database.insert_rows("users", vec![row(3)]).unwrap();
+                }
+                let tables = database.tables.clone();
+                let next_row_id = database.next_row_id;
+
+                fault::fail_after(failures);
+                let result = change(&mut database);
+                fault::disarm();
+                if result.is_ok() {
+                    assert!(failures > 0);
+                    break;
+                }
+                assert!(result.unwrap_err().starts_with("Fault: "));
+                assert_eq!(database.tables, tables);
+                assert_eq!(database.next_row_id, next_row_id);
+                assert_eq!(database.in_transaction(), in_transaction);
+            }
+        }
+    }
+
+    #[test]
+    fn test_failed_changes_are_undone() {
+        assert_atomic(|database| {
+            database
+                .write_rows("users", vec![row(5), row(6)], vec![(0, row(7))])
+                .map(|_| ())
+        });
+        assert_atomic(|database| database.delete_rows("users", &[0, 1]));
+        // Several changes made as one statement
+        assert_atomic(|database| {
+            database.atomically(|database| {
+                database.delete_rows("users", &[0])?;
+                database.insert_rows("users", vec![row(1), row(8)])?;
+                database.write_rows("users", vec![], vec![(1, row(9))])?;
+                Ok(())
+            })
+        });
+    }
+
+    fn temp_database(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(Wal::path_for(&path));
+        path
+    }
+
+    #[test]
+    fn test_wal_recovers_committed_changes() {
+        let path = temp_database("recover.db");
+        let mut database = Database::open(&path).unwrap();
+        database
+            .create_table(
+                "users".to_string(),
+                vec![
+                    Column::new("id".to_string(), DataType::Integer),
+                    Column::new("doc".to_string(), DataType::Json),
+                ],
+                vec![],
+            )
+            .unwrap();
+        database.insert_rows("users", vec![row(1), row(2)]).unwrap();
+        database.delete_rows("users", &[0]).unwrap();
+        database.begin().unwrap();
+        database.insert_rows("users", vec![row(3)]).unwrap();
+        database.commit().unwrap();
+        let committed = database.tables.clone();
+        database.begin().unwrap();
+        database.insert_rows("users", vec![row(4)]).unwrap();
+        // Crash with a transaction open and without ever writing the database file
+        drop(database);
+        assert!(!path.exists());
+
+        let reopen = || Database::open(&path).unwrap();
+        let database = reopen();
+        assert_eq!(database.tables, committed);
+        assert_eq!(database.next_row_id, 3);
+
+        // A crash during a checkpoint, after the file was written but before the log was emptied,
+        // replays the log onto the new file
+        database.save_to_file(&path).unwrap();
+        drop(database);
+        let mut database = reopen();
+        assert_eq!(database.tables, committed);
+
+        // Only the torn record is lost, whatever is left of it
+        database.checkpoint().unwrap();
+        database.insert_rows("users", vec![row(5)]).unwrap();
+        let before_last = database.tables.clone();
+        database.insert_rows("users", vec![row(6)]).unwrap();
+        let after_last = database.tables.clone();
+        drop(database);
+        let wal_path = Wal::path_for(&path);
+        let log = fs::read(&wal_path).unwrap();
+        for length in log.len() / 2 + 1..log.len() {
+            fs::write(&wal_path, &log[..length]).unwrap();
+            let database = reopen();
+            assert_eq!(database.tables, before_last);
+            assert_eq!(database.next_row_id, 4);
+        }
+        fs::write(&wal_path, [log.as_slice(), b"garbage"].concat()).unwrap();
+        assert_eq!(reopen().tables, after_last);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&wal_path).unwrap();
+    }
+
+    #[test]
+    fn test_snapshots_replace_the_file_atomically() {
+        let path = temp_database("snapshot.db");
+        let backup = with_suffix(&path, ".bak");
+        let _ = fs::remove_file(&backup);
+        let mut database = users();
+        database.save_to_file(&path).unwrap();
+        let first = fs::read(&path).unwrap();
+        database.insert_rows("users", vec![row(3)]).unwrap();
+
+        // Failing after the new version is written but before it replaces the file
+        fault::fail_after(0);
+        assert!(database.save_to_file(&path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), first);
+        assert!(!with_suffix(&path, ".tmp").exists());
+
+        database.save_to_file(&path).unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), first);
+        assert_eq!(read_snapshot(&path).unwrap().0.tables, database.tables);
+
+        let truncated = &first[..first.len() / 2];
+        fs::write(&path, truncated).unwrap();
+        let error = read_snapshot(&path).unwrap_err().to_string();
+        assert!(error.contains("is truncated"), "{}", error);
+        assert!(error.contains(".bak"), "{}", error);
+        fs::write(&path, b"").unwrap();
+        assert!(read_snapshot(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("is truncated"));
+        fs::write(
+            &path,
+            [&first[..30], b"\xff\x00 garbled", &first[30..]].concat(),
+        )
+        .unwrap();
+        let error = read_snapshot(&path).unwrap_err().to_string();
+        assert!(error.contains("is corrupt"), "{}", error);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn test_json_files_are_migrated() {
+        let path = temp_database("migrate.db");
+        let backup = with_suffix(&path, ".bak");
+        let _ = fs::remove_file(&backup);
+        let mut database = users();
+        database.insert_rows("users", vec![row(3)]).unwrap();
+        database.export_json(&path).unwrap();
+        let exported = fs::read(&path).unwrap();
+        assert!(file_format::is_json(&exported));
+
+        let loaded = Database::load_from_file(&path).unwrap();
+        assert_eq!(loaded.tables, database.tables);
+        assert_eq!(loaded.next_row_id, database.next_row_id);
+        assert!(fs::read(&path).unwrap().starts_with(file_format::MAGIC));
+        assert_eq!(fs::read(&backup).unwrap(), exported);
+        drop(loaded);
+        assert_eq!(
+            Database::load_from_file(&path).unwrap().tables,
+            database.tables
+        );
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+        fs::remove_file(Wal::path_for(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_first_json_files_are_migrated() {
+        let path = temp_database("first.db");
+        let backup = with_suffix(&path, ".bak");
+        let _ = fs::remove_file(&backup);
+        // As the first versions wrote it, with bare VARCHAR, CHAR and DECIMAL types and an
+        // `indexes` map that is now rebuilt on load instead
+        let old = r#"{"tables":{"items":{"columns":[
+            {"name":"id","data_type":"Integer"},
+            {"name":"name","data_type":"Varchar"},
+            {"name":"code","data_type":"Char"},
+            {"name":"price","data_type":"Decimal"}],
+            "data":{"0":{"values":[{"Integer":1},{"Text":"lamp"},{"Text":"L"},{"Integer":5}]}},
+            "indexes":{}}},"next_row_id":1}"#;
+        fs::write(&path, old).unwrap();
+
+        let types = |database: &Database| -> Vec<DataType> {
+            let table = database.get_table("items").unwrap();
+            table
+                .get_columns()
+                .iter()
+                .map(|column| column.data_type.clone())
+                .collect()
+        };
+        let expected = vec![
+            DataType::Integer,
+            DataType::Varchar(None),
+            DataType::Char(None),
+            DataType::Decimal(None),
+        ];
+        let loaded = Database::load_from_file(&path).unwrap();
+        assert_eq!(types(&loaded), expected);
+        assert_eq!(loaded.next_row_id, 1);
+        let rows: Vec<Row> = loaded
+            .get_table("items")
+            .unwrap()
+            .scan()
+            .map(|(_, row)| row.clone())
+            .collect();
+        assert_eq!(rows[0].values[1], Value::Text("lamp".to_string()));
+        assert_eq!(fs::read(&backup).unwrap(), old.as_bytes());
+        drop(loaded);
+
+        // The binary file it was rewritten as keeps the types, as do the JSON exports
+        let reloaded = Database::load_from_file(&path).unwrap();
+        assert_eq!(types(&reloaded), expected);
+        reloaded.export_json(&path).unwrap();
+        assert_eq!(types(&Database::load_from_file(&path).unwrap()), expected);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+        fs::remove_file(Wal::path_for(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_statistics_are_saved() {
+        let path = temp_database("statistics.db");
+        let mut database = users();
+        database.insert_rows("users", vec![row(3)]).unwrap();
+
+        let columns = database.schema("users").unwrap().columns;
+        let rows = database
+            .scan("users", ALL_ROWS)
+            .unwrap()
+            .map(|row| row.map(|(_, row)| row.values));
+        let statistics = TableStatistics::collect(&columns, rows).unwrap();
+        database
+            .set_statistics("users", statistics.clone())
+            .unwrap();
+        database.save_to_file(&path).unwrap();
+        let loaded = Database::load_from_file(&path).unwrap();
+        assert_eq!(loaded.schema("users").unwrap().statistics, Some(statistics));
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(Wal::path_for(&path)).unwrap();
+    }
 }
@@ -0,0 +1,891 @@
+// Behaviour every storage engine shares. Each engine's tests call `run` with a function that
+// opens a new, empty instance of it.
+use std::ops::Bound;
+
+use super::data_types::{Column, DataType, UniqueConstraint, Value};
+use super::decimal::Decimal;
+use super::engine::{self, StorageEngine};
+use super::full_text::FullTextIndex;
+use super::statistics::TableStatistics;
+use super::table::{ColumnIndex, IndexRange, ALL_ROWS};
+
+pub fn run(open: &dyn Fn() -> Box<dyn StorageEngine>) {
+    tables(open().as_mut());
+    rows(open().as_mut());
+    unique_constraints(open().as_mut());
+    index_lookups(open().as_mut());
+    created_indexes(open().as_mut());
+    composite_indexes(open().as_mut());
+    expression_indexes(open().as_mut());
+    full_text_indexes(open().as_mut());
+    statistics(open().as_mut());
+    transactions(open().as_mut());
+    statements(open().as_mut());
+}
+
+fn users(engine: &mut dyn StorageEngine) {
+    engine
+        .create_table(
+            "users".to_string(),
+            vec![
+                Column::new("id".to_string(), DataType::Integer),
+                Column::new("email".to_string(), DataType::Text),
+                Column::new("doc".to_string(), DataType::Json),
+            ],
+            vec![
+                UniqueConstraint {
+                    columns: vec!["id".to_string()],
+                    primary_key: true,
+                },
+                UniqueConstraint {
+                    columns: vec!["email".to_string()],
+                    primary_key: false,
+                },
+            ],
+        )
+        .unwrap();
+}
+
+fn user(id: i64, email: &str) -> Vec<Value> {
+    vec![
+        Value::Integer(id),
+        Value::Text(email.to_string()),
+        Value::Text(format!(r#"{{"n": {}}}"#, id)),
+    ]
+}
+
+fn log_table(engine: &mut dyn StorageEngine) {
+    engine
+        .create_table(
+            "log".to_string(),
+            vec![Column::new("line".to_string(), DataType::Text)],
+            vec![],
+        )
+        .unwrap();
+}
+
+fn index_on_line(name: &str, unique: bool) -> ColumnIndex {
+    ColumnIndex {
+        name: name.to_string(),
+        columns: vec!["LINE".to_string()],
+        unique,
+        predicate: None,
+    }
+}
+
+// An index on a path into the JSON column
+fn index_on_n() -> ColumnIndex {
+    ColumnIndex {
+        name: "users_n".to_string(),
+        columns: vec!["json_extract(doc, '$.n')".to_string()],
+        unique: false,
+        predicate: None,
+    }
+}
+
+// Row ids with the first value of each row
+fn contents(engine: &dyn StorageEngine, table: &str) -> Vec<(usize, Value)> {
+    engine
+        .scan(table, ALL_ROWS)
+        .unwrap()
+        .map(|row| {
+            let (row_id, row) = row.unwrap();
+            (row_id, row.values[0].clone())
+        })
+        .collect()
+}
+
+fn ids(values: &[i64]) -> Vec<Value> {
+    values.iter().map(|id| Value::Integer(*id)).collect()
+}
+
+fn first_values(engine: &dyn StorageEngine, table: &str) -> Vec<Value> {
+    contents(engine, table)
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect()
+}
+
+fn tables(engine: &mut dyn StorageEngine) {
+    assert!(engine.table_names().unwrap().is_empty());
+    users(engine);
+    log_table(engine);
+    assert_eq!(engine.table_names().unwrap(), vec!["log", "users"]);
+    let schema = engine.schema("users").unwrap();
+    assert_eq!(schema.columns.len(), 3);
+    assert_eq!(schema.constraints.len(), 2);
+
+    // Creating a table again keeps the one there, rows and all
+    engine
+        .write_rows("users", vec![user(1, "a")], vec![])
+        .unwrap();
+    let again = engine.create_table("USERS".to_string(), vec![], vec![]);
+    assert_eq!(again.unwrap_err(), "Table 'users' already exists");
+    assert_eq!(first_values(engine, "users"), vec![Value::Integer(1)]);
+
+    engine.drop_table("log").unwrap();
+    assert_eq!(engine.table_names().unwrap(), vec!["users"]);
+    assert_eq!(engine.schema("log").unwrap_err(), "Table 'log' not found");
+    assert!(engine.scan("log", ALL_ROWS).is_err());
+    assert!(engine.drop_table("log").is_err());
+
+    let invalid = engine.create_table(
+        "t".to_string(),
+        vec![Column::new("a".to_string(), DataType::Integer)],
+        vec![UniqueConstraint {
+            columns: vec!["nope".to_string()],
+            primary_key: false,
+        }],
+    );
+    assert_eq!(invalid.unwrap_err(), "Column 'nope' not found");
+    assert!(engine.schema("t").is_err());
+
+    assert!(engine.create_index("nope", index_on_n()).is_err());
+    engine.create_index("users", index_on_n()).unwrap();
+    assert_eq!(
+        engine.schema("users").unwrap().column_indexes,
+        vec![index_on_n()]
+    );
+    assert!(engine.create_index("users", index_on_n()).is_err());
+}
+
+fn rows(engine: &mut dyn StorageEngine) {
+    users(engine);
+    let inserted = engine
+        .write_rows(
+            "users",
+            vec![user(1, "a"), user(2, "b"), user(3, "c")],
+            vec![],
+        )
+        .unwrap();
+    assert_eq!(inserted, vec![0, 1, 2]);
+    // Values are coerced to the column types
+    let row = engine.get_row("users", 1).unwrap().unwrap();
+    assert_eq!(row.values[0], Value::Integer(2));
+    assert_eq!(row.values[2], Value::Json(r#"{"n":2}"#.to_string()));
+    assert_eq!(engine.get_row("users", 7).unwrap(), None);
+    assert!(engine.get_row("nope", 0).is_err());
+
+    let written = engine
+        .write_rows("users", vec![user(4, "d")], vec![(1, user(20, "b"))])
+        .unwrap();
+    assert_eq!(written, vec![3]);
+    assert_eq!(first_values(engine, "users"), ids(&[1, 20, 3, 4]));
+    assert_eq!(
+        engine
+            .write_rows("users", vec![], vec![(9, user(9, "z"))])
+            .unwrap_err(),
+        "Row 9 not found"
+    );
+    assert_eq!(
+        engine
+            .write_rows("users", vec![vec![Value::Integer(5)]], vec![])
+            .unwrap_err(),
+        "Number of values doesn't match number of columns"
+    );
+
+    engine.delete_rows("users", &[0]).unwrap();
+    assert_eq!(engine.get_row("users", 0).unwrap(), None);
+    assert_eq!(
+        engine.delete_rows("users", &[0, 1]).unwrap_err(),
+        "Row 0 not found"
+    );
+    assert!(engine.get_row("users", 1).unwrap().is_some());
+
+    let range = |engine: &dyn StorageEngine, start, end| -> Vec<usize> {
+        engine
+            .scan("users", (start, end))
+            .unwrap()
+            .map(|row| row.unwrap().0)
+            .collect()
+    };
+    assert_eq!(
+        range(engine, Bound::Unbounded, Bound::Unbounded),
+        vec![1, 2, 3]
+    );
+    assert_eq!(
+        range(engine, Bound::Included(2), Bound::Unbounded),
+        vec![2, 3]
+    );
+    assert_eq!(range(engine, Bound::Unbounded, Bound::Excluded(2)), vec![1]);
+    assert_eq!(
+        range(engine, Bound::Excluded(1), Bound::Included(2)),
+        vec![2]
+    );
+
+    // A batch that fails changes nothing and uses up no row ids
+    assert!(engine
+        .write_rows("users", vec![user(5, "e"), vec![Value::Null]], vec![])
+        .is_err());
+    assert_eq!(
+        engine
+            .write_rows("users", vec![user(5, "e")], vec![])
+            .unwrap(),
+        vec![4]
+    );
+}
+
+fn unique_constraints(engine: &mut dyn StorageEngine) {
+    users(engine);
+    engine
+        .write_rows("users", vec![user(1, "a"), user(2, "b")], vec![])
+        .unwrap();
+
+    assert_eq!(
+        engine
+            .write_rows("users", vec![user(1, "c")], vec![])
+            .unwrap_err(),
+        "Duplicate key (1) violates PRIMARY KEY (id)"
+    );
+    assert_eq!(
+        engine
+            .write_rows("users", vec![user(3, "a")], vec![])
+            .unwrap_err(),
+        "Duplicate key (a) violates UNIQUE (email)"
+    );
+    assert!(engine
+        .write_rows("users", vec![user(3, "c"), user(3, "d")], vec![])
+        .is_err());
+    assert_eq!(
+        engine
+            .write_rows("users", vec![vec![Value::Null; 3]], vec![])
+            .unwrap_err(),
+        "NULL value in PRIMARY KEY (id)"
+    );
+    assert!(engine
+        .write_rows("users", vec![], vec![(1, user(1, "b"))])
+        .is_err());
+    assert_eq!(first_values(engine, "users"), ids(&[1, 2]));
+
+    // NULLs never conflict in a UNIQUE column
+    let no_email = |id| vec![Value::Integer(id), Value::Null, Value::Null];
+    engine
+        .write_rows("users", vec![no_email(3), no_email(4)], vec![])
+        .unwrap();
+    // Keys may move between the rows of one batch
+    engine
+        .write_rows("users", vec![], vec![(0, user(2, "a")), (1, user(1, "b"))])
+        .unwrap();
+    assert_eq!(first_values(engine, "users"), ids(&[2, 1, 3, 4]));
+    engine
+        .write_rows("users", vec![], vec![(0, user(1, "a")), (1, user(2, "b"))])
+        .unwrap();
+    assert_eq!(first_values(engine, "users"), ids(&[1, 2, 3, 4]));
+}
+
+fn index_lookups(engine: &mut dyn StorageEngine) {
+    users(engine);
+    engine
+        .write_rows(
+            "users",
+            vec![user(1, "a"), user(2, "b"), user(3, "a2")],
+            vec![],
+        )
+        .unwrap();
+    let lookup = |engine: &dyn StorageEngine, index: &str, value: Value| {
+        engine.index_lookup("users", index, &[value]).unwrap()
+    };
+    assert_eq!(lookup(engine, "id", Value::Integer(2)), vec![1]);
+    assert_eq!(
+        lookup(engine, "email", Value::Text("a".to_string())),
+        vec![0]
+    );
+    assert!(lookup(engine, "id", Value::Integer(9)).is_empty());
+    assert!(lookup(engine, "nope", Value::Integer(1)).is_empty());
+    assert!(engine
+        .index_lookup("nope", "id", &[Value::Integer(1)])
+        .is_err());
+
+    engine.create_index("users", index_on_n()).unwrap();
+    assert_eq!(lookup(engine, "users_n", Value::Integer(3)), vec![2]);
+
+    engine
+        .write_rows("users", vec![], vec![(1, user(7, "b"))])
+        .unwrap();
+    assert!(lookup(engine, "id", Value::Integer(2)).is_empty());
+    assert_eq!(lookup(engine, "id", Value::Integer(7)), vec![1]);
+    assert_eq!(lookup(engine, "users_n", Value::Integer(7)), vec![1]);
+    engine.delete_rows("users", &[0]).unwrap();
+    assert!(lookup(engine, "email", Value::Text("a".to_string())).is_empty());
+
+    // Rows with equal values are found together, whichever way they are written
+    engine
+        .create_table(
+            "prices".to_string(),
+            vec![Column::new("price".to_string(), DataType::Decimal(None))],
+            vec![],
+        )
+        .unwrap();
+    let decimal = |s: &str| Value::Decimal(Decimal::parse(s).unwrap());
+    engine
+        .write_rows(
+            "prices",
+            vec![
+                vec![decimal("2.50")],
+                vec![decimal("2.5")],
+                vec![decimal("3")],
+            ],
+            vec![],
+        )
+        .unwrap();
+    // Only constrained columns are indexed without asking
+    assert!(engine
+        .index_lookup("prices", "price", &[decimal("2.5")])
+        .unwrap()
+        .is_empty());
+    engine
+        .create_index(
+            "prices",
+            ColumnIndex {
+                name: "by_price".to_string(),
+                columns: vec!["price".to_string()],
+                unique: false,
+                predicate: None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        engine
+            .index_lookup("prices", "by_price", &[decimal("2.500")])
+            .unwrap(),
+        vec![3, 4]
+    );
+}
+
+fn created_indexes(engine: &mut dyn StorageEngine) {
+    users(engine);
+    log_table(engine);
+    let line = |s: &str| vec![Value::Text(s.to_string())];
+    engine
+        .write_rows("log", vec![line("x"), line("y"), line("x")], vec![])
+        .unwrap();
+    let lookup = |engine: &dyn StorageEngine, index: &str, value: &str| {
+        engine
+            .index_lookup("log", index, &[Value::Text(value.to_string())])
+            .unwrap()
+    };
+
+    // Built from the rows already there, and kept up to date after that
+    engine
+        .create_index("log", index_on_line("log_line", false))
+        .unwrap();
+    assert_eq!(lookup(engine, "log_line", "x"), vec![0, 2]);
+    engine.write_rows("log", vec![line("x")], vec![]).unwrap();
+    assert_eq!(lookup(engine, "log_line", "x"), vec![0, 2, 3]);
+    assert_eq!(
+        engine.schema("log").unwrap().column_indexes,
+        vec![ColumnIndex {
+            columns: vec!["line".to_string()],
+            ..index_on_line("log_line", false)
+        }]
+    );
+
+    assert_eq!(
+        engine
+            .create_index("log", index_on_line("log_line", false))
+            .unwrap_err(),
+        "Index 'log_line' already exists"
+    );
+    assert!(engine
+        .create_index("log", index_on_line("line", false))
+        .is_err());
+    assert!(engine
+        .create_index("nope", index_on_line("other", false))
+        .is_err());
+    let mut unknown = index_on_line("other", false);
+    unknown.columns = vec!["nope".to_string()];
+    assert_eq!(
+        engine.create_index("log", unknown).unwrap_err(),
+        "Column 'nope' not found"
+    );
+
+    // A UNIQUE index checks the rows already there, and the ones written after it
+    assert_eq!(
+        engine
+            .create_index("log", index_on_line("log_line_key", true))
+            .unwrap_err(),
+        "Duplicate key (x) violates UNIQUE (line)"
+    );
+    assert_eq!(engine.schema("log").unwrap().column_indexes.len(), 1);
+    engine.delete_rows("log", &[2, 3]).unwrap();
+    engine
+        .create_index("log", index_on_line("log_line_key", true))
+        .unwrap();
+    assert_eq!(
+        engine
+            .write_rows("log", vec![line("y")], vec![])
+            .unwrap_err(),
+        "Duplicate key (y) violates UNIQUE (line)"
+    );
+    engine
+        .write_rows("log", vec![line("z")], vec![(1, line("x2"))])
+        .unwrap();
+    assert_eq!(lookup(engine, "log_line_key", "x2"), vec![1]);
+
+    engine.drop_index("log", "LOG_LINE").unwrap();
+    assert!(lookup(engine, "log_line", "x").is_empty());
+    assert_eq!(
+        engine.drop_index("log", "log_line").unwrap_err(),
+        "Index 'log_line' not found"
+    );
+    // The indexes behind constraints go with the constraint
+    assert!(engine.drop_index("users", "id").is_err());
+    engine.create_index("users", index_on_n()).unwrap();
+    engine.drop_index("users", "users_n").unwrap();
+    assert!(engine.schema("users").unwrap().column_indexes.is_empty());
+
+    // Dropped and created indexes come back and go again on rollback
+    engine.begin().unwrap();
+    engine.drop_index("log", "log_line_key").unwrap();
+    engine
+        .create_index("log", index_on_line("log_line", false))
+        .unwrap();
+    engine.write_rows("log", vec![line("y")], vec![]).unwrap();
+    assert_eq!(lookup(engine, "log_line", "y"), vec![5]);
+    engine.rollback().unwrap();
+    assert_eq!(
+        engine.schema("log").unwrap().column_indexes,
+        vec![ColumnIndex {
+            columns: vec!["line".to_string()],
+            ..index_on_line("log_line_key", true)
+        }]
+    );
+    assert_eq!(lookup(engine, "log_line_key", "x2"), vec![1]);
+    assert!(lookup(engine, "log_line_key", "y").is_empty());
+
+    // A UNIQUE index over an expression, here a path into the JSON column, keeps it unique
+    engine
+        .write_rows("users", vec![user(1, "a")], vec![])
+        .unwrap();
+    let unique_n = ColumnIndex {
+        name: "users_n_key".to_string(),
+        unique: true,
+        ..index_on_n()
+    };
+    engine.create_index("users", unique_n).unwrap();
+    let same_n = vec![
+        Value::Integer(2),
+        Value::Text("b".to_string()),
+        Value::Text(r#"{"n": 1}"#.to_string()),
+    ];
+    assert_eq!(
+        engine
+            .write_rows("users", vec![same_n], vec![])
+            .unwrap_err(),
+        "Duplicate key (1) violates UNIQUE (json_extract(doc, '$.n'))"
+    );
+    engine.drop_index("users", "users_n_key").unwrap();
+}
+
+fn composite_indexes(engine: &mut dyn StorageEngine) {
+    engine
+        .create_table(
+            "events".to_string(),
+            vec![
+                Column::new("kind".to_string(), DataType::Text),
+                Column::new("at".to_string(), DataType::Integer),
+            ],
+            vec![],
+        )
+        .unwrap();
+    let kind = |s: &str| Value::Text(s.to_string());
+    let event = |k: &str, at: i64| vec![kind(k), Value::Integer(at)];
+    engine
+        .write_rows(
+            "events",
+            vec![
+                event("b", 3),
+                event("a", 2),
+                event("b", 1),
+                event("a", 5),
+                vec![Value::Null, Value::Integer(4)],
+                event("b", 2),
+            ],
+            vec![],
+        )
+        .unwrap();
+    engine
+        .create_index(
+            "events",
+            ColumnIndex {
+                name: "events_kind_at".to_string(),
+                columns: vec!["kind".to_string(), "at".to_string()],
+                unique: false,
+                predicate: None,
+            },
+        )
+        .unwrap();
+    let scan = |engine: &dyn StorageEngine, range: IndexRange| {
+        engine
+            .index_scan("events", "events_kind_at", &range)
+            .unwrap()
+            .collect::<Result<Vec<usize>, String>>()
+            .unwrap()
+    };
+    let range = |k: &str, lower, upper| IndexRange {
+        prefix: vec![kind(k)],
+        lower,
+        upper,
+    };
+
+    // In key order, column by column, with NULLs first
+    assert_eq!(scan(engine, IndexRange::all()), vec![4, 1, 3, 2, 5, 0]);
+    assert_eq!(
+        engine
+            .index_lookup("events", "events_kind_at", &[kind("b")])
+            .unwrap(),
+        vec![0, 2, 5]
+    );
+    assert_eq!(
+        engine
+            .index_lookup("events", "events_kind_at", &event("b", 2))
+            .unwrap(),
+        vec![5]
+    );
+    // Equality on the first column and a range on the second
+    assert_eq!(
+        scan(
+            engine,
+            range(
+                "b",
+                Bound::Excluded(Value::Integer(1)),
+                Bound::Included(Value::Integer(3))
+            )
+        ),
+        vec![5, 0]
+    );
+    assert_eq!(
+        scan(
+            engine,
+            range("a", Bound::Unbounded, Bound::Excluded(Value::Integer(5)))
+        ),
+        vec![1]
+    );
+    assert_eq!(
+        scan(
+            engine,
+            range("a", Bound::Included(Value::Integer(2)), Bound::Unbounded)
+        ),
+        vec![1, 3]
+    );
+    assert!(scan(engine, IndexRange::prefix(vec![kind("c")])).is_empty());
+
+    // Uncommitted rows are merged into the order
+    engine.begin().unwrap();
+    engine
+        .write_rows("events", vec![event("a", 3)], vec![(0, event("a", 1))])
+        .unwrap();
+    assert_eq!(scan(engine, IndexRange::all()), vec![4, 0, 1, 6, 3, 2, 5]);
+    assert_eq!(
+        scan(
+            engine,
+            range("a", Bound::Excluded(Value::Integer(1)), Bound::Unbounded)
+        ),
+        vec![1, 6, 3]
+    );
+    engine.rollback().unwrap();
+    assert_eq!(scan(engine, IndexRange::all()), vec![4, 1, 3, 2, 5, 0]);
+}
+
+fn expression_indexes(engine: &mut dyn StorageEngine) {
+    engine
+        .create_table(
+            "tags".to_string(),
+            vec![
+                Column::new("name".to_string(), DataType::Text),
+                Column::new("hidden".to_string(), DataType::Boolean),
+            ],
+            vec![],
+        )
+        .unwrap();
+    let tag = |name: &str, hidden| vec![Value::Text(name.to_string()), hidden];
+    engine
+        .write_rows(
+            "tags",
+            vec![
+                tag("Rust", Value::Boolean(false)),
+                tag("rust", Value::Boolean(true)),
+                tag("Go", Value::Boolean(false)),
+                tag("GO", Value::Null),
+            ],
+            vec![],
+        )
+        .unwrap();
+    let index = |name: &str, key: &str, predicate: Option<&str>| ColumnIndex {
+        name: name.to_string(),
+        columns: vec![key.to_string()],
+        unique: false,
+        predicate: predicate.map(str::to_string),
+    };
+    engine
+        .create_index("tags", index("tags_lower", "lower(name)", None))
+        .unwrap();
+    engine
+        .create_index("tags", index("visible_tags", "name", Some("NOT hidden")))
+        .unwrap();
+    let all = |engine: &dyn StorageEngine, index: &str| {
+        engine
+            .index_scan("tags", index, &IndexRange::all())
+            .unwrap()
+            .collect::<Result<Vec<usize>, String>>()
+            .unwrap()
+    };
+    let lower = |engine: &dyn StorageEngine, name: &str| {
+        engine
+            .index_lookup("tags", "tags_lower", &[Value::Text(name.to_string())])
+            .unwrap()
+    };
+
+    assert_eq!(all(engine, "tags_lower"), vec![2, 3, 0, 1]);
+    assert_eq!(lower(engine, "rust"), vec![0, 1]);
+    // Only the rows the predicate is true for, so not the one with a NULL
+    assert_eq!(all(engine, "visible_tags"), vec![2, 0]);
+
+    engine.begin().unwrap();
+    engine
+        .write_rows(
+            "tags",
+            vec![],
+            vec![(1, tag("rust", Value::Boolean(false)))],
+        )
+        .unwrap();
+    engine.delete_rows("tags", &[0]).unwrap();
+    assert_eq!(all(engine, "visible_tags"), vec![2, 1]);
+    engine.commit().unwrap();
+    assert_eq!(all(engine, "visible_tags"), vec![2, 1]);
+    engine
+        .write_rows("tags", vec![], vec![(2, tag("go", Value::Boolean(true)))])
+        .unwrap();
+    assert_eq!(all(engine, "visible_tags"), vec![1]);
+    assert_eq!(lower(engine, "rust"), vec![1]);
+    assert_eq!(lower(engine, "go"), vec![2, 3]);
+}
+
+fn full_text_indexes(engine: &mut dyn StorageEngine) {
+    log_table(engine);
+    let line = |text: &str| vec![Value::Text(text.to_string())];
+    engine
+        .write_rows(
+            "log",
+            vec![
+                line("Disk full, disk FULL"),
+                line("network down"),
+                line("disk slow"),
+            ],
+            vec![],
+        )
+        .unwrap();
+    engine
+        .create_full_text_index(
+            "log",
+            FullTextIndex {
+                name: "log_words".to_string(),
+                column: "Line".to_string(),
+            },
+        )
+        .unwrap();
+    let word = |engine: &dyn StorageEngine, word: &str| {
+        engine
+            .index_lookup("log", "log_words", &[Value::Text(word.to_string())])
+            .unwrap()
+    };
+
+    // One entry per distinct word of a row
+    assert_eq!(word(engine, "disk"), vec![0, 2]);
+    assert_eq!(word(engine, "full"), vec![0]);
+    assert_eq!(word(engine, "Disk"), Vec::<usize>::new());
+    assert_eq!(
+        engine.schema("log").unwrap().full_text_indexes[0].column,
+        "line"
+    );
+
+    engine.begin().unwrap();
+    engine
+        .write_rows("log", vec![line("disk ok")], vec![(0, line("all ok"))])
+        .unwrap();
+    assert_eq!(word(engine, "disk"), vec![2, 3]);
+    assert_eq!(word(engine, "ok"), vec![0, 3]);
+    engine.rollback().unwrap();
+    assert_eq!(word(engine, "ok"), Vec::<usize>::new());
+
+    engine.delete_rows("log", &[2]).unwrap();
+    engine
+        .write_rows("log", vec![], vec![(1, line("disk down"))])
+        .unwrap();
+    assert_eq!(word(engine, "disk"), vec![0, 1]);
+    assert_eq!(word(engine, "network"), Vec::<usize>::new());
+    assert!(engine
+        .create_full_text_index(
+            "log",
+            FullTextIndex {
+                name: "log_words".to_string(),
+                column: "line".to_string(),
+            },
+        )
+        .is_err());
+    engine.drop_index("log", "log_words").unwrap();
+    assert!(engine.schema("log").unwrap().full_text_indexes.is_empty());
+}
+
+fn statistics(engine: &mut dyn StorageEngine) {
+    users(engine);
+    engine
+        .write_rows("users", vec![user(1, "a"), user(2, "b")], vec![])
+        .unwrap();
+    let analyze = |engine: &dyn StorageEngine| {
+        let columns = engine.schema("users").unwrap().columns;
+        let rows = engine
+            .scan("users", ALL_ROWS)
+            .unwrap()
+            .map(|row| row.map(|(_, row)| row.values));
+        TableStatistics::collect(&columns, rows).unwrap()
+    };
+    assert_eq!(engine.schema("users").unwrap().statistics, None);
+    let two = analyze(engine);
+    engine.set_statistics("users", two.clone()).unwrap();
+    assert_eq!(
+        engine.schema("users").unwrap().statistics.as_ref(),
+        Some(&two)
+    );
+    assert_eq!(two.rows, 2);
+
+    engine.begin().unwrap();
+    engine
+        .write_rows("users", vec![user(3, "c")], vec![])
+        .unwrap();
+    let three = analyze(engine);
+    assert_eq!(three.rows, 3);
+    engine.set_statistics("users", three.clone()).unwrap();
+    assert_eq!(
+        engine.schema("users").unwrap().statistics.as_ref(),
+        Some(&three)
+    );
+    engine.rollback().unwrap();
+    assert_eq!(engine.schema("users").unwrap().statistics, Some(two));
+
+    // Indexes are still there, and the rows they point to
+    assert_eq!(
+        engine
+            .index_lookup("users", "email", &[Value::Text("b".to_string())])
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(engine.set_statistics("nope", analyze(engine)).is_err());
+}
+
+fn transactions(engine: &mut dyn StorageEngine) {
+    users(engine);
+    engine
+        .write_rows("users", vec![user(1, "a")], vec![])
+        .unwrap();
+
+    engine.begin().unwrap();
+    assert!(engine.in_transaction());
+    assert_eq!(
+        engine.begin().unwrap_err(),
+        "A transaction is already in progress"
+    );
+    engine
+        .write_rows("users", vec![user(2, "b")], vec![(0, user(10, "a"))])
+        .unwrap();
+    log_table(engine);
+    engine.create_index("users", index_on_n()).unwrap();
+    engine.delete_rows("users", &[0]).unwrap();
+    // The transaction sees its own changes
+    assert_eq!(contents(engine, "users"), vec![(1, Value::Integer(2))]);
+    assert_eq!(
+        engine
+            .index_lookup("users", "users_n", &[Value::Integer(2)])
+            .unwrap(),
+        vec![1]
+    );
+    assert_eq!(engine.table_names().unwrap(), vec!["log", "users"]);
+    assert!(engine.checkpoint().is_err());
+
+    engine.rollback().unwrap();
+    assert!(!engine.in_transaction());
+    assert_eq!(contents(engine, "users"), vec![(0, Value::Integer(1))]);
+    assert_eq!(engine.table_names().unwrap(), vec!["users"]);
+    assert!(engine.schema("users").unwrap().column_indexes.is_empty());
+    // Row ids given out in the transaction are free again
+    assert_eq!(
+        engine
+            .write_rows("users", vec![user(2, "b")], vec![])
+            .unwrap(),
+        vec![1]
+    );
+
+    engine.begin().unwrap();
+    engine
+        .write_rows("users", vec![user(3, "c")], vec![])
+        .unwrap();
+    engine.savepoint("a").unwrap();
+    engine
+        .write_rows("users", vec![user(4, "d")], vec![])
+        .unwrap();
+    engine.savepoint("b").unwrap();
+    engine.drop_table("users").unwrap();
+    engine.rollback_to_savepoint("b").unwrap();
+    assert_eq!(first_values(engine, "users"), ids(&[1, 2, 3, 4]));
+    engine.rollback_to_savepoint("a").unwrap();
+    assert_eq!(first_values(engine, "users"), ids(&[1, 2, 3]));
+    // Released along with every later savepoint
+    engine.release_savepoint("a").unwrap();
+    assert!(engine.rollback_to_savepoint("b").is_err());
+    log_table(engine);
+    engine
+        .write_rows("log", vec![vec![Value::Text("x".to_string())]], vec![])
+        .unwrap();
+    engine.commit().unwrap();
+    assert_eq!(
+        engine.commit().unwrap_err(),
+        "No transaction is in progress"
+    );
+    assert!(engine.savepoint("c").is_err());
+    engine.checkpoint().unwrap();
+
+    assert_eq!(first_values(engine, "users"), ids(&[1, 2, 3]));
+    assert_eq!(
+        contents(engine, "log"),
+        vec![(3, Value::Text("x".to_string()))]
+    );
+    assert_eq!(
+        engine
+            .write_rows("users", vec![user(5, "e")], vec![])
+            .unwrap(),
+        vec![4]
+    );
+}
+
+fn statements(engine: &mut dyn StorageEngine) {
+    users(engine);
+    let failed = engine::atomically(engine, |engine| {
+        engine.write_rows("users", vec![user(1, "a")], vec![])?;
+        engine.write_rows("users", vec![user(1, "b")], vec![])
+    });
+    assert!(failed.is_err());
+    assert!(contents(engine, "users").is_empty());
+    assert_eq!(
+        engine
+            .write_rows("users", vec![user(1, "a")], vec![])
+            .unwrap(),
+        vec![0]
+    );
+
+    // Inside a transaction only the failed statement is undone
+    engine.begin().unwrap();
+    engine
+        .write_rows("users", vec![user(2, "b")], vec![])
+        .unwrap();
+    let failed: Result<(), String> = engine::atomically(engine, |engine| {
+        engine.delete_rows("users", &[0, 1])?;
+        log_table(engine);
+        Err("failed".to_string())
+    });
+    assert_eq!(failed.unwrap_err(), "failed");
+    assert!(engine.in_transaction());
+    assert_eq!(engine.table_names().unwrap(), vec!["users"]);
+    engine.commit().unwrap();
+    assert_eq!(first_values(engine, "users"), ids(&[1, 2]));
+}
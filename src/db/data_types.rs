@@ -1,7 +1,14 @@
 // TODO: might not need this anymore since we have tokenizer
-use serde::{Deserialize, Serialize};
+use core::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use super::datetime::{Date, Interval, Timestamp};
+use super::decimal::{Decimal, MAX_PRECISION};
+use super::json;
+
+// Serialized through the impls below, which fall back on the derived ones
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(remote = "Self")]
 pub enum DataType {
     Integer,
     Text,
@@ -11,11 +18,104 @@ pub enum DataType {
     Boolean,
     Date,
     Timestamp,
-    Varchar,
-    Char,
+    // Maximum length in characters, unbounded when omitted
+    Varchar(Option<u32>),
+    // Fixed length in characters, CHAR without a length means CHAR(1)
+    Char(Option<u32>),
     Float,
     Double,
-    Decimal,
+    // Precision and scale, unconstrained when omitted
+    Decimal(Option<(u32, u32)>),
+    Json,
+}
+
+impl Serialize for DataType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DataType::serialize(self, serializer)
+    }
+}
+
+// JSON files written before VARCHAR, CHAR and DECIMAL took parameters name them bare, as
+// "Varchar" rather than {"Varchar": null}. Binary files always had the parameters.
+impl<'de> Deserialize<'de> for DataType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        enum Bare {
+            Varchar,
+            Char,
+            Decimal,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Current(#[serde(with = "DataType")] DataType),
+            Bare(Bare),
+        }
+
+        if !deserializer.is_human_readable() {
+            return DataType::deserialize(deserializer);
+        }
+        Ok(match Stored::deserialize(deserializer)? {
+            Stored::Current(data_type) => data_type,
+            Stored::Bare(Bare::Varchar) => DataType::Varchar(None),
+            Stored::Bare(Bare::Char) => DataType::Char(None),
+            Stored::Bare(Bare::Decimal) => DataType::Decimal(None),
+        })
+    }
+}
+
+impl DataType {
+    // Checks the parameters given in a column definition such as VARCHAR(255) or DECIMAL(10, 2)
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            DataType::Varchar(Some(0)) | DataType::Char(Some(0)) => {
+                Err(format!("Data type: length of {} must be at least 1", self))
+            }
+            DataType::Decimal(Some((precision, scale))) => {
+                if *precision == 0 || *precision > MAX_PRECISION {
+                    return Err(format!(
+                        "Data type: DECIMAL precision must be between 1 and {}",
+                        MAX_PRECISION
+                    ));
+                }
+                if scale > precision {
+                    return Err(format!(
+                        "Data type: DECIMAL scale {} exceeds precision {}",
+                        scale, precision
+                    ));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Integer => write!(f, "INTEGER"),
+            DataType::Text => write!(f, "TEXT"),
+            DataType::Real => write!(f, "REAL"),
+            DataType::Blob => write!(f, "BLOB"),
+            DataType::Null => write!(f, "NULL"),
+            DataType::Boolean => write!(f, "BOOLEAN"),
+            DataType::Date => write!(f, "DATE"),
+            DataType::Timestamp => write!(f, "TIMESTAMP"),
+            DataType::Varchar(None) => write!(f, "VARCHAR"),
+            DataType::Varchar(Some(length)) => write!(f, "VARCHAR({})", length),
+            DataType::Char(None) => write!(f, "CHAR"),
+            DataType::Char(Some(length)) => write!(f, "CHAR({})", length),
+            DataType::Float => write!(f, "FLOAT"),
+            DataType::Double => write!(f, "DOUBLE"),
+            DataType::Decimal(None) => write!(f, "DECIMAL"),
+            DataType::Decimal(Some((precision, scale))) => {
+                write!(f, "DECIMAL({}, {})", precision, scale)
+            }
+            DataType::Json => write!(f, "JSON"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -32,10 +132,49 @@ pub enum Keyword {
     Or,
     Join,
     On,
+    Index,
+    As,
+    Not,
+    Is,
+    True,
+    False,
+    Interval,
+    Extract,
+    CurrentDate,
+    CurrentTimestamp,
+    Default,
+    Primary,
+    Unique,
+    Conflict,
+    Do,
+    Nothing,
+    Update,
+    Set,
+    Delete,
+    Returning,
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint,
+    Release,
+    Transaction,
+    To,
+    Drop,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Between,
+    In,
+    Match,
+    Fulltext,
+    Analyze,
+    Explain,
+    Limit,
 }
 
 // Used for defining the schema
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Column {
     pub name: String,
     pub data_type: DataType,
@@ -47,6 +186,37 @@ impl Column {
     }
 }
 
+// PRIMARY KEY or UNIQUE over one or more columns. NULLs never conflict, except that a primary
+// key doesn't accept them at all.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UniqueConstraint {
+    pub columns: Vec<String>,
+    pub primary_key: bool,
+}
+
+impl UniqueConstraint {
+    // True when `columns` names exactly this constraint's columns, in any order
+    pub fn covers(&self, columns: &[String]) -> bool {
+        self.columns.len() == columns.len()
+            && columns
+                .iter()
+                .all(|name| self.columns.iter().any(|c| c.eq_ignore_ascii_case(name)))
+    }
+}
+
+impl fmt::Display for UniqueConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.primary_key {
+            "PRIMARY KEY"
+        } else {
+            "UNIQUE"
+        };
+        write!(f, "{} ({})", kind, self.columns.join(", "))
+    }
+}
+
+// TODO: wire up once FOREIGN KEY constraints are parsed
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize)]
 pub struct ForeignKey {
     column: String,
@@ -57,20 +227,124 @@ pub struct ForeignKey {
 // Used for defining the schema
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Value {
+    // Declared first so that NULLs sort before every other value
+    Null,
     Integer(i64),
     Text(String),
+    Boolean(bool),
+    Date(Date),
+    Timestamp(Timestamp),
+    Decimal(Decimal),
+    // Canonical JSON text, see json::canonicalize
+    Json(String),
+    // Only produced by expressions (INTERVAL literals, timestamp differences), never stored
+    Interval(Interval),
     // Add more value types as needed
 }
 
 impl Value {
     pub fn matches_type(&self, data_type: &DataType) -> bool {
-        true
-        // match (self, data_type) {
-        //     (Value::Integer(_), DataType::INTEGER) => true,
-        //     (Value::Text(_), DataType::TEXT) => true,
-        //     _ => false,
-        // }
+        matches!(
+            (self, data_type),
+            (Value::Null, _)
+                | (Value::Integer(_), DataType::Integer)
+                | (Value::Integer(_), DataType::Real)
+                | (Value::Integer(_), DataType::Float)
+                | (Value::Integer(_), DataType::Double)
+                | (Value::Decimal(_), DataType::Decimal(_))
+                | (Value::Decimal(_), DataType::Real)
+                | (Value::Decimal(_), DataType::Float)
+                | (Value::Decimal(_), DataType::Double)
+                | (Value::Text(_), DataType::Text)
+                | (Value::Text(_), DataType::Varchar(_))
+                | (Value::Text(_), DataType::Char(_))
+                | (Value::Text(_), DataType::Blob)
+                | (Value::Boolean(_), DataType::Boolean)
+                | (Value::Date(_), DataType::Date)
+                | (Value::Timestamp(_), DataType::Timestamp)
+                | (Value::Json(_), DataType::Json)
+        )
+    }
+
+    // Converts a value into the representation used by a column of the given type, e.g. the
+    // string literal '2024-01-31' into a DATE, and enforces the type's parameters (VARCHAR/CHAR
+    // length, DECIMAL precision and scale). Values that can't be converted are returned
+    // unchanged and rejected later by matches_type.
+    pub fn coerce_to(self, data_type: &DataType) -> Result<Value, String> {
+        match (self, data_type) {
+            (Value::Text(s), DataType::Date) => Ok(Value::Date(Date::parse(&s)?)),
+            (Value::Text(s), DataType::Timestamp) => Ok(Value::Timestamp(Timestamp::parse(&s)?)),
+            (Value::Date(date), DataType::Timestamp) => Ok(Value::Timestamp(date.to_timestamp()?)),
+            (Value::Text(s), DataType::Varchar(Some(length))) => {
+                Ok(Value::Text(fit_length(s, *length, data_type)?))
+            }
+            (Value::Text(s), DataType::Char(length)) => {
+                // CHAR(n) values are always exactly n characters, padded with spaces
+                let length = length.unwrap_or(1);
+                let s = fit_length(s, length, data_type)?;
+                let padding = length as usize - s.chars().count();
+                Ok(Value::Text(s + &" ".repeat(padding)))
+            }
+            (Value::Text(s), DataType::Decimal(parameters)) => {
+                Value::Decimal(Decimal::parse(&s)?).coerce_to(&DataType::Decimal(*parameters))
+            }
+            (Value::Integer(n), DataType::Decimal(parameters)) => {
+                Value::Decimal(Decimal::from_i64(n)).coerce_to(&DataType::Decimal(*parameters))
+            }
+            (Value::Decimal(decimal), DataType::Decimal(Some((precision, scale)))) => {
+                Ok(Value::Decimal(decimal.fit(*precision, *scale)?))
+            }
+            // Documents are validated on the way in, so stored JSON always parses
+            (Value::Text(s), DataType::Json) | (Value::Json(s), DataType::Json) => {
+                Ok(Value::Json(json::canonicalize(&s)?))
+            }
+            (value, _) => Ok(value),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "NULL",
+            Value::Integer(_) => "INTEGER",
+            Value::Text(_) => "TEXT",
+            Value::Boolean(_) => "BOOLEAN",
+            Value::Date(_) => "DATE",
+            Value::Timestamp(_) => "TIMESTAMP",
+            Value::Decimal(_) => "DECIMAL",
+            Value::Json(_) => "JSON",
+            Value::Interval(_) => "INTERVAL",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Text(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Date(date) => write!(f, "{}", date),
+            Value::Timestamp(timestamp) => write!(f, "{}", timestamp),
+            Value::Decimal(decimal) => write!(f, "{}", decimal),
+            Value::Json(json) => write!(f, "{}", json),
+            Value::Interval(interval) => write!(f, "{}", interval),
+        }
+    }
+}
+
+// Enforces a VARCHAR/CHAR length limit. Like PostgreSQL, trailing spaces beyond the limit are
+// silently dropped while any other excess is an error.
+fn fit_length(s: String, length: u32, data_type: &DataType) -> Result<String, String> {
+    let length = length as usize;
+    if s.chars().count() <= length {
+        return Ok(s);
+    }
+    let (kept, excess) = s.split_at(s.char_indices().nth(length).map_or(s.len(), |(i, _)| i));
+    if excess.chars().all(|c| c == ' ') {
+        return Ok(kept.to_string());
     }
+    Err(format!("Value too long for type {}", data_type))
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
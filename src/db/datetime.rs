@@ -0,0 +1,784 @@
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_MINUTE: i64 = 60 * MICROS_PER_SECOND;
+const MICROS_PER_HOUR: i64 = 60 * MICROS_PER_MINUTE;
+const MICROS_PER_DAY: i64 = 24 * MICROS_PER_HOUR;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+// Calendar date stored as the number of days since 1970-01-01 (proleptic Gregorian calendar)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date(pub i32);
+
+// Point in time stored as microseconds since 1970-01-01 00:00:00 UTC
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+// Months and days are kept apart from the time part because their length depends on the date
+// they are added to (e.g. '1 month' from January 31st lands on the last day of February)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+    DayOfWeek,
+    DayOfYear,
+    Hour,
+    Minute,
+    Second,
+    Epoch,
+}
+
+impl DateField {
+    pub fn parse(s: &str) -> Option<DateField> {
+        match s.to_uppercase().as_str() {
+            "YEAR" => Some(DateField::Year),
+            "QUARTER" => Some(DateField::Quarter),
+            "MONTH" => Some(DateField::Month),
+            "WEEK" => Some(DateField::Week),
+            "DAY" => Some(DateField::Day),
+            "DOW" => Some(DateField::DayOfWeek),
+            "DOY" => Some(DateField::DayOfYear),
+            "HOUR" => Some(DateField::Hour),
+            "MINUTE" => Some(DateField::Minute),
+            "SECOND" => Some(DateField::Second),
+            "EPOCH" => Some(DateField::Epoch),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DateField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DateField::Year => "YEAR",
+            DateField::Quarter => "QUARTER",
+            DateField::Month => "MONTH",
+            DateField::Week => "WEEK",
+            DateField::Day => "DAY",
+            DateField::DayOfWeek => "DOW",
+            DateField::DayOfYear => "DOY",
+            DateField::Hour => "HOUR",
+            DateField::Minute => "MINUTE",
+            DateField::Second => "SECOND",
+            DateField::Epoch => "EPOCH",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Source of CURRENT_DATE / CURRENT_TIMESTAMP. Tests swap in a FixedClock so results don't depend
+// on when they run.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Timestamp;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Timestamp(elapsed.as_micros() as i64)
+    }
+}
+
+#[derive(Debug)]
+pub struct FixedClock(pub Timestamp);
+
+impl Clock for FixedClock {
+    fn now(&self) -> Timestamp {
+        self.0
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if is_leap_year(year) => 29,
+        _ => 28,
+    }
+}
+
+// Conversions between civil dates and day numbers, see
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl Date {
+    pub fn from_ymd(year: i64, month: u32, day: u32) -> Result<Date, String> {
+        if !(1..=12).contains(&month) {
+            return Err(format!("DateTime: month {} out of range", month));
+        }
+        if day == 0 || day > days_in_month(year, month) {
+            return Err(format!(
+                "DateTime: day {} out of range for {:04}-{:02}",
+                day, year, month
+            ));
+        }
+        i32::try_from(days_from_civil(year, month, day))
+            .map(Date)
+            .map_err(|_| "DateTime: date out of range".to_string())
+    }
+
+    // Accepts the ISO-8601 calendar form YYYY-MM-DD
+    pub fn parse(s: &str) -> Result<Date, String> {
+        let mut cursor = Cursor::new(s.trim());
+        let date = cursor.date()?;
+        if !cursor.is_empty() {
+            return Err(format!("DateTime: invalid date '{}'", s));
+        }
+        Ok(date)
+    }
+
+    pub fn ymd(&self) -> (i64, u32, u32) {
+        civil_from_days(self.0 as i64)
+    }
+
+    // 0 = Sunday, matching EXTRACT(DOW ...) and strftime's %w
+    pub fn weekday(&self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.0 as i64 + 4).rem_euclid(7) as u32
+    }
+
+    pub fn day_of_year(&self) -> u32 {
+        let (year, _, _) = self.ymd();
+        (self.0 as i64 - days_from_civil(year, 1, 1)) as u32 + 1
+    }
+
+    // ISO-8601 week number; the first week of a year is the one containing its first Thursday
+    pub fn iso_week(&self) -> u32 {
+        let (year, _, _) = self.ymd();
+        let iso_weekday = (self.weekday() + 6) % 7 + 1;
+        let week = (self.day_of_year() as i64 - iso_weekday as i64 + 10) / 7;
+        if week < 1 {
+            // Belongs to the last week of the previous year, which always contains December 28th
+            return Date(days_from_civil(year - 1, 12, 28) as i32).iso_week();
+        }
+        let last_week = Date(days_from_civil(year, 12, 28) as i32);
+        if self.0 > last_week.0 && week as u32 > last_week.iso_week() {
+            return 1;
+        }
+        week as u32
+    }
+
+    pub fn add_months(&self, months: i32) -> Result<Date, String> {
+        let (year, month, day) = self.ymd();
+        let total = year * 12 + (month as i64 - 1) + months as i64;
+        let year = total.div_euclid(12);
+        let month = (total.rem_euclid(12) + 1) as u32;
+        Date::from_ymd(year, month, day.min(days_in_month(year, month)))
+    }
+
+    pub fn add_days(&self, days: i64) -> Result<Date, String> {
+        i32::try_from(self.0 as i64 + days)
+            .map(Date)
+            .map_err(|_| "DateTime: date out of range".to_string())
+    }
+
+    // Dates reach further than timestamps do, so not every date has one
+    pub fn to_timestamp(self) -> Result<Timestamp, String> {
+        (self.0 as i64)
+            .checked_mul(MICROS_PER_DAY)
+            .map(Timestamp)
+            .ok_or_else(|| "DateTime: timestamp out of range".to_string())
+    }
+
+    pub fn add_interval(&self, interval: &Interval) -> Result<Date, String> {
+        self.add_months(interval.months)?
+            .add_days(interval.days as i64)
+    }
+
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        self.to_timestamp()?.format(format)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl Timestamp {
+    // Accepts YYYY-MM-DD, optionally followed by 'T' or ' ' and HH:MM[:SS[.ffffff]] and a 'Z' or
+    // ±HH:MM offset. Offsets are normalised away, timestamps are always stored as UTC.
+    pub fn parse(s: &str) -> Result<Timestamp, String> {
+        let mut cursor = Cursor::new(s.trim());
+        let date = cursor.date()?;
+        let mut micros = date.0 as i64 * MICROS_PER_DAY;
+        if cursor.eat('T') || cursor.eat(' ') {
+            let hour = cursor.number(2, 2)?;
+            cursor.expect(':')?;
+            let minute = cursor.number(2, 2)?;
+            let mut second = 0;
+            let mut fraction = 0;
+            if cursor.eat(':') {
+                second = cursor.number(2, 2)?;
+                if cursor.eat('.') {
+                    let digits = cursor.digits(1, 9)?;
+                    let padded = format!("{:0<6}", digits);
+                    fraction = padded[..6].parse::<i64>().unwrap_or(0);
+                }
+            }
+            if hour > 23 || minute > 59 || second > 59 {
+                return Err(format!("DateTime: invalid time in '{}'", s));
+            }
+            micros += hour * MICROS_PER_HOUR
+                + minute * MICROS_PER_MINUTE
+                + second * MICROS_PER_SECOND
+                + fraction;
+
+            if !cursor.eat('Z') {
+                let sign = if cursor.eat('+') {
+                    Some(1)
+                } else if cursor.eat('-') {
+                    Some(-1)
+                } else {
+                    None
+                };
+                if let Some(sign) = sign {
+                    let offset_hours = cursor.number(2, 2)?;
+                    cursor.eat(':');
+                    let offset_minutes = cursor.number(2, 2)?;
+                    micros -= sign
+                        * (offset_hours * MICROS_PER_HOUR + offset_minutes * MICROS_PER_MINUTE);
+                }
+            }
+        }
+        if !cursor.is_empty() {
+            return Err(format!("DateTime: invalid timestamp '{}'", s));
+        }
+        Ok(Timestamp(micros))
+    }
+
+    pub fn date(&self) -> Date {
+        Date(self.0.div_euclid(MICROS_PER_DAY) as i32)
+    }
+
+    pub fn time_of_day(&self) -> i64 {
+        self.0.rem_euclid(MICROS_PER_DAY)
+    }
+
+    pub fn add_interval(&self, interval: &Interval) -> Result<Timestamp, String> {
+        let midnight = self.date().add_interval(interval)?.to_timestamp()?;
+        self.time_of_day()
+            .checked_add(interval.micros)
+            .and_then(|micros| micros.checked_add(midnight.0))
+            .map(Timestamp)
+            .ok_or_else(|| "DateTime: timestamp out of range".to_string())
+    }
+
+    pub fn extract(&self, field: DateField) -> i64 {
+        let date = self.date();
+        let (year, month, day) = date.ymd();
+        let time = self.time_of_day();
+        match field {
+            DateField::Year => year,
+            DateField::Quarter => (month as i64 - 1) / 3 + 1,
+            DateField::Month => month as i64,
+            DateField::Week => date.iso_week() as i64,
+            DateField::Day => day as i64,
+            DateField::DayOfWeek => date.weekday() as i64,
+            DateField::DayOfYear => date.day_of_year() as i64,
+            DateField::Hour => time / MICROS_PER_HOUR,
+            DateField::Minute => time % MICROS_PER_HOUR / MICROS_PER_MINUTE,
+            DateField::Second => time % MICROS_PER_MINUTE / MICROS_PER_SECOND,
+            DateField::Epoch => self.0.div_euclid(MICROS_PER_SECOND),
+        }
+    }
+
+    // strftime-style formatting, supporting the directives most commonly used with SQLite and
+    // PostgreSQL
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        let date = self.date();
+        let (year, month, day) = date.ymd();
+        let time = self.time_of_day();
+        let hour = time / MICROS_PER_HOUR;
+        let minute = time % MICROS_PER_HOUR / MICROS_PER_MINUTE;
+        let second = time % MICROS_PER_MINUTE / MICROS_PER_SECOND;
+        let weekday = date.weekday() as usize;
+        let day_of_year = date.day_of_year() as usize - 1;
+
+        let mut result = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                result.push(c);
+                continue;
+            }
+            let directive = chars
+                .next()
+                .ok_or_else(|| "DateTime: format string ends with '%'".to_string())?;
+            let piece = match directive {
+                'Y' => format!("{:04}", year),
+                'y' => format!("{:02}", year.rem_euclid(100)),
+                'm' => format!("{:02}", month),
+                'd' => format!("{:02}", day),
+                'e' => format!("{:>2}", day),
+                'H' => format!("{:02}", hour),
+                'I' => format!("{:02}", (hour + 11) % 12 + 1),
+                'p' => if hour < 12 { "AM" } else { "PM" }.to_string(),
+                'M' => format!("{:02}", minute),
+                'S' => format!("{:02}", second),
+                'f' => format!("{:06}", time % MICROS_PER_SECOND),
+                'j' => format!("{:03}", date.day_of_year()),
+                // Weeks starting on the first Sunday (%U) or Monday (%W) of the year, the days
+                // before it being week 0
+                'U' => format!("{:02}", (day_of_year + 7 - weekday) / 7),
+                'W' => format!("{:02}", (day_of_year + 7 - (weekday + 6) % 7) / 7),
+                'V' => format!("{:02}", date.iso_week()),
+                'a' => WEEKDAY_NAMES[weekday][..3].to_string(),
+                'A' => WEEKDAY_NAMES[weekday].to_string(),
+                'b' => MONTH_NAMES[month as usize - 1][..3].to_string(),
+                'B' => MONTH_NAMES[month as usize - 1].to_string(),
+                'u' => ((weekday + 6) % 7 + 1).to_string(),
+                'w' => weekday.to_string(),
+                's' => self.0.div_euclid(MICROS_PER_SECOND).to_string(),
+                'F' => date.to_string(),
+                'T' => format!("{:02}:{:02}:{:02}", hour, minute, second),
+                '%' => "%".to_string(),
+                other => {
+                    return Err(format!(
+                        "DateTime: unsupported format directive '%{}'",
+                        other
+                    ))
+                }
+            };
+            result.push_str(&piece);
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time_of_day();
+        write!(
+            f,
+            "{} {:02}:{:02}:{:02}",
+            self.date(),
+            time / MICROS_PER_HOUR,
+            time % MICROS_PER_HOUR / MICROS_PER_MINUTE,
+            time % MICROS_PER_MINUTE / MICROS_PER_SECOND
+        )?;
+        if time % MICROS_PER_SECOND != 0 {
+            write!(f, ".{:06}", time % MICROS_PER_SECOND)?;
+        }
+        Ok(())
+    }
+}
+
+impl Interval {
+    pub fn new(months: i32, days: i32, micros: i64) -> Self {
+        Interval {
+            months,
+            days,
+            micros,
+        }
+    }
+
+    // Builds an interval from a quantity and a unit as written in `INTERVAL '3' DAY`
+    pub fn from_unit(quantity: i64, unit: &str) -> Result<Interval, String> {
+        let unit = unit.to_lowercase();
+        let unit = unit.strip_suffix('s').unwrap_or(&unit);
+        let overflow = || format!("DateTime: interval '{} {}' out of range", quantity, unit);
+        let small = |factor: i64| -> Result<i32, String> {
+            quantity
+                .checked_mul(factor)
+                .and_then(|n| i32::try_from(n).ok())
+                .ok_or_else(overflow)
+        };
+        let micros = |factor: i64| -> Result<i64, String> {
+            quantity.checked_mul(factor).ok_or_else(overflow)
+        };
+        match unit {
+            "year" => Ok(Interval::new(small(12)?, 0, 0)),
+            "month" => Ok(Interval::new(small(1)?, 0, 0)),
+            "week" => Ok(Interval::new(0, small(7)?, 0)),
+            "day" => Ok(Interval::new(0, small(1)?, 0)),
+            "hour" => Ok(Interval::new(0, 0, micros(MICROS_PER_HOUR)?)),
+            "minute" => Ok(Interval::new(0, 0, micros(MICROS_PER_MINUTE)?)),
+            "second" => Ok(Interval::new(0, 0, micros(MICROS_PER_SECOND)?)),
+            _ => Err(format!("DateTime: unknown interval unit '{}'", unit)),
+        }
+    }
+
+    // Parses the body of an interval literal, e.g. '3 days', '1 year 2 months' or
+    // '2 days 04:30:00'. A bare number is only valid when the unit follows the literal.
+    pub fn parse(s: &str) -> Result<Interval, String> {
+        let mut interval = Interval::new(0, 0, 0);
+        let mut words = s.split_whitespace().peekable();
+        if words.peek().is_none() {
+            return Err("DateTime: empty interval".to_string());
+        }
+        while let Some(word) = words.next() {
+            if word.contains(':') {
+                let negative = word.starts_with('-');
+                let parts: Vec<&str> = word.trim_start_matches('-').split(':').collect();
+                if parts.len() < 2 || parts.len() > 3 {
+                    return Err(format!("DateTime: invalid interval time '{}'", word));
+                }
+                let mut micros: i64 = 0;
+                for (part, factor) in
+                    parts
+                        .iter()
+                        .zip([MICROS_PER_HOUR, MICROS_PER_MINUTE, MICROS_PER_SECOND])
+                {
+                    let n: i64 = part
+                        .parse()
+                        .map_err(|_| format!("DateTime: invalid interval time '{}'", word))?;
+                    micros = n
+                        .checked_mul(factor)
+                        .and_then(|n| micros.checked_add(n))
+                        .ok_or_else(|| format!("DateTime: interval '{}' out of range", word))?;
+                }
+                interval = interval.add(&Interval::new(
+                    0,
+                    0,
+                    if negative { -micros } else { micros },
+                ))?;
+                continue;
+            }
+            let quantity: i64 = word
+                .parse()
+                .map_err(|_| format!("DateTime: invalid interval '{}'", s))?;
+            let unit = words
+                .next()
+                .ok_or_else(|| format!("DateTime: missing unit in interval '{}'", s))?;
+            interval = interval.add(&Interval::from_unit(quantity, unit)?)?;
+        }
+        Ok(interval)
+    }
+
+    pub fn add(&self, other: &Interval) -> Result<Interval, String> {
+        let overflow = || "DateTime: interval out of range".to_string();
+        Ok(Interval {
+            months: self.months.checked_add(other.months).ok_or_else(overflow)?,
+            days: self.days.checked_add(other.days).ok_or_else(overflow)?,
+            micros: self.micros.checked_add(other.micros).ok_or_else(overflow)?,
+        })
+    }
+
+    pub fn negate(&self) -> Interval {
+        Interval::new(-self.months, -self.days, -self.micros)
+    }
+
+    pub fn has_time(&self) -> bool {
+        self.micros != 0
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: i64| if n.abs() == 1 { "" } else { "s" };
+        let mut parts = Vec::new();
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(format!("{} year{}", years, plural(years as i64)));
+        }
+        if months != 0 {
+            parts.push(format!("{} month{}", months, plural(months as i64)));
+        }
+        if self.days != 0 {
+            parts.push(format!("{} day{}", self.days, plural(self.days as i64)));
+        }
+        if self.micros != 0 || parts.is_empty() {
+            let sign = if self.micros < 0 { "-" } else { "" };
+            let micros = self.micros.abs();
+            let mut time = format!(
+                "{}{:02}:{:02}:{:02}",
+                sign,
+                micros / MICROS_PER_HOUR,
+                micros % MICROS_PER_HOUR / MICROS_PER_MINUTE,
+                micros % MICROS_PER_MINUTE / MICROS_PER_SECOND
+            );
+            if micros % MICROS_PER_SECOND != 0 {
+                time.push_str(&format!(".{:06}", micros % MICROS_PER_SECOND));
+            }
+            parts.push(time);
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+// Small hand-rolled scanner for the ISO-8601 subset we accept
+struct Cursor<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Cursor { input, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.input.len()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.input[self.position..].starts_with(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("DateTime: expected '{}' in '{}'", c, self.input))
+        }
+    }
+
+    fn digits(&mut self, min: usize, max: usize) -> Result<&'a str, String> {
+        let rest = &self.input[self.position..];
+        let count = rest
+            .chars()
+            .take(max)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if count < min {
+            return Err(format!("DateTime: expected digits in '{}'", self.input));
+        }
+        self.position += count;
+        Ok(&rest[..count])
+    }
+
+    fn number(&mut self, min: usize, max: usize) -> Result<i64, String> {
+        self.digits(min, max)?
+            .parse()
+            .map_err(|_| format!("DateTime: invalid number in '{}'", self.input))
+    }
+
+    fn date(&mut self) -> Result<Date, String> {
+        let year = self.number(4, 4)?;
+        self.expect('-')?;
+        let month = self.number(2, 2)?;
+        self.expect('-')?;
+        let day = self.number(2, 2)?;
+        Date::from_ymd(year, month as u32, day as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_round_trip() {
+        for s in [
+            "1970-01-01",
+            "2000-02-29",
+            "1969-12-31",
+            "2024-12-31",
+            "1600-03-01",
+        ] {
+            assert_eq!(Date::parse(s).unwrap().to_string(), s);
+        }
+        assert_eq!(Date::parse("1970-01-02").unwrap(), Date(1));
+        assert_eq!(Date::parse("1969-12-31").unwrap(), Date(-1));
+    }
+
+    #[test]
+    fn test_date_parse_errors() {
+        assert!(Date::parse("2023-02-29").is_err());
+        assert!(Date::parse("2023-13-01").is_err());
+        assert!(Date::parse("2023-1-01").is_err());
+        assert!(Date::parse("2023-01-01x").is_err());
+    }
+
+    #[test]
+    fn test_timestamp_parse() {
+        let ts = Timestamp::parse("2024-03-10T12:34:56.5Z").unwrap();
+        assert_eq!(ts.to_string(), "2024-03-10 12:34:56.500000");
+
+        let offset = Timestamp::parse("2024-03-10 14:34:56+02:00").unwrap();
+        assert_eq!(offset.to_string(), "2024-03-10 12:34:56");
+
+        let midnight = Timestamp::parse("2024-03-10").unwrap();
+        assert_eq!(
+            midnight,
+            Date::parse("2024-03-10").unwrap().to_timestamp().unwrap()
+        );
+
+        assert!(Timestamp::parse("2024-03-10 25:00").is_err());
+    }
+
+    #[test]
+    fn test_add_months_clamps_to_month_end() {
+        let date = Date::parse("2024-01-31").unwrap();
+        assert_eq!(date.add_months(1).unwrap().to_string(), "2024-02-29");
+        assert_eq!(date.add_months(13).unwrap().to_string(), "2025-02-28");
+        assert_eq!(date.add_months(-2).unwrap().to_string(), "2023-11-30");
+    }
+
+    #[test]
+    fn test_timestamp_add_interval() {
+        let ts = Timestamp::parse("2024-02-28 23:30:00").unwrap();
+        let interval = Interval::parse("1 day 01:00:00").unwrap();
+        assert_eq!(
+            ts.add_interval(&interval).unwrap().to_string(),
+            "2024-03-01 00:30:00"
+        );
+        assert_eq!(
+            ts.add_interval(&interval.negate()).unwrap().to_string(),
+            "2024-02-27 22:30:00"
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let ts = Timestamp::parse("2024-01-01 00:00:00").unwrap();
+        // The last day a timestamp reaches is in the year 294247
+        let last = Interval::parse("106732268 days").unwrap();
+        assert_eq!(
+            ts.add_interval(&last).unwrap().date().ymd(),
+            (294247, 1, 10)
+        );
+        let past = Interval::parse("106732269 days").unwrap();
+        assert!(ts.add_interval(&past).is_err());
+        assert!(ts
+            .add_interval(&Interval::parse("2000000000 days").unwrap())
+            .is_err());
+        assert!(ts.add_interval(&Interval::new(0, 0, i64::MAX)).is_err());
+
+        // Dates go on further, but past the last timestamp they have none
+        let date = Date::parse("2024-01-01").unwrap();
+        let far = date.add_days(200_000_000).unwrap();
+        assert!(far.to_timestamp().is_err());
+        assert!(far.format("%Y").is_err());
+        assert!(date.add_days(i32::MAX as i64).is_err());
+        assert!(date.add_months(i32::MAX).is_err());
+
+        assert_eq!(
+            Interval::parse("2562047788:00:54").unwrap().micros,
+            i64::MAX / MICROS_PER_SECOND * MICROS_PER_SECOND
+        );
+        assert!(Interval::parse("2562047788:00:55").is_err());
+        assert!(Interval::parse("99999999999999:00:00").is_err());
+        assert!(Interval::parse("-99999999999999:00:00").is_err());
+    }
+
+    #[test]
+    fn test_interval_parse_and_display() {
+        let interval = Interval::parse("1 year 2 months 3 days").unwrap();
+        assert_eq!(interval, Interval::new(14, 3, 0));
+        assert_eq!(interval.to_string(), "1 year 2 months 3 days");
+        assert_eq!(
+            Interval::from_unit(2, "HOURS").unwrap().to_string(),
+            "02:00:00"
+        );
+        assert!(Interval::parse("3 fortnights").is_err());
+    }
+
+    #[test]
+    fn test_extract() {
+        let ts = Timestamp::parse("2021-01-03 07:08:09").unwrap();
+        assert_eq!(ts.extract(DateField::Year), 2021);
+        assert_eq!(ts.extract(DateField::Quarter), 1);
+        assert_eq!(ts.extract(DateField::DayOfWeek), 0);
+        assert_eq!(ts.extract(DateField::DayOfYear), 3);
+        // 2021-01-03 still belongs to ISO week 53 of 2020
+        assert_eq!(ts.extract(DateField::Week), 53);
+        assert_eq!(ts.extract(DateField::Hour), 7);
+        assert_eq!(ts.extract(DateField::Minute), 8);
+        assert_eq!(ts.extract(DateField::Second), 9);
+        assert_eq!(
+            Timestamp::parse("2024-12-30")
+                .unwrap()
+                .extract(DateField::Week),
+            1
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let ts = Timestamp::parse("2024-07-04 15:05:09.000123").unwrap();
+        assert_eq!(
+            ts.format("%A, %B %e %Y %I:%M %p").unwrap(),
+            "Thursday, July  4 2024 03:05 PM"
+        );
+        assert_eq!(ts.format("%F %T.%f").unwrap(), "2024-07-04 15:05:09.000123");
+        assert_eq!(ts.format("%j|%u|%w|%%").unwrap(), "186|4|4|%");
+        // Sunday and Monday based weeks against the ISO week, as C's strftime gives them
+        for (date, weeks) in [
+            ("2024-01-31", "04 05 05"),
+            ("2024-01-01", "00 01 01"),
+            ("2023-01-01", "01 00 52"),
+            ("2021-01-03", "01 00 53"),
+            ("2024-12-31", "52 53 01"),
+            ("2024-07-04", "26 27 27"),
+        ] {
+            let date = Date::parse(date).unwrap();
+            assert_eq!(date.format("%U %W %V").unwrap(), weeks, "{}", date);
+        }
+        assert!(ts.format("%Q").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use core::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+
+// DECIMAL supports up to 38 significant digits, the most an i128 mantissa can always hold
+pub const MAX_PRECISION: u32 = 38;
+// Extra fractional digits kept when dividing, on top of the larger operand scale
+const DIVISION_EXTRA_SCALE: u32 = 6;
+
+// Exact fixed-point number: `mantissa * 10^-scale`. Used for DECIMAL columns so that money
+// values never go through binary floating point.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+fn pow10(exponent: u32) -> Option<i128> {
+    10i128.checked_pow(exponent)
+}
+
+fn overflow() -> String {
+    "Decimal: numeric overflow".to_string()
+}
+
+impl Decimal {
+    pub fn new(mantissa: i128, scale: u32) -> Result<Decimal, String> {
+        if scale > MAX_PRECISION {
+            return Err(format!(
+                "Decimal: scale {} exceeds the maximum of {}",
+                scale, MAX_PRECISION
+            ));
+        }
+        Ok(Decimal { mantissa, scale })
+    }
+
+    pub fn from_i64(n: i64) -> Decimal {
+        Decimal {
+            mantissa: n as i128,
+            scale: 0,
+        }
+    }
+
+    // Accepts an optional sign, digits and an optional fractional part, e.g. "-12.50"
+    pub fn parse(s: &str) -> Result<Decimal, String> {
+        let invalid = || format!("Decimal: invalid number '{}'", s);
+        let trimmed = s.trim();
+        let (negative, digits) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let mut mantissa: i128 = 0;
+        for c in integer.chars().chain(fraction.chars()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_add(c.to_digit(10).unwrap() as i128))
+                .ok_or_else(overflow)?;
+        }
+        Decimal::new(
+            if negative { -mantissa } else { mantissa },
+            fraction.len() as u32,
+        )
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    // Changes the number of fractional digits, rounding half away from zero when digits are
+    // dropped
+    pub fn rescale(&self, scale: u32) -> Result<Decimal, String> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Ok(*self),
+            Ordering::Greater => {
+                let factor = pow10(scale - self.scale).ok_or_else(overflow)?;
+                let mantissa = self.mantissa.checked_mul(factor).ok_or_else(overflow)?;
+                Decimal::new(mantissa, scale)
+            }
+            Ordering::Less => {
+                let factor = pow10(self.scale - scale).ok_or_else(overflow)?;
+                let quotient = self.mantissa / factor;
+                let remainder = self.mantissa % factor;
+                let rounded = if remainder.unsigned_abs() * 2 >= factor.unsigned_abs() {
+                    quotient + self.mantissa.signum()
+                } else {
+                    quotient
+                };
+                Decimal::new(rounded, scale)
+            }
+        }
+    }
+
+    // The same number without trailing fractional zeros, e.g. 1.50 -> 1.5, so that equal numbers
+    // have one representation
+    pub fn normalize(&self) -> Decimal {
+        let mut normalized = *self;
+        while normalized.scale > 0 && normalized.mantissa % 10 == 0 {
+            normalized.mantissa /= 10;
+            normalized.scale -= 1;
+        }
+        normalized
+    }
+
+    // Rounds to `scale` and checks the result fits DECIMAL(precision, scale)
+    pub fn fit(&self, precision: u32, scale: u32) -> Result<Decimal, String> {
+        let rescaled = self.rescale(scale)?;
+        let limit = pow10(precision).ok_or_else(overflow)?;
+        if rescaled.mantissa.abs() >= limit {
+            return Err(format!(
+                "Decimal: value {} does not fit DECIMAL({}, {})",
+                self, precision, scale
+            ));
+        }
+        Ok(rescaled)
+    }
+
+    // Brings both operands to the larger of the two scales
+    fn align(&self, other: &Decimal) -> Result<(i128, i128, u32), String> {
+        let scale = self.scale.max(other.scale);
+        Ok((
+            self.rescale(scale)?.mantissa,
+            other.rescale(scale)?.mantissa,
+            scale,
+        ))
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Result<Decimal, String> {
+        let (a, b, scale) = self.align(other)?;
+        Decimal::new(a.checked_add(b).ok_or_else(overflow)?, scale)
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Result<Decimal, String> {
+        let (a, b, scale) = self.align(other)?;
+        Decimal::new(a.checked_sub(b).ok_or_else(overflow)?, scale)
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Result<Decimal, String> {
+        let mantissa = self
+            .mantissa
+            .checked_mul(other.mantissa)
+            .ok_or_else(overflow)?;
+        Decimal::new(mantissa, self.scale + other.scale)
+    }
+
+    pub fn checked_div(&self, other: &Decimal) -> Result<Decimal, String> {
+        if other.is_zero() {
+            return Err("Decimal: division by zero".to_string());
+        }
+        let scale = (self.scale.max(other.scale) + DIVISION_EXTRA_SCALE).min(MAX_PRECISION);
+        // Compute one digit more than needed so the last one can be rounded
+        let shift = scale + 1 + other.scale - self.scale;
+        let dividend = self
+            .mantissa
+            .checked_mul(pow10(shift).ok_or_else(overflow)?)
+            .ok_or_else(overflow)?;
+        let quotient = Decimal {
+            mantissa: dividend / other.mantissa,
+            scale: scale + 1,
+        };
+        quotient.rescale(scale)
+    }
+
+    pub fn checked_rem(&self, other: &Decimal) -> Result<Decimal, String> {
+        if other.is_zero() {
+            return Err("Decimal: division by zero".to_string());
+        }
+        let (a, b, scale) = self.align(other)?;
+        Decimal::new(a % b, scale)
+    }
+
+    pub fn negate(&self) -> Decimal {
+        Decimal {
+            mantissa: -self.mantissa,
+            scale: self.scale,
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Numeric ordering regardless of scale, so 1.5 == 1.50 (and both land on the same index key)
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.align(other) {
+            Ok((a, b, _)) => a.cmp(&b),
+            // Rescaling only overflows for values far larger than the other operand can be
+            Err(_) if self.scale < other.scale => self.mantissa.signum().cmp(&0),
+            Err(_) => 0.cmp(&other.mantissa.signum()),
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = scale + 1);
+        let (integer, fraction) = padded.split_at(padded.len() - scale);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+// Stored as a string so that the JSON snapshot stays exact and readable
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Decimal::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for s in [
+            "0",
+            "12.50",
+            "-0.05",
+            "123456789012345678901234567890.12345678",
+        ] {
+            assert_eq!(d(s).to_string(), s);
+        }
+        assert_eq!(d("+.5").to_string(), "0.5");
+        assert!(Decimal::parse("1.2.3").is_err());
+        assert!(Decimal::parse("abc").is_err());
+        assert!(Decimal::parse(".").is_err());
+    }
+
+    #[test]
+    fn test_equality_ignores_scale() {
+        assert_eq!(d("1.5"), d("1.500"));
+        assert!(d("1.49") < d("1.5"));
+        assert!(d("-2") < d("-1.99"));
+    }
+
+    #[test]
+    fn test_rescale_rounds_half_away_from_zero() {
+        assert_eq!(d("2.345").rescale(2).unwrap().to_string(), "2.35");
+        assert_eq!(d("-2.345").rescale(2).unwrap().to_string(), "-2.35");
+        assert_eq!(d("2.344").rescale(2).unwrap().to_string(), "2.34");
+        assert_eq!(d("7").rescale(2).unwrap().to_string(), "7.00");
+    }
+
+    #[test]
+    fn test_fit_checks_precision() {
+        assert_eq!(d("999.994").fit(5, 2).unwrap().to_string(), "999.99");
+        assert!(d("999.995").fit(5, 2).is_err());
+        assert!(d("1000").fit(5, 2).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_is_exact() {
+        // 0.1 + 0.2 is the classic floating point failure
+        assert_eq!(d("0.1").checked_add(&d("0.2")).unwrap().to_string(), "0.3");
+        assert_eq!(
+            d("19.99").checked_mul(&d("3")).unwrap().to_string(),
+            "59.97"
+        );
+        assert_eq!(
+            d("10.00").checked_sub(&d("0.01")).unwrap().to_string(),
+            "9.99"
+        );
+        assert_eq!(
+            d("10").checked_div(&d("3")).unwrap().to_string(),
+            "3.333333"
+        );
+        assert_eq!(
+            d("-1").checked_div(&d("8")).unwrap().to_string(),
+            "-0.125000"
+        );
+        assert_eq!(d("10.5").checked_rem(&d("3")).unwrap().to_string(), "1.5");
+        assert!(d("1").checked_div(&d("0.00")).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+use super::data_types::{Column, UniqueConstraint, Value};
+use super::datetime::Timestamp;
+use super::full_text::FullTextIndex;
+use super::statistics::TableStatistics;
+use super::table::{ColumnIndex, IndexRange, Row, RowRange, TableSchema, ALL_ROWS};
+
+pub type RowIter<'a> = Box<dyn Iterator<Item = Result<(usize, Row), String>> + 'a>;
+pub type RowIdIter<'a> = Box<dyn Iterator<Item = Result<usize, String>> + 'a>;
+
+// What the SQL layer needs from a place to keep tables. Rows are addressed by row ids the engine
+// hands out on insert, and every change happens inside a transaction: an explicit one between
+// `begin` and `commit`, or else an implicit one around each statement.
+pub trait StorageEngine {
+    // The time CURRENT_DATE and CURRENT_TIMESTAMP refer to
+    fn now(&self) -> Timestamp;
+
+    fn table_names(&self) -> Result<Vec<String>, String>;
+    fn schema(&self, table: &str) -> Result<TableSchema, String>;
+    // Fails if a table of the same name exists
+    fn create_table(
+        &mut self,
+        name: String,
+        columns: Vec<Column>,
+        constraints: Vec<UniqueConstraint>,
+    ) -> Result<(), String>;
+    fn drop_table(&mut self, name: &str) -> Result<(), String>;
+    // Indexes are built from the rows already in the table. Engines that build them on commit
+    // report the duplicates a UNIQUE index finds among the committed rows then.
+    fn create_index(&mut self, table: &str, index: ColumnIndex) -> Result<(), String>;
+    fn create_full_text_index(&mut self, table: &str, index: FullTextIndex) -> Result<(), String>;
+    // Drops a created or full-text index
+    fn drop_index(&mut self, table: &str, name: &str) -> Result<(), String>;
+    // Replaces the statistics in the table's schema
+    fn set_statistics(&mut self, table: &str, statistics: TableStatistics) -> Result<(), String>;
+
+    // Inserts new rows and replaces existing ones as one batch that either fully succeeds or
+    // changes nothing, returning the ids given to the inserted rows
+    fn write_rows(
+        &mut self,
+        table: &str,
+        inserts: Vec<Vec<Value>>,
+        updates: Vec<(usize, Vec<Value>)>,
+    ) -> Result<Vec<usize>, String>;
+    fn delete_rows(&mut self, table: &str, row_ids: &[usize]) -> Result<(), String>;
+    fn get_row(&self, table: &str, row_id: usize) -> Result<Option<Row>, String>;
+    // Rows in row id order
+    fn scan(&self, table: &str, range: RowRange) -> Result<RowIter<'_>, String>;
+    // Ids of the rows whose key in `index` lies in `range`, in the order of their keys and then
+    // of row id, read as they are asked for. See `TableSchema::index_entries` for the keys.
+    fn index_scan(
+        &self,
+        table: &str,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<RowIdIter<'_>, String>;
+    // Ids, in order, of the rows whose key in `index` starts with `prefix`
+    fn index_lookup(
+        &self,
+        table: &str,
+        index: &str,
+        prefix: &[Value],
+    ) -> Result<Vec<usize>, String> {
+        let mut row_ids = self
+            .index_scan(table, index, &IndexRange::prefix(prefix.to_vec()))?
+            .collect::<Result<Vec<usize>, String>>()?;
+        row_ids.sort_unstable();
+        Ok(row_ids)
+    }
+
+    fn in_transaction(&self) -> bool;
+    fn begin(&mut self) -> Result<(), String>;
+    fn commit(&mut self) -> Result<(), String>;
+    fn rollback(&mut self) -> Result<(), String>;
+    fn savepoint(&mut self, name: &str) -> Result<(), String>;
+    fn release_savepoint(&mut self, name: &str) -> Result<(), String>;
+    fn rollback_to_savepoint(&mut self, name: &str) -> Result<(), String>;
+    // Bracket a statement, see `atomically`. Statements may nest.
+    fn begin_statement(&mut self);
+    fn end_statement(&mut self, succeeded: bool) -> Result<(), String>;
+
+    // Makes sure everything committed is in the engine's own files
+    fn checkpoint(&mut self) -> io::Result<()>;
+    fn export_json(&self, path: &Path) -> io::Result<()>;
+}
+
+// Runs `change` as a single statement: if it fails, everything it did is undone, while an
+// enclosing transaction carries on. Outside of a transaction it commits on success.
+pub fn atomically<T>(
+    engine: &mut dyn StorageEngine,
+    change: impl FnOnce(&mut dyn StorageEngine) -> Result<T, String>,
+) -> Result<T, String> {
+    engine.begin_statement();
+    let result = change(engine);
+    let ended = engine.end_statement(result.is_ok());
+    let value = result?;
+    ended?;
+    Ok(value)
+}
+
+// The row that already holds `values`' key for `constraint`, found through an index on the
+// constraint's first column or else by a scan
+pub fn conflicting_row(
+    engine: &dyn StorageEngine,
+    table: &str,
+    schema: &TableSchema,
+    constraint: &UniqueConstraint,
+    values: &[Value],
+) -> Result<Option<usize>, String> {
+    let Some(key) = schema.unique_key(constraint, values) else {
+        return Ok(None);
+    };
+    let Some(index) = schema.lookup_index(&constraint.columns[0]) else {
+        for row in engine.scan(table, ALL_ROWS)? {
+            let (row_id, row) = row?;
+            if schema.unique_key(constraint, &row.values).as_ref() == Some(&key) {
+                return Ok(Some(row_id));
+            }
+        }
+        return Ok(None);
+    };
+    for row_id in engine.index_lookup(table, index, &key[..1])? {
+        if let Some(row) = engine.get_row(table, row_id)? {
+            if schema.unique_key(constraint, &row.values).as_ref() == Some(&key) {
+                return Ok(Some(row_id));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Checks a batch of prepared rows, given with their row ids, against each constraint: against the
+// stored rows, ignoring the ones being replaced, and against each other
+pub fn check_unique(
+    engine: &dyn StorageEngine,
+    table: &str,
+    schema: &TableSchema,
+    rows: &[(usize, Vec<Value>)],
+) -> Result<(), String> {
+    let replaced: BTreeSet<usize> = rows.iter().map(|(row_id, _)| *row_id).collect();
+    for constraint in &schema.unique_constraints() {
+        let mut batch_keys = BTreeSet::new();
+        for (_, values) in rows {
+            let key = match schema.unique_key(constraint, values) {
+                Some(key) => key,
+                None if constraint.primary_key => {
+                    return Err(format!("NULL value in {}", constraint));
+                }
+                None => continue,
+            };
+            let stored = conflicting_row(engine, table, schema, constraint, values)?
+                .filter(|existing| !replaced.contains(existing));
+            if stored.is_some() || !batch_keys.insert(key.clone()) {
+                let key: Vec<String> = key.iter().map(|value| value.to_string()).collect();
+                return Err(format!(
+                    "Duplicate key ({}) violates {}",
+                    key.join(", "),
+                    constraint
+                ));
+            }
+        }
+    }
+    Ok(())
+}
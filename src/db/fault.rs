@@ -0,0 +1,43 @@
+// Fault injection for tests. Changes call `check` at the points where a real failure (a full disk,
+// a bug) could interrupt them; after `fail_after(n)` the check following n successful ones fails,
+// so tests can stop a change halfway through and look at what is left behind. Outside of tests
+// `check` always succeeds.
+
+#[cfg(test)]
+use std::cell::Cell;
+
+#[cfg(test)]
+thread_local! {
+    static REMAINING: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+#[cfg(test)]
+pub fn fail_after(checks: usize) {
+    REMAINING.with(|remaining| remaining.set(Some(checks)));
+}
+
+#[cfg(test)]
+pub fn disarm() {
+    REMAINING.with(|remaining| remaining.set(None));
+}
+
+#[cfg(test)]
+pub fn check(point: &str) -> Result<(), String> {
+    REMAINING.with(|remaining| match remaining.get() {
+        Some(0) => {
+            remaining.set(None);
+            Err(format!("Fault: injected failure in {}", point))
+        }
+        Some(n) => {
+            remaining.set(Some(n - 1));
+            Ok(())
+        }
+        None => Ok(()),
+    })
+}
+
+#[cfg(not(test))]
+#[inline]
+pub fn check(_point: &str) -> Result<(), String> {
+    Ok(())
+}
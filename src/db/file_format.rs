@@ -0,0 +1,102 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+
+// Layout of a database file: the magic number, the format version (u32), the length of the
+// payload (u64) and its CRC-32 (u32), all little endian, followed by the bincode encoded payload.
+// Files written before this format are plain JSON and are recognised by their opening brace.
+pub const MAGIC: &[u8; 8] = b"BANDITDB";
+pub const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 24;
+
+pub fn encode<T: Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    let payload = bincode::serialize(value).map_err(io::Error::other)?;
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+// Errors describe what is wrong with the file, e.g. "is truncated", for the caller to name it
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(if MAGIC.starts_with(bytes) || bytes.starts_with(MAGIC) {
+            "is truncated".to_string()
+        } else {
+            "is not a database file".to_string()
+        });
+    }
+    if !bytes.starts_with(MAGIC) {
+        return Err("is not a database file".to_string());
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version > VERSION {
+        return Err(format!(
+            "uses format version {}, but this version of BanditDB only reads up to {}",
+            version, VERSION
+        ));
+    }
+    let length = u64::from_le_bytes(bytes[12..20].try_into().unwrap());
+    let checksum = u32::from_le_bytes(bytes[20..24].try_into().unwrap());
+    let payload = &bytes[HEADER_SIZE..];
+    if (payload.len() as u64) < length {
+        return Err("is truncated".to_string());
+    }
+    if payload.len() as u64 > length || crc32fast::hash(payload) != checksum {
+        return Err("is corrupt (checksum mismatch)".to_string());
+    }
+    bincode::deserialize(payload).map_err(|e| format!("is corrupt ({})", e))
+}
+
+// True for the JSON files written by earlier versions
+pub fn is_json(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .is_some_and(|byte| *byte == b'{')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_damaged_files_are_recognised() {
+        let value = vec![(1u32, "one".to_string()), (2, "two".to_string())];
+        let bytes = encode(&value).unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        assert_eq!(decode::<Vec<(u32, String)>>(&bytes).unwrap(), value);
+
+        for length in 0..bytes.len() {
+            let error = decode::<Vec<(u32, String)>>(&bytes[..length]).unwrap_err();
+            assert_eq!(error, "is truncated", "cut at {}", length);
+        }
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(decode::<Vec<(u32, String)>>(&flipped)
+            .unwrap_err()
+            .starts_with("is corrupt"));
+        let longer = [bytes.as_slice(), b"x"].concat();
+        assert!(decode::<Vec<(u32, String)>>(&longer)
+            .unwrap_err()
+            .starts_with("is corrupt"));
+
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(decode::<Vec<(u32, String)>>(&newer)
+            .unwrap_err()
+            .starts_with(&format!("uses format version {}", VERSION + 1)));
+
+        assert_eq!(
+            decode::<Vec<(u32, String)>>(b"{\"tables\": {}}").unwrap_err(),
+            "is not a database file"
+        );
+        assert!(is_json(b"  {\"tables\": {}}"));
+        assert!(!is_json(&bytes));
+        assert!(!is_json(b""));
+    }
+}
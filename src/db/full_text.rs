@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Index over the words of a TEXT column, e.g. `CREATE FULLTEXT INDEX ON tickets (body)`. A row has
+// one entry per distinct word, keyed by the word, so each key leads to the list of rows holding it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FullTextIndex {
+    pub name: String,
+    pub column: String,
+}
+
+// The words of a text: runs of letters and digits, lowercased
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// A search such as `refund AND (card OR paypal) "not received"`. Words next to each other must
+// all be there, OR takes either side and quotes ask for the words in that order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Phrase(Vec<String>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+#[derive(Debug, PartialEq)]
+enum QueryToken {
+    Words(Vec<String>),
+    Quoted(Vec<String>),
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, String> {
+        let tokens = query_tokens(query)?;
+        let mut iter = tokens.into_iter().peekable();
+        let parsed = parse_or(&mut iter)?;
+        match iter.next() {
+            None => Ok(parsed),
+            Some(QueryToken::Close) => Err("Full-text: unbalanced ')' in query".to_string()),
+            Some(_) => Err("Full-text: unexpected operator in query".to_string()),
+        }
+    }
+
+    pub fn matches(&self, words: &[String]) -> bool {
+        match self {
+            Query::Phrase(phrase) => words
+                .windows(phrase.len())
+                .any(|window| window == phrase.as_slice()),
+            Query::And(queries) => queries.iter().all(|query| query.matches(words)),
+            Query::Or(queries) => queries.iter().any(|query| query.matches(words)),
+        }
+    }
+
+    // Every word the query mentions, for ranking
+    pub fn words(&self) -> Vec<&str> {
+        let mut words: Vec<&str> = match self {
+            Query::Phrase(phrase) => phrase.iter().map(String::as_str).collect(),
+            Query::And(queries) | Query::Or(queries) => {
+                queries.iter().flat_map(Query::words).collect()
+            }
+        };
+        words.sort_unstable();
+        words.dedup();
+        words
+    }
+
+    // Words at least one of which every matching text holds, so that the rows listed under them
+    // in an index include every match: one word of a phrase, the words of one side of an AND
+    // (the side needing the fewest) and the words of each side of an OR
+    pub fn covering_words(&self) -> Vec<&str> {
+        match self {
+            Query::Phrase(phrase) => phrase.iter().take(1).map(String::as_str).collect(),
+            Query::And(queries) => queries
+                .iter()
+                .map(Query::covering_words)
+                .min_by_key(Vec::len)
+                .unwrap_or_default(),
+            Query::Or(queries) => {
+                let mut words: Vec<&str> = queries.iter().flat_map(Query::covering_words).collect();
+                words.sort_unstable();
+                words.dedup();
+                words
+            }
+        }
+    }
+}
+
+fn query_tokens(query: &str) -> Result<Vec<QueryToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => {
+                tokens.push(QueryToken::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(QueryToken::Close);
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => return Err("Full-text: unclosed '\"' in query".to_string()),
+                    }
+                }
+                tokens.push(QueryToken::Quoted(tokenize(&phrase)));
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                // Only upper case AND / OR are operators, so the words themselves can be searched
+                tokens.push(match word.as_str() {
+                    "AND" => QueryToken::And,
+                    "OR" => QueryToken::Or,
+                    _ => QueryToken::Words(tokenize(&word)),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+type QueryTokens = std::iter::Peekable<std::vec::IntoIter<QueryToken>>;
+
+fn parse_or(iter: &mut QueryTokens) -> Result<Query, String> {
+    let mut queries = vec![parse_and(iter)?];
+    while iter.next_if_eq(&QueryToken::Or).is_some() {
+        queries.push(parse_and(iter)?);
+    }
+    Ok(match queries.len() {
+        1 => queries.remove(0),
+        _ => Query::Or(queries),
+    })
+}
+
+fn parse_and(iter: &mut QueryTokens) -> Result<Query, String> {
+    let mut queries = Vec::new();
+    loop {
+        let explicit = !queries.is_empty() && iter.next_if_eq(&QueryToken::And).is_some();
+        match iter.next_if(|token| {
+            matches!(
+                token,
+                QueryToken::Words(_) | QueryToken::Quoted(_) | QueryToken::Open
+            )
+        }) {
+            // A word the tokenizer splits up, such as e-mail, is a phrase
+            Some(QueryToken::Words(words) | QueryToken::Quoted(words)) => {
+                if !words.is_empty() {
+                    queries.push(Query::Phrase(words));
+                }
+            }
+            Some(_) => {
+                queries.push(parse_or(iter)?);
+                if iter.next() != Some(QueryToken::Close) {
+                    return Err("Full-text: expected ')' in query".to_string());
+                }
+            }
+            None if queries.is_empty() || explicit => {
+                return Err("Full-text: expected a word or a phrase in query".to_string())
+            }
+            None => break,
+        }
+    }
+    match queries.len() {
+        1 => Ok(queries.remove(0)),
+        _ => Ok(Query::And(queries)),
+    }
+}
+
+// Okapi BM25 with the usual k1 = 1.2 and b = 0.75. Scores are higher for better matches.
+#[derive(Debug, Clone)]
+pub struct Bm25 {
+    documents: usize,
+    average_length: f64,
+    // How many documents hold each of the query's words
+    frequencies: BTreeMap<String, usize>,
+}
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+impl Bm25 {
+    // Statistics over every document that is searched, for the given words
+    pub fn new<D: AsRef<[String]>>(words: &[&str], documents: impl IntoIterator<Item = D>) -> Bm25 {
+        let mut frequencies: BTreeMap<String, usize> =
+            words.iter().map(|word| (word.to_string(), 0)).collect();
+        let (mut count, mut total_length) = (0, 0);
+        for document in documents {
+            let document = document.as_ref();
+            count += 1;
+            total_length += document.len();
+            for (word, frequency) in frequencies.iter_mut() {
+                if document.contains(word) {
+                    *frequency += 1;
+                }
+            }
+        }
+        Bm25 {
+            documents: count,
+            average_length: match count {
+                0 => 0.0,
+                _ => total_length as f64 / count as f64,
+            },
+            frequencies,
+        }
+    }
+
+    pub fn score(&self, document: &[String]) -> f64 {
+        let length_ratio = match self.average_length {
+            average if average > 0.0 => document.len() as f64 / average,
+            _ => 0.0,
+        };
+        let mut score = 0.0;
+        for (word, &frequency) in &self.frequencies {
+            let occurrences = document.iter().filter(|w| *w == word).count() as f64;
+            if occurrences == 0.0 {
+                continue;
+            }
+            let n = self.documents as f64;
+            let f = frequency as f64;
+            let idf = ((n - f + 0.5) / (f + 0.5) + 1.0).ln();
+            score +=
+                idf * occurrences * (K1 + 1.0) / (occurrences + K1 * (1.0 - B + B * length_ratio));
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        tokenize(text)
+    }
+
+    fn phrase(text: &str) -> Query {
+        Query::Phrase(words(text))
+    }
+
+    #[test]
+    fn test_queries() {
+        assert_eq!(
+            words("Can't log-in, since 2.0!"),
+            vec!["can", "t", "log", "in", "since", "2", "0"]
+        );
+        assert_eq!(Query::parse("Refund").unwrap(), phrase("refund"));
+        assert_eq!(
+            Query::parse("refund card OR \"Pay Pal\" AND late").unwrap(),
+            Query::Or(vec![
+                Query::And(vec![phrase("refund"), phrase("card")]),
+                Query::And(vec![phrase("pay pal"), phrase("late")]),
+            ])
+        );
+        assert_eq!(
+            Query::parse("(card OR paypal) e-mail").unwrap(),
+            Query::And(vec![
+                Query::Or(vec![phrase("card"), phrase("paypal")]),
+                phrase("e mail"),
+            ])
+        );
+        // Lower case operators are words
+        assert_eq!(
+            Query::parse("this or that").unwrap(),
+            Query::And(vec![phrase("this"), phrase("or"), phrase("that")])
+        );
+        for invalid in ["", "!?", "OR card", "card AND", "(card", "card)", "\"card"] {
+            assert!(Query::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        let text = words("The refund for my card payment never arrived");
+        let matches = |query: &str| Query::parse(query).unwrap().matches(&text);
+        assert!(matches("REFUND card"));
+        assert!(matches("\"card payment\""));
+        assert!(!matches("\"payment card\""));
+        assert!(matches("paypal OR card"));
+        assert!(!matches("refund AND paypal"));
+        assert!(matches("(paypal OR refund) \"never arrived\""));
+    }
+
+    #[test]
+    fn test_covering_words() {
+        let covering = |query: &str| {
+            let query = Query::parse(query).unwrap();
+            let words: Vec<String> = query
+                .covering_words()
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            words
+        };
+        assert_eq!(covering("\"card payment\""), vec!["card"]);
+        assert_eq!(covering("(a OR b) c"), vec!["c"]);
+        assert_eq!(covering("a b OR c"), vec!["a", "c"]);
+        assert_eq!(
+            Query::parse("b a OR \"a c\"").unwrap().words(),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn test_bm25_prefers_rare_words_and_short_texts() {
+        let documents: Vec<Vec<String>> = [
+            "printer jammed again",
+            "printer offline",
+            "printer out of toner, printer jammed",
+            "password reset",
+        ]
+        .iter()
+        .map(|text| words(text))
+        .collect();
+        let bm25 = Bm25::new(&["printer", "jammed"], &documents);
+
+        let scores: Vec<f64> = documents.iter().map(|d| bm25.score(d)).collect();
+        assert_eq!(scores[3], 0.0);
+        // Both words beat only the common one
+        assert!(scores[0] > scores[1]);
+        // More occurrences count for more
+        assert!(scores[2] > scores[1]);
+        assert!(scores.iter().all(|score| *score >= 0.0));
+    }
+}
@@ -0,0 +1,365 @@
+use std::io;
+
+use super::pager::{
+    self, read_u16, read_u32, write_u16, write_u32, Page, PageId, Pager, PAGE_SIZE,
+};
+
+// Where a record is: its page and slot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordId {
+    pub page_id: PageId,
+    pub slot: u16,
+}
+
+// Records larger than this go to a chain of overflow pages, so that a page always has room for a
+// few of them
+const MAX_INLINE: usize = PAGE_SIZE / 4;
+const INLINE: u8 = 0;
+const OVERFLOW: u8 = 1;
+
+// A slotted page starts with the next page of the heap, the number of slots, where the record
+// area begins and, on the first page only, the last page of the heap. The slot directory follows,
+// an offset and length per slot, and records fill the page from the end. A deleted record leaves
+// an empty slot behind so that the other records keep their slots.
+const NEXT: usize = 0;
+const SLOT_COUNT: usize = 4;
+const FREE_END: usize = 6;
+const LAST: usize = 8;
+const SLOTS: usize = 12;
+const SLOT_SIZE: usize = 4;
+
+// An unordered collection of records in a linked list of slotted pages. Records are added to the
+// last page, so space freed elsewhere is only reused by records that grow in place.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heap {
+    pub first: PageId,
+}
+
+impl Heap {
+    pub fn create(pager: &mut Pager) -> io::Result<Heap> {
+        let first = pager.allocate()?;
+        pager.write(first, |page| {
+            init(page);
+            write_u32(page, LAST, first);
+        })?;
+        Ok(Heap { first })
+    }
+
+    pub fn insert(&self, pager: &mut Pager, record: &[u8]) -> io::Result<RecordId> {
+        let stored = store(pager, record)?;
+        self.append(pager, &stored)
+    }
+
+    pub fn get(&self, pager: &mut Pager, id: RecordId) -> io::Result<Vec<u8>> {
+        let stored = pager.read(id.page_id, |page| record(page, id.slot).map(<[u8]>::to_vec))?;
+        load(pager, &stored.ok_or_else(|| missing(id))?)
+    }
+
+    // Replaces a record, in place if it still fits on its page, and returns where it is now
+    pub fn update(&self, pager: &mut Pager, id: RecordId, record: &[u8]) -> io::Result<RecordId> {
+        let stored = store(pager, record)?;
+        let old = pager
+            .read(id.page_id, |page| {
+                self::record(page, id.slot).map(<[u8]>::to_vec)
+            })?
+            .ok_or_else(|| missing(id))?;
+        free_overflow(pager, &old)?;
+        let placed = pager.write(id.page_id, |page| {
+            remove(page, id.slot);
+            place(page, Some(id.slot), &stored)
+        })?;
+        if placed.is_some() {
+            return Ok(id);
+        }
+        self.append(pager, &stored)
+    }
+
+    pub fn delete(&self, pager: &mut Pager, id: RecordId) -> io::Result<()> {
+        let old = pager
+            .read(id.page_id, |page| record(page, id.slot).map(<[u8]>::to_vec))?
+            .ok_or_else(|| missing(id))?;
+        free_overflow(pager, &old)?;
+        pager.write(id.page_id, |page| remove(page, id.slot))
+    }
+
+    // Frees every page of the heap and of its records
+    pub fn destroy(self, pager: &mut Pager) -> io::Result<()> {
+        let mut page_id = self.first;
+        while page_id != 0 {
+            let (next, records) = pager.read(page_id, |page| {
+                let records: Vec<Vec<u8>> = (0..read_u16(page, SLOT_COUNT))
+                    .filter_map(|slot| record(page, slot).map(<[u8]>::to_vec))
+                    .collect();
+                (read_u32(page, NEXT), records)
+            })?;
+            for stored in records {
+                free_overflow(pager, &stored)?;
+            }
+            pager.free(page_id)?;
+            page_id = next;
+        }
+        Ok(())
+    }
+
+    // Adds a record to the last page, or to a new one if it is full
+    fn append(&self, pager: &mut Pager, stored: &[u8]) -> io::Result<RecordId> {
+        let last = pager.read(self.first, |page| read_u32(page, LAST))?;
+        let mut page_id = last;
+        let mut slot = pager.write(last, |page| place(page, None, stored))?;
+        if slot.is_none() {
+            page_id = pager.allocate()?;
+            slot = pager.write(page_id, |page| {
+                init(page);
+                place(page, None, stored)
+            })?;
+            pager.write(last, |page| write_u32(page, NEXT, page_id))?;
+            pager.write(self.first, |page| write_u32(page, LAST, page_id))?;
+        }
+        Ok(RecordId {
+            page_id,
+            slot: slot.expect("an empty page has room for an inline record"),
+        })
+    }
+}
+
+// Writes a blob to a chain of pages and returns the first. Each page holds the next one and the
+// length of its part of the blob.
+pub fn write_blob(pager: &mut Pager, blob: &[u8]) -> io::Result<PageId> {
+    const CHUNK: usize = PAGE_SIZE - 8;
+    let chunks: Vec<&[u8]> = if blob.is_empty() {
+        vec![&[]]
+    } else {
+        blob.chunks(CHUNK).collect()
+    };
+    let mut next = 0;
+    for chunk in chunks.iter().rev() {
+        let page_id = pager.allocate()?;
+        pager.write(page_id, |page| {
+            write_u32(page, 0, next);
+            write_u32(page, 4, chunk.len() as u32);
+            page[8..8 + chunk.len()].copy_from_slice(chunk);
+        })?;
+        next = page_id;
+    }
+    Ok(next)
+}
+
+pub fn read_blob(pager: &mut Pager, first: PageId) -> io::Result<Vec<u8>> {
+    let mut blob = Vec::new();
+    let mut page_id = first;
+    while page_id != 0 {
+        page_id = pager.read(page_id, |page| {
+            let len = (read_u32(page, 4) as usize).min(PAGE_SIZE - 8);
+            blob.extend_from_slice(&page[8..8 + len]);
+            read_u32(page, 0)
+        })?;
+    }
+    Ok(blob)
+}
+
+pub fn free_blob(pager: &mut Pager, first: PageId) -> io::Result<()> {
+    let mut page_id = first;
+    while page_id != 0 {
+        let next = pager.read(page_id, |page| read_u32(page, 0))?;
+        pager.free(page_id)?;
+        page_id = next;
+    }
+    Ok(())
+}
+
+// What goes in the slot: the record itself, or where its overflow chain starts
+fn store(pager: &mut Pager, record: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stored = Vec::with_capacity(record.len().min(MAX_INLINE) + 1);
+    if record.len() < MAX_INLINE {
+        stored.push(INLINE);
+        stored.extend_from_slice(record);
+    } else {
+        stored.push(OVERFLOW);
+        stored.extend_from_slice(&write_blob(pager, record)?.to_le_bytes());
+    }
+    Ok(stored)
+}
+
+fn load(pager: &mut Pager, stored: &[u8]) -> io::Result<Vec<u8>> {
+    match stored.first() {
+        Some(&INLINE) => Ok(stored[1..].to_vec()),
+        Some(&OVERFLOW) if stored.len() == 5 => read_blob(pager, read_u32(stored, 1)),
+        _ => Err(pager::corrupt("damaged heap record")),
+    }
+}
+
+fn free_overflow(pager: &mut Pager, stored: &[u8]) -> io::Result<()> {
+    match stored.first() {
+        Some(&OVERFLOW) if stored.len() == 5 => free_blob(pager, read_u32(stored, 1)),
+        _ => Ok(()),
+    }
+}
+
+fn missing(id: RecordId) -> io::Error {
+    pager::corrupt(&format!(
+        "no record in slot {} of page {}",
+        id.slot, id.page_id
+    ))
+}
+
+fn init(page: &mut Page) {
+    page.fill(0);
+    write_u16(page, FREE_END, PAGE_SIZE as u16);
+}
+
+fn slot_offset(slot: u16) -> usize {
+    SLOTS + slot as usize * SLOT_SIZE
+}
+
+fn record(page: &Page, slot: u16) -> Option<&[u8]> {
+    if slot >= read_u16(page, SLOT_COUNT) {
+        return None;
+    }
+    let offset = read_u16(page, slot_offset(slot)) as usize;
+    let len = read_u16(page, slot_offset(slot) + 2) as usize;
+    if offset == 0 {
+        return None;
+    }
+    page.get(offset..offset + len)
+}
+
+fn remove(page: &mut Page, slot: u16) {
+    write_u16(page, slot_offset(slot), 0);
+    write_u16(page, slot_offset(slot) + 2, 0);
+}
+
+// Puts a record in the given empty slot, or else in the first empty or a new one, compacting the
+// page if its free space is scattered. None if the page is too full.
+fn place(page: &mut Page, slot: Option<u16>, stored: &[u8]) -> Option<u16> {
+    let slot_count = read_u16(page, SLOT_COUNT);
+    let slot = slot.unwrap_or_else(|| {
+        (0..slot_count)
+            .find(|slot| read_u16(page, slot_offset(*slot)) == 0)
+            .unwrap_or(slot_count)
+    });
+    let new_slots = (slot + 1).max(slot_count);
+    let directory_end = slot_offset(new_slots);
+
+    let live: usize = (0..slot_count)
+        .filter_map(|slot| record(page, slot).map(<[u8]>::len))
+        .sum();
+    if directory_end + live + stored.len() > PAGE_SIZE {
+        return None;
+    }
+    if directory_end + stored.len() > read_u16(page, FREE_END) as usize {
+        compact(page);
+    }
+
+    let offset = read_u16(page, FREE_END) as usize - stored.len();
+    page[offset..offset + stored.len()].copy_from_slice(stored);
+    write_u16(page, FREE_END, offset as u16);
+    write_u16(page, SLOT_COUNT, new_slots);
+    write_u16(page, slot_offset(slot), offset as u16);
+    write_u16(page, slot_offset(slot) + 2, stored.len() as u16);
+    Some(slot)
+}
+
+// Moves the records to the end of the page so that the free space is in one piece
+fn compact(page: &mut Page) {
+    let records: Vec<(u16, Vec<u8>)> = (0..read_u16(page, SLOT_COUNT))
+        .filter_map(|slot| record(page, slot).map(|record| (slot, record.to_vec())))
+        .collect();
+    let mut free_end = PAGE_SIZE;
+    for (slot, record) in records {
+        free_end -= record.len();
+        page[free_end..free_end + record.len()].copy_from_slice(&record);
+        write_u16(page, slot_offset(slot), free_end as u16);
+    }
+    write_u16(page, FREE_END, free_end as u16);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pager::{journal_path, DEFAULT_MEMORY_BUDGET};
+    use crate::db::wal::Wal;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(Wal::path_for(&path));
+        let _ = fs::remove_file(journal_path(&path));
+        path
+    }
+
+    fn remove_files(path: &Path) {
+        fs::remove_file(path).unwrap();
+        fs::remove_file(Wal::path_for(path)).unwrap();
+        fs::remove_file(journal_path(path)).unwrap();
+    }
+
+    #[test]
+    fn test_slotted_page_reuses_space() {
+        let mut page = [0; PAGE_SIZE];
+        init(&mut page);
+        let records: Vec<Vec<u8>> = (0..8u8).map(|n| vec![n; 500]).collect();
+        for (n, record) in records.iter().enumerate() {
+            assert_eq!(place(&mut page, None, record), Some(n as u16));
+        }
+        // 8 * 500 bytes plus the slots leave less than 500 bytes
+        assert_eq!(place(&mut page, None, &records[0]), None);
+
+        remove(&mut page, 2);
+        remove(&mut page, 5);
+        // The two holes are only big enough together, so the page is compacted
+        assert_eq!(place(&mut page, None, &[9; 900]), Some(2));
+        assert_eq!(record(&page, 2), Some(&[9; 900][..]));
+        assert_eq!(record(&page, 5), None);
+        for n in [0, 1, 3, 4, 6, 7] {
+            assert_eq!(record(&page, n), Some(&records[n as usize][..]));
+        }
+    }
+
+    #[test]
+    fn test_heap_records() {
+        let path = temp_path("heap.pages");
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let heap = Heap::create(&mut pager).unwrap();
+        let large = vec![7u8; 3 * PAGE_SIZE];
+        let ids: Vec<RecordId> = (0..100u8)
+            .map(|n| heap.insert(&mut pager, &[n; 100]).unwrap())
+            .collect();
+        let overflow = heap.insert(&mut pager, &large).unwrap();
+        assert!(ids.iter().any(|id| id.page_id != ids[0].page_id));
+        assert_eq!(heap.get(&mut pager, ids[42]).unwrap(), vec![42; 100]);
+        assert_eq!(heap.get(&mut pager, overflow).unwrap(), large);
+
+        // Shrinking stays in place, growing past the free space moves the record
+        assert_eq!(heap.update(&mut pager, ids[0], &[1; 10]).unwrap(), ids[0]);
+        let moved = heap.update(&mut pager, ids[1], &[2; 1000]).unwrap();
+        assert_ne!(moved, ids[1]);
+        assert_eq!(heap.get(&mut pager, moved).unwrap(), vec![2; 1000]);
+        let shrunk = heap.update(&mut pager, overflow, &[3; 10]).unwrap();
+        assert_eq!(heap.get(&mut pager, shrunk).unwrap(), vec![3; 10]);
+
+        heap.delete(&mut pager, ids[2]).unwrap();
+        assert!(heap.get(&mut pager, ids[2]).is_err());
+        let pages = pager.allocate().unwrap();
+        heap.destroy(&mut pager).unwrap();
+        // Every page of the heap is on the free list now
+        assert!(pager.allocate().unwrap() < pages);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_blobs() {
+        let path = temp_path("blob.pages");
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        for len in [0, 10, PAGE_SIZE - 8, 2 * PAGE_SIZE + 5] {
+            let blob: Vec<u8> = (0..len).map(|n| n as u8).collect();
+            let first = write_blob(&mut pager, &blob).unwrap();
+            assert_eq!(read_blob(&mut pager, first).unwrap(), blob);
+            free_blob(&mut pager, first).unwrap();
+        }
+        drop(pager);
+        remove_files(&path);
+    }
+}
@@ -0,0 +1,230 @@
+use core::fmt;
+use serde_json::Value as JsonValue;
+
+use super::data_types::Value;
+use super::decimal::Decimal;
+
+// JSON documents are stored as canonical (compact, keys sorted) text so that `Value` keeps its
+// total order and equal documents compare equal
+pub fn canonicalize(s: &str) -> Result<String, String> {
+    let json: JsonValue =
+        serde_json::from_str(s).map_err(|e| format!("JSON: invalid document: {}", e))?;
+    Ok(json.to_string())
+}
+
+pub fn parse(s: &str) -> Result<JsonValue, String> {
+    serde_json::from_str(s).map_err(|e| format!("JSON: invalid document: {}", e))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathStep {
+    Key(String),
+    Index(i64),
+}
+
+// A location inside a document, written either as a single key / array index (the right hand
+// side of `->`) or as a SQLite style path such as '$.items[0].name'
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath(Vec<PathStep>);
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<JsonPath, String> {
+        let invalid = || format!("JSON: invalid path '{}'", path);
+        let rest = match path.strip_prefix('$') {
+            Some(rest) => rest,
+            // A plain key, as in data->'name'
+            None => return Ok(JsonPath(vec![PathStep::Key(path.to_string())])),
+        };
+
+        let mut steps = Vec::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    if chars.peek() == Some(&'"') {
+                        chars.next();
+                        for c in chars.by_ref() {
+                            if c == '"' {
+                                break;
+                            }
+                            key.push(c);
+                        }
+                    } else {
+                        while let Some(&c) = chars.peek() {
+                            if c == '.' || c == '[' {
+                                break;
+                            }
+                            key.push(c);
+                            chars.next();
+                        }
+                    }
+                    if key.is_empty() {
+                        return Err(invalid());
+                    }
+                    steps.push(PathStep::Key(key));
+                }
+                '[' => {
+                    let mut index = String::new();
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        index.push(c);
+                    }
+                    // '#-1' addresses the last element, as in SQLite
+                    let step = match index.strip_prefix("#-") {
+                        Some(from_end) => {
+                            PathStep::Index(-from_end.parse::<i64>().map_err(|_| invalid())?)
+                        }
+                        None => PathStep::Index(index.parse().map_err(|_| invalid())?),
+                    };
+                    steps.push(step);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(JsonPath(steps))
+    }
+
+    pub fn index(index: i64) -> JsonPath {
+        JsonPath(vec![PathStep::Index(index)])
+    }
+
+    pub fn select<'a>(&self, json: &'a JsonValue) -> Option<&'a JsonValue> {
+        let mut current = json;
+        for step in &self.0 {
+            current = match (step, current) {
+                (PathStep::Key(key), JsonValue::Object(map)) => map.get(key)?,
+                (PathStep::Index(index), JsonValue::Array(items)) => {
+                    let position = if *index < 0 {
+                        items.len().checked_sub(index.unsigned_abs() as usize)?
+                    } else {
+                        *index as usize
+                    };
+                    items.get(position)?
+                }
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$")?;
+        for step in &self.0 {
+            match step {
+                PathStep::Key(key) => write!(f, ".{}", key)?,
+                PathStep::Index(index) if *index < 0 => write!(f, "[#{}]", index)?,
+                PathStep::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// How a selected part of a document is turned back into a SQL value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JsonAccess {
+    // `->`: always JSON
+    Json,
+    // `->>`: text, with strings unquoted
+    Text,
+    // json_extract(): scalars become the matching SQL type, objects and arrays stay JSON
+    Value,
+}
+
+impl JsonAccess {
+    pub fn convert(&self, json: &JsonValue) -> Value {
+        match (self, json) {
+            (_, JsonValue::Null) => Value::Null,
+            (JsonAccess::Json, json) => Value::Json(json.to_string()),
+            (JsonAccess::Text, JsonValue::String(s)) => Value::Text(s.clone()),
+            (JsonAccess::Text, json) => Value::Text(json.to_string()),
+            (JsonAccess::Value, json) => to_sql_value(json),
+        }
+    }
+}
+
+pub fn to_sql_value(json: &JsonValue) -> Value {
+    match json {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Boolean(*b),
+        JsonValue::String(s) => Value::Text(s.clone()),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Decimal::parse(&n.to_string())
+                .map(Value::Decimal)
+                .unwrap_or_else(|_| Value::Json(n.to_string())),
+        },
+        JsonValue::Array(_) | JsonValue::Object(_) => Value::Json(json.to_string()),
+    }
+}
+
+// The type names reported by json_each, as in SQLite
+pub fn type_name(json: &JsonValue) -> &'static str {
+    match json {
+        JsonValue::Null => "null",
+        JsonValue::Bool(true) => "true",
+        JsonValue::Bool(false) => "false",
+        JsonValue::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        JsonValue::Number(_) => "real",
+        JsonValue::String(_) => "text",
+        JsonValue::Array(_) => "array",
+        JsonValue::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonicalize_sorts_keys() {
+        assert_eq!(
+            canonicalize(r#"{ "b": 1, "a": [true, null] }"#).unwrap(),
+            r#"{"a":[true,null],"b":1}"#
+        );
+        assert!(canonicalize("{not json}").is_err());
+    }
+
+    #[test]
+    fn test_path_select() {
+        let json = parse(r#"{"items": [{"name": "a"}, {"name": "b"}], "n": 1}"#).unwrap();
+        let select = |path: &str| {
+            JsonPath::parse(path)
+                .unwrap()
+                .select(&json)
+                .map(|v| v.to_string())
+        };
+        assert_eq!(select("$.items[1].name"), Some(r#""b""#.to_string()));
+        assert_eq!(select("$.items[#-1].name"), Some(r#""b""#.to_string()));
+        assert_eq!(select("n"), Some("1".to_string()));
+        assert_eq!(select("$.missing"), None);
+        assert_eq!(select("$.n[0]"), None);
+        assert!(JsonPath::parse("$items").is_err());
+    }
+
+    #[test]
+    fn test_access_conversions() {
+        let json = parse(r#"{"s": "x", "n": 2.50, "o": {"k": 1}}"#).unwrap();
+        let get = |key: &str, access: JsonAccess| {
+            access.convert(JsonPath::parse(key).unwrap().select(&json).unwrap())
+        };
+        assert_eq!(
+            get("s", JsonAccess::Json),
+            Value::Json(r#""x""#.to_string())
+        );
+        assert_eq!(get("s", JsonAccess::Text), Value::Text("x".to_string()));
+        assert_eq!(
+            get("n", JsonAccess::Value),
+            Value::Decimal(Decimal::parse("2.5").unwrap())
+        );
+        assert_eq!(
+            get("o", JsonAccess::Value),
+            Value::Json(r#"{"k":1}"#.to_string())
+        );
+    }
+}
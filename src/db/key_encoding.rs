@@ -0,0 +1,307 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use super::data_types::Value;
+use super::decimal::Decimal;
+use super::table::IndexRange;
+
+// Encodes values as byte strings that compare, bytewise, the way the values do, so that B+tree
+// indexes keep them in order. A value's encoding is never a prefix of another's, which lets
+// index keys put more after it.
+//
+// Every encoding starts with the value's position in `Value`, which orders values of different
+// types. Integers are stored big endian with the sign bit flipped, strings with zero bytes
+// escaped as 0x00 0xFF and closed with 0x00 0x00, and decimals as a sign, an exponent and their
+// digits, inverted for negative numbers.
+pub fn encode_value(value: &Value, key: &mut Vec<u8>) {
+    match value {
+        Value::Null => key.push(0),
+        Value::Integer(n) => {
+            key.push(1);
+            encode_i64(*n, key);
+        }
+        Value::Text(s) => {
+            key.push(2);
+            encode_bytes(s.as_bytes(), key);
+        }
+        Value::Boolean(b) => key.extend([3, *b as u8]),
+        Value::Date(date) => {
+            key.push(4);
+            key.extend(((date.0 as u32) ^ (1 << 31)).to_be_bytes());
+        }
+        Value::Timestamp(timestamp) => {
+            key.push(5);
+            encode_i64(timestamp.0, key);
+        }
+        Value::Decimal(decimal) => {
+            key.push(6);
+            encode_decimal(decimal, key);
+        }
+        Value::Json(json) => {
+            key.push(7);
+            encode_bytes(json.as_bytes(), key);
+        }
+        Value::Interval(interval) => {
+            key.push(8);
+            key.extend(((interval.months as u32) ^ (1 << 31)).to_be_bytes());
+            key.extend(((interval.days as u32) ^ (1 << 31)).to_be_bytes());
+            encode_i64(interval.micros, key);
+        }
+    }
+}
+
+pub fn value_key(value: &Value) -> Vec<u8> {
+    let mut key = Vec::new();
+    encode_value(value, &mut key);
+    key
+}
+
+// The start of an index entry: the index name, a zero byte and the encoded values of the key, of
+// which only the first `limit` bytes are kept
+pub fn index_prefix(index: &str, values: &[Value], limit: usize) -> (Vec<u8>, bool) {
+    let mut encoded = Vec::new();
+    for value in values {
+        encode_value(value, &mut encoded);
+    }
+    let cut_short = encoded.len() > limit;
+    encoded.truncate(limit);
+    let mut prefix = index.as_bytes().to_vec();
+    prefix.push(0);
+    prefix.extend(encoded);
+    (prefix, cut_short)
+}
+
+// The index entries an `IndexRange` covers, for stores that keep them as `index_prefix` followed
+// by the row id. A bound that was cut short can't tell the entries it was cut in apart, so those
+// count as inside the range, for the store to check against their rows.
+pub struct KeyRange {
+    prefix: Vec<u8>,
+    lower: Bound<(Vec<u8>, bool)>,
+    upper: Bound<(Vec<u8>, bool)>,
+}
+
+impl KeyRange {
+    pub fn new(index: &str, range: &IndexRange, limit: usize) -> Self {
+        let bound = |bound: &Bound<Value>| {
+            let key = |value: &Value| {
+                let values: Vec<Value> = range.prefix.iter().chain([value]).cloned().collect();
+                index_prefix(index, &values, limit)
+            };
+            match bound {
+                Bound::Included(value) => Bound::Included(key(value)),
+                Bound::Excluded(value) => Bound::Excluded(key(value)),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        };
+        KeyRange {
+            prefix: index_prefix(index, &range.prefix, limit).0,
+            lower: bound(&range.lower),
+            upper: bound(&range.upper),
+        }
+    }
+
+    // Where to start looking
+    pub fn start(&self) -> &[u8] {
+        match &self.lower {
+            Bound::Included((lower, _)) | Bound::Excluded((lower, _)) => lower,
+            Bound::Unbounded => &self.prefix,
+        }
+    }
+
+    // Whether `key` comes before the range, inside it or after it. Keys are compared as bytes:
+    // since encoded values are never a prefix of one another, a key starting with a bound holds
+    // the bound's value.
+    pub fn locate(&self, key: &[u8]) -> Ordering {
+        if !key.starts_with(&self.prefix) {
+            return key.cmp(&self.prefix);
+        }
+        match &self.lower {
+            Bound::Included((lower, _)) if key < lower.as_slice() => return Ordering::Less,
+            Bound::Excluded((lower, cut_short))
+                if key < lower.as_slice() || (key.starts_with(lower) && !cut_short) =>
+            {
+                return Ordering::Less
+            }
+            _ => {}
+        }
+        match &self.upper {
+            Bound::Included((upper, _)) => {
+                if key > upper.as_slice() && !key.starts_with(upper) {
+                    return Ordering::Greater;
+                }
+            }
+            Bound::Excluded((upper, cut_short)) => {
+                if key.starts_with(upper) {
+                    if !cut_short {
+                        return Ordering::Greater;
+                    }
+                } else if key > upper.as_slice() {
+                    return Ordering::Greater;
+                }
+            }
+            Bound::Unbounded => {}
+        }
+        Ordering::Equal
+    }
+}
+
+fn encode_i64(n: i64, key: &mut Vec<u8>) {
+    key.extend(((n as u64) ^ (1 << 63)).to_be_bytes());
+}
+
+fn encode_bytes(bytes: &[u8], key: &mut Vec<u8>) {
+    for byte in bytes {
+        key.push(*byte);
+        if *byte == 0 {
+            key.push(0xFF);
+        }
+    }
+    key.extend([0, 0]);
+}
+
+// The number is 0.d1d2...dn * 10^exponent, written as a sign class (negative, zero, positive),
+// the exponent and the digits shifted up by one and closed by a zero, so that a shorter run of
+// digits sorts first. Negative numbers invert everything after the sign class. Equal numbers
+// have one encoding whatever their scale.
+fn encode_decimal(decimal: &Decimal, key: &mut Vec<u8>) {
+    let mantissa = decimal.mantissa();
+    if mantissa == 0 {
+        key.push(1);
+        return;
+    }
+    let digits = mantissa.unsigned_abs().to_string();
+    let exponent = digits.len() as i16 - decimal.scale() as i16;
+    let mut encoded: Vec<u8> = ((exponent as u16) ^ (1 << 15)).to_be_bytes().to_vec();
+    encoded.extend(
+        digits
+            .trim_end_matches('0')
+            .bytes()
+            .map(|digit| digit - b'0' + 1),
+    );
+    encoded.push(0);
+    if mantissa < 0 {
+        key.push(0);
+        key.extend(encoded.iter().map(|byte| !byte));
+    } else {
+        key.push(2);
+        key.extend(encoded);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::datetime::{Date, Timestamp};
+
+    fn d(s: &str) -> Value {
+        Value::Decimal(Decimal::parse(s).unwrap())
+    }
+
+    #[test]
+    fn test_keys_sort_like_values() {
+        let values = vec![
+            Value::Null,
+            Value::Integer(i64::MIN),
+            Value::Integer(-1),
+            Value::Integer(0),
+            Value::Integer(255),
+            Value::Integer(256),
+            Value::Integer(i64::MAX),
+            Value::Text(String::new()),
+            Value::Text("a".to_string()),
+            Value::Text("a\0".to_string()),
+            Value::Text("a\0b".to_string()),
+            Value::Text("ab".to_string()),
+            Value::Text("b".to_string()),
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Date(Date(-10)),
+            Value::Date(Date(3)),
+            Value::Timestamp(Timestamp(-5)),
+            Value::Timestamp(Timestamp(7)),
+            d("-1000"),
+            d("-12.5"),
+            d("-12.345"),
+            d("-0.001"),
+            d("0"),
+            d("0.00999"),
+            d("0.01"),
+            d("1.2"),
+            d("1.25"),
+            d("9.99"),
+            d("10"),
+            d("100"),
+            d("12345678901234567890.5"),
+            Value::Json("[]".to_string()),
+            Value::Json("{}".to_string()),
+        ];
+        for pair in values.windows(2) {
+            assert!(pair[0] < pair[1], "{:?} < {:?}", pair[0], pair[1]);
+            assert!(
+                value_key(&pair[0]) < value_key(&pair[1]),
+                "{:?} < {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_ranges() {
+        let key = |values: &[Value]| index_prefix("i", values, usize::MAX).0;
+        let range = IndexRange {
+            prefix: vec![Value::Integer(1)],
+            lower: Bound::Excluded(Value::Integer(2)),
+            upper: Bound::Included(Value::Integer(4)),
+        };
+        let keys = KeyRange::new("i", &range, usize::MAX);
+        assert_eq!(keys.start(), key(&[Value::Integer(1), Value::Integer(2)]));
+        for (values, expected) in [
+            (vec![Value::Integer(0), Value::Integer(3)], Ordering::Less),
+            (vec![Value::Integer(1), Value::Null], Ordering::Less),
+            (vec![Value::Integer(1), Value::Integer(2)], Ordering::Less),
+            (vec![Value::Integer(1), Value::Integer(3)], Ordering::Equal),
+            (vec![Value::Integer(1), Value::Integer(4)], Ordering::Equal),
+            (
+                vec![Value::Integer(1), Value::Integer(5)],
+                Ordering::Greater,
+            ),
+            (
+                vec![Value::Integer(2), Value::Integer(0)],
+                Ordering::Greater,
+            ),
+        ] {
+            let mut entry = key(&values);
+            entry.extend(7u64.to_be_bytes());
+            assert_eq!(keys.locate(&entry), expected, "{:?}", values);
+        }
+        // Another index's entries are outside the range too
+        assert_eq!(
+            keys.locate(&index_prefix("j", &[Value::Integer(1)], usize::MAX).0),
+            Ordering::Greater
+        );
+
+        // Entries cut short at a bound may or may not be past it
+        let long = |c: char| Value::Text(c.to_string().repeat(10));
+        let range = IndexRange {
+            prefix: Vec::new(),
+            lower: Bound::Excluded(long('a')),
+            upper: Bound::Unbounded,
+        };
+        let keys = KeyRange::new("i", &range, 4);
+        let (entry, cut_short) = index_prefix("i", &[long('a')], 4);
+        assert!(cut_short);
+        assert_eq!(keys.locate(&entry), Ordering::Equal);
+        assert_eq!(
+            KeyRange::new("i", &range, usize::MAX).locate(&key(&[long('a')])),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_equal_decimals_share_a_key() {
+        assert_eq!(value_key(&d("1.5")), value_key(&d("1.500")));
+        assert_eq!(value_key(&d("-20")), value_key(&d("-20.0")));
+        assert_eq!(value_key(&d("0.000")), value_key(&d("0")));
+    }
+}
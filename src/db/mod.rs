@@ -1,4 +1,23 @@
+pub mod btree;
 pub mod btreemap_database;
+#[cfg(test)]
+mod conformance;
 pub mod data_types;
+pub mod datetime;
+pub mod decimal;
+pub mod engine;
+pub mod fault;
+pub mod file_format;
+pub mod full_text;
+pub mod heap;
+pub mod json;
+pub mod key_encoding;
+pub mod overlay;
+pub mod paged_database;
+pub mod pager;
 pub mod sled_database;
+pub mod spill;
+pub mod statistics;
 pub mod table;
+pub mod transaction;
+pub mod wal;
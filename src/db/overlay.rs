@@ -0,0 +1,751 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::iter::Peekable;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::btreemap_database::Database;
+use super::data_types::{CaseInsensitiveString, Column, UniqueConstraint, Value};
+use super::datetime::{Clock, SystemClock, Timestamp};
+use super::engine::{self, RowIdIter, RowIter, StorageEngine};
+use super::full_text::FullTextIndex;
+use super::spill::{self, SpillFile, Spilled};
+use super::statistics::TableStatistics;
+use super::table::{ColumnIndex, IndexRange, Row, RowRange, Table, TableSchema, ALL_ROWS};
+use super::transaction::Transaction;
+use super::wal::Redo;
+
+// The committed state of an `OverlayDatabase`, kept by an engine in its own files
+pub trait CommittedStore: fmt::Debug {
+    fn table_names(&self) -> Result<Vec<CaseInsensitiveString>, String>;
+    fn schema(&self, table: &CaseInsensitiveString) -> Result<Option<TableSchema>, String>;
+    fn next_row_id(&self) -> Result<usize, String>;
+    fn get_row(&self, table: &CaseInsensitiveString, row_id: usize) -> Result<Option<Row>, String>;
+    fn scan(&self, table: &CaseInsensitiveString, range: RowRange) -> Result<RowIter<'_>, String>;
+    // Ids of the committed rows in `range` of `index`, in key order, see `StorageEngine`
+    fn index_scan(
+        &self,
+        table: &CaseInsensitiveString,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<RowIdIter<'_>, String>;
+    // Stores the changes of one transaction durably, all or nothing, reading them as it goes
+    fn commit(
+        &mut self,
+        changes: &mut dyn Iterator<Item = Result<Redo, String>>,
+    ) -> Result<(), String>;
+    // Makes sure everything committed is in the store's files
+    fn checkpoint(&mut self) -> io::Result<()>;
+}
+
+// A database whose committed rows live in a store on disk rather than in memory: queries read
+// them from the store as they go. Changes that aren't committed yet are held in `pending` and
+// laid over the committed rows, and reach the store in one go on commit.
+#[derive(Debug)]
+pub struct OverlayDatabase<S> {
+    store: S,
+    clock: Arc<dyn Clock>,
+    pending: Pending,
+    // Bytes of pending rows held in memory before the rest go to a spill file
+    memory: usize,
+    // Takes back changes to `pending` on rollback
+    transaction: Option<Transaction<Undo>>,
+    // Where each statement in progress started, see `Database::statements`
+    statements: Vec<(bool, usize)>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    tables: BTreeMap<CaseInsensitiveString, PendingTable>,
+    // Rows written since the last commit
+    rows: BTreeMap<CaseInsensitiveString, BTreeMap<usize, Written>>,
+    next_row_id: Option<usize>,
+    // Bytes of the rows kept in memory, which the undo log may share. Only grows until the next
+    // commit or rollback, since the undo log keeps the rows that were written over. The entries
+    // saying where each row is stay in memory either way.
+    held: usize,
+    spill: Option<SpillFile>,
+}
+
+// A row written since the last commit. The undo log shares it rather than copying it.
+#[derive(Debug, Clone)]
+enum Written {
+    Deleted,
+    Held(Arc<Row>),
+    // Written once the pending rows had taken up their memory
+    Spilled(Spilled),
+}
+
+// Rows per change handed to the store on commit, read back from the spill file together
+const COMMIT_BATCH: usize = 1024;
+
+// A table created, replaced, dropped or given a new index since the last commit
+#[derive(Debug, Clone)]
+struct PendingTable {
+    // None once dropped
+    schema: Option<TableSchema>,
+    // Whether the committed rows are gone, i.e. the table was (re)created or dropped
+    replaced: bool,
+}
+
+// What `pending` held before a change
+#[derive(Debug)]
+enum Undo {
+    Table {
+        name: CaseInsensitiveString,
+        previous: Option<PendingTable>,
+        // The pending rows, for changes that discarded them
+        rows: Option<BTreeMap<usize, Written>>,
+    },
+    Rows {
+        table: CaseInsensitiveString,
+        rows: Vec<(usize, Option<Written>)>,
+    },
+    NextRowId(Option<usize>),
+}
+
+impl Pending {
+    fn load(&self, written: &Written) -> Result<Option<Row>, String> {
+        match written {
+            Written::Deleted => Ok(None),
+            Written::Held(row) => Ok(Some(Row::clone(row))),
+            Written::Spilled(spilled) => match &self.spill {
+                Some(spill) => spill.read(*spilled).map(Some),
+                None => Err("Overlay: a spilled row has no spill file".to_string()),
+            },
+        }
+    }
+
+    // Keeps the row in memory while the rows held take up less than `memory` bytes
+    fn hold(&mut self, row: Option<Row>, memory: usize) -> Result<Written, String> {
+        let Some(row) = row else {
+            return Ok(Written::Deleted);
+        };
+        if self.held < memory {
+            self.held += mem::size_of::<Row>() + spill::heap_size(&row.values);
+            return Ok(Written::Held(Arc::new(row)));
+        }
+        let spill = match &mut self.spill {
+            Some(spill) => spill,
+            None => self.spill.insert(SpillFile::create()?),
+        };
+        spill.write(&row).map(Written::Spilled)
+    }
+}
+
+impl<S: CommittedStore> OverlayDatabase<S> {
+    // Pending rows hold up to about `memory` bytes before they are spilled to a temporary file
+    pub fn new(store: S, memory: usize) -> Self {
+        OverlayDatabase {
+            store,
+            clock: Arc::new(SystemClock),
+            pending: Pending::default(),
+            memory,
+            transaction: None,
+            statements: Vec::new(),
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    // Bytes of pending rows held in memory
+    pub fn held(&self) -> usize {
+        self.pending.held
+    }
+
+    fn atomically<T>(
+        &mut self,
+        change: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.begin_statement();
+        let result = change(self);
+        let ended = self.end_statement(result.is_ok());
+        let value = result?;
+        ended?;
+        Ok(value)
+    }
+
+    // None if there is no such table, counting pending changes
+    fn find_schema(&self, name: &CaseInsensitiveString) -> Result<Option<TableSchema>, String> {
+        match self.pending.tables.get(name) {
+            Some(pending) => Ok(pending.schema.clone()),
+            None => self.store.schema(name),
+        }
+    }
+
+    fn replaced(&self, table: &CaseInsensitiveString) -> bool {
+        self.pending
+            .tables
+            .get(table)
+            .is_some_and(|pending| pending.replaced)
+    }
+
+    fn next_row_id(&self) -> Result<usize, String> {
+        match self.pending.next_row_id {
+            Some(next_row_id) => Ok(next_row_id),
+            None => self.store.next_row_id(),
+        }
+    }
+
+    // The whole table, committed and pending rows together
+    pub(crate) fn table(&self, table_name: &str) -> Result<Table, String> {
+        let mut table = Table::with_schema(self.schema(table_name)?);
+        let rows = self
+            .scan(table_name, ALL_ROWS)?
+            .map(|row| row.map(|(row_id, row)| (row_id, Some(row))))
+            .collect::<Result<Vec<_>, String>>()?;
+        table.restore_rows(rows);
+        Ok(table)
+    }
+
+    // Creates, replaces or drops a table, whose committed and pending rows no longer count
+    fn replace_table(&mut self, name: CaseInsensitiveString, schema: Option<TableSchema>) {
+        let previous = self.pending.tables.get(&name).cloned();
+        let rows = self.pending.rows.remove(&name).unwrap_or_default();
+        self.record(Undo::Table {
+            name: name.clone(),
+            previous,
+            rows: Some(rows),
+        });
+        self.pending.tables.insert(
+            name,
+            PendingTable {
+                schema,
+                replaced: true,
+            },
+        );
+    }
+
+    // Gives a table new indexes or statistics, keeping its rows. The store adds and removes the
+    // entries of the indexes that changed when the change is committed.
+    fn alter_table(&mut self, table_name: &str, schema: TableSchema) -> Result<(), String> {
+        let name = CaseInsensitiveString(table_name.to_string());
+        self.atomically(|database| {
+            let previous = database.pending.tables.get(&name).cloned();
+            let replaced = database.replaced(&name);
+            database.record(Undo::Table {
+                name: name.clone(),
+                previous,
+                rows: None,
+            });
+            database.pending.tables.insert(
+                name,
+                PendingTable {
+                    schema: Some(schema),
+                    replaced,
+                },
+            );
+            Ok(())
+        })
+    }
+
+    fn set_rows(
+        &mut self,
+        table_name: &str,
+        rows: Vec<(usize, Option<Row>)>,
+    ) -> Result<(), String> {
+        let table = CaseInsensitiveString(table_name.to_string());
+        let pending = self.pending.rows.get(&table);
+        let previous: Vec<(usize, Option<Written>)> = rows
+            .iter()
+            .map(|(row_id, _)| (*row_id, pending.and_then(|rows| rows.get(row_id)).cloned()))
+            .collect();
+        self.record(Undo::Rows {
+            table: table.clone(),
+            rows: previous,
+        });
+        for (row_id, row) in rows {
+            let written = self.pending.hold(row, self.memory)?;
+            self.pending
+                .rows
+                .entry(table.clone())
+                .or_default()
+                .insert(row_id, written);
+        }
+        Ok(())
+    }
+
+    fn set_next_row_id(&mut self, next_row_id: usize) {
+        self.record(Undo::NextRowId(self.pending.next_row_id));
+        self.pending.next_row_id = Some(next_row_id);
+    }
+
+    fn record(&mut self, entry: Undo) {
+        if let Some(transaction) = self.transaction.as_mut() {
+            transaction.log.push(entry);
+        }
+    }
+
+    // Applies the entries newest first, restoring the exact earlier state
+    fn undo(&mut self, log: Vec<Undo>) {
+        for entry in log.into_iter().rev() {
+            match entry {
+                Undo::Table {
+                    name,
+                    previous,
+                    rows,
+                } => {
+                    match previous {
+                        Some(table) => self.pending.tables.insert(name.clone(), table),
+                        None => self.pending.tables.remove(&name),
+                    };
+                    if let Some(rows) = rows {
+                        self.pending.rows.insert(name, rows);
+                    }
+                }
+                Undo::Rows { table, rows } => {
+                    let pending = self.pending.rows.entry(table).or_default();
+                    for (row_id, previous) in rows {
+                        match previous {
+                            Some(row) => pending.insert(row_id, row),
+                            None => pending.remove(&row_id),
+                        };
+                    }
+                }
+                Undo::NextRowId(next_row_id) => self.pending.next_row_id = next_row_id,
+            }
+        }
+    }
+
+    // Hands the pending changes to the store as redo records: tables that were created go first,
+    // then the changed rows of every table in batches and the new schemas of tables that were
+    // altered
+    fn write_pending(&mut self) -> Result<(), String> {
+        let pending = &self.pending;
+        let tables = pending.tables.iter().filter(|(_, table)| table.replaced);
+        let mut changes = tables
+            .map(|(name, table)| {
+                Ok(Redo::Table {
+                    name: name.clone(),
+                    table: table.schema.clone().map(Table::with_schema),
+                })
+            })
+            .chain(pending.rows.iter().flat_map(|(table, rows)| {
+                let mut rows = rows.iter().peekable();
+                std::iter::from_fn(move || {
+                    rows.peek()?;
+                    let batch = rows
+                        .by_ref()
+                        .take(COMMIT_BATCH)
+                        .map(|(row_id, written)| Ok((*row_id, pending.load(written)?)))
+                        .collect::<Result<Vec<_>, String>>();
+                    Some(batch.map(|rows| Redo::Rows {
+                        table: table.clone(),
+                        rows,
+                    }))
+                })
+            }))
+            // After the rows, so that new indexes are built from them too
+            .chain(pending.tables.iter().filter_map(|(name, table)| {
+                match (table.replaced, &table.schema) {
+                    (false, Some(schema)) => Some(Ok(Redo::Schema {
+                        name: name.clone(),
+                        schema: schema.clone(),
+                    })),
+                    _ => None,
+                }
+            }))
+            .chain(
+                pending
+                    .next_row_id
+                    .map(|next_row_id| Ok(Redo::NextRowId(next_row_id))),
+            )
+            .peekable();
+
+        if changes.peek().is_some() {
+            self.store.commit(&mut changes)?;
+        }
+        self.pending = Pending::default();
+        Ok(())
+    }
+
+    fn transaction_mut(&mut self) -> Result<&mut Transaction<Undo>, String> {
+        self.transaction
+            .as_mut()
+            .ok_or_else(|| "Savepoints can only be used inside a transaction".to_string())
+    }
+}
+
+impl<S: CommittedStore> StorageEngine for OverlayDatabase<S> {
+    fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    fn table_names(&self) -> Result<Vec<String>, String> {
+        let mut names: BTreeSet<CaseInsensitiveString> =
+            self.store.table_names()?.into_iter().collect();
+        for (name, pending) in &self.pending.tables {
+            match pending.schema {
+                Some(_) => names.insert(name.clone()),
+                None => names.remove(name),
+            };
+        }
+        Ok(names.into_iter().map(|name| name.0).collect())
+    }
+
+    fn schema(&self, table_name: &str) -> Result<TableSchema, String> {
+        self.find_schema(&CaseInsensitiveString(table_name.to_string()))?
+            .ok_or_else(|| format!("Table '{}' not found", table_name))
+    }
+
+    fn create_table(
+        &mut self,
+        table_name: String,
+        columns: Vec<Column>,
+        constraints: Vec<UniqueConstraint>,
+    ) -> Result<(), String> {
+        let mut table = Table::new(columns);
+        for constraint in constraints {
+            table.add_constraint(constraint)?;
+        }
+        let name: CaseInsensitiveString = table_name.into();
+        if self.find_schema(&name)?.is_some() {
+            return Err(format!("Table '{}' already exists", name.0));
+        }
+        self.atomically(|database| {
+            database.replace_table(name, Some(table.schema()));
+            Ok(())
+        })
+    }
+
+    fn drop_table(&mut self, table_name: &str) -> Result<(), String> {
+        self.schema(table_name)?;
+        self.atomically(|database| {
+            database.replace_table(CaseInsensitiveString(table_name.to_string()), None);
+            Ok(())
+        })
+    }
+
+    // The store checks a UNIQUE index against the committed rows as it builds the index on
+    // commit. The rows of a table created since then are all pending, and are checked here.
+    fn create_index(&mut self, table_name: &str, index: ColumnIndex) -> Result<(), String> {
+        let name = CaseInsensitiveString(table_name.to_string());
+        let mut table = if index.unique && self.replaced(&name) {
+            self.table(table_name)?
+        } else {
+            Table::with_schema(self.schema(table_name)?)
+        };
+        table.create_index(index)?;
+        self.alter_table(table_name, table.schema())
+    }
+
+    fn create_full_text_index(
+        &mut self,
+        table_name: &str,
+        index: FullTextIndex,
+    ) -> Result<(), String> {
+        let mut table = Table::with_schema(self.schema(table_name)?);
+        table.create_full_text_index(index)?;
+        self.alter_table(table_name, table.schema())
+    }
+
+    fn drop_index(&mut self, table_name: &str, name: &str) -> Result<(), String> {
+        let mut table = Table::with_schema(self.schema(table_name)?);
+        table.drop_index(name)?;
+        self.alter_table(table_name, table.schema())
+    }
+
+    fn set_statistics(
+        &mut self,
+        table_name: &str,
+        statistics: TableStatistics,
+    ) -> Result<(), String> {
+        let mut schema = self.schema(table_name)?;
+        schema.statistics = Some(statistics);
+        self.alter_table(table_name, schema)
+    }
+
+    // Checked in full before anything is written, like `Table::write_rows`
+    fn write_rows(
+        &mut self,
+        table_name: &str,
+        inserts: Vec<Vec<Value>>,
+        updates: Vec<(usize, Vec<Value>)>,
+    ) -> Result<Vec<usize>, String> {
+        let schema = self.schema(table_name)?;
+        for (row_id, _) in &updates {
+            if self.get_row(table_name, *row_id)?.is_none() {
+                return Err(format!("Row {} not found", row_id));
+            }
+        }
+        let next_row_id = self.next_row_id()?;
+        let row_ids: Vec<usize> = (next_row_id..next_row_id + inserts.len()).collect();
+        let rows = updates
+            .into_iter()
+            .chain(row_ids.iter().copied().zip(inserts))
+            .map(|(row_id, values)| Ok((row_id, schema.prepare_row(values)?)))
+            .collect::<Result<Vec<(usize, Vec<Value>)>, String>>()?;
+        engine::check_unique(self, table_name, &schema, &rows)?;
+
+        self.atomically(|database| {
+            if !row_ids.is_empty() {
+                database.set_next_row_id(next_row_id + row_ids.len());
+            }
+            let rows = rows
+                .into_iter()
+                .map(|(row_id, values)| (row_id, Some(Row { values })))
+                .collect();
+            database.set_rows(table_name, rows)?;
+            Ok(row_ids)
+        })
+    }
+
+    fn delete_rows(&mut self, table_name: &str, row_ids: &[usize]) -> Result<(), String> {
+        for row_id in row_ids {
+            if self.get_row(table_name, *row_id)?.is_none() {
+                return Err(format!("Row {} not found", row_id));
+            }
+        }
+        self.atomically(|database| {
+            let rows = row_ids.iter().map(|row_id| (*row_id, None)).collect();
+            database.set_rows(table_name, rows)
+        })
+    }
+
+    fn get_row(&self, table_name: &str, row_id: usize) -> Result<Option<Row>, String> {
+        self.schema(table_name)?;
+        let table = CaseInsensitiveString(table_name.to_string());
+        if let Some(row) = self
+            .pending
+            .rows
+            .get(&table)
+            .and_then(|rows| rows.get(&row_id))
+        {
+            return self.pending.load(row);
+        }
+        if self.replaced(&table) {
+            return Ok(None);
+        }
+        self.store.get_row(&table, row_id)
+    }
+
+    fn scan(&self, table_name: &str, range: RowRange) -> Result<RowIter<'_>, String> {
+        self.schema(table_name)?;
+        let table = CaseInsensitiveString(table_name.to_string());
+        let committed: RowIter<'_> = if self.replaced(&table) {
+            Box::new(std::iter::empty())
+        } else {
+            self.store.scan(&table, range)?
+        };
+        let pending: PendingRows<'_> = match self.pending.rows.get(&table) {
+            Some(rows) => Box::new(rows.range(range)),
+            None => Box::new(std::iter::empty()),
+        };
+        Ok(Box::new(Overlay {
+            committed: committed.peekable(),
+            pending: pending.peekable(),
+            rows: &self.pending,
+        }))
+    }
+
+    fn index_scan(
+        &self,
+        table_name: &str,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<RowIdIter<'_>, String> {
+        let schema = self.schema(table_name)?;
+        let table = CaseInsensitiveString(table_name.to_string());
+        let keys_in_range = |row: &Row| {
+            let mut keys = schema.index_keys(index, &row.values);
+            keys.retain(|key| range.contains(key));
+            keys
+        };
+
+        // The store doesn't have the indexes of a table created or altered since the last
+        // commit, and rows written since then have to be merged in by key. Otherwise the store's
+        // entries are passed on as they are read.
+        let mut entries = Vec::new();
+        if self.pending.tables.contains_key(&table) {
+            for row in self.scan(table_name, ALL_ROWS)? {
+                let (row_id, row) = row?;
+                for key in keys_in_range(&row) {
+                    entries.push((key, row_id));
+                }
+            }
+        } else {
+            let committed = self.store.index_scan(&table, index, range)?;
+            let Some(pending) = self.pending.rows.get(&table) else {
+                return Ok(committed);
+            };
+            let committed = committed
+                .filter(|row_id| !matches!(row_id, Ok(row_id) if pending.contains_key(row_id)));
+            let mut written: Vec<(Vec<Value>, usize)> = Vec::new();
+            for (row_id, row) in pending {
+                if let Some(row) = self.pending.load(row)? {
+                    written.extend(keys_in_range(&row).into_iter().map(|key| (key, *row_id)));
+                }
+            }
+            if written.is_empty() {
+                return Ok(Box::new(committed));
+            }
+            // A row is listed once per key it has in the range, but only needs reading once
+            let committed = committed.collect::<Result<BTreeSet<usize>, String>>()?;
+            for row_id in committed {
+                if let Some(row) = self.store.get_row(&table, row_id)? {
+                    for key in keys_in_range(&row) {
+                        entries.push((key, row_id));
+                    }
+                }
+            }
+            entries.extend(written);
+        }
+        entries.sort();
+        Ok(Box::new(entries.into_iter().map(|(_, row_id)| Ok(row_id))))
+    }
+
+    fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    fn begin(&mut self) -> Result<(), String> {
+        if self.transaction.is_some() {
+            return Err("A transaction is already in progress".to_string());
+        }
+        self.transaction = Some(Transaction::default());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        let transaction = self
+            .transaction
+            .take()
+            .ok_or_else(|| "No transaction is in progress".to_string())?;
+        if let Err(e) = self.write_pending() {
+            // Still open, so it can be committed again or rolled back
+            self.transaction = Some(transaction);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), String> {
+        let transaction = self
+            .transaction
+            .take()
+            .ok_or_else(|| "No transaction is in progress".to_string())?;
+        self.undo(transaction.log);
+        // Nothing was pending when the transaction began, so the spill file can go
+        self.pending = Pending::default();
+        Ok(())
+    }
+
+    fn savepoint(&mut self, name: &str) -> Result<(), String> {
+        self.transaction_mut()?.savepoint(name);
+        Ok(())
+    }
+
+    fn release_savepoint(&mut self, name: &str) -> Result<(), String> {
+        self.transaction_mut()?.release(name).map(|_| ())
+    }
+
+    fn rollback_to_savepoint(&mut self, name: &str) -> Result<(), String> {
+        let transaction = self.transaction_mut()?;
+        let position = transaction.release(name)?;
+        let undone = transaction.log.split_off(position);
+        transaction.savepoint(name);
+        self.undo(undone);
+        Ok(())
+    }
+
+    fn begin_statement(&mut self) {
+        let implicit = self.transaction.is_none();
+        let start = self
+            .transaction
+            .get_or_insert_with(Transaction::default)
+            .log
+            .len();
+        self.statements.push((implicit, start));
+    }
+
+    fn end_statement(&mut self, succeeded: bool) -> Result<(), String> {
+        let Some((implicit, start)) = self.statements.pop() else {
+            return Ok(());
+        };
+        if implicit {
+            let log = self.transaction.take().map(|t| t.log).unwrap_or_default();
+            let mut result = Ok(());
+            if succeeded {
+                result = self.write_pending();
+            }
+            if !succeeded || result.is_err() {
+                self.undo(log);
+                self.pending = Pending::default();
+            }
+            result
+        } else {
+            if !succeeded {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    let undone = transaction.log.split_off(start);
+                    self.undo(undone);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn checkpoint(&mut self) -> io::Result<()> {
+        if self.in_transaction() {
+            return Err(io::Error::other(
+                "Cannot checkpoint while a transaction is in progress",
+            ));
+        }
+        self.store.checkpoint()
+    }
+
+    fn export_json(&self, path: &Path) -> io::Result<()> {
+        let mut tables = BTreeMap::new();
+        for name in self.table_names().map_err(io::Error::other)? {
+            let table = self.table(&name).map_err(io::Error::other)?;
+            tables.insert(CaseInsensitiveString(name), table);
+        }
+        let next_row_id = self.next_row_id().map_err(io::Error::other)?;
+        Database::from_tables(tables, next_row_id).export_json(path)
+    }
+}
+
+type PendingRows<'a> = Box<dyn Iterator<Item = (&'a usize, &'a Written)> + 'a>;
+
+// Committed rows with the pending changes laid over them, both in row id order
+struct Overlay<'a> {
+    committed: Peekable<RowIter<'a>>,
+    pending: Peekable<PendingRows<'a>>,
+    // Where the pending rows are read from
+    rows: &'a Pending,
+}
+
+impl Iterator for Overlay<'_> {
+    type Item = Result<(usize, Row), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let committed = match self.committed.peek() {
+                Some(Ok((row_id, _))) => Some(*row_id),
+                Some(Err(_)) => return self.committed.next(),
+                None => None,
+            };
+            let Some(&(&row_id, _)) = self.pending.peek() else {
+                return self.committed.next();
+            };
+            match committed {
+                Some(committed) if committed < row_id => return self.committed.next(),
+                // Replaced or deleted by the pending change
+                Some(committed) if committed == row_id => {
+                    self.committed.next();
+                }
+                _ => {}
+            }
+            if let Some((row_id, written)) = self.pending.next() {
+                match self.rows.load(written) {
+                    Ok(Some(row)) => return Some(Ok((*row_id, row))),
+                    Ok(None) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1052 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::ops::Bound;
+use std::path::Path;
+
+use super::btree::{BTree, Cursor};
+use super::data_types::{CaseInsensitiveString, UniqueConstraint, Value};
+use super::engine::{RowIdIter, RowIter};
+use super::heap::{self, Heap, RecordId};
+use super::key_encoding::{self, KeyRange};
+use super::overlay::{CommittedStore, OverlayDatabase};
+use super::pager::{self, read_u32, write_u32, PageId, Pager, HEADER_PAGE, HEADER_SIZE};
+use super::table::{duplicate_key, IndexRange, Row, RowRange, TableSchema};
+use super::wal::Redo;
+
+// Page 0 holds, after the pager's header, the first page of the catalog and the next row id
+const CATALOG: usize = HEADER_SIZE;
+const NEXT_ROW_ID: usize = HEADER_SIZE + 4;
+
+// The longest key encoding an index entry holds in full. Longer ones are cut short, and scans
+// check the rows they lead to.
+const MAX_INDEXED_VALUE: usize = 512;
+
+// Where a table's pages are: its rows are records in a heap, a B+tree maps row ids to them, and
+// another holds the entries of all of its indexes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TableEntry {
+    schema: TableSchema,
+    heap: PageId,
+    rows: PageId,
+    index: PageId,
+}
+
+type Catalog = BTreeMap<CaseInsensitiveString, TableEntry>;
+
+// The committed state, kept in a page file behind a buffer pool of limited size, so that tables
+// don't have to fit in memory and opening a database only reads its catalog. The catalog is a
+// blob of pages listing every table's schema and pages, and is rewritten when tables change.
+//
+// Every commit is one pager transaction, so it is durable once the pages it changed are in the
+// pager's log, and is dropped as a whole if it fails halfway.
+#[derive(Debug)]
+pub struct PageStore {
+    // Reads go through the buffer pool too, which changes as pages come and go
+    pager: RefCell<Pager>,
+    catalog: Catalog,
+}
+
+// A database kept in a page file rather than in memory
+pub type PagedDatabase = OverlayDatabase<PageStore>;
+
+impl PagedDatabase {
+    // Opens the page file at `path`, creating it if needed. The buffer pool and the rows written
+    // since the last commit each hold about half of `memory_budget` bytes.
+    pub fn open(path: &Path, memory_budget: usize) -> io::Result<Self> {
+        let pending = memory_budget / 2;
+        Ok(OverlayDatabase::new(
+            PageStore::open(path, memory_budget - pending)?,
+            pending,
+        ))
+    }
+}
+
+impl PageStore {
+    fn open(path: &Path, memory_budget: usize) -> io::Result<PageStore> {
+        let mut pager = Pager::open(path, memory_budget)?;
+        if pager.read(HEADER_PAGE, |page| read_u32(page, CATALOG))? == 0 {
+            let catalog = heap::write_blob(&mut pager, &encode(&Catalog::new())?)?;
+            pager.write(HEADER_PAGE, |page| write_u32(page, CATALOG, catalog))?;
+            pager.commit()?;
+        }
+        let catalog = load_catalog(&mut pager)?;
+        Ok(PageStore {
+            pager: RefCell::new(pager),
+            catalog,
+        })
+    }
+
+    fn apply(&mut self, change: &Redo) -> io::Result<()> {
+        match change {
+            Redo::Table { name, table } => {
+                self.drop_table(name)?;
+                if let Some(table) = table {
+                    let pager = self.pager.get_mut();
+                    let schema = table.schema();
+                    let heap = Heap::create(pager)?;
+                    let mut locations = Vec::new();
+                    let mut index_entries = Vec::new();
+                    for (row_id, row) in table.scan() {
+                        let id = heap.insert(pager, &encode(row)?)?;
+                        locations.push((row_key(row_id).to_vec(), location(id).to_vec()));
+                        for (index, key) in schema.index_entries(&row.values) {
+                            index_entries.push((index_key(&index, &key, row_id), Vec::new()));
+                        }
+                    }
+                    // Both trees are built bottom up from sorted entries
+                    index_entries.sort_unstable();
+                    let entry = TableEntry {
+                        schema,
+                        heap: heap.first,
+                        rows: BTree::bulk_load(pager, locations)?.root,
+                        index: BTree::bulk_load(pager, index_entries)?.root,
+                    };
+                    self.catalog.insert(name.clone(), entry);
+                }
+                self.save_catalog()?;
+            }
+            Redo::Rows { table, rows } => {
+                let entry = self.catalog.get(table).ok_or_else(|| {
+                    pager::corrupt(&format!("table '{}' is not in the catalog", table.0))
+                })?;
+                for (row_id, row) in rows {
+                    set_row(self.pager.get_mut(), entry, *row_id, row.as_ref())?;
+                }
+            }
+            Redo::NextRowId(next_row_id) => {
+                self.pager.get_mut().write(HEADER_PAGE, |page| {
+                    page[NEXT_ROW_ID..NEXT_ROW_ID + 8]
+                        .copy_from_slice(&(*next_row_id as u64).to_le_bytes())
+                })?;
+            }
+            // Only the catalog changes unless the indexes did
+            Redo::Schema { name, schema } => {
+                let entry = self.catalog.get_mut(name).ok_or_else(|| {
+                    pager::corrupt(&format!("table '{}' is not in the catalog", name.0))
+                })?;
+                if !entry.schema.same_indexes(schema) {
+                    reindex(self.pager.get_mut(), entry, schema)?;
+                }
+                entry.schema = schema.clone();
+                self.save_catalog()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Frees the table's pages
+    fn drop_table(&mut self, name: &CaseInsensitiveString) -> io::Result<()> {
+        if let Some(entry) = self.catalog.remove(name) {
+            let pager = self.pager.get_mut();
+            Heap { first: entry.heap }.destroy(pager)?;
+            BTree { root: entry.rows }.destroy(pager)?;
+            BTree { root: entry.index }.destroy(pager)?;
+        }
+        Ok(())
+    }
+
+    fn save_catalog(&mut self) -> io::Result<()> {
+        let pager = self.pager.get_mut();
+        let old = pager.read(HEADER_PAGE, |page| read_u32(page, CATALOG))?;
+        heap::free_blob(pager, old)?;
+        let catalog = heap::write_blob(pager, &encode(&self.catalog)?)?;
+        pager.write(HEADER_PAGE, |page| write_u32(page, CATALOG, catalog))
+    }
+
+    fn read_row(&self, entry: &TableEntry, row_id: usize) -> io::Result<Option<Row>> {
+        load_row(&mut self.pager.borrow_mut(), entry, row_id)
+    }
+}
+
+impl CommittedStore for PageStore {
+    fn table_names(&self) -> Result<Vec<CaseInsensitiveString>, String> {
+        Ok(self.catalog.keys().cloned().collect())
+    }
+
+    fn schema(&self, table: &CaseInsensitiveString) -> Result<Option<TableSchema>, String> {
+        Ok(self.catalog.get(table).map(|entry| entry.schema.clone()))
+    }
+
+    fn next_row_id(&self) -> Result<usize, String> {
+        let next_row_id = self.pager.borrow_mut().read(HEADER_PAGE, |page| {
+            u64::from_le_bytes(page[NEXT_ROW_ID..NEXT_ROW_ID + 8].try_into().unwrap())
+        });
+        next_row_id.map(|n| n as usize).map_err(page_error)
+    }
+
+    fn get_row(&self, table: &CaseInsensitiveString, row_id: usize) -> Result<Option<Row>, String> {
+        match self.catalog.get(table) {
+            Some(entry) => self.read_row(entry, row_id).map_err(page_error),
+            None => Ok(None),
+        }
+    }
+
+    fn scan(&self, table: &CaseInsensitiveString, range: RowRange) -> Result<RowIter<'_>, String> {
+        let Some(entry) = self.catalog.get(table) else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let start = range.0.map(row_key);
+        let cursor = BTree { root: entry.rows }
+            .seek(
+                &mut self.pager.borrow_mut(),
+                start.as_ref().map(|key| key.as_slice()),
+            )
+            .map_err(page_error)?;
+        Ok(Box::new(RowScan {
+            store: self,
+            heap: Heap { first: entry.heap },
+            cursor,
+            end: range.1,
+        }))
+    }
+
+    // Reads the index entries in the range, which follow each other in the index tree, as they
+    // are asked for. See `IndexScan` for entries whose key was cut short.
+    fn index_scan(
+        &self,
+        table: &CaseInsensitiveString,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<RowIdIter<'_>, String> {
+        let Some(entry) = self.catalog.get(table) else {
+            return Ok(Box::new(std::iter::empty()));
+        };
+        let keys = KeyRange::new(index, range, MAX_INDEXED_VALUE);
+        let cursor = BTree { root: entry.index }
+            .seek(&mut self.pager.borrow_mut(), Bound::Included(keys.start()))
+            .map_err(page_error)?;
+        Ok(Box::new(IndexScan {
+            store: self,
+            entry,
+            index: index.to_string(),
+            range: range.clone(),
+            keys,
+            cursor: Some(cursor),
+            next: None,
+            checked: VecDeque::new(),
+        }))
+    }
+
+    // Each change is applied as it is read, and the pager spills the pages of a transaction that
+    // outgrows the pool
+    fn commit(
+        &mut self,
+        changes: &mut dyn Iterator<Item = Result<Redo, String>>,
+    ) -> Result<(), String> {
+        let apply = || {
+            for change in changes {
+                self.apply(&change?).map_err(page_error)?;
+            }
+            self.pager.get_mut().commit().map_err(page_error)
+        };
+        if let Err(e) = apply() {
+            let pager = self.pager.get_mut();
+            pager.discard().map_err(page_error)?;
+            // The catalog may have changed along with the discarded pages
+            self.catalog = load_catalog(pager).map_err(page_error)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> io::Result<()> {
+        self.pager.get_mut().checkpoint()
+    }
+}
+
+// The rows of a table in row id order, read from the pager as the scan goes
+struct RowScan<'a> {
+    store: &'a PageStore,
+    heap: Heap,
+    cursor: Cursor,
+    end: Bound<usize>,
+}
+
+impl RowScan<'_> {
+    fn next_row(&mut self) -> io::Result<Option<(usize, Row)>> {
+        let pager = &mut self.store.pager.borrow_mut();
+        let Some((key, location)) = self.cursor.next(pager)? else {
+            return Ok(None);
+        };
+        let row_id = u64::from_be_bytes(
+            key.try_into()
+                .map_err(|_| pager::corrupt("damaged row id"))?,
+        ) as usize;
+        let past_end = match self.end {
+            Bound::Included(end) => row_id > end,
+            Bound::Excluded(end) => row_id >= end,
+            Bound::Unbounded => false,
+        };
+        if past_end {
+            return Ok(None);
+        }
+        let record = self.heap.get(pager, record_id(&location)?)?;
+        Ok(Some((row_id, decode(&record)?)))
+    }
+}
+
+impl Iterator for RowScan<'_> {
+    type Item = Result<(usize, Row), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row().map_err(page_error).transpose()
+    }
+}
+
+// The ids behind the entries of an index in a range, read from the pager as the scan goes.
+// Entries whose key was cut short may be out of order and outside the range, so the rows of each
+// run of them are read to check and sort them.
+struct IndexScan<'a> {
+    store: &'a PageStore,
+    entry: &'a TableEntry,
+    index: String,
+    range: IndexRange,
+    keys: KeyRange,
+    // None once past the range
+    cursor: Option<Cursor>,
+    // An entry read ahead while looking for the end of a run
+    next: Option<(usize, Option<Vec<u8>>)>,
+    // The checked ids of the run being handed on
+    checked: VecDeque<usize>,
+}
+
+impl IndexScan<'_> {
+    // The next entry in the range: its row id, and its key if it was cut short
+    fn next_entry(&mut self) -> io::Result<Option<(usize, Option<Vec<u8>>)>> {
+        if let Some(entry) = self.next.take() {
+            return Ok(Some(entry));
+        }
+        let Some(cursor) = &mut self.cursor else {
+            return Ok(None);
+        };
+        let pager = &mut self.store.pager.borrow_mut();
+        while let Some((key, _)) = cursor.next(pager)? {
+            match self.keys.locate(&key) {
+                Ordering::Less => continue,
+                Ordering::Equal => {
+                    let cut_short = key.len() - self.index.len() - 9 >= MAX_INDEXED_VALUE;
+                    return Ok(Some((trailing_row_id(&key), cut_short.then_some(key))));
+                }
+                Ordering::Greater => break,
+            }
+        }
+        self.cursor = None;
+        Ok(None)
+    }
+
+    fn next_row_id(&mut self) -> io::Result<Option<usize>> {
+        if let Some(row_id) = self.checked.pop_front() {
+            return Ok(Some(row_id));
+        }
+        let Some((row_id, cut_short)) = self.next_entry()? else {
+            return Ok(None);
+        };
+        let Some(key) = cut_short else {
+            return Ok(Some(row_id));
+        };
+        let mut run = BTreeSet::from([key]);
+        while let Some(entry) = self.next_entry()? {
+            match entry {
+                (_, Some(key)) => {
+                    run.insert(key);
+                }
+                entry => {
+                    self.next = Some(entry);
+                    break;
+                }
+            }
+        }
+        // A row can have more than one key in an index, so only the ones behind the entries of
+        // the run count
+        let mut checked = Vec::new();
+        let run_rows: BTreeSet<usize> = run.iter().map(|key| trailing_row_id(key)).collect();
+        for row_id in run_rows {
+            let Some(row) = self.store.read_row(self.entry, row_id)? else {
+                continue;
+            };
+            for key in self.entry.schema.index_keys(&self.index, &row.values) {
+                if self.range.contains(&key) && run.contains(&index_key(&self.index, &key, row_id))
+                {
+                    checked.push((key, row_id));
+                }
+            }
+        }
+        checked.sort();
+        self.checked = checked.into_iter().map(|(_, row_id)| row_id).collect();
+        Ok(self.checked.pop_front())
+    }
+}
+
+impl Iterator for IndexScan<'_> {
+    type Item = Result<usize, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row_id().map_err(page_error).transpose()
+    }
+}
+
+// Inserts, replaces or deletes a row in the table's heap and B+trees
+fn set_row(
+    pager: &mut Pager,
+    entry: &TableEntry,
+    row_id: usize,
+    row: Option<&Row>,
+) -> io::Result<()> {
+    let heap = Heap { first: entry.heap };
+    let rows = BTree { root: entry.rows };
+    let index = BTree { root: entry.index };
+    let key = row_key(row_id);
+    let stored = match rows.get(pager, &key)? {
+        Some(location) => Some(record_id(&location)?),
+        None => None,
+    };
+
+    // Only the index entries that change are touched
+    let old_entries = match stored {
+        Some(id) => index_keys(&entry.schema, row_id, &decode(&heap.get(pager, id)?)?),
+        None => BTreeSet::new(),
+    };
+    let new_entries = match row {
+        Some(row) => index_keys(&entry.schema, row_id, row),
+        None => BTreeSet::new(),
+    };
+    for old in old_entries.difference(&new_entries) {
+        index.delete(pager, old)?;
+    }
+    for new in new_entries.difference(&old_entries) {
+        index.insert(pager, new, &[])?;
+    }
+
+    match (stored, row) {
+        (Some(id), Some(row)) => {
+            let moved = heap.update(pager, id, &encode(row)?)?;
+            if moved != id {
+                rows.insert(pager, &key, &location(moved))?;
+            }
+        }
+        (None, Some(row)) => {
+            let id = heap.insert(pager, &encode(row)?)?;
+            rows.insert(pager, &key, &location(id))?;
+        }
+        (Some(id), None) => {
+            heap.delete(pager, id)?;
+            rows.delete(pager, &key)?;
+        }
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+// Brings the index entries of a table in line with a new schema, reading its rows one at a time.
+// A new UNIQUE index is checked as it is built: a row whose key some other row's entry already
+// has is a duplicate, once the other row is read to make sure.
+fn reindex(pager: &mut Pager, entry: &TableEntry, schema: &TableSchema) -> io::Result<()> {
+    let heap = Heap { first: entry.heap };
+    let index = BTree { root: entry.index };
+    let unique: Vec<(&str, UniqueConstraint)> = schema
+        .column_indexes
+        .iter()
+        .filter(|created| !entry.schema.column_indexes.contains(created))
+        .filter_map(|created| Some((created.name.as_str(), created.constraint()?)))
+        .collect();
+    let mut rows = BTree { root: entry.rows }.seek(pager, Bound::Unbounded)?;
+    while let Some((key, location)) = rows.next(pager)? {
+        let row_id = trailing_row_id(&key);
+        let row: Row = decode(&heap.get(pager, record_id(&location)?)?)?;
+        for (name, constraint) in &unique {
+            let Some(key) = schema.unique_key(constraint, &row.values) else {
+                continue;
+            };
+            let (prefix, _) = key_encoding::index_prefix(name, &key, MAX_INDEXED_VALUE);
+            let mut entries = index.seek(pager, Bound::Included(&prefix))?;
+            while let Some((entry_key, _)) = entries.next(pager)? {
+                if !entry_key.starts_with(&prefix) {
+                    break;
+                }
+                let other = trailing_row_id(&entry_key);
+                if other == row_id {
+                    continue;
+                }
+                let Some(other) = load_row(pager, entry, other)? else {
+                    continue;
+                };
+                if schema.unique_key(constraint, &other.values).as_ref() == Some(&key) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        duplicate_key(&key, constraint),
+                    ));
+                }
+            }
+        }
+        let old_entries = index_keys(&entry.schema, row_id, &row);
+        let new_entries = index_keys(schema, row_id, &row);
+        for old in old_entries.difference(&new_entries) {
+            index.delete(pager, old)?;
+        }
+        for new in new_entries.difference(&old_entries) {
+            index.insert(pager, new, &[])?;
+        }
+    }
+    Ok(())
+}
+
+fn load_row(pager: &mut Pager, entry: &TableEntry, row_id: usize) -> io::Result<Option<Row>> {
+    let rows = BTree { root: entry.rows };
+    match rows.get(pager, &row_key(row_id))? {
+        Some(location) => {
+            let record = Heap { first: entry.heap }.get(pager, record_id(&location)?)?;
+            decode(&record).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+fn index_keys(schema: &TableSchema, row_id: usize, row: &Row) -> BTreeSet<Vec<u8>> {
+    schema
+        .index_entries(&row.values)
+        .iter()
+        .map(|(index, key)| index_key(index, key, row_id))
+        .collect()
+}
+
+// The index name, a zero byte, the key's sort encoding, cut short if it is too long, and the row
+// id, so that the entries of an index are in key order, and in row id order for equal keys
+fn index_key(index: &str, key: &[Value], row_id: usize) -> Vec<u8> {
+    let (mut entry, _) = key_encoding::index_prefix(index, key, MAX_INDEXED_VALUE);
+    entry.extend(row_key(row_id));
+    entry
+}
+
+// The row id an index key ends with
+fn trailing_row_id(key: &[u8]) -> usize {
+    let bytes: [u8; 8] = key[key.len() - 8..]
+        .try_into()
+        .expect("index keys end with a row id");
+    u64::from_be_bytes(bytes) as usize
+}
+
+fn load_catalog(pager: &mut Pager) -> io::Result<Catalog> {
+    let first = pager.read(HEADER_PAGE, |page| read_u32(page, CATALOG))?;
+    decode(&heap::read_blob(pager, first)?)
+}
+
+// Big endian, so that the B+tree keeps row ids in order
+fn row_key(row_id: usize) -> [u8; 8] {
+    (row_id as u64).to_be_bytes()
+}
+
+fn location(id: RecordId) -> [u8; 6] {
+    let mut location = [0; 6];
+    location[..4].copy_from_slice(&id.page_id.to_le_bytes());
+    location[4..].copy_from_slice(&id.slot.to_le_bytes());
+    location
+}
+
+fn record_id(location: &[u8]) -> io::Result<RecordId> {
+    if location.len() != 6 {
+        return Err(pager::corrupt("damaged row location"));
+    }
+    Ok(RecordId {
+        page_id: read_u32(location, 0),
+        slot: pager::read_u16(location, 4),
+    })
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(value).map_err(io::Error::other)
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    bincode::deserialize(bytes)
+        .map_err(|e| pager::corrupt(&format!("stored data is corrupt ({})", e)))
+}
+
+// Errors about the data itself already say where they come from
+fn page_error(e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => e.to_string(),
+        _ => format!("Pager: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::conformance;
+    use crate::db::data_types::{Column, DataType, UniqueConstraint};
+    use crate::db::engine::StorageEngine;
+    use crate::db::fault;
+    use crate::db::pager::{DEFAULT_MEMORY_BUDGET, PAGE_SIZE};
+    use crate::db::statistics::TableStatistics;
+    use crate::db::table::{ColumnIndex, Table, ALL_ROWS};
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn users(path: &Path) -> PagedDatabase {
+        let mut database = PagedDatabase::open(path, DEFAULT_MEMORY_BUDGET).unwrap();
+        database
+            .create_table(
+                "users".to_string(),
+                vec![
+                    Column::new("id".to_string(), DataType::Integer),
+                    Column::new("bio".to_string(), DataType::Text),
+                ],
+                vec![UniqueConstraint {
+                    columns: vec!["id".to_string()],
+                    primary_key: true,
+                }],
+            )
+            .unwrap();
+        database
+    }
+
+    fn row(id: i64, bio: &str) -> Vec<Value> {
+        vec![Value::Integer(id), Value::Text(bio.to_string())]
+    }
+
+    fn table(database: &PagedDatabase, name: &str) -> Table {
+        database.table(name).unwrap()
+    }
+
+    #[test]
+    fn test_conformance() {
+        let path = temp_dir("conformance-paged");
+        let opened = Cell::new(0);
+        // With no memory to spare, every row written is spilled until it is committed
+        for memory_budget in [DEFAULT_MEMORY_BUDGET, 0] {
+            conformance::run(&|| {
+                opened.set(opened.get() + 1);
+                let file = path.join(format!("{}.pages", opened.get()));
+                Box::new(PagedDatabase::open(&file, memory_budget).unwrap())
+            });
+        }
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reopens_with_committed_changes() {
+        let dir = temp_dir("reopen-paged");
+        let path = dir.join("users.pages");
+        let mut database = users(&path);
+        let long_bio = "x".repeat(3 * PAGE_SIZE);
+        let ids = database
+            .write_rows(
+                "users",
+                vec![row(1, "short"), row(2, &long_bio), row(3, "")],
+                vec![],
+            )
+            .unwrap();
+        // Grows past its page, and shrinks out of its overflow pages
+        database
+            .write_rows(
+                "users",
+                vec![],
+                vec![(ids[0], row(1, &long_bio)), (ids[1], row(2, "short"))],
+            )
+            .unwrap();
+        database.delete_rows("users", &[ids[2]]).unwrap();
+        database
+            .create_table(
+                "log".to_string(),
+                vec![Column::new("line".to_string(), DataType::Text)],
+                vec![],
+            )
+            .unwrap();
+        database
+            .write_rows("log", vec![vec![Value::Text("hello".to_string())]], vec![])
+            .unwrap();
+        database.checkpoint().unwrap();
+        database
+            .write_rows("users", vec![row(4, "")], vec![])
+            .unwrap();
+        let users_table = table(&database, "users");
+        let log_table = table(&database, "log");
+
+        // Left open, so never committed
+        database.begin().unwrap();
+        database
+            .write_rows("users", vec![row(5, "")], vec![])
+            .unwrap();
+        database.drop_table("log").unwrap();
+        // Closed without a checkpoint, so the last commit is only in the pager's log
+        drop(database);
+
+        let mut database = PagedDatabase::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        assert_eq!(table(&database, "users"), users_table);
+        assert_eq!(table(&database, "log"), log_table);
+        assert_eq!(
+            database
+                .index_lookup("users", "id", &[Value::Integer(4)])
+                .unwrap(),
+            vec![4]
+        );
+        // Row ids carry on where the committed ones stopped
+        assert_eq!(
+            database
+                .write_rows("log", vec![vec![Value::Null]], vec![])
+                .unwrap(),
+            vec![5]
+        );
+        drop(database);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_large_transactions_spill_pending_rows() {
+        let dir = temp_dir("spill-paged");
+        let path = dir.join("users.pages");
+        let budget = 32 * PAGE_SIZE;
+        let mut database = users(&path);
+        database.checkpoint().unwrap();
+        drop(database);
+        let mut database = PagedDatabase::open(&path, budget).unwrap();
+        let bio = |n: i64| format!("bio {:0>500}", n);
+
+        database.begin().unwrap();
+        let ids = database
+            .write_rows("users", (0..400).map(|n| row(n, &bio(n))).collect(), vec![])
+            .unwrap();
+        database.savepoint("half").unwrap();
+        let updates = ids[..200]
+            .iter()
+            .map(|id| (*id, row(*id as i64, "changed")))
+            .collect();
+        database.write_rows("users", vec![], updates).unwrap();
+        database.delete_rows("users", &ids[200..300]).unwrap();
+        assert!(database.held() <= budget, "{}", database.held());
+        assert_eq!(
+            database.get_row("users", ids[7]).unwrap().unwrap().values,
+            row(7, "changed")
+        );
+        assert_eq!(database.scan("users", ALL_ROWS).unwrap().count(), 300);
+        database.rollback_to_savepoint("half").unwrap();
+        assert_eq!(
+            database
+                .index_lookup("users", "id", &[Value::Integer(234)])
+                .unwrap(),
+            vec![ids[234]]
+        );
+        database.commit().unwrap();
+        assert_eq!(database.held(), 0);
+        drop(database);
+
+        let database = PagedDatabase::open(&path, budget).unwrap();
+        let rows: Vec<Vec<Value>> = database
+            .scan("users", ALL_ROWS)
+            .unwrap()
+            .map(|row| row.unwrap().1.values)
+            .collect();
+        assert_eq!(rows, (0..400).map(|n| row(n, &bio(n))).collect::<Vec<_>>());
+        drop(database);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_commit_changes_nothing() {
+        let dir = temp_dir("failed-paged");
+        let path = dir.join("users.pages");
+        let mut database = users(&path);
+        database
+            .write_rows("users", vec![row(1, "")], vec![])
+            .unwrap();
+        let before = table(&database, "users");
+
+        fault::fail_after(0);
+        assert!(database
+            .write_rows("users", vec![row(2, "")], vec![(0, row(1, "changed"))])
+            .is_err());
+        assert_eq!(table(&database, "users"), before);
+        // The store carries on from the last commit
+        database
+            .write_rows("users", vec![row(3, "")], vec![])
+            .unwrap();
+        drop(database);
+
+        let database = PagedDatabase::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let ids: Vec<usize> = database
+            .scan("users", ALL_ROWS)
+            .unwrap()
+            .map(|row| row.unwrap().0)
+            .collect();
+        assert_eq!(ids, vec![0, 1]);
+        drop(database);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unique_indexes_are_checked_on_commit() {
+        let dir = temp_dir("unique-paged");
+        let mut database = users(&dir.join("users.pages"));
+        database
+            .write_rows("users", vec![row(1, "a"), row(2, "b")], vec![])
+            .unwrap();
+        let before = table(&database, "users");
+
+        database.begin().unwrap();
+        database
+            .write_rows("users", vec![row(3, "a")], vec![])
+            .unwrap();
+        let index = ColumnIndex {
+            name: "users_bio".to_string(),
+            columns: vec!["bio".to_string()],
+            unique: true,
+            predicate: None,
+        };
+        database.create_index("users", index).unwrap();
+        assert_eq!(
+            database.commit().unwrap_err(),
+            "Duplicate key (a) violates UNIQUE (bio)"
+        );
+        database.rollback().unwrap();
+        assert_eq!(table(&database, "users"), before);
+        drop(database);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_schema_changes_keep_the_rows_where_they_are() {
+        let dir = temp_dir("schema-paged");
+        let mut store = PageStore::open(&dir.join("big.pages"), 8 * PAGE_SIZE).unwrap();
+        let name = CaseInsensitiveString("big".to_string());
+        let mut table = Table::new(vec![
+            Column::new("n".to_string(), DataType::Integer),
+            Column::new("text".to_string(), DataType::Text),
+        ]);
+        table
+            .insert_rows(
+                (0..2000)
+                    .map(|n| (n, vec![Value::Integer(n as i64 % 500), long_text(n)]))
+                    .collect(),
+            )
+            .unwrap();
+        let schema = table.schema();
+        store
+            .commit(
+                &mut [Redo::Table {
+                    name: name.clone(),
+                    table: Some(table),
+                }]
+                .into_iter()
+                .map(Ok),
+            )
+            .unwrap();
+        store.checkpoint().unwrap();
+        let accesses = |store: &PageStore| {
+            let stats = store.pager.borrow().stats();
+            stats.hits + stats.misses
+        };
+        let change = |store: &mut PageStore, schema: &TableSchema| {
+            store.commit(
+                &mut [Redo::Schema {
+                    name: name.clone(),
+                    schema: schema.clone(),
+                }]
+                .into_iter()
+                .map(Ok),
+            )
+        };
+        let scan = |store: &PageStore, n: i64| {
+            store
+                .index_scan(&name, "big_n", &IndexRange::prefix(vec![Value::Integer(n)]))
+                .unwrap()
+                .collect::<Result<Vec<usize>, String>>()
+                .unwrap()
+        };
+
+        // New statistics only change the catalog
+        let mut analyzed = schema.clone();
+        analyzed.statistics = Some(TableStatistics {
+            rows: 2000,
+            columns: vec![],
+        });
+        let before = accesses(&store);
+        change(&mut store, &analyzed).unwrap();
+        assert!(accesses(&store) - before < 20);
+        assert_eq!(store.schema(&name).unwrap(), Some(analyzed.clone()));
+
+        // New indexes are built a row at a time within the pool, and UNIQUE ones checked as they
+        // are
+        let mut indexed = analyzed.clone();
+        indexed.column_indexes.push(ColumnIndex {
+            name: "big_n".to_string(),
+            columns: vec!["n".to_string()],
+            unique: true,
+            predicate: None,
+        });
+        assert_eq!(
+            change(&mut store, &indexed).unwrap_err(),
+            "Duplicate key (0) violates UNIQUE (n)"
+        );
+        assert_eq!(store.schema(&name).unwrap(), Some(analyzed.clone()));
+        indexed.column_indexes[0].unique = false;
+        change(&mut store, &indexed).unwrap();
+        assert!(store.pager.borrow().resident() <= 8);
+        assert_eq!(scan(&store, 7), vec![7, 507, 1007, 1507]);
+
+        change(&mut store, &analyzed).unwrap();
+        assert!(scan(&store, 7).is_empty());
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn long_text(n: usize) -> Value {
+        Value::Text(format!("row {:0>100}", n))
+    }
+
+    #[test]
+    fn test_scans_tables_larger_than_the_pool() {
+        let dir = temp_dir("budget-paged");
+        let mut store = PageStore::open(&dir.join("big.pages"), 8 * PAGE_SIZE).unwrap();
+        let name = CaseInsensitiveString("big".to_string());
+        let mut table = Table::new(vec![Column::new("text".to_string(), DataType::Text)]);
+        table
+            .insert_rows(
+                (0..2000)
+                    .map(|n| (n, vec![Value::Text(format!("row {:0>100}", n))]))
+                    .collect(),
+            )
+            .unwrap();
+        store
+            .commit(
+                &mut [Redo::Table {
+                    name: name.clone(),
+                    table: Some(table),
+                }]
+                .into_iter()
+                .map(Ok),
+            )
+            .unwrap();
+        store.checkpoint().unwrap();
+
+        let rows: Vec<(usize, Row)> = store
+            .scan(&name, (Bound::Included(100), Bound::Excluded(1900)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 1800);
+        assert_eq!(
+            rows[0].1.values,
+            vec![Value::Text(format!("row {:0>100}", 100))]
+        );
+        let pager = store.pager.borrow();
+        assert!(pager.resident() <= 8);
+        assert!(pager.stats().evictions > 0);
+        drop(pager);
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_lookups_read_only_the_index() {
+        let dir = temp_dir("index-paged");
+        let mut store = PageStore::open(&dir.join("big.pages"), 8 * PAGE_SIZE).unwrap();
+        let name = CaseInsensitiveString("big".to_string());
+        let mut table = Table::new(vec![
+            Column::new("n".to_string(), DataType::Integer),
+            Column::new("text".to_string(), DataType::Text),
+        ]);
+        let long = |n: usize| Value::Text(format!("{}{}", "x".repeat(600), n));
+        table
+            .insert_rows(
+                (0..2000)
+                    .map(|n| (n, vec![Value::Integer(n as i64 % 500), long(n % 3)]))
+                    .collect(),
+            )
+            .unwrap();
+        for column in ["n", "text"] {
+            table
+                .create_index(ColumnIndex {
+                    name: format!("big_{}", column),
+                    columns: vec![column.to_string()],
+                    unique: false,
+                    predicate: None,
+                })
+                .unwrap();
+        }
+        store
+            .commit(
+                &mut [Redo::Table {
+                    name: name.clone(),
+                    table: Some(table),
+                }]
+                .into_iter()
+                .map(Ok),
+            )
+            .unwrap();
+        store
+            .commit(
+                &mut [Redo::Rows {
+                    table: name.clone(),
+                    rows: vec![
+                        (7, None),
+                        (
+                            1507,
+                            Some(Row {
+                                values: vec![Value::Integer(8), long(0)],
+                            }),
+                        ),
+                    ],
+                }]
+                .into_iter()
+                .map(Ok),
+            )
+            .unwrap();
+        store.checkpoint().unwrap();
+
+        let scan = |index: &str, range: IndexRange| {
+            store
+                .index_scan(&name, index, &range)
+                .unwrap()
+                .collect::<Result<Vec<usize>, String>>()
+                .unwrap()
+        };
+        let misses = store.pager.borrow().stats().misses;
+        assert_eq!(
+            scan("big_n", IndexRange::prefix(vec![Value::Integer(7)])),
+            vec![507, 1007]
+        );
+        assert!(store.pager.borrow().stats().misses - misses < 10);
+        assert_eq!(
+            scan("big_n", IndexRange::prefix(vec![Value::Integer(8)])),
+            vec![8, 508, 1008, 1507, 1508]
+        );
+        // Cut short in the index, so told apart by their rows
+        let matching = scan("big_text", IndexRange::prefix(vec![long(1)]));
+        assert_eq!(matching.len(), 665);
+        assert!(matching
+            .iter()
+            .all(|row_id| row_id % 3 == 1 && *row_id != 7));
+
+        // Entries are read as they are asked for, so taking a few reads a few pages
+        let accesses = || {
+            let stats = store.pager.borrow().stats();
+            stats.hits + stats.misses
+        };
+        let before = accesses();
+        let first: Vec<usize> = store
+            .index_scan(&name, "big_n", &IndexRange::all())
+            .unwrap()
+            .take(3)
+            .collect::<Result<_, String>>()
+            .unwrap();
+        assert_eq!(first, vec![0, 500, 1000]);
+        let few = accesses() - before;
+        let before = accesses();
+        assert_eq!(scan("big_n", IndexRange::all()).len(), 1999);
+        let all = accesses() - before;
+        assert!(few * 3 < all, "{} of {}", few, all);
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,684 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::fault;
+use super::wal::Wal;
+
+pub const PAGE_SIZE: usize = 4096;
+// Used when no memory budget is given
+pub const DEFAULT_MEMORY_BUDGET: usize = 16 * 1024 * 1024;
+// The pool keeps at least this many pages, whatever the budget
+const MIN_FRAMES: usize = 4;
+// Committed pages are written back and the log emptied once it grows past this
+const WAL_LIMIT: u64 = 8 * 1024 * 1024;
+
+pub type PageId = u32;
+pub type Page = [u8; PAGE_SIZE];
+
+const MAGIC: &[u8; 8] = b"BANDITPG";
+const VERSION: u32 = 1;
+// Page 0 starts with the pager's header: the magic bytes, the format version, the number of pages
+// and the first page of the free list. The rest of the page, from `HEADER_SIZE` on, is free for
+// the layers above.
+pub const HEADER_PAGE: PageId = 0;
+pub const HEADER_SIZE: usize = 20;
+const PAGE_COUNT: usize = 12;
+const FREE_LIST: usize = 16;
+
+// A page as it was when a transaction committed, the record type of the pager's log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageImage {
+    page_id: PageId,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    // The same as in the file
+    Clean,
+    // Changed by a committed transaction: in the log, but not yet in the file
+    Logged,
+    // Changed by the transaction in progress, so it can't be written to the file without first
+    // journalling what the file holds, see `Pager::spill`
+    Changed,
+}
+
+#[derive(Debug)]
+struct Frame {
+    page_id: PageId,
+    data: Box<Page>,
+    state: State,
+    // Set on every access and cleared by the clock hand, which evicts frames it finds unset
+    referenced: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    // Pages written back to the file
+    pub writes: u64,
+}
+
+// A file of fixed size pages with a buffer pool in front of it. Pages are read into the pool when
+// first used and stay there until the clock hand evicts them to stay within the memory budget.
+//
+// Changes are grouped into transactions: `commit` appends an image of every page changed since
+// the last commit to the log and waits until it is on disk, while `discard` drops the changed
+// pages so that they are read again as they were. Committed pages reach the file later, when
+// they are evicted or at a checkpoint, and whatever is only in the log after a crash is copied to
+// the file when it is opened again.
+//
+// A transaction that changes more pages than the pool holds writes them to the file before it
+// commits, keeping what the file held for them in a journal next to it. Discarding the
+// transaction, or opening the file after a crash before its commit, puts those pages back.
+#[derive(Debug)]
+pub struct Pager {
+    file: File,
+    wal: Wal<PageImage>,
+    journal: Wal<PageImage>,
+    // The pages the transaction in progress wrote to the file, whose earlier images are in the
+    // journal
+    spilled: HashSet<PageId>,
+    frames: Vec<Frame>,
+    // Where each page in the pool is
+    pages: HashMap<PageId, usize>,
+    hand: usize,
+    capacity: usize,
+    stats: PoolStats,
+}
+
+impl Pager {
+    // Opens or creates the page file at `path`, with a pool of about `memory_budget` bytes
+    pub fn open(path: &Path, memory_budget: usize) -> io::Result<Pager> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let (wal, records) = Wal::open(&Wal::path_for(path))?;
+        // A journal is left behind by a transaction that spilled pages and then either didn't
+        // commit, leaving the log empty, or did but wasn't done emptying the journal
+        let mut restored = false;
+        let mut journal = Wal::open_with(&journal_path(path), |images: Vec<PageImage>| {
+            if records.is_empty() {
+                for image in images {
+                    write_page(&file, image.page_id, &image.data)?;
+                }
+                restored = true;
+            }
+            Ok(())
+        })?;
+        if restored {
+            file.sync_data()?;
+        }
+        if journal.size() > 0 {
+            journal.reset()?;
+        }
+        let mut pager = Pager {
+            file,
+            wal,
+            journal,
+            spilled: HashSet::new(),
+            frames: Vec::new(),
+            pages: HashMap::new(),
+            hand: 0,
+            capacity: (memory_budget / PAGE_SIZE).max(MIN_FRAMES),
+            stats: PoolStats::default(),
+        };
+
+        // Committed after the last checkpoint
+        if !records.is_empty() {
+            for image in records.into_iter().flatten() {
+                pager.write_to_file(image.page_id, &image.data)?;
+            }
+            pager.file.sync_data()?;
+            pager.wal.reset()?;
+        }
+
+        if pager.file.metadata()?.len() == 0 {
+            let id = pager.new_frame(HEADER_PAGE, Box::new([0; PAGE_SIZE]))?;
+            let header = &mut pager.frames[id].data[..];
+            header[..8].copy_from_slice(MAGIC);
+            write_u32(header, 8, VERSION);
+            write_u32(header, PAGE_COUNT, 1);
+            pager.commit()?;
+        }
+        let (magic, version) =
+            pager.read(HEADER_PAGE, |page| (page[..8].to_vec(), read_u32(page, 8)))?;
+        if magic != MAGIC {
+            return Err(corrupt("not a page file"));
+        }
+        if version != VERSION {
+            return Err(corrupt(&format!("unsupported format version {}", version)));
+        }
+        Ok(pager)
+    }
+
+    pub fn read<T>(&mut self, page_id: PageId, f: impl FnOnce(&Page) -> T) -> io::Result<T> {
+        let frame = self.fetch(page_id)?;
+        Ok(f(&self.frames[frame].data))
+    }
+
+    pub fn write<T>(&mut self, page_id: PageId, f: impl FnOnce(&mut Page) -> T) -> io::Result<T> {
+        let frame = self.fetch(page_id)?;
+        if self.frames[frame].state == State::Logged {
+            // The file has to hold the committed page in case the transaction is discarded
+            self.write_back(frame)?;
+        }
+        self.frames[frame].state = State::Changed;
+        Ok(f(&mut self.frames[frame].data))
+    }
+
+    // A zeroed page, taken from the free list if it has any
+    pub fn allocate(&mut self) -> io::Result<PageId> {
+        let free = self.read(HEADER_PAGE, |page| read_u32(page, FREE_LIST))?;
+        if free != 0 {
+            let next = self.write(free, |page| {
+                let next = read_u32(page, 0);
+                page.fill(0);
+                next
+            })?;
+            self.write(HEADER_PAGE, |page| write_u32(page, FREE_LIST, next))?;
+            return Ok(free);
+        }
+        let page_id = self.write(HEADER_PAGE, |page| {
+            let count = read_u32(page, PAGE_COUNT);
+            write_u32(page, PAGE_COUNT, count + 1);
+            count
+        })?;
+        self.new_frame(page_id, Box::new([0; PAGE_SIZE]))?;
+        Ok(page_id)
+    }
+
+    // Puts a page on the free list, which links free pages through their first four bytes
+    pub fn free(&mut self, page_id: PageId) -> io::Result<()> {
+        let next = self.read(HEADER_PAGE, |page| read_u32(page, FREE_LIST))?;
+        self.write(page_id, |page| {
+            page.fill(0);
+            write_u32(page, 0, next);
+        })?;
+        self.write(HEADER_PAGE, |page| write_u32(page, FREE_LIST, page_id))
+    }
+
+    // Makes the changes since the last commit durable. The log's record is what makes them count,
+    // so it is written even without any pages when all of them were spilled.
+    pub fn commit(&mut self) -> io::Result<()> {
+        let mut images: Vec<PageImage> = self
+            .frames
+            .iter()
+            .filter(|frame| frame.state == State::Changed)
+            .map(|frame| PageImage {
+                page_id: frame.page_id,
+                data: frame.data.to_vec(),
+            })
+            .collect();
+        if images.is_empty() && self.spilled.is_empty() {
+            return Ok(());
+        }
+        images.sort_by_key(|image| image.page_id);
+        fault::check("Pager::commit").map_err(io::Error::other)?;
+        if !self.spilled.is_empty() {
+            self.file.sync_data()?;
+        }
+        self.wal.append(&images)?;
+        for frame in &mut self.frames {
+            if frame.state == State::Changed {
+                frame.state = State::Logged;
+            }
+        }
+        if !self.spilled.is_empty() {
+            self.spilled.clear();
+            // The commit stands either way: a journal left behind is emptied before the log is
+            let _ = self.journal.reset();
+        }
+
+        // Back within budget now that every page can be evicted again
+        while self.frames.len() > self.capacity {
+            let last = self.frames.len() - 1;
+            self.evict(last)?;
+            self.frames.pop();
+        }
+        if self.hand >= self.frames.len() {
+            self.hand = 0;
+        }
+        if self.wal.size() > WAL_LIMIT {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    // Forgets the changes since the last commit, putting back the pages that were spilled
+    pub fn discard(&mut self) -> io::Result<()> {
+        let spilled = &self.spilled;
+        self.frames
+            .retain(|frame| frame.state != State::Changed && !spilled.contains(&frame.page_id));
+        self.pages = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| (frame.page_id, index))
+            .collect();
+        self.hand = 0;
+        if !self.spilled.is_empty() {
+            let file = &self.file;
+            self.journal.for_each(|images| {
+                images
+                    .iter()
+                    .try_for_each(|image| write_page(file, image.page_id, &image.data))
+            })?;
+            self.file.sync_data()?;
+            self.journal.reset()?;
+            self.spilled.clear();
+        }
+        Ok(())
+    }
+
+    // Writes every committed page to the file and empties the log
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        for frame in 0..self.frames.len() {
+            if self.frames[frame].state == State::Logged {
+                self.write_back(frame)?;
+            }
+        }
+        self.file.sync_data()?;
+        // Once the log is empty, a journal would count as that of a transaction that didn't
+        // commit
+        if self.spilled.is_empty() && self.journal.size() > 0 {
+            self.journal.reset()?;
+        }
+        self.wal.reset()
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    // The number of pages in the pool
+    pub fn resident(&self) -> usize {
+        self.frames.len()
+    }
+
+    // The frame holding the page, reading it from the file if it isn't in the pool
+    fn fetch(&mut self, page_id: PageId) -> io::Result<usize> {
+        if let Some(&frame) = self.pages.get(&page_id) {
+            self.stats.hits += 1;
+            self.frames[frame].referenced = true;
+            return Ok(frame);
+        }
+        self.stats.misses += 1;
+        let data = read_page(&self.file, page_id)?;
+        let frame = self.new_frame(page_id, data)?;
+        self.frames[frame].state = State::Clean;
+        Ok(frame)
+    }
+
+    // Puts a page in the pool as changed, evicting another one if the pool is full
+    fn new_frame(&mut self, page_id: PageId, data: Box<Page>) -> io::Result<usize> {
+        let frame = Frame {
+            page_id,
+            data,
+            state: State::Changed,
+            referenced: true,
+        };
+        let index = match self.victim()? {
+            Some(index) => {
+                self.frames[index] = frame;
+                index
+            }
+            None => {
+                self.frames.push(frame);
+                self.frames.len() - 1
+            }
+        };
+        self.pages.insert(page_id, index);
+        Ok(index)
+    }
+
+    // Evicts a frame with the clock algorithm if the pool is full, spilling the transaction's
+    // pages when they are all that is left
+    fn victim(&mut self) -> io::Result<Option<usize>> {
+        if self.frames.len() < self.capacity {
+            return Ok(None);
+        }
+        if let Some(index) = self.sweep()? {
+            return Ok(Some(index));
+        }
+        self.spill()?;
+        self.sweep()
+    }
+
+    fn sweep(&mut self) -> io::Result<Option<usize>> {
+        // Two sweeps clear every reference bit, so a third finds nothing new
+        for _ in 0..2 * self.frames.len() {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.frames.len();
+            let frame = &mut self.frames[index];
+            if frame.state == State::Changed {
+                continue;
+            }
+            if frame.referenced {
+                frame.referenced = false;
+                continue;
+            }
+            self.evict(index)?;
+            return Ok(Some(index));
+        }
+        Ok(None)
+    }
+
+    // Writes the pages changed by the transaction in progress to the file, so that they can be
+    // evicted, after putting what the file holds for them in the journal. The log is emptied
+    // first: replayed after a crash, it could otherwise overwrite the spilled pages with older
+    // images, and it then tells a commit that got as far as its record from one that didn't.
+    fn spill(&mut self) -> io::Result<()> {
+        if self.spilled.is_empty() {
+            self.checkpoint()?;
+        }
+        let mut images = Vec::new();
+        for frame in &self.frames {
+            if frame.state == State::Changed && !self.spilled.contains(&frame.page_id) {
+                images.push(PageImage {
+                    page_id: frame.page_id,
+                    data: read_page(&self.file, frame.page_id)?.to_vec(),
+                });
+            }
+        }
+        fault::check("Pager::spill").map_err(io::Error::other)?;
+        self.journal.append(&images)?;
+        for frame in 0..self.frames.len() {
+            if self.frames[frame].state == State::Changed {
+                let page_id = self.frames[frame].page_id;
+                self.spilled.insert(page_id);
+                self.write_back(frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn evict(&mut self, frame: usize) -> io::Result<()> {
+        if self.frames[frame].state == State::Logged {
+            self.write_back(frame)?;
+        }
+        self.pages.remove(&self.frames[frame].page_id);
+        self.stats.evictions += 1;
+        Ok(())
+    }
+
+    // Without syncing: until the next checkpoint the log still has the page
+    fn write_back(&mut self, frame: usize) -> io::Result<()> {
+        let page_id = self.frames[frame].page_id;
+        let data = *self.frames[frame].data;
+        self.write_to_file(page_id, &data)?;
+        self.frames[frame].state = State::Clean;
+        self.stats.writes += 1;
+        Ok(())
+    }
+
+    fn write_to_file(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        write_page(&self.file, page_id, data)
+    }
+}
+
+// The journal that belongs to the page file at `path`, e.g. `users.pages-journal`
+pub fn journal_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("-journal");
+    PathBuf::from(name)
+}
+
+fn read_page(mut file: &File, page_id: PageId) -> io::Result<Box<Page>> {
+    let mut data = Box::new([0; PAGE_SIZE]);
+    file.seek(SeekFrom::Start(page_id as u64 * PAGE_SIZE as u64))?;
+    // Pages past the end of the file haven't been written back yet and are still zeroed
+    let mut filled = 0;
+    while filled < PAGE_SIZE {
+        match file.read(&mut data[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(data)
+}
+
+fn write_page(mut file: &File, page_id: PageId, data: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(page_id as u64 * PAGE_SIZE as u64))?;
+    file.write_all(data)
+}
+
+pub fn read_u16(page: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(page[offset..offset + 2].try_into().unwrap())
+}
+
+pub fn write_u16(page: &mut [u8], offset: usize, value: u16) {
+    page[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn read_u32(page: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap())
+}
+
+pub fn write_u32(page: &mut [u8], offset: usize, value: u32) {
+    page[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Pager: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(Wal::path_for(&path));
+        let _ = fs::remove_file(journal_path(&path));
+        path
+    }
+
+    fn remove_files(path: &Path) {
+        fs::remove_file(path).unwrap();
+        fs::remove_file(Wal::path_for(path)).unwrap();
+        fs::remove_file(journal_path(path)).unwrap();
+    }
+
+    // Allocates `count` pages, each filled with its own number
+    fn fill(pager: &mut Pager, count: u32) -> Vec<PageId> {
+        (0..count)
+            .map(|n| {
+                let page_id = pager.allocate().unwrap();
+                pager.write(page_id, |page| write_u32(page, 0, n)).unwrap();
+                page_id
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pool_stays_within_budget() {
+        let path = temp_path("budget.pages");
+        let mut pager = Pager::open(&path, 8 * PAGE_SIZE).unwrap();
+        for chunk in 0..10 {
+            let pages = fill(&mut pager, 10);
+            pager.commit().unwrap();
+            assert!(pager.resident() <= 8);
+            for (n, page_id) in pages.iter().enumerate() {
+                assert_eq!(
+                    pager.read(*page_id, |page| read_u32(page, 0)).unwrap(),
+                    n as u32
+                );
+            }
+            assert!(pager.resident() <= 8, "chunk {}", chunk);
+        }
+        let stats = pager.stats();
+        assert!(stats.evictions > 0 && stats.writes > 0 && stats.misses > 0);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_discard_restores_committed_pages() {
+        let path = temp_path("discard.pages");
+        let mut pager = Pager::open(&path, 4 * PAGE_SIZE).unwrap();
+        let pages = fill(&mut pager, 6);
+        pager.commit().unwrap();
+
+        pager
+            .write(pages[0], |page| write_u32(page, 0, 99))
+            .unwrap();
+        let extra = pager.allocate().unwrap();
+        pager.free(pages[1]).unwrap();
+        pager.discard().unwrap();
+        assert_eq!(pager.read(pages[0], |page| read_u32(page, 0)).unwrap(), 0);
+        assert_eq!(pager.read(pages[1], |page| read_u32(page, 0)).unwrap(), 1);
+        // The page count went back too, so the same page is handed out again
+        assert_eq!(pager.allocate().unwrap(), extra);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_large_transactions_spill_within_budget() {
+        let path = temp_path("spill.pages");
+        let mut pager = Pager::open(&path, 8 * PAGE_SIZE).unwrap();
+        let pages = fill(&mut pager, 10);
+        pager.commit().unwrap();
+        let read = |pager: &mut Pager, page_id: PageId| {
+            pager.read(page_id, |page| read_u32(page, 0)).unwrap()
+        };
+
+        for page_id in &pages {
+            pager
+                .write(*page_id, |page| write_u32(page, 0, 99))
+                .unwrap();
+        }
+        let added = fill(&mut pager, 40);
+        assert!(pager.resident() <= 8);
+        assert!(pages.iter().all(|page_id| read(&mut pager, *page_id) == 99));
+        assert_eq!(read(&mut pager, added[39]), 39);
+        pager.discard().unwrap();
+        let values: Vec<u32> = pages
+            .iter()
+            .map(|page_id| read(&mut pager, *page_id))
+            .collect();
+        assert_eq!(values, (0..10).collect::<Vec<u32>>());
+        assert_eq!(pager.allocate().unwrap(), added[0]);
+        pager.discard().unwrap();
+
+        for page_id in &pages {
+            pager
+                .write(*page_id, |page| write_u32(page, 0, 42))
+                .unwrap();
+        }
+        let added = fill(&mut pager, 40);
+        pager.commit().unwrap();
+        assert!(pager.resident() <= 8);
+        drop(pager);
+        let mut pager = Pager::open(&path, 8 * PAGE_SIZE).unwrap();
+        assert!(pages.iter().all(|page_id| read(&mut pager, *page_id) == 42));
+        assert_eq!(read(&mut pager, added[20]), 20);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_spilled_pages_are_put_back_after_a_crash() {
+        let path = temp_path("spill-crash.pages");
+        let mut pager = Pager::open(&path, 4 * PAGE_SIZE).unwrap();
+        let pages = fill(&mut pager, 6);
+        pager.commit().unwrap();
+        for page_id in &pages {
+            pager.write(*page_id, |page| write_u32(page, 0, 7)).unwrap();
+        }
+        assert!(fs::metadata(journal_path(&path)).unwrap().len() > 0);
+        // Dropped before committing, as if the process had died
+        drop(pager);
+
+        let mut pager = Pager::open(&path, 4 * PAGE_SIZE).unwrap();
+        let values: Vec<u32> = pages
+            .iter()
+            .map(|page_id| pager.read(*page_id, |page| read_u32(page, 0)).unwrap())
+            .collect();
+        assert_eq!(values, (0..6).collect::<Vec<u32>>());
+        assert_eq!(fs::metadata(journal_path(&path)).unwrap().len(), 0);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_freed_pages_are_reused() {
+        let path = temp_path("free.pages");
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let pages = fill(&mut pager, 3);
+        pager.free(pages[1]).unwrap();
+        pager.free(pages[0]).unwrap();
+        assert_eq!(pager.allocate().unwrap(), pages[0]);
+        assert_eq!(pager.allocate().unwrap(), pages[1]);
+        assert_eq!(pager.read(pages[1], |page| read_u32(page, 0)).unwrap(), 0);
+        assert_eq!(pager.allocate().unwrap(), pages[2] + 1);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_log_is_replayed_after_a_crash() {
+        let path = temp_path("crash.pages");
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let pages = fill(&mut pager, 3);
+        pager.commit().unwrap();
+        pager.checkpoint().unwrap();
+        pager
+            .write(pages[2], |page| write_u32(page, 0, 42))
+            .unwrap();
+        pager.commit().unwrap();
+        // Never committed
+        pager.write(pages[0], |page| write_u32(page, 0, 7)).unwrap();
+        // Dropped without writing anything back, as if the process had died
+        drop(pager);
+
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let values: Vec<u32> = pages
+            .iter()
+            .map(|page_id| pager.read(*page_id, |page| read_u32(page, 0)).unwrap())
+            .collect();
+        assert_eq!(values, vec![0, 1, 42]);
+        assert_eq!(pager.wal.size(), 0);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_failed_commit_can_be_discarded() {
+        let path = temp_path("failed.pages");
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        let pages = fill(&mut pager, 1);
+        pager.commit().unwrap();
+        pager.write(pages[0], |page| write_u32(page, 0, 5)).unwrap();
+        fault::fail_after(0);
+        assert!(pager.commit().is_err());
+        pager.discard().unwrap();
+        drop(pager);
+
+        let mut pager = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        assert_eq!(pager.read(pages[0], |page| read_u32(page, 0)).unwrap(), 0);
+        drop(pager);
+        remove_files(&path);
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = temp_path("other.pages");
+        fs::write(&path, [1u8; PAGE_SIZE]).unwrap();
+        let error = Pager::open(&path, DEFAULT_MEMORY_BUDGET).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        remove_files(&path);
+    }
+}
@@ -1,181 +1,646 @@
 use serde::{Deserialize, Serialize};
-use sled::{Db, IVec};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::Path;
 
-// Represent a column
-#[derive(Clone, Serialize, Deserialize)]
-struct Column {
-    name: String,
-    data_type: DataType,
+use super::data_types::{CaseInsensitiveString, UniqueConstraint, Value};
+use super::engine::{RowIdIter, RowIter};
+use super::fault;
+use super::key_encoding::{self, KeyRange};
+use super::overlay::{CommittedStore, OverlayDatabase};
+use super::table::{duplicate_key, IndexRange, Row, RowRange, TableSchema};
+use super::wal::Redo;
+
+// The committed state. The `catalog` tree holds each table's schema, and every table has three
+// trees of its own: `rows:<name>`, keyed by the primary key (or by row id for tables without one),
+// `rowids:<name>`, which maps row ids to those keys, and `index:<name>`, with an entry for each
+// index name, key and row id, encoded so that they sort like the keys. Rows are stored bincode
+// encoded.
+//
+// Committed changes arrive as the same redo records the write-ahead log stores. Each one is put
+// in the `journal` tree and flushed, which is the moment it counts as committed, and then applied
+// to the other trees. Since redo records can be applied more than once, whatever is left in the
+// journal after a crash is simply applied again when the store is opened.
+#[derive(Debug)]
+pub struct SledStore {
+    db: sled::Db,
+    catalog: sled::Tree,
+    journal: sled::Tree,
 }
 
-// Supported data types
-#[derive(Clone, Serialize, Deserialize)]
-enum DataType {
-    Integer,
-    Float,
-    String,
-    Boolean,
+// The trees of one table
+struct TableTrees {
+    rows: sled::Tree,
+    rowids: sled::Tree,
+    index: sled::Tree,
 }
 
-// Represent a table
-struct Table {
-    name: String,
-    columns: Vec<Column>,
-    primary_key: String,
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRow {
+    row_id: usize,
+    values: Vec<Value>,
 }
 
-// Represent a database
-struct Database {
-    db: Db,
-    tables: HashMap<String, Table>,
+const NEXT_ROW_ID: &str = "next_row_id";
+
+// A database kept in sled rather than in memory
+pub type SledDatabase = OverlayDatabase<SledStore>;
+
+impl SledDatabase {
+    // Opens the sled database in the directory at `path`, creating it if needed. Rows written
+    // since the last commit hold about `memory_budget` bytes.
+    pub fn open(path: &Path, memory_budget: usize) -> io::Result<Self> {
+        Ok(OverlayDatabase::new(
+            SledStore::open(path).map_err(io::Error::other)?,
+            memory_budget,
+        ))
+    }
 }
 
-impl Database {
-    fn new(path: &str) -> Result<Self, sled::Error> {
-        let db = sled::open(path)?;
-        Ok(Self {
+impl SledStore {
+    fn open(path: &Path) -> Result<SledStore, String> {
+        let db = sled::open(path).map_err(sled_error)?;
+        let store = SledStore {
+            catalog: db.open_tree("catalog").map_err(sled_error)?,
+            journal: db.open_tree("journal").map_err(sled_error)?,
             db,
-            tables: HashMap::new(),
-        })
+        };
+        store.apply_journal()?;
+        Ok(store)
     }
 
-    fn create_table(
-        &mut self,
-        name: &str,
-        columns: Vec<Column>,
-        primary_key: &str,
-    ) -> Result<(), String> {
-        if self.tables.contains_key(name) {
-            return Err(format!("Table '{}' already exists", name));
+    // Applies the journalled changes oldest first, removing each once it is in place
+    fn apply_journal(&self) -> Result<(), String> {
+        for entry in self.journal.iter() {
+            let (sequence, record) = entry.map_err(sled_error)?;
+            let changes: Vec<Redo> =
+                bincode::deserialize(&record).map_err(|e| format!("Sled: {}", e))?;
+            fault::check("SledStore::apply_journal")?;
+            for change in changes {
+                self.apply(change)?;
+            }
+            self.journal.remove(sequence).map_err(sled_error)?;
         }
+        Ok(())
+    }
 
-        let table = Table {
-            name: name.to_string(),
-            columns,
-            primary_key: primary_key.to_string(),
-        };
+    fn apply(&self, change: Redo) -> Result<(), String> {
+        match change {
+            Redo::Table { name, table } => {
+                let trees = self.table_trees(&name)?;
+                trees.clear()?;
+                match table {
+                    Some(table) => {
+                        let schema = table.schema();
+                        self.catalog
+                            .insert(name.0.as_bytes(), encode(&schema)?)
+                            .map_err(sled_error)?;
+                        for (row_id, row) in table.scan() {
+                            trees.store(&schema, row_id, &row.values)?;
+                        }
+                    }
+                    None => {
+                        self.catalog.remove(name.0.as_bytes()).map_err(sled_error)?;
+                        trees.drop(&self.db)?;
+                    }
+                }
+            }
+            Redo::Rows {
+                table,
+                rows: changed,
+            } => {
+                let schema = self
+                    .schema(&table)?
+                    .ok_or_else(|| format!("Sled: table '{}' is not in the catalog", table.0))?;
+                let trees = self.table_trees(&table)?;
+                // All the old rows go first: rows may swap primary keys, and so their keys
+                for (row_id, _) in &changed {
+                    trees.remove(&schema, *row_id)?;
+                }
+                for (row_id, row) in changed {
+                    if let Some(row) = row {
+                        trees.store(&schema, row_id, &row.values)?;
+                    }
+                }
+            }
+            Redo::NextRowId(next_row_id) => {
+                self.db
+                    .insert(NEXT_ROW_ID, encode(&next_row_id)?)
+                    .map_err(sled_error)?;
+            }
+            // The schema goes last, so that a crash halfway reindexes from the old one again
+            Redo::Schema { name, schema } => {
+                let stored = self
+                    .schema(&name)?
+                    .ok_or_else(|| format!("Sled: table '{}' is not in the catalog", name.0))?;
+                if !stored.same_indexes(&schema) {
+                    self.table_trees(&name)?.reindex(&stored, &schema)?;
+                }
+                self.catalog
+                    .insert(name.0.as_bytes(), encode(&schema)?)
+                    .map_err(sled_error)?;
+            }
+        }
+        Ok(())
+    }
 
-        self.tables.insert(name.to_string(), table);
+    // Checks the UNIQUE indexes a batch of changes creates against the rows the tables will have,
+    // before the batch is journalled and has to apply. The keys are collected in a tree of their
+    // own, which finds a duplicate as it is built.
+    fn check_created_indexes(&self, changes: &[Redo]) -> Result<(), String> {
+        for change in changes {
+            let Redo::Schema { name, schema } = change else {
+                continue;
+            };
+            let Some(stored) = self.schema(name)? else {
+                continue;
+            };
+            let created: Vec<(&str, UniqueConstraint)> = schema
+                .column_indexes
+                .iter()
+                .filter(|index| !stored.column_indexes.contains(index))
+                .filter_map(|index| Some((index.name.as_str(), index.constraint()?)))
+                .collect();
+            if created.is_empty() {
+                continue;
+            }
+            // Rows the batch changes count as it leaves them
+            let mut changed = BTreeMap::new();
+            for change in changes {
+                if let Redo::Rows { table, rows } = change {
+                    if table == name {
+                        changed.extend(rows.iter().map(|(row_id, row)| (*row_id, row.as_ref())));
+                    }
+                }
+            }
+            let keys = self
+                .db
+                .open_tree(format!("unique:{}", name.0))
+                .map_err(sled_error)?;
+            keys.clear().map_err(sled_error)?;
+            let checked = self.add_unique_keys(&keys, name, schema, &created, &changed);
+            self.db.drop_tree(keys.name()).map_err(sled_error)?;
+            checked?;
+        }
         Ok(())
     }
 
-    fn insert(
+    // Adds the keys of every row the table will have to `keys`
+    fn add_unique_keys(
         &self,
-        table_name: &str,
-        data: HashMap<String, serde_json::Value>,
+        keys: &sled::Tree,
+        table: &CaseInsensitiveString,
+        schema: &TableSchema,
+        indexes: &[(&str, UniqueConstraint)],
+        changed: &BTreeMap<usize, Option<&Row>>,
     ) -> Result<(), String> {
-        let table = self
-            .tables
-            .get(table_name)
-            .ok_or_else(|| format!("Table '{}' not found", table_name))?;
+        for row in self.table_trees(table)?.rows.iter().values() {
+            let row: StoredRow = decode(&row.map_err(sled_error)?)?;
+            if !changed.contains_key(&row.row_id) {
+                add_row_keys(keys, schema, indexes, &row.values)?;
+            }
+        }
+        for row in changed.values().flatten() {
+            add_row_keys(keys, schema, indexes, &row.values)?;
+        }
+        Ok(())
+    }
 
-        let primary_key_value = data
-            .get(&table.primary_key)
-            .ok_or_else(|| format!("Primary key '{}' not provided", table.primary_key))?;
+    fn table_trees(&self, table: &CaseInsensitiveString) -> Result<TableTrees, String> {
+        let tree = |kind: &str| {
+            self.db
+                .open_tree(format!("{}:{}", kind, table.0))
+                .map_err(sled_error)
+        };
+        Ok(TableTrees {
+            rows: tree("rows")?,
+            rowids: tree("rowids")?,
+            index: tree("index")?,
+        })
+    }
+}
 
-        let serialized_data =
-            serde_json::to_vec(&data).map_err(|e| format!("Failed to serialize data: {}", e))?;
+impl CommittedStore for SledStore {
+    fn table_names(&self) -> Result<Vec<CaseInsensitiveString>, String> {
+        self.catalog
+            .iter()
+            .keys()
+            .map(|name| {
+                let name = name.map_err(sled_error)?;
+                Ok(CaseInsensitiveString(
+                    String::from_utf8_lossy(&name).into_owned(),
+                ))
+            })
+            .collect()
+    }
 
-        let key = format!("{}:{}", table_name, primary_key_value);
-        self.db
-            .insert(key.as_bytes(), serialized_data)
-            .map_err(|e| format!("Failed to insert data: {}", e))?;
+    fn schema(&self, table: &CaseInsensitiveString) -> Result<Option<TableSchema>, String> {
+        match self.catalog.get(table.0.as_bytes()).map_err(sled_error)? {
+            Some(bytes) => decode(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
 
-        Ok(())
+    fn next_row_id(&self) -> Result<usize, String> {
+        match self.db.get(NEXT_ROW_ID).map_err(sled_error)? {
+            Some(bytes) => decode(&bytes),
+            None => Ok(0),
+        }
     }
 
-    fn get(
+    fn get_row(&self, table: &CaseInsensitiveString, row_id: usize) -> Result<Option<Row>, String> {
+        let trees = self.table_trees(table)?;
+        match trees.rowids.get(row_id_key(row_id)).map_err(sled_error)? {
+            Some(key) => trees.get(&key),
+            None => Ok(None),
+        }
+    }
+
+    fn scan(&self, table: &CaseInsensitiveString, range: RowRange) -> Result<RowIter<'_>, String> {
+        let trees = self.table_trees(table)?;
+        let range = (range.0.map(row_id_key), range.1.map(row_id_key));
+        let rowids = trees.rowids.range(range);
+        Ok(Box::new(rowids.map(move |entry| {
+            let (row_id, key) = entry.map_err(sled_error)?;
+            let row = trees.get(&key)?;
+            row.map(|row| (trailing_row_id(&row_id), row))
+                .ok_or_else(|| "Sled: stored data is corrupt (missing row)".to_string())
+        })))
+    }
+
+    fn index_scan(
         &self,
-        table_name: &str,
-        primary_key_value: &str,
-    ) -> Result<Option<HashMap<String, serde_json::Value>>, String> {
-        let key = format!("{}:{}", table_name, primary_key_value);
-        let result = self
-            .db
-            .get(key.as_bytes())
-            .map_err(|e| format!("Failed to get data: {}", e))?;
-
-        match result {
-            Some(ivec) => {
-                let data: HashMap<String, serde_json::Value> = serde_json::from_slice(&ivec)
-                    .map_err(|e| format!("Failed to deserialize data: {}", e))?;
-                Ok(Some(data))
+        table: &CaseInsensitiveString,
+        index: &str,
+        range: &IndexRange,
+    ) -> Result<RowIdIter<'_>, String> {
+        let trees = self.table_trees(table)?;
+        let range = KeyRange::new(index, range, usize::MAX);
+        let mut keys = trees.index.range(range.start()..).keys();
+        let row_ids = std::iter::from_fn(move || loop {
+            let key = match keys.next()? {
+                Ok(key) => key,
+                Err(e) => return Some(Err(sled_error(e))),
+            };
+            match range.locate(&key) {
+                Ordering::Less => continue,
+                Ordering::Equal => return Some(Ok(trailing_row_id(&key))),
+                Ordering::Greater => return None,
+            }
+        });
+        Ok(Box::new(row_ids.fuse()))
+    }
+
+    // Commits a set of changes. Once they are in the journal they are durable, so a failure to
+    // apply them afterwards isn't reported: they stay in the journal and are applied later. The
+    // journal takes them as one record, so they are gathered in memory first.
+    fn commit(
+        &mut self,
+        changes: &mut dyn Iterator<Item = Result<Redo, String>>,
+    ) -> Result<(), String> {
+        let changes = join_rows(changes.collect::<Result<Vec<Redo>, String>>()?);
+        self.check_created_indexes(&changes)?;
+        let record = bincode::serialize(&changes).map_err(|e| format!("Sled: {}", e))?;
+        let sequence = self.db.generate_id().map_err(sled_error)?;
+        self.journal
+            .insert(sequence.to_be_bytes(), record)
+            .map_err(sled_error)?;
+        if let Err(e) = self.db.flush() {
+            let _ = self.journal.remove(sequence.to_be_bytes());
+            return Err(sled_error(e));
+        }
+        let _ = self.apply_journal();
+        Ok(())
+    }
+
+    // sled keeps its own files up to date, so this only makes sure they are on disk
+    fn checkpoint(&mut self) -> io::Result<()> {
+        self.db.flush().map(|_| ()).map_err(io::Error::other)
+    }
+}
+
+impl TableTrees {
+    fn get(&self, key: &[u8]) -> Result<Option<Row>, String> {
+        match self.rows.get(key).map_err(sled_error)? {
+            Some(bytes) => {
+                let row: StoredRow = decode(&bytes)?;
+                Ok(Some(Row { values: row.values }))
             }
             None => Ok(None),
         }
     }
 
-    // Add methods for updating and deleting records, querying, etc.
+    fn store(&self, schema: &TableSchema, row_id: usize, values: &[Value]) -> Result<(), String> {
+        let key = row_key(schema, row_id, values)?;
+        let row = StoredRow {
+            row_id,
+            values: values.to_vec(),
+        };
+        self.rows.insert(&key, encode(&row)?).map_err(sled_error)?;
+        self.rowids
+            .insert(row_id_key(row_id), key)
+            .map_err(sled_error)?;
+        for (name, key) in schema.index_entries(values) {
+            self.index
+                .insert(index_entry(&name, &key, row_id), &[])
+                .map_err(sled_error)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, schema: &TableSchema, row_id: usize) -> Result<(), String> {
+        let Some(key) = self.rowids.remove(row_id_key(row_id)).map_err(sled_error)? else {
+            return Ok(());
+        };
+        if let Some(bytes) = self.rows.remove(key).map_err(sled_error)? {
+            let row: StoredRow = decode(&bytes)?;
+            for (name, key) in schema.index_entries(&row.values) {
+                self.index
+                    .remove(index_entry(&name, &key, row_id))
+                    .map_err(sled_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Brings the index entries in line with a new schema, reading the rows one at a time
+    fn reindex(&self, old: &TableSchema, new: &TableSchema) -> Result<(), String> {
+        for row in self.rows.iter().values() {
+            let row: StoredRow = decode(&row.map_err(sled_error)?)?;
+            let entries = |schema: &TableSchema| -> BTreeSet<Vec<u8>> {
+                schema
+                    .index_entries(&row.values)
+                    .iter()
+                    .map(|(name, key)| index_entry(name, key, row.row_id))
+                    .collect()
+            };
+            let (old_entries, new_entries) = (entries(old), entries(new));
+            for entry in old_entries.difference(&new_entries) {
+                self.index.remove(entry).map_err(sled_error)?;
+            }
+            for entry in new_entries.difference(&old_entries) {
+                self.index.insert(entry, &[]).map_err(sled_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), String> {
+        self.rows.clear().map_err(sled_error)?;
+        self.rowids.clear().map_err(sled_error)?;
+        self.index.clear().map_err(sled_error)
+    }
+
+    fn drop(self, db: &sled::Db) -> Result<(), String> {
+        for tree in [self.rows, self.rowids, self.index] {
+            db.drop_tree(tree.name()).map_err(sled_error)?;
+        }
+        Ok(())
+    }
 }
 
-// Example usage
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut db = Database::new("my_database")?;
+// Joins the batches of rows a table's changes come in back into one change, since `apply` takes
+// out every old row of a change before it stores the new ones: rows may swap primary keys
+fn join_rows(changes: Vec<Redo>) -> Vec<Redo> {
+    let mut joined: Vec<Redo> = Vec::new();
+    for change in changes {
+        match (joined.last_mut(), change) {
+            (
+                Some(Redo::Rows { table, rows }),
+                Redo::Rows {
+                    table: next,
+                    rows: more,
+                },
+            ) if *table == next => rows.extend(more),
+            (_, change) => joined.push(change),
+        }
+    }
+    joined
+}
 
-    // Create tables
-    db.create_table(
-        "users",
-        vec![
-            Column {
-                name: "id".to_string(),
-                data_type: DataType::Integer,
-            },
-            Column {
-                name: "name".to_string(),
-                data_type: DataType::String,
-            },
-            Column {
-                name: "age".to_string(),
-                data_type: DataType::Integer,
-            },
-        ],
-        "id",
-    )?;
-
-    db.create_table(
-        "posts",
+// Adds a row's keys for UNIQUE indexes to `keys`, failing on one that is there already
+fn add_row_keys(
+    keys: &sled::Tree,
+    schema: &TableSchema,
+    indexes: &[(&str, UniqueConstraint)],
+    values: &[Value],
+) -> Result<(), String> {
+    for (name, constraint) in indexes {
+        let Some(key) = schema.unique_key(constraint, values) else {
+            continue;
+        };
+        let (entry, _) = key_encoding::index_prefix(name, &key, usize::MAX);
+        if keys.insert(entry, &[]).map_err(sled_error)?.is_some() {
+            return Err(duplicate_key(&key, constraint));
+        }
+    }
+    Ok(())
+}
+
+// The primary key values, or the row id for a table without a primary key
+fn row_key(schema: &TableSchema, row_id: usize, values: &[Value]) -> Result<Vec<u8>, String> {
+    match schema.constraints.iter().find(|c| c.primary_key) {
+        Some(primary_key) => {
+            let key: Vec<&Value> = primary_key
+                .columns
+                .iter()
+                .filter_map(|name| {
+                    let position = schema.columns.iter().position(|c| &c.name == name)?;
+                    values.get(position)
+                })
+                .collect();
+            encode(&key)
+        }
+        None => Ok(row_id_key(row_id).to_vec()),
+    }
+}
+
+// Big endian, so that the trees keep row ids in order
+fn row_id_key(row_id: usize) -> [u8; 8] {
+    (row_id as u64).to_be_bytes()
+}
+
+// The row id a `rowids` or `index` key ends with
+fn trailing_row_id(key: &[u8]) -> usize {
+    let bytes: [u8; 8] = key[key.len() - 8..]
+        .try_into()
+        .expect("keys end with a row id");
+    u64::from_be_bytes(bytes) as usize
+}
+
+// The index name, a zero byte, the key's sort encoding and the row id, so that the entries of an
+// index are in key order, and in row id order for equal keys
+fn index_entry(index: &str, key: &[Value], row_id: usize) -> Vec<u8> {
+    let (mut entry, _) = key_encoding::index_prefix(index, key, usize::MAX);
+    entry.extend_from_slice(&row_id_key(row_id));
+    entry
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, String> {
+    bincode::serialize(value).map_err(|e| format!("Sled: {}", e))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    bincode::deserialize(bytes).map_err(|e| format!("Sled: stored data is corrupt ({})", e))
+}
+
+fn sled_error(e: sled::Error) -> String {
+    format!("Sled: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::conformance;
+    use crate::db::data_types::{Column, DataType, UniqueConstraint};
+    use crate::db::engine::StorageEngine;
+    use crate::db::pager::DEFAULT_MEMORY_BUDGET;
+    use crate::db::table::{ColumnIndex, Table, ALL_ROWS};
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    fn users(path: &Path) -> SledDatabase {
+        let mut database = SledDatabase::open(path, DEFAULT_MEMORY_BUDGET).unwrap();
+        database
+            .create_table(
+                "users".to_string(),
+                vec![
+                    Column::new("id".to_string(), DataType::Integer),
+                    Column::new("doc".to_string(), DataType::Json),
+                ],
+                vec![UniqueConstraint {
+                    columns: vec!["id".to_string()],
+                    primary_key: true,
+                }],
+            )
+            .unwrap();
+        database
+    }
+
+    fn row(id: i64) -> Vec<Value> {
         vec![
-            Column {
-                name: "id".to_string(),
-                data_type: DataType::Integer,
-            },
-            Column {
-                name: "user_id".to_string(),
-                data_type: DataType::Integer,
-            },
-            Column {
-                name: "title".to_string(),
-                data_type: DataType::String,
-            },
-            Column {
-                name: "content".to_string(),
-                data_type: DataType::String,
-            },
-        ],
-        "id",
-    )?;
-
-    // Insert data
-    let mut user_data = HashMap::new();
-    user_data.insert("id".to_string(), serde_json::json!(1));
-    user_data.insert("name".to_string(), serde_json::json!("Alice"));
-    user_data.insert("age".to_string(), serde_json::json!(30));
-    db.insert("users", user_data)?;
-
-    let mut post_data = HashMap::new();
-    post_data.insert("id".to_string(), serde_json::json!(1));
-    post_data.insert("user_id".to_string(), serde_json::json!(1));
-    post_data.insert("title".to_string(), serde_json::json!("First Post"));
-    post_data.insert("content".to_string(), serde_json::json!("Hello, World!"));
-    db.insert("posts", post_data)?;
-
-    // Retrieve data
-    let user = db.get("users", "1")?;
-    println!("User: {:?}", user);
-
-    let post = db.get("posts", "1")?;
-    println!("Post: {:?}", post);
+            Value::Integer(id),
+            Value::Text(format!(r#"{{"a": {}}}"#, id)),
+        ]
+    }
 
-    Ok(())
+    fn table(database: &SledDatabase, name: &str) -> Table {
+        database.table(name).unwrap()
+    }
+
+    #[test]
+    fn test_conformance() {
+        let path = temp_dir("conformance");
+        let opened = Cell::new(0);
+        // With no memory to spare, every row written is spilled until it is committed
+        for memory_budget in [DEFAULT_MEMORY_BUDGET, 0] {
+            conformance::run(&|| {
+                opened.set(opened.get() + 1);
+                let path = path.join(opened.get().to_string());
+                Box::new(SledDatabase::open(&path, memory_budget).unwrap())
+            });
+        }
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reopens_with_committed_changes() {
+        let path = temp_dir("reopen.sled");
+        let mut database = users(&path);
+        database
+            .create_table(
+                "log".to_string(),
+                vec![Column::new("line".to_string(), DataType::Text)],
+                vec![],
+            )
+            .unwrap();
+        let ids = database
+            .write_rows("users", vec![row(1), row(2), row(3)], vec![])
+            .unwrap();
+        database
+            .write_rows("log", vec![vec![Value::Text("hello".to_string())]], vec![])
+            .unwrap();
+        database
+            .create_index(
+                "users",
+                ColumnIndex {
+                    name: "users_a".to_string(),
+                    columns: vec!["json_extract(doc, '$.a')".to_string()],
+                    unique: false,
+                    predicate: None,
+                },
+            )
+            .unwrap();
+        // Changing the primary key moves the row to another key
+        database
+            .write_rows("users", vec![], vec![(ids[0], row(10))])
+            .unwrap();
+        database.delete_rows("users", &[ids[1]]).unwrap();
+        database.begin().unwrap();
+        database.write_rows("users", vec![row(4)], vec![]).unwrap();
+        database.commit().unwrap();
+        let users_table = table(&database, "users");
+        let log_table = table(&database, "log");
+
+        // Left open, so never committed
+        database.begin().unwrap();
+        database.write_rows("users", vec![row(5)], vec![]).unwrap();
+        drop(database);
+
+        let mut database = SledDatabase::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        assert_eq!(table(&database, "users"), users_table);
+        assert_eq!(table(&database, "log"), log_table);
+        // Read from the indexes kept in sled
+        assert_eq!(
+            database
+                .index_lookup("users", "users_a", &[Value::Integer(10)])
+                .unwrap(),
+            vec![ids[0]]
+        );
+        assert!(database
+            .index_lookup("users", "id", &[Value::Integer(1)])
+            .unwrap()
+            .is_empty());
+        // Row ids carry on where the committed ones stopped
+        assert_eq!(
+            database
+                .write_rows("log", vec![vec![Value::Null]], vec![])
+                .unwrap(),
+            vec![5]
+        );
+        drop(database);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_is_applied_after_a_crash() {
+        let path = temp_dir("journal.sled");
+        let mut database = users(&path);
+        database.write_rows("users", vec![row(1)], vec![]).unwrap();
+
+        // Committed to the journal, then interrupted before reaching the table trees
+        fault::fail_after(0);
+        database.write_rows("users", vec![row(2)], vec![]).unwrap();
+        assert!(fault::check("test").is_ok(), "the fault was never reached");
+        drop(database);
+
+        let database = SledDatabase::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        assert_eq!(
+            database
+                .index_lookup("users", "id", &[Value::Integer(2)])
+                .unwrap(),
+            vec![1]
+        );
+        assert_eq!(database.scan("users", ALL_ROWS).unwrap().count(), 2);
+        drop(database);
+        fs::remove_dir_all(&path).unwrap();
+    }
 }
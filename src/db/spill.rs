@@ -0,0 +1,115 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::sync::atomic::{self, AtomicUsize};
+
+use super::data_types::Value;
+use super::table::Row;
+
+// Tells the spill files of the databases open in one process apart
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+// Rows kept out of memory in a temporary file, which goes away with it. Rows are only ever
+// appended, so a row stays where it was written for as long as the file lives.
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+    file: File,
+    end: u64,
+}
+
+// Where a row was written in a `SpillFile`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spilled {
+    offset: u64,
+    len: usize,
+}
+
+impl SpillFile {
+    pub fn create() -> Result<SpillFile, String> {
+        let path = std::env::temp_dir().join(format!(
+            "banditdb-{}-spill-{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| format!("Overlay: could not spill rows: {}", e))?;
+        Ok(SpillFile { path, file, end: 0 })
+    }
+
+    pub fn write(&mut self, row: &Row) -> Result<Spilled, String> {
+        let error = |e: &dyn std::fmt::Display| format!("Overlay: could not spill rows: {}", e);
+        let bytes = bincode::serialize(row).map_err(|e| error(&e))?;
+        self.file
+            .seek(SeekFrom::Start(self.end))
+            .and_then(|_| self.file.write_all(&bytes))
+            .map_err(|e| error(&e))?;
+        let spilled = Spilled {
+            offset: self.end,
+            len: bytes.len(),
+        };
+        self.end += bytes.len() as u64;
+        Ok(spilled)
+    }
+
+    pub fn read(&self, spilled: Spilled) -> Result<Row, String> {
+        let error =
+            |e: &dyn std::fmt::Display| format!("Overlay: could not read spilled rows back: {}", e);
+        let mut bytes = vec![0; spilled.len];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(spilled.offset))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(|e| error(&e))?;
+        bincode::deserialize(&bytes).map_err(|e| error(&e))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Memory a row's values hold, counting each value and the text it points to. Every other value,
+// DECIMAL and INTERVAL included, is held inside the value itself, and BLOBs are held as text.
+pub fn heap_size(values: &[Value]) -> usize {
+    values
+        .iter()
+        .map(|value| {
+            mem::size_of::<Value>()
+                + match value {
+                    Value::Text(s) | Value::Json(s) => s.capacity(),
+                    _ => 0,
+                }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill_file() {
+        let mut spill = SpillFile::create().unwrap();
+        let path = spill.path.clone();
+        let rows: Vec<Row> = (0..3)
+            .map(|n| Row {
+                values: vec![Value::Integer(n), Value::Text("x".repeat(n as usize * 100))],
+            })
+            .collect();
+        let spilled: Vec<Spilled> = rows.iter().map(|row| spill.write(row).unwrap()).collect();
+        // Read back in any order
+        for (row, spilled) in rows.iter().zip(&spilled).rev() {
+            assert_eq!(&spill.read(*spilled).unwrap(), row);
+        }
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
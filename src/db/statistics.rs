@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Bound;
+
+use super::data_types::{Column, Value};
+
+// What ANALYZE found out about a table's rows, kept in its schema for estimating how many rows a
+// query reads. Nothing updates it as rows change, so it ages until the next ANALYZE.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableStatistics {
+    pub rows: usize,
+    // In column order
+    pub columns: Vec<ColumnStatistics>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStatistics {
+    pub column: String,
+    // Distinct values other than NULL
+    pub distinct: usize,
+    pub null_fraction: f64,
+    // None when every value is NULL
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    // Upper bounds of equi-depth buckets: about as many values lie above one bound and at or
+    // below the next as in any other bucket. The first bucket starts at `min`.
+    pub histogram: Vec<Value>,
+}
+
+pub const HISTOGRAM_BUCKETS: usize = 16;
+// Rows ANALYZE keeps to build histograms from, so that a table of any size fits in memory
+pub const SAMPLE_ROWS: usize = 30_000;
+
+impl TableStatistics {
+    // Reads every row, counting them and their NULLs and keeping the least and greatest values of
+    // each column. Histograms and distinct counts come from a uniform sample of the rows.
+    pub fn collect(
+        columns: &[Column],
+        rows: impl Iterator<Item = Result<Vec<Value>, String>>,
+    ) -> Result<TableStatistics, String> {
+        Self::sample(columns, rows, SAMPLE_ROWS)
+    }
+
+    fn sample(
+        columns: &[Column],
+        rows: impl Iterator<Item = Result<Vec<Value>, String>>,
+        size: usize,
+    ) -> Result<TableStatistics, String> {
+        let mut count = 0;
+        let mut nulls = vec![0; columns.len()];
+        let mut bounds: Vec<Option<(Value, Value)>> = vec![None; columns.len()];
+        let mut sample: Vec<Vec<Value>> = Vec::new();
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for row in rows {
+            let row = row?;
+            count += 1;
+            for (i, value) in row.iter().enumerate() {
+                match (&mut bounds[i], value) {
+                    (_, Value::Null) => nulls[i] += 1,
+                    (Some((min, max)), value) => {
+                        if value < min {
+                            *min = value.clone();
+                        } else if value > max {
+                            *max = value.clone();
+                        }
+                    }
+                    (bounds, value) => *bounds = Some((value.clone(), value.clone())),
+                }
+            }
+            // Each row read so far stays in the sample with the same chance
+            if sample.len() < size {
+                sample.push(row);
+            } else {
+                let slot = random.below(count);
+                if slot < size {
+                    sample[slot] = row;
+                }
+            }
+        }
+        let mut values: Vec<Vec<Value>> = vec![Vec::new(); columns.len()];
+        for row in sample {
+            for (column, value) in values.iter_mut().zip(row) {
+                if value != Value::Null {
+                    column.push(value);
+                }
+            }
+        }
+        let columns = columns
+            .iter()
+            .zip(values)
+            .zip(nulls.into_iter().zip(bounds))
+            .map(|((column, values), (nulls, bounds))| {
+                ColumnStatistics::new(&column.name, count, nulls, bounds, values)
+            })
+            .collect();
+        Ok(TableStatistics {
+            rows: count,
+            columns,
+        })
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStatistics> {
+        self.columns
+            .iter()
+            .find(|column| column.column.eq_ignore_ascii_case(name))
+    }
+
+    pub fn column_mut(&mut self, name: &str) -> Option<&mut ColumnStatistics> {
+        self.columns
+            .iter_mut()
+            .find(|column| column.column.eq_ignore_ascii_case(name))
+    }
+}
+
+impl ColumnStatistics {
+    // `values` are the sampled values other than NULL, out of the `rows - nulls` in the column
+    fn new(
+        column: &str,
+        rows: usize,
+        nulls: usize,
+        bounds: Option<(Value, Value)>,
+        mut values: Vec<Value>,
+    ) -> ColumnStatistics {
+        values.sort_unstable();
+        let mut sampled_distinct = 0;
+        let mut once = 0;
+        let mut start = 0;
+        for end in 1..=values.len() {
+            if end == values.len() || values[end] != values[start] {
+                sampled_distinct += 1;
+                if end - start == 1 {
+                    once += 1;
+                }
+                start = end;
+            }
+        }
+        // Values seen once in the sample hint at as many more that were not sampled. This is the
+        // Haas and Stokes estimate, exact when the sample holds every value.
+        let total = rows - nulls;
+        let sampled = values.len();
+        let distinct = match sampled {
+            0 => 0,
+            _ if sampled == total => sampled_distinct,
+            _ => {
+                let (n, total) = (sampled as f64, total as f64);
+                let estimate =
+                    n * sampled_distinct as f64 / (n - once as f64 + once as f64 * n / total);
+                (estimate.round() as usize).clamp(sampled_distinct, total as usize)
+            }
+        };
+        let buckets = HISTOGRAM_BUCKETS.min(values.len());
+        let histogram = (1..=buckets)
+            .map(|bucket| values[bucket * values.len() / buckets - 1].clone())
+            .collect();
+        let (min, max) = bounds.unzip();
+        ColumnStatistics {
+            column: column.to_string(),
+            distinct,
+            null_fraction: match rows {
+                0 => 0.0,
+                _ => nulls as f64 / rows as f64,
+            },
+            min,
+            max,
+            histogram,
+        }
+    }
+
+    // The fraction of rows whose value equals `value`, taking every distinct value to be as common
+    pub fn equal_fraction(&self, value: &Value) -> f64 {
+        match (&self.min, &self.max) {
+            (Some(min), Some(max)) if min <= value && value <= max => {
+                (1.0 - self.null_fraction) / self.distinct as f64
+            }
+            _ => 0.0,
+        }
+    }
+
+    // The fraction of rows whose value lies between the bounds, from the buckets they cover. A
+    // bucket a bound falls inside counts for half.
+    pub fn range_fraction(&self, lower: Bound<&Value>, upper: Bound<&Value>) -> f64 {
+        let below_lower = match lower {
+            Bound::Included(value) => self.fraction_below(value, false),
+            Bound::Excluded(value) => self.fraction_below(value, true),
+            Bound::Unbounded => 0.0,
+        };
+        let below_upper = match upper {
+            Bound::Included(value) => self.fraction_below(value, true),
+            Bound::Excluded(value) => self.fraction_below(value, false),
+            Bound::Unbounded => 1.0,
+        };
+        (1.0 - self.null_fraction) * (below_upper - below_lower).max(0.0)
+    }
+
+    // The fraction of the values other than NULL that are less than `value`, or equal to it too
+    fn fraction_below(&self, value: &Value, inclusive: bool) -> f64 {
+        let (Some(min), Some(max)) = (&self.min, &self.max) else {
+            return 0.0;
+        };
+        if value < min || (value == min && !inclusive) {
+            return 0.0;
+        }
+        if value > max || (value == max && inclusive) {
+            return 1.0;
+        }
+        let buckets = self.histogram.len() as f64;
+        let below = self
+            .histogram
+            .iter()
+            .take_while(|bound| *bound < value || (inclusive && *bound == value))
+            .count() as f64;
+        ((below + 0.5) / buckets).min(1.0)
+    }
+}
+
+// A xorshift generator, seeded the same each time so that ANALYZE of the same rows gives the
+// same statistics
+struct Random(u64);
+
+impl Random {
+    // About uniform in 0..bound
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::data_types::DataType;
+
+    #[test]
+    fn test_collect() {
+        let columns = vec![
+            Column::new("id".to_string(), DataType::Integer),
+            Column::new("kind".to_string(), DataType::Text),
+        ];
+        let rows = (0..100).map(|id| {
+            let kind = match id % 4 {
+                0 => Value::Null,
+                1 => Value::Text("a".to_string()),
+                _ => Value::Text("b".to_string()),
+            };
+            Ok(vec![Value::Integer(id), kind])
+        });
+        let statistics = TableStatistics::collect(&columns, rows).unwrap();
+        assert_eq!(statistics.rows, 100);
+
+        let id = statistics.column("ID").unwrap();
+        assert_eq!(id.distinct, 100);
+        assert_eq!(id.null_fraction, 0.0);
+        assert_eq!(id.min, Some(Value::Integer(0)));
+        assert_eq!(id.max, Some(Value::Integer(99)));
+        assert_eq!(id.histogram.len(), HISTOGRAM_BUCKETS);
+        assert_eq!(id.histogram[0], Value::Integer(5));
+        assert_eq!(id.histogram[HISTOGRAM_BUCKETS - 1], Value::Integer(99));
+
+        let kind = statistics.column("kind").unwrap();
+        assert_eq!(kind.distinct, 2);
+        assert_eq!(kind.null_fraction, 0.25);
+        // A third of the values are 'a', so about a third of the buckets end on it
+        let a = Value::Text("a".to_string());
+        assert_eq!(kind.histogram.iter().filter(|v| **v == a).count(), 5);
+
+        let empty = TableStatistics::collect(&columns, std::iter::empty()).unwrap();
+        assert_eq!(empty.rows, 0);
+        assert_eq!(empty.columns[1].min, None);
+        assert!(empty.columns[1].histogram.is_empty());
+        assert!(TableStatistics::collect(&columns, std::iter::once(Err("x".to_string()))).is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let columns = vec![
+            Column::new("id".to_string(), DataType::Integer),
+            Column::new("group".to_string(), DataType::Integer),
+        ];
+        let rows = (0..10_000).map(|id| {
+            let group = match id % 10 {
+                0 => Value::Null,
+                group => Value::Integer(group),
+            };
+            Ok(vec![Value::Integer(id), group])
+        });
+        let statistics = TableStatistics::sample(&columns, rows, 1000).unwrap();
+        // Counts and bounds come from every row, the rest from 1000 of them
+        assert_eq!(statistics.rows, 10_000);
+        let id = statistics.column("id").unwrap();
+        assert_eq!(id.min, Some(Value::Integer(0)));
+        assert_eq!(id.max, Some(Value::Integer(9999)));
+        assert_eq!(id.distinct, 10_000);
+        let Value::Integer(middle) = id.histogram[HISTOGRAM_BUCKETS / 2 - 1] else {
+            panic!("{:?}", id.histogram);
+        };
+        assert!((4500..5500).contains(&middle), "{}", middle);
+
+        let group = statistics.column("group").unwrap();
+        assert_eq!(group.null_fraction, 0.1);
+        assert_eq!(group.distinct, 9);
+        assert_eq!(group.max, Some(Value::Integer(9)));
+    }
+
+    #[test]
+    fn test_fractions() {
+        let columns = vec![Column::new("n".to_string(), DataType::Integer)];
+        // 0 to 799 and 200 NULLs
+        let rows = (0..1000).map(|n| {
+            Ok(vec![match n {
+                800.. => Value::Null,
+                n => Value::Integer(n),
+            }])
+        });
+        let statistics = TableStatistics::collect(&columns, rows).unwrap();
+        let n = statistics.column("n").unwrap();
+        let close = |estimate: f64, actual: f64| (estimate - actual).abs() < 0.05;
+
+        assert!(close(n.equal_fraction(&Value::Integer(5)), 0.001));
+        assert_eq!(n.equal_fraction(&Value::Integer(800)), 0.0);
+        let integer = |n: &i64| Value::Integer(*n);
+        let range = |lower: Bound<&i64>, upper: Bound<&i64>| {
+            n.range_fraction(lower.map(integer).as_ref(), upper.map(integer).as_ref())
+        };
+        assert!(close(range(Bound::Unbounded, Bound::Excluded(&400)), 0.4));
+        assert!(close(
+            range(Bound::Included(&100), Bound::Included(&299)),
+            0.2
+        ));
+        assert!(close(range(Bound::Excluded(&-5), Bound::Unbounded), 0.8));
+        assert_eq!(range(Bound::Included(&900), Bound::Unbounded), 0.0);
+        assert_eq!(range(Bound::Included(&300), Bound::Excluded(&200)), 0.0);
+    }
+}
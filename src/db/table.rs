@@ -1,20 +1,242 @@
 use core::fmt;
 use prettytable::{Cell, Row as PrettyRow, Table as PrettyTable};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
 
-use super::data_types::{Column, Value};
+use super::data_types::{Column, DataType, UniqueConstraint, Value};
+use super::fault;
+use super::full_text::{self, FullTextIndex};
+use super::statistics::TableStatistics;
+use crate::sql_engine::evaluator;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Row {
     pub values: Vec<Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Index created with `CREATE [UNIQUE] INDEX name ON t (col, ...)`. Entries are keyed by the values
+// of its columns, in order, so rows come out sorted by them and a lookup can give just the first
+// few. A UNIQUE index also keeps its columns unique, like a UNIQUE constraint.
+// A key can also be an expression over the row, e.g. `lower(email)`, stored as its SQL text, and
+// a partial index (`... WHERE active = 1`) only has entries for the rows its predicate holds for.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ColumnIndex {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+    #[serde(default)]
+    pub predicate: Option<String>,
+}
+
+impl ColumnIndex {
+    // The constraint a UNIQUE index enforces
+    pub fn constraint(&self) -> Option<UniqueConstraint> {
+        self.unique.then(|| UniqueConstraint {
+            columns: self.columns.clone(),
+            primary_key: false,
+        })
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.predicate.is_some()
+    }
+}
+
+// The index keys that start with `prefix` and whose next value lies between `lower` and `upper`:
+// an equality lookup on the first columns of the index, then a range on the one after them
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexRange {
+    pub prefix: Vec<Value>,
+    pub lower: Bound<Value>,
+    pub upper: Bound<Value>,
+}
+
+impl IndexRange {
+    pub fn prefix(prefix: Vec<Value>) -> Self {
+        IndexRange {
+            prefix,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    pub fn all() -> Self {
+        Self::prefix(Vec::new())
+    }
+
+    pub fn contains(&self, key: &[Value]) -> bool {
+        if !key.starts_with(&self.prefix) {
+            return false;
+        }
+        let Some(next) = key.get(self.prefix.len()) else {
+            return self.lower == Bound::Unbounded && self.upper == Bound::Unbounded;
+        };
+        let above = match &self.lower {
+            Bound::Included(lower) => next >= lower,
+            Bound::Excluded(lower) => next > lower,
+            Bound::Unbounded => true,
+        };
+        let below = match &self.upper {
+            Bound::Included(upper) => next <= upper,
+            Bound::Excluded(upper) => next < upper,
+            Bound::Unbounded => true,
+        };
+        above && below
+    }
+}
+
+// Row ids from one bound to another
+pub type RowRange = (Bound<usize>, Bound<usize>);
+pub const ALL_ROWS: RowRange = (Bound::Unbounded, Bound::Unbounded);
+
+// Everything about a table except its rows. Storage engines share it to validate rows and work out
+// their index entries the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableSchema {
+    pub columns: Vec<Column>,
+    pub constraints: Vec<UniqueConstraint>,
+    pub column_indexes: Vec<ColumnIndex>,
+    pub full_text_indexes: Vec<FullTextIndex>,
+    // Set by ANALYZE
+    pub statistics: Option<TableStatistics>,
+}
+
+impl TableSchema {
+    pub fn prepare_row(&self, values: Vec<Value>) -> Result<Vec<Value>, String> {
+        prepare_row(&self.columns, values)
+    }
+
+    pub fn unique_key(
+        &self,
+        constraint: &UniqueConstraint,
+        values: &[Value],
+    ) -> Option<Vec<Value>> {
+        unique_key(&self.columns, constraint, values)
+    }
+
+    pub fn unique_constraints(&self) -> Vec<UniqueConstraint> {
+        unique_constraints(&self.constraints, &self.column_indexes)
+    }
+
+    pub fn lookup_index(&self, column: &str) -> Option<&str> {
+        lookup_index(&self.constraints, &self.column_indexes, column)
+    }
+
+    // Whether both give every row the same index entries, i.e. differ at most in statistics
+    pub fn same_indexes(&self, other: &TableSchema) -> bool {
+        self.constraints == other.constraints
+            && self.column_indexes == other.column_indexes
+            && self.full_text_indexes == other.full_text_indexes
+    }
+
+    // Every index over columns or expressions with the keys it is made of and the predicate of a
+    // partial index: the first column of each constraint, then the created indexes
+    pub fn column_index_keys(&self) -> Vec<(&str, Vec<&str>, Option<&str>)> {
+        let mut keys: Vec<(&str, Vec<&str>, Option<&str>)> = Vec::new();
+        for constraint in &self.constraints {
+            let first = constraint.columns[0].as_str();
+            if !keys.iter().any(|(name, _, _)| *name == first) {
+                keys.push((first, vec![first], None));
+            }
+        }
+        for index in &self.column_indexes {
+            let columns = index.columns.iter().map(String::as_str).collect();
+            keys.push((&index.name, columns, index.predicate.as_deref()));
+        }
+        keys
+    }
+
+    // An index that keeps every row ordered by `columns` among the rows whose `fixed` keys each
+    // have a single value: a constraint's index for a single column, or a created index whose
+    // columns are the wanted ones in order with fixed ones before or among them. Partial indexes
+    // miss rows.
+    pub fn ordering_index(&self, columns: &[&str], fixed: &[String]) -> Option<&str> {
+        let is_fixed = |column: &str| fixed.iter().any(|key| key.eq_ignore_ascii_case(column));
+        let columns: Vec<&str> = columns
+            .iter()
+            .copied()
+            .filter(|column| !is_fixed(column))
+            .collect();
+        match columns.as_slice() {
+            [] => return None,
+            [column] => {
+                if let Some(index) = self.lookup_index(column) {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+        self.column_indexes
+            .iter()
+            .find(|index| {
+                let mut wanted = columns.iter().peekable();
+                for indexed in &index.columns {
+                    match wanted.peek() {
+                        Some(column) if column.eq_ignore_ascii_case(indexed) => {
+                            wanted.next();
+                        }
+                        _ if is_fixed(indexed) => {}
+                        _ => break,
+                    }
+                }
+                !index.is_partial() && wanted.peek().is_none()
+            })
+            .map(|index| index.name.as_str())
+    }
+
+    // The keys a row has in the index called `index`: none if it isn't in a partial index, one
+    // per word in a full-text index
+    pub fn index_keys(&self, index: &str, values: &[Value]) -> Vec<Vec<Value>> {
+        self.index_entries(values)
+            .into_iter()
+            .filter(|(name, _)| name == index)
+            .map(|(_, key)| key)
+            .collect()
+    }
+
+    pub fn index_entries(&self, values: &[Value]) -> Vec<(String, Vec<Value>)> {
+        index_entries(
+            &self.columns,
+            &self.constraints,
+            &self.column_indexes,
+            &self.full_text_indexes,
+            values,
+        )
+    }
+
+    // Whether `name` is taken by an index, including the ones behind constraints
+    pub fn has_index(&self, name: &str) -> bool {
+        self.columns
+            .iter()
+            .any(|column| column.name.eq_ignore_ascii_case(name))
+            || self
+                .column_indexes
+                .iter()
+                .any(|index| index.name.eq_ignore_ascii_case(name))
+            || self
+                .full_text_indexes
+                .iter()
+                .any(|index| index.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     columns: Vec<Column>,
     data: BTreeMap<usize, Row>,
-    indexes: BTreeMap<String, BTreeMap<Value, Vec<usize>>>,
+    #[serde(default)]
+    constraints: Vec<UniqueConstraint>,
+    #[serde(default)]
+    column_indexes: Vec<ColumnIndex>,
+    #[serde(default)]
+    full_text_indexes: Vec<FullTextIndex>,
+    #[serde(default)]
+    statistics: Option<TableStatistics>,
+    // Derived from `data`, so it is not written to disk (JSON object keys must be strings anyway)
+    // and gets rebuilt by `rebuild_indexes` after loading
+    #[serde(skip)]
+    indexes: BTreeMap<String, BTreeMap<Vec<Value>, Vec<usize>>>,
 }
 
 impl Table {
@@ -22,56 +244,415 @@ impl Table {
         Table {
             columns,
             data: BTreeMap::new(),
+            constraints: Vec::new(),
+            column_indexes: Vec::new(),
+            full_text_indexes: Vec::new(),
+            statistics: None,
             indexes: BTreeMap::new(),
         }
     }
 
+    // A table with an already validated schema and no rows
+    pub fn with_schema(schema: TableSchema) -> Self {
+        Table {
+            columns: schema.columns,
+            data: BTreeMap::new(),
+            constraints: schema.constraints,
+            column_indexes: schema.column_indexes,
+            full_text_indexes: schema.full_text_indexes,
+            statistics: schema.statistics,
+            indexes: BTreeMap::new(),
+        }
+    }
+
+    pub fn schema(&self) -> TableSchema {
+        TableSchema {
+            columns: self.columns.clone(),
+            constraints: self.constraints.clone(),
+            column_indexes: self.column_indexes.clone(),
+            full_text_indexes: self.full_text_indexes.clone(),
+            statistics: self.statistics.clone(),
+        }
+    }
+
+    // Puts back the constraints, indexes and statistics of an earlier schema, e.g. to undo
+    // CREATE INDEX
+    pub fn restore_schema(&mut self, schema: TableSchema) {
+        let rebuild = !self.schema().same_indexes(&schema);
+        self.constraints = schema.constraints;
+        self.column_indexes = schema.column_indexes;
+        self.full_text_indexes = schema.full_text_indexes;
+        self.statistics = schema.statistics;
+        if rebuild {
+            self.rebuild_indexes();
+        }
+    }
+
+    pub fn add_constraint(&mut self, mut constraint: UniqueConstraint) -> Result<(), String> {
+        // Store the declared column names, which are also the index names
+        for name in constraint.columns.iter_mut() {
+            let column = self
+                .columns
+                .iter()
+                .find(|column| column.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("Column '{}' not found", name))?;
+            *name = column.name.clone();
+        }
+        if constraint.primary_key && self.constraints.iter().any(|c| c.primary_key) {
+            return Err("Table already has a PRIMARY KEY".to_string());
+        }
+        self.check_stored_unique(&constraint)?;
+
+        self.constraints.push(constraint);
+        self.rebuild_indexes();
+        Ok(())
+    }
+
+    pub fn constraints(&self) -> &[UniqueConstraint] {
+        &self.constraints
+    }
+
     pub fn insert_row(&mut self, row_id: usize, values: Vec<Value>) -> Result<(), String> {
-        if values.len() != self.columns.len() {
-            return Err("Number of values doesn't match number of columns".to_string());
+        self.insert_rows(vec![(row_id, values)])
+    }
+
+    pub fn insert_rows(&mut self, rows: Vec<(usize, Vec<Value>)>) -> Result<(), String> {
+        self.write_rows(rows, Vec::new())
+    }
+
+    // Inserts new rows and replaces existing ones as a single batch: every row is checked, including
+    // against the unique constraints, before anything is stored. A failure while storing leaves
+    // the batch half written; callers undo it with `restore_rows`.
+    pub fn write_rows(
+        &mut self,
+        inserts: Vec<(usize, Vec<Value>)>,
+        updates: Vec<(usize, Vec<Value>)>,
+    ) -> Result<(), String> {
+        for (row_id, _) in &updates {
+            if !self.data.contains_key(row_id) {
+                return Err(format!("Row {} not found", row_id));
+            }
+        }
+        let replaced: BTreeSet<usize> = updates.iter().map(|(row_id, _)| *row_id).collect();
+        let rows = updates
+            .into_iter()
+            .chain(inserts)
+            .map(|(row_id, values)| Ok((row_id, self.prepare_row(values)?)))
+            .collect::<Result<Vec<(usize, Vec<Value>)>, String>>()?;
+        for constraint in &self.unique_constraints() {
+            self.check_unique(constraint, &rows, &replaced)?;
         }
 
-        for (value, column) in values.iter().zip(self.columns.iter()) {
-            if !value.matches_type(&column.data_type) {
-                return Err(format!("Type mismatch for column '{}'", column.name));
+        for (row_id, values) in rows {
+            fault::check("Table::write_rows")?;
+            if let Some(old) = self.data.remove(&row_id) {
+                self.unindex_row(row_id, &old.values);
+            }
+            // Stored before it is indexed, so `restore_rows` finds and unindexes a row whose
+            // indexing was interrupted
+            let entries = self.index_entries(&values);
+            self.data.insert(row_id, Row { values });
+            for (name, key) in entries {
+                fault::check("Table::write_rows")?;
+                self.add_index_entry(name, key, row_id);
             }
         }
 
-        let row = Row {
-            values: values.clone(),
+        Ok(())
+    }
+
+    // Coerces the values to the column types and checks they fit
+    pub fn prepare_row(&self, values: Vec<Value>) -> Result<Vec<Value>, String> {
+        prepare_row(&self.columns, values)
+    }
+
+    // The constraint's key for a row, or None when part of it is NULL
+    pub fn unique_key(
+        &self,
+        constraint: &UniqueConstraint,
+        values: &[Value],
+    ) -> Option<Vec<Value>> {
+        unique_key(&self.columns, constraint, values)
+    }
+
+    // PRIMARY KEY and UNIQUE constraints together with the ones UNIQUE indexes add
+    pub fn unique_constraints(&self) -> Vec<UniqueConstraint> {
+        unique_constraints(&self.constraints, &self.column_indexes)
+    }
+
+    // The stored row that already holds `values`' key for `constraint`, found through an index on
+    // the constraint's first column
+    pub fn conflicting_row(
+        &self,
+        constraint: &UniqueConstraint,
+        values: &[Value],
+    ) -> Option<usize> {
+        let key = self.unique_key(constraint, values)?;
+        let holds_key = |row_id: &usize| {
+            self.unique_key(constraint, &self.data[row_id].values)
+                .as_ref()
+                == Some(&key)
         };
-        self.data.insert(row_id, row);
+        match lookup_index(
+            &self.constraints,
+            &self.column_indexes,
+            &constraint.columns[0],
+        ) {
+            Some(index) => self
+                .index_lookup(index, &key[..1])
+                .into_iter()
+                .find(holds_key),
+            None => self.data.keys().copied().find(holds_key),
+        }
+    }
 
-        // Update indexes
-        for (i, (column, value)) in self.columns.iter().zip(values.iter()).enumerate() {
-            self.indexes
-                .entry(column.name.clone())
-                .or_insert_with(BTreeMap::new)
-                .entry(value.clone())
-                .or_insert_with(Vec::new)
-                .push(row_id);
+    // Fails if two stored rows share a key for `constraint`, before it is added
+    fn check_stored_unique(&self, constraint: &UniqueConstraint) -> Result<(), String> {
+        let mut keys = BTreeSet::new();
+        for row in self.data.values() {
+            let key = match self.unique_key(constraint, &row.values) {
+                Some(key) => key,
+                None if constraint.primary_key => {
+                    return Err(format!("NULL value in {}", constraint));
+                }
+                None => continue,
+            };
+            if !keys.insert(key.clone()) {
+                return Err(duplicate_key(&key, constraint));
+            }
         }
+        Ok(())
+    }
 
+    // Checks a batch of rows against the stored rows, ignoring the stored rows being replaced, and
+    // against each other
+    fn check_unique(
+        &self,
+        constraint: &UniqueConstraint,
+        rows: &[(usize, Vec<Value>)],
+        replaced: &BTreeSet<usize>,
+    ) -> Result<(), String> {
+        let mut batch_keys = BTreeSet::new();
+        for (row_id, values) in rows {
+            let key = match self.unique_key(constraint, values) {
+                Some(key) => key,
+                None if constraint.primary_key => {
+                    return Err(format!("NULL value in {}", constraint));
+                }
+                None => continue,
+            };
+            let stored = self
+                .conflicting_row(constraint, values)
+                .filter(|existing| existing != row_id && !replaced.contains(existing));
+            if stored.is_some() || !batch_keys.insert(key.clone()) {
+                return Err(duplicate_key(&key, constraint));
+            }
+        }
         Ok(())
     }
 
-    pub fn select(&self, columns: &[String]) -> Result<Vec<Row>, String> {
-        let column_indices: Vec<usize> = columns
+    // Builds the new index from the stored rows. The columns are stored under their declared
+    // names, like a constraint's; any other key is an expression.
+    pub fn create_index(&mut self, mut index: ColumnIndex) -> Result<(), String> {
+        for key in index.columns.iter_mut() {
+            match self
+                .columns
+                .iter()
+                .find(|column| column.name.eq_ignore_ascii_case(key))
+            {
+                Some(column) => *key = column.name.clone(),
+                None if is_identifier(key) => return Err(format!("Column '{}' not found", key)),
+                None => {}
+            }
+        }
+        if index.unique && index.is_partial() {
+            return Err("A UNIQUE index cannot have a WHERE clause".to_string());
+        }
+        if self.schema().has_index(&index.name) {
+            return Err(format!("Index '{}' already exists", index.name));
+        }
+        if let Some(constraint) = index.constraint() {
+            self.check_stored_unique(&constraint)?;
+        }
+
+        self.column_indexes.push(index);
+        self.rebuild_indexes();
+        Ok(())
+    }
+
+    // Drops a created or full-text index. The indexes behind constraints go with the
+    // constraint.
+    pub fn drop_index(&mut self, name: &str) -> Result<(), String> {
+        let count = |table: &Self| table.column_indexes.len() + table.full_text_indexes.len();
+        let before = count(self);
+        self.column_indexes
+            .retain(|index| !index.name.eq_ignore_ascii_case(name));
+        self.full_text_indexes
+            .retain(|index| !index.name.eq_ignore_ascii_case(name));
+        if count(self) == before {
+            return Err(format!("Index '{}' not found", name));
+        }
+        self.rebuild_indexes();
+        Ok(())
+    }
+
+    // The column is stored under its declared name
+    pub fn create_full_text_index(&mut self, mut index: FullTextIndex) -> Result<(), String> {
+        let column = self
+            .columns
             .iter()
-            .map(|col| self.columns.iter().position(|c| c.name == *col))
-            .collect::<Option<Vec<usize>>>()
-            .ok_or_else(|| "One or more columns not found".to_string())?;
+            .find(|column| column.name.eq_ignore_ascii_case(&index.column))
+            .ok_or_else(|| format!("Column '{}' not found", index.column))?;
+        if !matches!(
+            column.data_type,
+            DataType::Text | DataType::Varchar(_) | DataType::Char(_)
+        ) {
+            return Err(format!("Column '{}' is not of type TEXT", column.name));
+        }
+        index.column = column.name.clone();
+        if self.schema().has_index(&index.name) {
+            return Err(format!("Index '{}' already exists", index.name));
+        }
+
+        self.full_text_indexes.push(index);
+        self.rebuild_indexes();
+        Ok(())
+    }
+
+    // Replaces what ANALYZE found; the rows and indexes stay as they are
+    pub fn set_statistics(&mut self, statistics: TableStatistics) {
+        self.statistics = Some(statistics);
+    }
+
+    // Puts rows back the way they were, without any checks: Some(row) is stored again under its
+    // id, None removes the row. Used to roll back changes.
+    pub fn restore_rows(&mut self, rows: Vec<(usize, Option<Row>)>) {
+        for (row_id, row) in rows {
+            if let Some(current) = self.data.remove(&row_id) {
+                self.unindex_row(row_id, &current.values);
+            }
+            if let Some(row) = row {
+                self.index_row(row_id, &row.values);
+                self.data.insert(row_id, row);
+            }
+        }
+    }
+
+    // Row ids, in order, whose key in `index` starts with `prefix`. Only constrained columns and
+    // the columns of created indexes are indexed.
+    pub fn index_lookup(&self, index: &str, prefix: &[Value]) -> Vec<usize> {
+        let mut row_ids: Vec<usize> = self
+            .index_scan(index, &IndexRange::prefix(prefix.to_vec()))
+            .collect();
+        row_ids.sort_unstable();
+        row_ids
+    }
 
-        Ok(self
+    // Row ids in the order of their keys in `index`, and of row id for equal keys
+    pub fn index_scan(&self, index: &str, range: &IndexRange) -> impl Iterator<Item = usize> + '_ {
+        let mut start = range.prefix.clone();
+        if let Bound::Included(lower) | Bound::Excluded(lower) = &range.lower {
+            start.push(lower.clone());
+        }
+        let (before, within) = (range.clone(), range.clone());
+        self.indexes
+            .get(index)
+            .into_iter()
+            .flat_map(move |entries| entries.range(start.clone()..))
+            .skip_while(move |(key, _)| !before.contains(key) && key.starts_with(&before.prefix))
+            .take_while(move |(key, _)| within.contains(key))
+            .flat_map(|(_, row_ids)| row_ids.iter().copied())
+    }
+
+    pub fn rebuild_indexes(&mut self) {
+        self.indexes.clear();
+        let entries: Vec<_> = self
             .data
-            .values()
-            .map(|row| Row {
-                values: column_indices
-                    .iter()
-                    .map(|&i| row.values[i].clone())
-                    .collect(),
-            })
-            .collect())
+            .iter()
+            .map(|(row_id, row)| (*row_id, self.index_entries(&row.values)))
+            .collect();
+        for (row_id, entries) in entries {
+            self.add_index_entries(row_id, entries);
+        }
+    }
+
+    fn index_row(&mut self, row_id: usize, values: &[Value]) {
+        let entries = self.index_entries(values);
+        self.add_index_entries(row_id, entries);
+    }
+
+    // Empty entries are removed, so the indexes only ever depend on the rows currently stored
+    fn unindex_row(&mut self, row_id: usize, values: &[Value]) {
+        for (name, key) in self.index_entries(values) {
+            if let Some(index) = self.indexes.get_mut(&name) {
+                if let Some(row_ids) = index.get_mut(&key) {
+                    row_ids.retain(|id| *id != row_id);
+                    if row_ids.is_empty() {
+                        index.remove(&key);
+                    }
+                }
+                if index.is_empty() {
+                    self.indexes.remove(&name);
+                }
+            }
+        }
+    }
+
+    fn index_entries(&self, values: &[Value]) -> Vec<(String, Vec<Value>)> {
+        index_entries(
+            &self.columns,
+            &self.constraints,
+            &self.column_indexes,
+            &self.full_text_indexes,
+            values,
+        )
+    }
+
+    fn add_index_entries(&mut self, row_id: usize, entries: Vec<(String, Vec<Value>)>) {
+        for (name, key) in entries {
+            self.add_index_entry(name, key, row_id);
+        }
+    }
+
+    fn add_index_entry(&mut self, name: String, key: Vec<Value>, row_id: usize) {
+        // Kept sorted so that the order doesn't depend on how the rows got there
+        let row_ids = self
+            .indexes
+            .entry(name)
+            .or_default()
+            .entry(key)
+            .or_default();
+        if let Err(position) = row_ids.binary_search(&row_id) {
+            row_ids.insert(position, row_id);
+        }
+    }
+
+    // Rows with their ids, in row id (i.e. insertion) order
+    pub fn scan(&self) -> impl Iterator<Item = (usize, &Row)> {
+        self.data.iter().map(|(row_id, row)| (*row_id, row))
+    }
+
+    pub fn scan_range(&self, range: RowRange) -> impl Iterator<Item = (usize, &Row)> {
+        self.data.range(range).map(|(row_id, row)| (*row_id, row))
+    }
+
+    // Removes the rows once they are all known to exist; like `write_rows`, a failure partway
+    // through is undone with `restore_rows`
+    pub fn delete_rows(&mut self, row_ids: &[usize]) -> Result<(), String> {
+        if let Some(missing) = row_ids.iter().find(|id| !self.data.contains_key(id)) {
+            return Err(format!("Row {} not found", missing));
+        }
+        for row_id in row_ids {
+            fault::check("Table::delete_rows")?;
+            if let Some(row) = self.data.remove(row_id) {
+                self.unindex_row(*row_id, &row.values);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_row(&self, row_id: usize) -> Option<&Row> {
+        self.data.get(&row_id)
     }
 
     pub fn get_columns(&self) -> &Vec<Column> {
@@ -79,6 +660,143 @@ impl Table {
     }
 }
 
+fn prepare_row(columns: &[Column], values: Vec<Value>) -> Result<Vec<Value>, String> {
+    if values.len() != columns.len() {
+        return Err("Number of values doesn't match number of columns".to_string());
+    }
+
+    let values = values
+        .into_iter()
+        .zip(columns.iter())
+        .map(|(value, column)| value.coerce_to(&column.data_type))
+        .collect::<Result<Vec<Value>, String>>()?;
+
+    for (value, column) in values.iter().zip(columns.iter()) {
+        if !value.matches_type(&column.data_type) {
+            return Err(format!("Type mismatch for column '{}'", column.name));
+        }
+    }
+
+    Ok(values)
+}
+
+// None when part of the key is NULL. The key of a UNIQUE index can have expressions, which are
+// evaluated like the index's own (see `index_entries`).
+fn unique_key(
+    columns: &[Column],
+    constraint: &UniqueConstraint,
+    values: &[Value],
+) -> Option<Vec<Value>> {
+    constraint
+        .columns
+        .iter()
+        .map(|name| {
+            let value = match columns.iter().position(|c| &c.name == name) {
+                Some(position) => values[position].clone(),
+                None => evaluator::evaluate_stored(name, columns, values).unwrap_or(Value::Null),
+            };
+            match value {
+                Value::Null => None,
+                value => Some(value),
+            }
+        })
+        .collect()
+}
+
+pub fn duplicate_key(key: &[Value], constraint: &UniqueConstraint) -> String {
+    let key: Vec<String> = key.iter().map(|value| value.to_string()).collect();
+    format!("Duplicate key ({}) violates {}", key.join(", "), constraint)
+}
+
+fn unique_constraints(
+    constraints: &[UniqueConstraint],
+    column_indexes: &[ColumnIndex],
+) -> Vec<UniqueConstraint> {
+    constraints
+        .iter()
+        .cloned()
+        .chain(column_indexes.iter().filter_map(ColumnIndex::constraint))
+        .collect()
+}
+
+// The index whose keys are the values of `column`: the one behind a constraint starting with the
+// column, which is named after it, or else a created index over every row starting with it
+fn lookup_index<'a>(
+    constraints: &'a [UniqueConstraint],
+    column_indexes: &'a [ColumnIndex],
+    column: &str,
+) -> Option<&'a str> {
+    match constraints
+        .iter()
+        .find(|constraint| constraint.columns[0].eq_ignore_ascii_case(column))
+    {
+        Some(constraint) => Some(&constraint.columns[0]),
+        None => column_indexes
+            .iter()
+            .find(|index| !index.is_partial() && index.columns[0].eq_ignore_ascii_case(column))
+            .map(|index| index.name.as_str()),
+    }
+}
+
+// The (index name, key) pairs a row contributes: one for the first column of each constraint,
+// named after the column, one per created index the row belongs in, keyed by its columns and
+// expressions, and one per distinct word for each full-text index
+fn index_entries(
+    columns: &[Column],
+    constraints: &[UniqueConstraint],
+    column_indexes: &[ColumnIndex],
+    full_text_indexes: &[FullTextIndex],
+    values: &[Value],
+) -> Vec<(String, Vec<Value>)> {
+    let position = |name: &str| {
+        columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))
+    };
+    let value_of = |name: &str| position(name).map_or(Value::Null, |i| values[i].clone());
+    let mut entries: Vec<(String, Vec<Value>)> = Vec::new();
+    for constraint in constraints {
+        let first = &constraint.columns[0];
+        if !entries.iter().any(|(name, _)| name == first) {
+            entries.push((first.clone(), vec![value_of(first)]));
+        }
+    }
+    // An expression that fails on a row, e.g. lower() of a number, indexes it under NULL
+    let evaluate = |sql: &str| evaluator::evaluate_stored(sql, columns, values);
+    for index in column_indexes {
+        if let Some(predicate) = &index.predicate {
+            if !matches!(evaluate(predicate), Ok(value) if evaluator::is_truthy(&value)) {
+                continue;
+            }
+        }
+        let key = index
+            .columns
+            .iter()
+            .map(|key| match position(key) {
+                Some(i) => values[i].clone(),
+                None => evaluate(key).unwrap_or(Value::Null),
+            })
+            .collect();
+        entries.push((index.name.clone(), key));
+    }
+
+    for index in full_text_indexes {
+        let Some(Value::Text(text)) = position(&index.column).map(|i| &values[i]) else {
+            continue;
+        };
+        let words: BTreeSet<String> = full_text::tokenize(text).into_iter().collect();
+        for word in words {
+            entries.push((index.name.clone(), vec![Value::Text(word)]));
+        }
+    }
+    entries
+}
+
+// Whether an index key that names no column is meant as one, rather than as an expression
+fn is_identifier(key: &str) -> bool {
+    key.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
 impl fmt::Display for Table {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut pretty_table = PrettyTable::new();
@@ -97,7 +815,7 @@ impl fmt::Display for Table {
             let pretty_row = PrettyRow::new(
                 row.values
                     .iter()
-                    .map(|value| Cell::new(&value_to_string(value)))
+                    .map(|value| Cell::new(&value.to_string()))
                     .collect(),
             );
             pretty_table.add_row(pretty_row);
@@ -106,12 +824,3 @@ impl fmt::Display for Table {
         write!(f, "{}", pretty_table)
     }
 }
-
-// Helper function to convert Value to String
-fn value_to_string(value: &Value) -> String {
-    match value {
-        Value::Integer(i) => i.to_string(),
-        Value::Text(s) => s.clone(),
-        // Add more variants as needed
-    }
-}
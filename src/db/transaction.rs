@@ -0,0 +1,59 @@
+use super::data_types::CaseInsensitiveString;
+use super::table::{Row, Table, TableSchema};
+
+// One change to the database, recorded as what is needed to take it back
+#[derive(Debug)]
+pub enum UndoEntry {
+    // CREATE TABLE replaces a table of the same name, so the old one is kept
+    CreateTable {
+        name: CaseInsensitiveString,
+        previous: Option<Table>,
+    },
+    // The rows as they were before the change; None for rows that didn't exist yet
+    Rows {
+        table: CaseInsensitiveString,
+        rows: Vec<(usize, Option<Row>)>,
+    },
+    // CREATE and DROP INDEX only change the schema; the rows stay as they are
+    Schema {
+        table: CaseInsensitiveString,
+        previous: TableSchema,
+    },
+    NextRowId(usize),
+}
+
+// An open transaction: every change made since BEGIN, and the position in the log at which each
+// savepoint was set. Engines log their changes in whatever form they need to take them back.
+#[derive(Debug)]
+pub struct Transaction<E = UndoEntry> {
+    pub log: Vec<E>,
+    savepoints: Vec<(String, usize)>,
+}
+
+impl<E> Default for Transaction<E> {
+    fn default() -> Self {
+        Transaction {
+            log: Vec::new(),
+            savepoints: Vec::new(),
+        }
+    }
+}
+
+impl<E> Transaction<E> {
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.push((name.to_string(), self.log.len()));
+    }
+
+    // Forgets the savepoint and every savepoint set after it, returning its log position. Names
+    // may repeat, in which case the most recent one is meant.
+    pub fn release(&mut self, name: &str) -> Result<usize, String> {
+        let position = self
+            .savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Savepoint '{}' does not exist", name))?;
+        let (_, log_position) = self.savepoints[position];
+        self.savepoints.truncate(position);
+        Ok(log_position)
+    }
+}
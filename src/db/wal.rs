@@ -0,0 +1,259 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use super::data_types::CaseInsensitiveString;
+use super::fault;
+use super::table::{Row, Table, TableSchema};
+
+// One part of a committed change, stored as the state it left behind rather than as the operation
+// that produced it. Replaying a record therefore gives the same result however often it is
+// applied, which is what makes it safe to replay a log that was already checkpointed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Redo {
+    // The whole table, for CREATE TABLE and DROP TABLE; None if it no longer exists
+    Table {
+        name: CaseInsensitiveString,
+        table: Option<Table>,
+    },
+    // The rows as they are after the change; None for deleted rows
+    Rows {
+        table: CaseInsensitiveString,
+        rows: Vec<(usize, Option<Row>)>,
+    },
+    NextRowId(usize),
+    // The table's new schema, for changes to its indexes or statistics that keep its rows: stores
+    // add and remove the entries of the indexes that changed
+    Schema {
+        name: CaseInsensitiveString,
+        schema: TableSchema,
+    },
+}
+
+// Each record is framed as its length and CRC-32, both little endian u32s, followed by the
+// bincode encoded entries
+const HEADER_SIZE: usize = 8;
+
+// Append-only log of the changes committed since the last checkpoint, kept next to the database
+// file. A record only counts once it has been written completely and fsynced, so a crash can at
+// worst leave a torn record at the end, which is cut off the next time the log is opened.
+// Records are lists of `E`: redo records for the in-memory engine, page images for the paged one.
+#[derive(Debug)]
+pub struct Wal<E = Redo> {
+    file: File,
+    size: u64,
+    entries: PhantomData<E>,
+}
+
+impl Wal {
+    // The log that belongs to the database file at `path`, e.g. `users.db-wal` for `users.db`
+    pub fn path_for(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push("-wal");
+        PathBuf::from(name)
+    }
+}
+
+impl<E: Serialize + DeserializeOwned> Wal<E> {
+    // Opens or creates the log and returns the complete records in it, oldest first
+    pub fn open(path: &Path) -> io::Result<(Wal<E>, Vec<Vec<E>>)> {
+        let mut records = Vec::new();
+        let wal = Wal::open_with(path, |record| {
+            records.push(record);
+            Ok(())
+        })?;
+        Ok((wal, records))
+    }
+
+    // Like `open`, but hands the records to `read` one at a time instead of keeping them all
+    pub fn open_with(path: &Path, read: impl FnMut(Vec<E>) -> io::Result<()>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let length = file.metadata()?.len();
+        let valid = read_records(&mut file, length, read)?;
+        if valid < length {
+            // Drop the torn tail so that new records don't end up behind it
+            file.set_len(valid)?;
+            file.sync_all()?;
+        }
+        Ok(Wal {
+            file,
+            size: valid,
+            entries: PhantomData,
+        })
+    }
+
+    // Hands the records to `read`, oldest first
+    pub fn for_each(&mut self, read: impl FnMut(Vec<E>) -> io::Result<()>) -> io::Result<()> {
+        read_records(&mut self.file, self.size, read).map(|_| ())
+    }
+
+    // Appends a record and waits until it is on disk
+    pub fn append(&mut self, entries: &[E]) -> io::Result<()> {
+        let payload = bincode::serialize(entries).map_err(io::Error::other)?;
+        let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        // Opened without append mode so that writes land at the end of the valid records
+        let written = self.write_frame(&frame);
+        if let Err(e) = written {
+            // Don't leave part of the frame behind for the next record to follow
+            let _ = self.file.set_len(self.size);
+            return Err(e);
+        }
+        self.size += frame.len() as u64;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.size))?;
+        fault::check("Wal::append").map_err(io::Error::other)?;
+        self.file.write_all(frame)?;
+        self.file.sync_data()
+    }
+
+    // Empties the log once everything in it is part of the database file
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.size = 0;
+        Ok(())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+// Reads the records in the first `length` bytes of the file up to the first incomplete or damaged
+// one, returning the number of bytes they take up
+fn read_records<E: DeserializeOwned>(
+    file: &mut File,
+    length: u64,
+    mut read: impl FnMut(Vec<E>) -> io::Result<()>,
+) -> io::Result<u64> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file.take(length));
+    let mut offset = 0;
+    loop {
+        let mut header = [0; HEADER_SIZE];
+        if !read_fully(&mut reader, &mut header)? {
+            break;
+        }
+        let size = u32::from_le_bytes(header[0..4].try_into().unwrap()) as u64;
+        let checksum = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let end = offset + HEADER_SIZE as u64 + size;
+        if end > length {
+            break;
+        }
+        let mut payload = vec![0; size as usize];
+        if !read_fully(&mut reader, &mut payload)? || crc32fast::hash(&payload) != checksum {
+            break;
+        }
+        match bincode::deserialize(&payload) {
+            Ok(entries) => read(entries)?,
+            Err(_) => break,
+        }
+        offset = end;
+    }
+    Ok(offset)
+}
+
+// Fills `buffer`, or returns false if the input ends first
+fn read_fully(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::data_types::Value;
+    use std::fs;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn record(id: usize) -> Vec<Redo> {
+        vec![
+            Redo::Rows {
+                table: CaseInsensitiveString("t".to_string()),
+                rows: vec![(
+                    id,
+                    Some(Row {
+                        values: vec![Value::Integer(id as i64), Value::Text("x".to_string())],
+                    }),
+                )],
+            },
+            Redo::NextRowId(id + 1),
+        ]
+    }
+
+    #[test]
+    fn test_torn_tail_is_dropped() {
+        let path = temp_path("torn.db-wal");
+        let (mut wal, records) = Wal::open(&path).unwrap();
+        assert!(records.is_empty());
+        wal.append(&record(0)).unwrap();
+        wal.append(&record(1)).unwrap();
+        let first = fs::read(&path).unwrap().len() / 2;
+        wal.append(&record(2)).unwrap();
+        drop(wal);
+        let full = fs::read(&path).unwrap();
+
+        // A log cut anywhere in the last record keeps the records before it
+        for length in 2 * first..full.len() {
+            fs::write(&path, &full[..length]).unwrap();
+            let (_, records) = Wal::<Redo>::open(&path).unwrap();
+            assert_eq!(records, vec![record(0), record(1)]);
+            assert_eq!(fs::read(&path).unwrap().len(), 2 * first);
+        }
+
+        // So does a damaged last record, and appending afterwards picks up after the good ones
+        let mut damaged = full.clone();
+        *damaged.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, &damaged).unwrap();
+        let (mut wal, records) = Wal::open(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        wal.append(&record(3)).unwrap();
+        drop(wal);
+        let (_, records) = Wal::<Redo>::open(&path).unwrap();
+        assert_eq!(records, vec![record(0), record(1), record(3)]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_append_leaves_nothing_behind() {
+        let path = temp_path("failed.db-wal");
+        let (mut wal, _) = Wal::open(&path).unwrap();
+        wal.append(&record(0)).unwrap();
+        fault::fail_after(0);
+        assert!(wal.append(&record(1)).is_err());
+        wal.append(&record(2)).unwrap();
+        drop(wal);
+
+        let (mut wal, records) = Wal::<Redo>::open(&path).unwrap();
+        assert_eq!(records, vec![record(0), record(2)]);
+        wal.reset().unwrap();
+        assert_eq!(wal.size(), 0);
+        assert!(Wal::<Redo>::open(&path).unwrap().1.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
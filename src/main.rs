@@ -1,13 +1,10 @@
-mod db;
-mod sql_engine;
-
-use db::btreemap_database::Database;
-use db::data_types::{Column, DataType};
-use db::table::{Row, Table};
-use serde::{Deserialize, Serialize};
-use sql_engine::{process_sql, SqlCommand};
-use std::collections::BTreeMap;
-use std::fs;
+use banditdb::db::btreemap_database::Database;
+use banditdb::db::engine::StorageEngine;
+use banditdb::db::paged_database::PagedDatabase;
+use banditdb::db::pager::DEFAULT_MEMORY_BUDGET;
+use banditdb::db::sled_database::SledDatabase;
+use banditdb::sql_engine::executor::{self, ExecutionResult, Settings};
+use banditdb::sql_engine::{process_sql, SqlCommand};
 use std::io::{self, BufRead, Write};
 use std::path::Path;
 
@@ -118,24 +115,101 @@ use std::path::Path;
 //     Ok(())
 // }
 
-#[allow(unused_variables)]
 fn main() -> io::Result<()> {
-    // To run application use cargo run -- my_database_name
+    // To run application use
+    // cargo run -- my_database_name [--engine memory|sled|paged] [--memory-budget <MiB>]
+    //     [--sort-memory <MiB>]
     let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
+    if args.len() < 2 || !args.len().is_multiple_of(2) {
         eprint!("Main: invalid number of arguments.");
         std::process::exit(1);
     }
+    let mut engine = "memory";
+    let mut memory_budget = DEFAULT_MEMORY_BUDGET;
+    let mut settings = Settings::default();
+    for option in args[2..].chunks(2) {
+        match option[0].as_str() {
+            "--engine" => engine = option[1].as_str(),
+            // What the sled and paged engines hold in memory of the rows written since the last
+            // commit, and the paged engine's buffer pool, which share it
+            "--memory-budget" => match option[1]
+                .parse::<usize>()
+                .ok()
+                .filter(|mebibytes| *mebibytes > 0)
+                .and_then(|mebibytes| mebibytes.checked_mul(1024 * 1024))
+            {
+                Some(bytes) => memory_budget = bytes,
+                None => {
+                    eprintln!(
+                        "Main: invalid memory budget '{}', expected at least 1 MiB.",
+                        option[1]
+                    );
+                    std::process::exit(1);
+                }
+            },
+            // How much an ORDER BY sorts in memory before it spills to temporary files. None at
+            // all would write each row to a file of its own.
+            "--sort-memory" => match option[1]
+                .parse::<usize>()
+                .ok()
+                .filter(|mebibytes| *mebibytes > 0)
+                .and_then(|mebibytes| mebibytes.checked_mul(1024 * 1024))
+            {
+                Some(bytes) => settings.sort_memory = bytes,
+                None => {
+                    eprintln!(
+                        "Main: invalid sort memory '{}', expected at least 1 MiB.",
+                        option[1]
+                    );
+                    std::process::exit(1);
+                }
+            },
+            other => {
+                eprintln!("Main: unknown option '{}'.", other);
+                std::process::exit(1);
+            }
+        }
+    }
 
     let db_name = &args[1];
-    let db_path = Path::new("src").join(format!("{}.db", db_name));
-
-    let mut database = if db_path.exists() {
-        println!("Main: loading existing database: {}", db_path.display());
-        Database::load_from_file(&db_path)?
-    } else {
-        println!("Main: creating new database: {}", db_path.display());
-        Database::new()
+    let opened: io::Result<Box<dyn StorageEngine>> = match engine {
+        // Kept in memory, with a snapshot file and a write-ahead log on disk
+        "memory" => {
+            let db_path = Path::new("src").join(format!("{}.db", db_name));
+            if db_path.exists() {
+                println!("Main: loading existing database: {}", db_path.display());
+            } else {
+                println!("Main: creating new database: {}", db_path.display());
+            }
+            // Replays whatever was committed after the last checkpoint
+            Database::open(&db_path).map(|database| Box::new(database) as _)
+        }
+        "sled" => {
+            let db_path = Path::new("src").join(format!("{}.sled", db_name));
+            println!("Main: opening sled database: {}", db_path.display());
+            SledDatabase::open(&db_path, memory_budget).map(|database| Box::new(database) as _)
+        }
+        // Only what the queries touch is read, through a buffer pool of half of `memory_budget`
+        // bytes
+        "paged" => {
+            let db_path = Path::new("src").join(format!("{}.pages", db_name));
+            println!("Main: opening paged database: {}", db_path.display());
+            PagedDatabase::open(&db_path, memory_budget).map(|database| Box::new(database) as _)
+        }
+        other => {
+            eprintln!(
+                "Main: unknown engine '{}', expected memory, sled or paged.",
+                other
+            );
+            std::process::exit(1);
+        }
+    };
+    let mut database = match opened {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("Main: could not open database: {}", e);
+            std::process::exit(1);
+        }
     };
 
     let stdin = io::stdin();
@@ -153,53 +227,45 @@ fn main() -> io::Result<()> {
             break;
         }
 
+        // `export <file>` writes a JSON copy of the database
+        if let Some(("export", target)) = input.split_once(' ') {
+            match database.export_json(Path::new(target.trim())) {
+                Ok(()) => println!("Main: database exported to '{}'.", target.trim()),
+                Err(e) => println!("Main: error exporting database: {}", e),
+            }
+            continue;
+        }
+
         match process_sql(input) {
-            Ok(command) => match execute_command(&mut database, command) {
-                Ok(message) => {
-                    println!("{}", message);
-                    database.save_to_file(&db_path)?;
-                }
+            Ok(command) => match execute_command(database.as_mut(), command, &settings) {
+                // Committed changes are already stored by the engine
+                Ok(message) => println!("{}", message),
                 Err(e) => println!("Main: error executing command: {}", e),
             },
             Err(e) => println!("Main: error processing SQL: {}", e),
         }
     }
 
-    database.save_to_file(&db_path)?;
+    if database.in_transaction() {
+        println!("Main: rolling back the open transaction.");
+        database.rollback().map_err(io::Error::other)?;
+    }
+    database.checkpoint()?;
     println!("Database saved. Exiting.");
     Ok(())
 }
 
-fn execute_command(database: &mut Database, command: SqlCommand) -> Result<String, String> {
-    match command {
-        SqlCommand::CreateTable { name, columns } => {
-            database.create_table(name.clone(), columns);
-            Ok(format!("Main: table '{}' created successfully.", name))
-        }
-        SqlCommand::Insert {
-            table,
-            columns,
-            values,
-        } => {
-            // TODO: fix method
-            // database.insert_row(&table, values)?;
-            Ok(format!(
-                "Main: row inserted successfully into table '{}'.",
-                table
-            ))
-        }
-        SqlCommand::Select {
-            table,
-            columns,
-            where_clause,
-            join_clause,
-        } => {
-            let result = database.select(&table, &columns)?;
-            // Assuming you have a function to pretty print the result
-            // TODO: either put the logic in here to print the table, leave logic in the table, or
-            // return the table
-            // print_result(&result, &columns);
-            Ok("Main: query executed successfully.".to_string())
-        }
+fn execute_command(
+    database: &mut dyn StorageEngine,
+    command: SqlCommand,
+    settings: &Settings,
+) -> Result<String, String> {
+    match executor::execute_with(database, command, settings)? {
+        ExecutionResult::Message(message) => Ok(message),
+        ExecutionResult::Rows(result) => Ok(format!(
+            "{}Main: query returned {} row(s).",
+            result,
+            result.rows.len()
+        )),
     }
 }
@@ -0,0 +1,613 @@
+use std::cmp::Ordering;
+
+use super::{parser, tokenizer, BinaryOperator, Expr, UnaryOperator};
+use crate::db::data_types::{Column, Value};
+use crate::db::datetime::{Date, DateField, Interval, Timestamp};
+use crate::db::decimal::Decimal;
+use crate::db::full_text::{self, Bm25, Query};
+use crate::db::json::{self, JsonAccess, JsonPath};
+
+// Per-statement state shared by every expression evaluated in it. CURRENT_DATE and
+// CURRENT_TIMESTAMP read `now`, which is taken from the database clock once per statement so that
+// every row sees the same instant.
+#[derive(Debug, Clone, Copy)]
+pub struct EvalContext {
+    pub now: Timestamp,
+}
+
+// The row an expression is evaluated against. Constant expressions (e.g. INSERT values) use an
+// empty scope.
+#[derive(Debug, Clone, Copy)]
+pub struct Scope<'a> {
+    columns: &'a [Column],
+    values: &'a [Value],
+    // Name of the table the row comes from, so that `table.column` resolves too
+    table: Option<&'a str>,
+    // A second row with the same columns that is only reachable through its qualifier, e.g. the
+    // `excluded` row in ON CONFLICT DO UPDATE
+    other: Option<(&'a str, &'a [Value])>,
+    // Id of a stored row, readable as the `rowid` pseudo column
+    row_id: Option<usize>,
+    // What rank() scores the row by
+    ranking: Option<&'a Ranking>,
+}
+
+// The statement's `column MATCH 'query'` condition, with BM25 statistics over the column for the
+// words of the query. rank() gives a row's score, higher for better matches.
+#[derive(Debug, Clone)]
+pub struct Ranking {
+    pub column: String,
+    pub bm25: Bm25,
+}
+
+impl<'a> Scope<'a> {
+    pub fn new(columns: &'a [Column], values: &'a [Value]) -> Self {
+        Scope {
+            columns,
+            values,
+            table: None,
+            other: None,
+            row_id: None,
+            ranking: None,
+        }
+    }
+
+    pub fn empty() -> Scope<'static> {
+        Scope::new(&[], &[])
+    }
+
+    pub fn with_table(self, table: &'a str) -> Self {
+        Scope {
+            table: Some(table),
+            ..self
+        }
+    }
+
+    pub fn with_other(self, qualifier: &'a str, values: &'a [Value]) -> Self {
+        Scope {
+            other: Some((qualifier, values)),
+            ..self
+        }
+    }
+
+    pub fn with_row_id(self, row_id: usize) -> Self {
+        Scope {
+            row_id: Some(row_id),
+            ..self
+        }
+    }
+
+    pub fn with_ranking(self, ranking: Option<&'a Ranking>) -> Self {
+        Scope { ranking, ..self }
+    }
+
+    // The BM25 score of the row, with four decimal places; NULL for a row without text
+    fn rank(&self) -> Result<Value, String> {
+        let ranking = self.ranking.ok_or_else(|| {
+            "Evaluator: rank() needs a 'column MATCH query' condition in the WHERE clause"
+                .to_string()
+        })?;
+        match self.lookup(&ranking.column)? {
+            Value::Text(text) => {
+                let score = ranking.bm25.score(&full_text::tokenize(&text));
+                Ok(Value::Decimal(Decimal::new(
+                    (score * 10_000.0).round() as i128,
+                    4,
+                )?))
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    // A real column named rowid takes precedence over the pseudo column
+    fn lookup(&self, name: &str) -> Result<Value, String> {
+        match (self.lookup_in(self.values, name), self.row_id) {
+            (Err(_), Some(row_id)) if name.eq_ignore_ascii_case("rowid") => {
+                Ok(Value::Integer(row_id as i64))
+            }
+            (result, _) => result.cloned(),
+        }
+    }
+
+    fn lookup_qualified(&self, table: &str, name: &str) -> Result<Value, String> {
+        match (self.table, self.other) {
+            (Some(own), _) if own.eq_ignore_ascii_case(table) => self.lookup(name),
+            (_, Some((qualifier, values))) if qualifier.eq_ignore_ascii_case(table) => {
+                self.lookup_in(values, name).cloned()
+            }
+            _ => Err(format!("Evaluator: unknown table '{}'", table)),
+        }
+    }
+
+    fn lookup_in(&self, values: &'a [Value], name: &str) -> Result<&'a Value, String> {
+        self.columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))
+            .and_then(|i| values.get(i))
+            .ok_or_else(|| format!("Evaluator: column '{}' not found", name))
+    }
+}
+
+pub fn evaluate(expr: &Expr, scope: &Scope, ctx: &EvalContext) -> Result<Value, String> {
+    match expr {
+        Expr::Column(name) => scope.lookup(name),
+        Expr::QualifiedColumn { table, column } => scope.lookup_qualified(table, column),
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Unary { operator, expr } => {
+            let value = evaluate(expr, scope, ctx)?;
+            match (operator, value) {
+                (_, Value::Null) => Ok(Value::Null),
+                (UnaryOperator::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+                (UnaryOperator::Minus, Value::Integer(n)) => n
+                    .checked_neg()
+                    .map(Value::Integer)
+                    .ok_or_else(|| "Evaluator: integer overflow".to_string()),
+                (UnaryOperator::Minus, Value::Decimal(decimal)) => {
+                    Ok(Value::Decimal(decimal.negate()))
+                }
+                (UnaryOperator::Minus, Value::Interval(interval)) => {
+                    Ok(Value::Interval(interval.negate()))
+                }
+                (operator, value) => Err(format!(
+                    "Evaluator: cannot apply '{}' to {}",
+                    operator.to_string().trim(),
+                    value.type_name()
+                )),
+            }
+        }
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left = evaluate(left, scope, ctx)?;
+            let right = evaluate(right, scope, ctx)?;
+            evaluate_binary(left, *operator, right)
+        }
+        Expr::IsNull { expr, negated } => {
+            let is_null = evaluate(expr, scope, ctx)? == Value::Null;
+            Ok(Value::Boolean(is_null != *negated))
+        }
+        // `low <= expr AND expr <= high`, with the same NULL handling
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => {
+            let value = evaluate(expr, scope, ctx)?;
+            let low = evaluate(low, scope, ctx)?;
+            let high = evaluate(high, scope, ctx)?;
+            let above = evaluate_binary(value.clone(), BinaryOperator::GreaterOrEqual, low)?;
+            let below = evaluate_binary(value, BinaryOperator::LessOrEqual, high)?;
+            let between = evaluate_binary(above, BinaryOperator::And, below)?;
+            Ok(negate_if(between, *negated))
+        }
+        // TRUE when some item is equal, otherwise NULL if an item or the value is NULL
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let value = evaluate(expr, scope, ctx)?;
+            let mut found = Value::Boolean(false);
+            for item in list {
+                let item = evaluate(item, scope, ctx)?;
+                found = evaluate_binary(
+                    found,
+                    BinaryOperator::Or,
+                    evaluate_binary(value.clone(), BinaryOperator::Equal, item)?,
+                )?;
+                if found == Value::Boolean(true) {
+                    break;
+                }
+            }
+            Ok(negate_if(found, *negated))
+        }
+        Expr::Function { name, args } if name == "rank" => {
+            if !args.is_empty() {
+                return Err("Evaluator: rank() takes no arguments".to_string());
+            }
+            scope.rank()
+        }
+        Expr::Function { name, args } => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, scope, ctx))
+                .collect::<Result<Vec<Value>, String>>()?;
+            evaluate_function(name, args, ctx)
+        }
+        Expr::Extract { field, expr } => {
+            let value = evaluate(expr, scope, ctx)?;
+            extract(*field, value)
+        }
+        Expr::CurrentDate => Ok(Value::Date(ctx.now.date())),
+        Expr::CurrentTimestamp => Ok(Value::Timestamp(ctx.now)),
+    }
+}
+
+// NOT BETWEEN and NOT IN, which leave NULL as it is
+fn negate_if(value: Value, negated: bool) -> Value {
+    match value {
+        Value::Boolean(b) if negated => Value::Boolean(!b),
+        value => value,
+    }
+}
+
+// Evaluates an expression kept as SQL text in the schema, such as the key or the predicate of an
+// index, against a row. Such expressions never read the clock.
+pub fn evaluate_stored(sql: &str, columns: &[Column], values: &[Value]) -> Result<Value, String> {
+    let tokens = tokenizer::tokenize(sql)?;
+    let mut iter = tokens.iter().peekable();
+    let expr = parser::parse_expression(&mut iter)?;
+    if iter.next().is_some() {
+        return Err(format!("Evaluator: '{}' is not a single expression", sql));
+    }
+    let ctx = EvalContext { now: Timestamp(0) };
+    evaluate(&expr, &Scope::new(columns, values), &ctx)
+}
+
+// WHERE clauses only keep rows for which the condition is TRUE; NULL (unknown) filters the row out
+pub fn is_truthy(value: &Value) -> bool {
+    matches!(value, Value::Boolean(true))
+}
+
+// Orders two non-NULL values, converting between compatible types first (a DATE compares with a
+// TIMESTAMP at midnight, and a string literal compares with a DATE/TIMESTAMP column by parsing it)
+pub fn compare_values(left: &Value, right: &Value) -> Result<Ordering, String> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => Ok(a.cmp(b)),
+        // Trailing spaces are insignificant (PAD SPACE), so a CHAR(n) column padded on insert
+        // still equals the unpadded literal it was inserted with
+        (Value::Text(a), Value::Text(b)) => {
+            Ok(a.trim_end_matches(' ').cmp(b.trim_end_matches(' ')))
+        }
+        (Value::Decimal(a), Value::Decimal(b)) => Ok(a.cmp(b)),
+        (Value::Decimal(a), Value::Integer(b)) => Ok(a.cmp(&Decimal::from_i64(*b))),
+        (Value::Integer(a), Value::Decimal(b)) => Ok(Decimal::from_i64(*a).cmp(b)),
+        (Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
+        (Value::Date(a), Value::Date(b)) => Ok(a.cmp(b)),
+        (Value::Timestamp(a), Value::Timestamp(b)) => Ok(a.cmp(b)),
+        (Value::Interval(a), Value::Interval(b)) => Ok(a.cmp(b)),
+        (Value::Json(a), Value::Json(b)) => Ok(a.cmp(b)),
+        (Value::Date(a), Value::Timestamp(b)) => Ok(a.to_timestamp()?.cmp(b)),
+        (Value::Timestamp(a), Value::Date(b)) => Ok(a.cmp(&b.to_timestamp()?)),
+        (Value::Date(_), Value::Text(s)) | (Value::Timestamp(_), Value::Text(s)) => {
+            compare_values(left, &Value::Timestamp(Timestamp::parse(s)?))
+        }
+        (Value::Text(s), Value::Date(_)) | (Value::Text(s), Value::Timestamp(_)) => {
+            compare_values(&Value::Timestamp(Timestamp::parse(s)?), right)
+        }
+        _ => Err(format!(
+            "Evaluator: cannot compare {} with {}",
+            left.type_name(),
+            right.type_name()
+        )),
+    }
+}
+
+fn evaluate_binary(left: Value, operator: BinaryOperator, right: Value) -> Result<Value, String> {
+    match operator {
+        BinaryOperator::And => {
+            return match (left, right) {
+                (Value::Boolean(false), _) | (_, Value::Boolean(false)) => {
+                    Ok(Value::Boolean(false))
+                }
+                (Value::Boolean(true), Value::Boolean(true)) => Ok(Value::Boolean(true)),
+                (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                (left, right) => Err(logical_type_error("AND", &left, &right)),
+            }
+        }
+        BinaryOperator::Or => {
+            return match (left, right) {
+                (Value::Boolean(true), _) | (_, Value::Boolean(true)) => Ok(Value::Boolean(true)),
+                (Value::Boolean(false), Value::Boolean(false)) => Ok(Value::Boolean(false)),
+                (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                (left, right) => Err(logical_type_error("OR", &left, &right)),
+            }
+        }
+        _ => {}
+    }
+
+    if left == Value::Null || right == Value::Null {
+        return Ok(Value::Null);
+    }
+
+    let comparison = |predicate: fn(Ordering) -> bool| {
+        compare_values(&left, &right).map(|ordering| Value::Boolean(predicate(ordering)))
+    };
+    match operator {
+        BinaryOperator::Equal => comparison(|o| o == Ordering::Equal),
+        BinaryOperator::NotEqual => comparison(|o| o != Ordering::Equal),
+        BinaryOperator::Less => comparison(|o| o == Ordering::Less),
+        BinaryOperator::LessOrEqual => comparison(|o| o != Ordering::Greater),
+        BinaryOperator::Greater => comparison(|o| o == Ordering::Greater),
+        BinaryOperator::GreaterOrEqual => comparison(|o| o != Ordering::Less),
+        BinaryOperator::JsonGet => json_get(&left, &right, JsonAccess::Json),
+        BinaryOperator::JsonGetText => json_get(&left, &right, JsonAccess::Text),
+        BinaryOperator::Match => match (&left, &right) {
+            (Value::Text(text), Value::Text(query)) => Ok(Value::Boolean(
+                Query::parse(query)?.matches(&full_text::tokenize(text)),
+            )),
+            _ => Err(format!(
+                "Evaluator: MATCH expects TEXT, got {} and {}",
+                left.type_name(),
+                right.type_name()
+            )),
+        },
+        _ => evaluate_arithmetic(left, operator, right),
+    }
+}
+
+fn logical_type_error(operator: &str, left: &Value, right: &Value) -> String {
+    format!(
+        "Evaluator: {} expects BOOLEAN operands, got {} and {}",
+        operator,
+        left.type_name(),
+        right.type_name()
+    )
+}
+
+fn evaluate_arithmetic(
+    left: Value,
+    operator: BinaryOperator,
+    right: Value,
+) -> Result<Value, String> {
+    let overflow = || "Evaluator: integer overflow".to_string();
+    match (left, operator, right) {
+        (Value::Integer(a), operator, Value::Integer(b)) => {
+            let result = match operator {
+                BinaryOperator::Plus => a.checked_add(b),
+                BinaryOperator::Minus => a.checked_sub(b),
+                BinaryOperator::Multiply => a.checked_mul(b),
+                BinaryOperator::Divide | BinaryOperator::Modulo if b == 0 => {
+                    return Err("Evaluator: division by zero".to_string())
+                }
+                BinaryOperator::Divide => a.checked_div(b),
+                BinaryOperator::Modulo => a.checked_rem(b),
+                _ => unreachable!("comparison and logical operators are handled by the caller"),
+            };
+            result.map(Value::Integer).ok_or_else(overflow)
+        }
+
+        // Mixing an INTEGER with a DECIMAL promotes the integer, the result stays exact
+        (Value::Decimal(a), operator, Value::Integer(b)) => {
+            evaluate_decimal(a, operator, Decimal::from_i64(b))
+        }
+        (Value::Integer(a), operator, Value::Decimal(b)) => {
+            evaluate_decimal(Decimal::from_i64(a), operator, b)
+        }
+        (Value::Decimal(a), operator, Value::Decimal(b)) => evaluate_decimal(a, operator, b),
+
+        // Date arithmetic. Adding an interval with a time part to a DATE yields a TIMESTAMP.
+        (Value::Date(date), BinaryOperator::Plus, Value::Interval(interval))
+        | (Value::Interval(interval), BinaryOperator::Plus, Value::Date(date)) => {
+            add_interval_to_date(date, &interval)
+        }
+        (Value::Date(date), BinaryOperator::Minus, Value::Interval(interval)) => {
+            add_interval_to_date(date, &interval.negate())
+        }
+        (Value::Date(date), BinaryOperator::Plus, Value::Integer(days))
+        | (Value::Integer(days), BinaryOperator::Plus, Value::Date(date)) => {
+            Ok(Value::Date(date.add_days(days)?))
+        }
+        (Value::Date(date), BinaryOperator::Minus, Value::Integer(days)) => Ok(Value::Date(
+            date.add_days(days.checked_neg().ok_or_else(overflow)?)?,
+        )),
+        (Value::Date(a), BinaryOperator::Minus, Value::Date(b)) => {
+            Ok(Value::Integer(a.0 as i64 - b.0 as i64))
+        }
+
+        (Value::Timestamp(timestamp), BinaryOperator::Plus, Value::Interval(interval))
+        | (Value::Interval(interval), BinaryOperator::Plus, Value::Timestamp(timestamp)) => {
+            Ok(Value::Timestamp(timestamp.add_interval(&interval)?))
+        }
+        (Value::Timestamp(timestamp), BinaryOperator::Minus, Value::Interval(interval)) => Ok(
+            Value::Timestamp(timestamp.add_interval(&interval.negate())?),
+        ),
+        (Value::Timestamp(a), BinaryOperator::Minus, Value::Timestamp(b)) => {
+            let micros = a.0.checked_sub(b.0).ok_or_else(overflow)?;
+            Ok(Value::Interval(Interval::new(0, 0, micros)))
+        }
+        (Value::Timestamp(a), BinaryOperator::Minus, Value::Date(b)) => evaluate_arithmetic(
+            Value::Timestamp(a),
+            operator,
+            Value::Timestamp(b.to_timestamp()?),
+        ),
+        (Value::Date(a), BinaryOperator::Minus, Value::Timestamp(b)) => evaluate_arithmetic(
+            Value::Timestamp(a.to_timestamp()?),
+            operator,
+            Value::Timestamp(b),
+        ),
+
+        (Value::Interval(a), BinaryOperator::Plus, Value::Interval(b)) => {
+            Ok(Value::Interval(a.add(&b)?))
+        }
+        (Value::Interval(a), BinaryOperator::Minus, Value::Interval(b)) => {
+            Ok(Value::Interval(a.add(&b.negate())?))
+        }
+
+        (left, operator, right) => Err(format!(
+            "Evaluator: unsupported operation {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+    }
+}
+
+// Parses a JSON document held in a JSON value or, as SQLite allows, in plain text
+fn json_document(value: &Value) -> Result<serde_json::Value, String> {
+    match value {
+        Value::Json(s) | Value::Text(s) => json::parse(s),
+        other => Err(format!(
+            "Evaluator: expected JSON, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+// Right hand side of -> / ->>: a key or '$.path' string, or an array index
+fn json_path(value: &Value) -> Result<JsonPath, String> {
+    match value {
+        Value::Text(path) => JsonPath::parse(path),
+        Value::Integer(index) => Ok(JsonPath::index(*index)),
+        other => Err(format!(
+            "Evaluator: JSON path must be TEXT or INTEGER, got {}",
+            other.type_name()
+        )),
+    }
+}
+
+fn json_get(document: &Value, path: &Value, access: JsonAccess) -> Result<Value, String> {
+    let document = json_document(document)?;
+    Ok(json_path(path)?
+        .select(&document)
+        .map_or(Value::Null, |selected| access.convert(selected)))
+}
+
+fn evaluate_decimal(
+    left: Decimal,
+    operator: BinaryOperator,
+    right: Decimal,
+) -> Result<Value, String> {
+    let result = match operator {
+        BinaryOperator::Plus => left.checked_add(&right),
+        BinaryOperator::Minus => left.checked_sub(&right),
+        BinaryOperator::Multiply => left.checked_mul(&right),
+        BinaryOperator::Divide => left.checked_div(&right),
+        BinaryOperator::Modulo => left.checked_rem(&right),
+        _ => unreachable!("comparison and logical operators are handled by the caller"),
+    };
+    result.map(Value::Decimal)
+}
+
+fn add_interval_to_date(date: Date, interval: &Interval) -> Result<Value, String> {
+    if interval.has_time() {
+        Ok(Value::Timestamp(
+            date.to_timestamp()?.add_interval(interval)?,
+        ))
+    } else {
+        Ok(Value::Date(date.add_interval(interval)?))
+    }
+}
+
+fn extract(field: DateField, value: Value) -> Result<Value, String> {
+    let timestamp = match value {
+        Value::Null => return Ok(Value::Null),
+        Value::Date(date) => date.to_timestamp()?,
+        Value::Timestamp(timestamp) => timestamp,
+        Value::Text(s) => Timestamp::parse(&s)?,
+        other => {
+            return Err(format!(
+                "Evaluator: cannot EXTRACT {} from {}",
+                field,
+                other.type_name()
+            ))
+        }
+    };
+    Ok(Value::Integer(timestamp.extract(field)))
+}
+
+fn evaluate_function(name: &str, args: Vec<Value>, ctx: &EvalContext) -> Result<Value, String> {
+    match name {
+        "now" => {
+            expect_arguments(name, &args, 0)?;
+            Ok(Value::Timestamp(ctx.now))
+        }
+        // strftime(format, value) with SQLite's argument order; 'now' formats the current time
+        "strftime" => {
+            expect_arguments(name, &args, 2)?;
+            let format = match &args[0] {
+                Value::Null => return Ok(Value::Null),
+                Value::Text(format) => format,
+                other => {
+                    return Err(format!(
+                        "Evaluator: strftime expects a TEXT format, got {}",
+                        other.type_name()
+                    ))
+                }
+            };
+            let timestamp = match &args[1] {
+                Value::Null => return Ok(Value::Null),
+                Value::Date(date) => date.to_timestamp()?,
+                Value::Timestamp(timestamp) => *timestamp,
+                Value::Text(s) if s.eq_ignore_ascii_case("now") => ctx.now,
+                Value::Text(s) => Timestamp::parse(s)?,
+                other => {
+                    return Err(format!(
+                        "Evaluator: strftime cannot format {}",
+                        other.type_name()
+                    ))
+                }
+            };
+            Ok(Value::Text(timestamp.format(format)?))
+        }
+        // json(text) validates a document and returns it in canonical form
+        "json" => {
+            expect_arguments(name, &args, 1)?;
+            match &args[0] {
+                Value::Null => Ok(Value::Null),
+                Value::Json(s) | Value::Text(s) => Ok(Value::Json(json::canonicalize(s)?)),
+                other => Err(format!(
+                    "Evaluator: json() expects TEXT, got {}",
+                    other.type_name()
+                )),
+            }
+        }
+        "json_extract" => {
+            expect_arguments(name, &args, 2)?;
+            if args.contains(&Value::Null) {
+                return Ok(Value::Null);
+            }
+            json_get(&args[0], &args[1], JsonAccess::Value)
+        }
+        // json_array_length(document [, path]); 0 for anything that isn't an array
+        "json_array_length" => {
+            if args.is_empty() || args.len() > 2 {
+                return Err("Evaluator: json_array_length() takes 1 or 2 arguments".to_string());
+            }
+            if args.contains(&Value::Null) {
+                return Ok(Value::Null);
+            }
+            let document = json_document(&args[0])?;
+            let target = match args.get(1) {
+                Some(path) => match json_path(path)?.select(&document) {
+                    Some(target) => target.clone(),
+                    None => return Ok(Value::Null),
+                },
+                None => document,
+            };
+            match target {
+                serde_json::Value::Array(items) => Ok(Value::Integer(items.len() as i64)),
+                _ => Ok(Value::Integer(0)),
+            }
+        }
+        "lower" | "upper" => {
+            expect_arguments(name, &args, 1)?;
+            match &args[0] {
+                Value::Null => Ok(Value::Null),
+                Value::Text(s) if name == "lower" => Ok(Value::Text(s.to_lowercase())),
+                Value::Text(s) => Ok(Value::Text(s.to_uppercase())),
+                other => Err(format!(
+                    "Evaluator: {}() expects TEXT, got {}",
+                    name,
+                    other.type_name()
+                )),
+            }
+        }
+        _ => Err(format!("Evaluator: unknown function '{}'", name)),
+    }
+}
+
+fn expect_arguments(name: &str, args: &[Value], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!(
+            "Evaluator: {}() takes {} argument(s) but {} were given",
+            name,
+            count,
+            args.len()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,2935 @@
+use core::fmt;
+use prettytable::{Cell, Row as PrettyRow, Table as PrettyTable};
+
+use super::evaluator::{compare_values, evaluate, is_truthy, EvalContext, Ranking, Scope};
+use super::external_sort::{ExternalSort, DEFAULT_SORT_MEMORY};
+use super::optimizer::{self, Operator, PhysicalPlan};
+use super::planner::{self, IndexPlan};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::{
+    BinaryOperator, ConflictAction, Expr, InsertSource, OnConflict, OrderBy, SelectItem, SqlCommand,
+};
+use crate::db::data_types::{Column, DataType, UniqueConstraint, Value};
+use crate::db::engine::{self, RowIdIter, RowIter, StorageEngine};
+use crate::db::full_text::{self, Bm25, FullTextIndex};
+use crate::db::json::{self, JsonPath};
+use crate::db::statistics::TableStatistics;
+use crate::db::table::{ColumnIndex, IndexRange, Row, TableSchema, ALL_ROWS};
+
+#[derive(Debug, PartialEq)]
+pub enum ExecutionResult {
+    Message(String),
+    Rows(QueryResult),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+// How much a statement may hold in memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    // Bytes of rows an ORDER BY sorts in memory before it spills them to temporary files
+    pub sort_memory: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            sort_memory: DEFAULT_SORT_MEMORY,
+        }
+    }
+}
+
+pub fn execute(
+    database: &mut dyn StorageEngine,
+    command: SqlCommand,
+) -> Result<ExecutionResult, String> {
+    execute_with(database, command, &Settings::default())
+}
+
+// Every statement is all or nothing: one that fails partway leaves the database as it found it
+pub fn execute_with(
+    database: &mut dyn StorageEngine,
+    command: SqlCommand,
+    settings: &Settings,
+) -> Result<ExecutionResult, String> {
+    match command {
+        SqlCommand::Begin
+        | SqlCommand::Commit
+        | SqlCommand::Rollback { .. }
+        | SqlCommand::Savepoint { .. }
+        | SqlCommand::Release { .. } => execute_transaction_control(database, command),
+        command => engine::atomically(database, |database| {
+            execute_statement(database, command, settings)
+        }),
+    }
+}
+
+fn execute_transaction_control(
+    database: &mut dyn StorageEngine,
+    command: SqlCommand,
+) -> Result<ExecutionResult, String> {
+    match command {
+        SqlCommand::Begin => {
+            database.begin()?;
+            Ok(ExecutionResult::Message(
+                "Main: transaction started.".to_string(),
+            ))
+        }
+        SqlCommand::Commit => {
+            database.commit()?;
+            Ok(ExecutionResult::Message(
+                "Main: transaction committed.".to_string(),
+            ))
+        }
+        SqlCommand::Rollback { savepoint: None } => {
+            database.rollback()?;
+            Ok(ExecutionResult::Message(
+                "Main: transaction rolled back.".to_string(),
+            ))
+        }
+        SqlCommand::Rollback {
+            savepoint: Some(name),
+        } => {
+            database.rollback_to_savepoint(&name)?;
+            Ok(ExecutionResult::Message(format!(
+                "Main: rolled back to savepoint '{}'.",
+                name
+            )))
+        }
+        SqlCommand::Savepoint { name } => {
+            database.savepoint(&name)?;
+            Ok(ExecutionResult::Message(format!(
+                "Main: savepoint '{}' created.",
+                name
+            )))
+        }
+        SqlCommand::Release { name } => {
+            database.release_savepoint(&name)?;
+            Ok(ExecutionResult::Message(format!(
+                "Main: savepoint '{}' released.",
+                name
+            )))
+        }
+        _ => unreachable!("not a transaction control statement"),
+    }
+}
+
+fn execute_statement(
+    database: &mut dyn StorageEngine,
+    command: SqlCommand,
+    settings: &Settings,
+) -> Result<ExecutionResult, String> {
+    let ctx = EvalContext {
+        now: database.now(),
+    };
+
+    match command {
+        SqlCommand::CreateTable {
+            name,
+            columns,
+            constraints,
+        } => {
+            database.create_table(name.clone(), columns, constraints)?;
+            Ok(ExecutionResult::Message(format!(
+                "Main: table '{}' created successfully.",
+                name
+            )))
+        }
+        SqlCommand::Insert {
+            table,
+            columns,
+            source,
+            on_conflict,
+            returning,
+        } => {
+            let schema = database.schema(&table)?.columns;
+            let rows = match source {
+                InsertSource::Values(rows) => rows
+                    .iter()
+                    .map(|values| {
+                        values
+                            .iter()
+                            .map(|expr| evaluate(expr, &Scope::empty(), &ctx))
+                            .collect::<Result<Vec<Value>, String>>()
+                    })
+                    .collect::<Result<Vec<Vec<Value>>, String>>()?,
+                // The query runs to completion before anything is inserted, so selecting from the
+                // target table only sees the rows that were there before
+                InsertSource::Select(query) => match execute(database, *query)? {
+                    ExecutionResult::Rows(result) => {
+                        result.rows.into_iter().map(|row| row.values).collect()
+                    }
+                    ExecutionResult::Message(_) => {
+                        return Err("Executor: INSERT ... SELECT expects a query".to_string())
+                    }
+                },
+                // Columns have no DEFAULT clause yet, so every default is NULL
+                InsertSource::DefaultValues => vec![vec![Value::Null; schema.len()]],
+            };
+
+            let rows = rows
+                .into_iter()
+                .map(|values| build_row(&schema, &table, &columns, values))
+                .collect::<Result<Vec<Vec<Value>>, String>>()?;
+            let (inserts, updates) = match on_conflict {
+                Some(on_conflict) => resolve_conflicts(database, &table, rows, &on_conflict, &ctx)?,
+                None => (rows, Vec::new()),
+            };
+            let message = match updates.len() {
+                0 => format!(
+                    "Main: {} row(s) inserted successfully into table '{}'.",
+                    inserts.len(),
+                    table
+                ),
+                updated => format!(
+                    "Main: {} row(s) inserted and {} updated in table '{}'.",
+                    inserts.len(),
+                    updated,
+                    table
+                ),
+            };
+            let mut affected: Vec<usize> = updates.iter().map(|(row_id, _)| *row_id).collect();
+            affected.extend(database.write_rows(&table, inserts, updates)?);
+            match returning {
+                Some(items) => {
+                    affected.sort_unstable();
+                    returning_rows(database, &table, &affected, &items, &ctx)
+                }
+                None => Ok(ExecutionResult::Message(message)),
+            }
+        }
+        SqlCommand::Update {
+            table,
+            assignments,
+            where_clause,
+            returning,
+        } => {
+            let table_schema = database.schema(&table)?;
+            let schema = &table_schema.columns;
+            let mut updates = Vec::new();
+            let rows =
+                candidate_rows(database, &table, &table_schema, where_clause.as_ref(), &ctx)?;
+            for row in rows {
+                let (row_id, row) = row?;
+                let scope = Scope::new(schema, &row.values)
+                    .with_table(&table)
+                    .with_row_id(row_id);
+                if let Some(condition) = &where_clause {
+                    if !is_truthy(&evaluate(condition, &scope, &ctx)?) {
+                        continue;
+                    }
+                }
+                let values = assign(schema, &table, &row.values, &assignments, &scope, &ctx)?;
+                updates.push((row_id, values));
+            }
+
+            let affected: Vec<usize> = updates.iter().map(|(row_id, _)| *row_id).collect();
+            database.write_rows(&table, Vec::new(), updates)?;
+            match returning {
+                Some(items) => returning_rows(database, &table, &affected, &items, &ctx),
+                None => Ok(ExecutionResult::Message(format!(
+                    "Main: {} row(s) updated in table '{}'.",
+                    affected.len(),
+                    table
+                ))),
+            }
+        }
+        SqlCommand::Delete {
+            table,
+            where_clause,
+            returning,
+        } => {
+            let schema = database.schema(&table)?;
+            let mut affected = Vec::new();
+            for row in candidate_rows(database, &table, &schema, where_clause.as_ref(), &ctx)? {
+                let (row_id, row) = row?;
+                let scope = Scope::new(&schema.columns, &row.values)
+                    .with_table(&table)
+                    .with_row_id(row_id);
+                if let Some(condition) = &where_clause {
+                    if !is_truthy(&evaluate(condition, &scope, &ctx)?) {
+                        continue;
+                    }
+                }
+                affected.push(row_id);
+            }
+
+            // RETURNING describes the rows as they were, so it is computed before they go
+            let result = match returning {
+                Some(items) => returning_rows(database, &table, &affected, &items, &ctx)?,
+                None => ExecutionResult::Message(format!(
+                    "Main: {} row(s) deleted from table '{}'.",
+                    affected.len(),
+                    table
+                )),
+            };
+            database.delete_rows(&table, &affected)?;
+            Ok(result)
+        }
+        SqlCommand::CreateIndex {
+            name,
+            table,
+            unique,
+            full_text,
+            exprs,
+            where_clause,
+        } => {
+            let index_name = match exprs.as_slice() {
+                _ if full_text => {
+                    let index = full_text_index_definition(&table, name, &exprs, &where_clause)?;
+                    let index_name = index.name.clone();
+                    database.create_full_text_index(&table, index)?;
+                    index_name
+                }
+                _ => {
+                    let columns = database.schema(&table)?.columns;
+                    let index = column_index_definition(
+                        &table,
+                        &columns,
+                        name,
+                        unique,
+                        &exprs,
+                        where_clause.as_ref(),
+                    )?;
+                    let index_name = index.name.clone();
+                    database.create_index(&table, index)?;
+                    index_name
+                }
+            };
+            Ok(ExecutionResult::Message(format!(
+                "Main: index '{}' created successfully on table '{}'.",
+                index_name, table
+            )))
+        }
+        SqlCommand::DropIndex { name, table } => {
+            let table = match table {
+                Some(table) => table,
+                None => index_table(database, &name)?,
+            };
+            database.drop_index(&table, &name)?;
+            Ok(ExecutionResult::Message(format!(
+                "Main: index '{}' dropped from table '{}'.",
+                name, table
+            )))
+        }
+        SqlCommand::Analyze { table } => {
+            let tables = match table {
+                Some(table) => vec![table],
+                None => database.table_names()?,
+            };
+            for table in &tables {
+                let schema = database.schema(table)?;
+                let rows = database
+                    .scan(table, ALL_ROWS)?
+                    .map(|row| row.map(|(_, row)| row.values));
+                let mut statistics = TableStatistics::collect(&schema.columns, rows)?;
+                // A sample misjudges how many values a column has, which an index that orders it
+                // tells exactly
+                for (position, column) in schema.columns.iter().enumerate() {
+                    if let Some(index) = schema.ordering_index(&[column.name.as_str()], &[]) {
+                        let distinct = distinct_keys(database, table, index, position)?;
+                        if let Some(column) = statistics.column_mut(&column.name) {
+                            column.distinct = distinct;
+                        }
+                    }
+                }
+                database.set_statistics(table, statistics)?;
+            }
+            Ok(ExecutionResult::Message(format!(
+                "Main: {} table(s) analyzed.",
+                tables.len()
+            )))
+        }
+        SqlCommand::Begin
+        | SqlCommand::Commit
+        | SqlCommand::Rollback { .. }
+        | SqlCommand::Savepoint { .. }
+        | SqlCommand::Release { .. } => unreachable!("handled by execute_transaction_control"),
+        select @ SqlCommand::Select { .. } => {
+            let (plan, schema, ranking) = plan_select(database, select, &ctx)?;
+            execute_plan(
+                database,
+                &plan,
+                schema.as_ref(),
+                ranking.as_ref(),
+                None,
+                settings,
+                &ctx,
+            )
+            .map(ExecutionResult::Rows)
+        }
+        SqlCommand::Explain { analyze, query } => {
+            let (plan, schema, ranking) = plan_select(database, *query, &ctx)?;
+            let mut lines = plan.explain();
+            if analyze {
+                let profile = Profile::default();
+                let start = Instant::now();
+                execute_plan(
+                    database,
+                    &plan,
+                    schema.as_ref(),
+                    ranking.as_ref(),
+                    Some(&profile),
+                    settings,
+                    &ctx,
+                )?;
+                let elapsed = start.elapsed();
+                for (line, actual) in lines.iter_mut().zip(profile.into_inner()) {
+                    let actual = actual.borrow();
+                    line.push_str(&format!(
+                        " (actual rows={} time={:.3} ms",
+                        actual.rows,
+                        milliseconds(actual.elapsed)
+                    ));
+                    if actual.spilled > 0 {
+                        line.push_str(&format!(" spilled={} runs", actual.spilled));
+                    }
+                    line.push(')');
+                }
+                lines.push(format!("Execution time: {:.3} ms", milliseconds(elapsed)));
+            }
+            Ok(ExecutionResult::Rows(QueryResult {
+                columns: vec!["plan".to_string()],
+                rows: lines
+                    .into_iter()
+                    .map(|line| Row {
+                        values: vec![Value::Text(line)],
+                    })
+                    .collect(),
+            }))
+        }
+    }
+}
+
+// The plan for a SELECT, with the schema of the table it reads and the ranking rank() needs
+fn plan_select(
+    database: &dyn StorageEngine,
+    select: SqlCommand,
+    ctx: &EvalContext,
+) -> Result<(PhysicalPlan, Option<TableSchema>, Option<Ranking>), String> {
+    let SqlCommand::Select {
+        table,
+        table_args,
+        columns,
+        where_clause,
+        join_clause: _,
+        order_by,
+        limit,
+    } = select
+    else {
+        unreachable!("only SELECT queries are planned")
+    };
+    let schema = match table_args {
+        Some(_) => None,
+        None => Some(database.schema(&table)?),
+    };
+    let order_by = match &schema {
+        Some(schema) => resolve_positions(order_by, &columns, &schema.columns)?,
+        None => resolve_positions(order_by, &columns, &function_columns(&table)?)?,
+    };
+    let ranking = match &schema {
+        Some(schema) => ranking(
+            database,
+            &table,
+            schema,
+            where_clause.as_ref(),
+            &columns,
+            &order_by,
+            ctx,
+        )?,
+        None => None,
+    };
+    let plan = optimizer::logical_plan(table, table_args, columns, where_clause, order_by, limit);
+    let plan = optimizer::optimize(plan, schema.as_ref(), ctx);
+    Ok((plan, schema, ranking))
+}
+
+// `ORDER BY 2` sorts by the second column of the select list, `*` counting for every column of
+// the table
+fn resolve_positions(
+    order_by: Vec<OrderBy>,
+    items: &[SelectItem],
+    columns: &[Column],
+) -> Result<Vec<OrderBy>, String> {
+    let mut selected = Vec::new();
+    for item in items {
+        match item {
+            SelectItem::Wildcard => selected.extend(
+                columns
+                    .iter()
+                    .map(|column| Expr::Column(column.name.clone())),
+            ),
+            SelectItem::Expr { expr, .. } => selected.push(expr.clone()),
+        }
+    }
+    order_by
+        .into_iter()
+        .map(|item| match item.expr {
+            Expr::Literal(Value::Integer(position)) => {
+                let expr = usize::try_from(position)
+                    .ok()
+                    .and_then(|position| selected.get(position.checked_sub(1)?))
+                    .ok_or_else(|| {
+                        format!(
+                            "Executor: ORDER BY position {} is not in the select list",
+                            position
+                        )
+                    })?;
+                Ok(OrderBy {
+                    expr: expr.clone(),
+                    descending: item.descending,
+                })
+            }
+            _ => Ok(item),
+        })
+        .collect()
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// The rows the WHERE clause may keep, in row id order: the ones an index leads to, or else all of
+// them. The clause still has to be checked on each.
+fn candidate_rows<'a>(
+    database: &'a dyn StorageEngine,
+    table: &str,
+    schema: &TableSchema,
+    where_clause: Option<&Expr>,
+    ctx: &EvalContext,
+) -> Result<RowIter<'a>, String> {
+    let plan = optimizer::scan_plan(
+        schema,
+        table.to_string(),
+        where_clause.cloned(),
+        None,
+        &[],
+        None,
+        ctx,
+    );
+    match plan.operator {
+        Operator::IndexScan { plan, .. } => {
+            let row_ids = planned_row_ids(database, table, &plan)?;
+            Ok(fetch_rows(
+                database,
+                table,
+                Box::new(row_ids.into_iter().map(Ok)),
+            ))
+        }
+        _ => database.scan(table, ALL_ROWS),
+    }
+}
+
+// The ids in all of the plan's ranges, in row id order, as a scan would find them
+fn planned_row_ids(
+    database: &dyn StorageEngine,
+    table: &str,
+    plan: &IndexPlan,
+) -> Result<Vec<usize>, String> {
+    let mut row_ids = Vec::new();
+    for range in &plan.ranges {
+        for row_id in database.index_scan(table, &plan.index, range)? {
+            row_ids.push(row_id?);
+        }
+    }
+    row_ids.sort_unstable();
+    row_ids.dedup();
+    Ok(row_ids)
+}
+
+// Reads each row as it is asked for, so that a query that stops early reads no more of them
+fn fetch_rows<'a>(
+    database: &'a dyn StorageEngine,
+    table: &str,
+    row_ids: RowIdIter<'a>,
+) -> RowIter<'a> {
+    let table = table.to_string();
+    Box::new(row_ids.filter_map(move |row_id| {
+        row_id
+            .and_then(|row_id| {
+                let row = database.get_row(&table, row_id)?;
+                Ok(row.map(|row| (row_id, row)))
+            })
+            .transpose()
+    }))
+}
+
+// What rank() scores rows by when the SELECT asks for it. BM25 weighs words by how many rows of
+// the column hold them, so every row is read once to count them.
+fn ranking(
+    database: &dyn StorageEngine,
+    table: &str,
+    schema: &TableSchema,
+    where_clause: Option<&Expr>,
+    items: &[SelectItem],
+    order_by: &[OrderBy],
+    ctx: &EvalContext,
+) -> Result<Option<Ranking>, String> {
+    let is_rank = |expr: &Expr| matches!(expr, Expr::Function { name, .. } if name == "rank");
+    let ranked = items.iter().any(|item| match item {
+        SelectItem::Expr { expr, .. } => expr.contains(&is_rank),
+        SelectItem::Wildcard => false,
+    }) || order_by.iter().any(|item| item.expr.contains(&is_rank));
+    if !ranked {
+        return Ok(None);
+    }
+    let Some((column, query)) = planner::full_text_match(schema, where_clause, ctx) else {
+        return Ok(None);
+    };
+    let position = schema
+        .columns
+        .iter()
+        .position(|c| c.name == column)
+        .expect("MATCH conditions name one of the table's columns");
+
+    let mut error = None;
+    let documents = database
+        .scan(table, ALL_ROWS)?
+        .map_while(|row| row.map_err(|e| error = Some(e)).ok())
+        .filter_map(|(_, row)| match &row.values[position] {
+            Value::Text(text) => Some(full_text::tokenize(text)),
+            _ => None,
+        });
+    let bm25 = Bm25::new(&query.words(), documents);
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Some(Ranking { column, bm25 })),
+    }
+}
+
+// Without a name the index is called after the table and its columns, e.g. `users_email_idx`
+fn column_index_definition(
+    table: &str,
+    columns: &[Column],
+    name: Option<String>,
+    unique: bool,
+    exprs: &[Expr],
+    where_clause: Option<&Expr>,
+) -> Result<ColumnIndex, String> {
+    // Expressions are stored as SQL text, read back for every row the index gets an entry for
+    let stored = |expr: &Expr| {
+        check_index_expression(expr, columns)?;
+        Ok::<_, String>(planner::canonical(expr, columns).to_string())
+    };
+    let keys = exprs
+        .iter()
+        .map(|expr| match expr {
+            Expr::Column(column) => Ok(column.clone()),
+            expr => stored(expr),
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    let predicate = where_clause.map(stored).transpose()?;
+    let name = name.unwrap_or_else(|| {
+        let parts: Vec<&str> = exprs
+            .iter()
+            .map(|expr| match expr {
+                Expr::Column(column) => column.as_str(),
+                Expr::Function { name, .. } => name.as_str(),
+                // A path into a JSON column, e.g. `doc->>'email'`
+                Expr::Binary {
+                    left,
+                    operator: BinaryOperator::JsonGet | BinaryOperator::JsonGetText,
+                    ..
+                } => match left.as_ref() {
+                    Expr::Column(column) => column.as_str(),
+                    _ => "expr",
+                },
+                _ => "expr",
+            })
+            .collect();
+        format!("{}_{}_idx", table, parts.join("_"))
+    });
+    Ok(ColumnIndex {
+        name,
+        columns: keys,
+        unique,
+        predicate,
+    })
+}
+
+// Index expressions have to give the same value for a row whenever they are evaluated, so they
+// can only read the row's own columns and not the clock
+fn check_index_expression(expr: &Expr, columns: &[Column]) -> Result<(), String> {
+    let check = |expr: &Expr| check_index_expression(expr, columns);
+    let time_dependent = || "Executor: index expressions cannot depend on the current time";
+    match expr {
+        Expr::Column(name) => match columns
+            .iter()
+            .any(|column| column.name.eq_ignore_ascii_case(name))
+        {
+            true => Ok(()),
+            false => Err(format!("Column '{}' not found", name)),
+        },
+        Expr::QualifiedColumn { .. } => {
+            Err("Executor: index expressions can only use the table's own columns".to_string())
+        }
+        Expr::Literal(_) => Ok(()),
+        Expr::CurrentDate | Expr::CurrentTimestamp => Err(time_dependent().to_string()),
+        Expr::Function { name, args } => {
+            let reads_clock = name == "now"
+                || name == "strftime"
+                    && args.iter().any(|arg| {
+                        matches!(arg, Expr::Literal(Value::Text(s)) if s.eq_ignore_ascii_case("now"))
+                    });
+            if reads_clock {
+                return Err(time_dependent().to_string());
+            }
+            args.iter().try_for_each(check)
+        }
+        Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Extract { expr, .. } => {
+            check(expr)
+        }
+        Expr::Binary { left, right, .. } => check(left).and_then(|_| check(right)),
+        Expr::Between {
+            expr, low, high, ..
+        } => [expr, low, high]
+            .into_iter()
+            .try_for_each(|expr| check(expr)),
+        Expr::InList { expr, list, .. } => {
+            check(expr)?;
+            list.iter().try_for_each(check)
+        }
+    }
+}
+
+fn full_text_index_definition(
+    table: &str,
+    name: Option<String>,
+    exprs: &[Expr],
+    where_clause: &Option<Expr>,
+) -> Result<FullTextIndex, String> {
+    match (exprs, where_clause) {
+        ([Expr::Column(column)], None) => Ok(FullTextIndex {
+            name: name.unwrap_or_else(|| format!("{}_{}_fts", table, column)),
+            column: column.clone(),
+        }),
+        _ => Err("Executor: a FULLTEXT index is over a single column, without WHERE".to_string()),
+    }
+}
+
+// How many values other than NULL the column at `position` has, by skipping through `index`, which
+// starts with it, from one value to the next. Each skip reads a single row.
+fn distinct_keys(
+    database: &dyn StorageEngine,
+    table: &str,
+    index: &str,
+    position: usize,
+) -> Result<usize, String> {
+    let mut distinct = 0;
+    let mut range = IndexRange::all();
+    while let Some(row_id) = database.index_scan(table, index, &range)?.next() {
+        let row_id = row_id?;
+        let value = database
+            .get_row(table, row_id)?
+            .ok_or_else(|| format!("Row {} not found", row_id))?
+            .values
+            .swap_remove(position);
+        if value != Value::Null {
+            distinct += 1;
+        }
+        range.lower = Bound::Excluded(value);
+    }
+    Ok(distinct)
+}
+
+// The table that has an index called `name`, for DROP INDEX without ON
+fn index_table(database: &dyn StorageEngine, name: &str) -> Result<String, String> {
+    let mut found = Vec::new();
+    for table in database.table_names()? {
+        let schema = database.schema(&table)?;
+        let column_index = schema
+            .column_indexes
+            .iter()
+            .any(|index| index.name.eq_ignore_ascii_case(name));
+        let full_text_index = schema
+            .full_text_indexes
+            .iter()
+            .any(|index| index.name.eq_ignore_ascii_case(name));
+        if column_index || full_text_index {
+            found.push(table);
+        }
+    }
+    match found.len() {
+        0 => Err(format!("Executor: index '{}' not found", name)),
+        1 => Ok(found.remove(0)),
+        _ => Err(format!(
+            "Executor: index '{}' exists on tables {}; use DROP INDEX {} ON <table>",
+            name,
+            found.join(", "),
+            name
+        )),
+    }
+}
+
+// The columns of a table-valued function's rows
+fn function_columns(name: &str) -> Result<Vec<Column>, String> {
+    match name.to_lowercase().as_str() {
+        "json_each" => Ok(vec![
+            Column::new("key".to_string(), DataType::Text),
+            Column::new("value".to_string(), DataType::Json),
+            Column::new("type".to_string(), DataType::Text),
+        ]),
+        _ => Err(format!(
+            "Executor: unknown table-valued function '{}'",
+            name
+        )),
+    }
+}
+
+// Table-valued functions usable in FROM
+fn table_function(name: &str, args: Vec<Value>) -> Result<(Vec<Column>, Vec<Row>), String> {
+    let schema = function_columns(name)?;
+    match name.to_lowercase().as_str() {
+        // json_each(document [, path]) yields one row per array element or object member
+        "json_each" => {
+            let (document, path) = match args.as_slice() {
+                [document] => (document, None),
+                [document, Value::Text(path)] => (document, Some(JsonPath::parse(path)?)),
+                _ => {
+                    return Err(
+                        "Executor: json_each expects a document and an optional path".to_string(),
+                    )
+                }
+            };
+            let document = match document {
+                Value::Null => return Ok((schema, Vec::new())),
+                Value::Json(s) | Value::Text(s) => json::parse(s)?,
+                other => {
+                    return Err(format!(
+                        "Executor: json_each expects JSON, got {}",
+                        other.type_name()
+                    ))
+                }
+            };
+            let target = match &path {
+                Some(path) => match path.select(&document) {
+                    Some(target) => target,
+                    None => return Ok((schema, Vec::new())),
+                },
+                None => &document,
+            };
+
+            let row = |key: Value, value: &serde_json::Value| Row {
+                values: vec![
+                    key,
+                    json::to_sql_value(value),
+                    Value::Text(json::type_name(value).to_string()),
+                ],
+            };
+            let rows = match target {
+                serde_json::Value::Array(items) => items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| row(Value::Integer(i as i64), item))
+                    .collect(),
+                serde_json::Value::Object(members) => members
+                    .iter()
+                    .map(|(key, member)| row(Value::Text(key.clone()), member))
+                    .collect(),
+                scalar => vec![row(Value::Null, scalar)],
+            };
+            Ok((schema, rows))
+        }
+        _ => unreachable!("function_columns knows every table-valued function"),
+    }
+}
+
+// Rows to insert, and (row id, new values) replacements of stored rows
+type RowWrites = (Vec<Vec<Value>>, Vec<(usize, Vec<Value>)>);
+
+// Splits the proposed rows of an INSERT ... ON CONFLICT into rows to insert and updates of the
+// stored rows they collide with. Conflicts are looked up through the table's indexes.
+fn resolve_conflicts(
+    database: &dyn StorageEngine,
+    table_name: &str,
+    rows: Vec<Vec<Value>>,
+    on_conflict: &OnConflict,
+    ctx: &EvalContext,
+) -> Result<RowWrites, String> {
+    let schema = database.schema(table_name)?;
+    let unique_constraints = schema.unique_constraints();
+    let constraints: Vec<&UniqueConstraint> = if on_conflict.target.is_empty() {
+        unique_constraints.iter().collect()
+    } else {
+        let constraint = unique_constraints
+            .iter()
+            .find(|constraint| constraint.covers(&on_conflict.target))
+            .ok_or_else(|| {
+                format!(
+                    "Executor: ON CONFLICT ({}) does not match a PRIMARY KEY or UNIQUE constraint",
+                    on_conflict.target.join(", ")
+                )
+            })?;
+        vec![constraint]
+    };
+    let twice = || "Executor: ON CONFLICT DO UPDATE cannot affect the same row twice".to_string();
+
+    let mut inserts = Vec::new();
+    let mut proposed_keys = BTreeSet::new();
+    let mut updates: BTreeMap<usize, Vec<Value>> = BTreeMap::new();
+    for values in rows {
+        // Coerced first so that the keys compare like the stored ones
+        let values = schema.prepare_row(values)?;
+
+        // A clash with a row proposed earlier in the same statement
+        let keys: Vec<(usize, Vec<Value>)> = constraints
+            .iter()
+            .enumerate()
+            .filter_map(|(i, constraint)| Some((i, schema.unique_key(constraint, &values)?)))
+            .collect();
+        if keys.iter().any(|key| proposed_keys.contains(key)) {
+            match on_conflict.action {
+                ConflictAction::DoNothing => continue,
+                ConflictAction::DoUpdate { .. } => return Err(twice()),
+            }
+        }
+
+        let mut existing = None;
+        for constraint in &constraints {
+            existing = engine::conflicting_row(database, table_name, &schema, constraint, &values)?;
+            if existing.is_some() {
+                break;
+            }
+        }
+        match (existing, &on_conflict.action) {
+            (None, _) => {
+                proposed_keys.extend(keys);
+                inserts.push(values);
+            }
+            (Some(_), ConflictAction::DoNothing) => {}
+            (
+                Some(row_id),
+                ConflictAction::DoUpdate {
+                    assignments,
+                    where_clause,
+                },
+            ) => {
+                if updates.contains_key(&row_id) {
+                    return Err(twice());
+                }
+                let current = database
+                    .get_row(table_name, row_id)?
+                    .ok_or_else(|| format!("Row {} not found", row_id))?
+                    .values;
+                let scope = Scope::new(&schema.columns, &current)
+                    .with_table(table_name)
+                    .with_other("excluded", &values);
+                if let Some(condition) = where_clause {
+                    if !is_truthy(&evaluate(condition, &scope, ctx)?) {
+                        continue;
+                    }
+                }
+
+                updates.insert(
+                    row_id,
+                    assign(
+                        &schema.columns,
+                        table_name,
+                        &current,
+                        assignments,
+                        &scope,
+                        ctx,
+                    )?,
+                );
+            }
+        }
+    }
+
+    Ok((inserts, updates.into_iter().collect()))
+}
+
+// The new values of the scope's row after `SET col = expr, ...`. Every expression sees the row as
+// it was before the update.
+fn assign(
+    schema: &[Column],
+    table: &str,
+    current: &[Value],
+    assignments: &[(String, Expr)],
+    scope: &Scope,
+    ctx: &EvalContext,
+) -> Result<Vec<Value>, String> {
+    let mut values = current.to_vec();
+    for (name, expr) in assignments {
+        let position = schema
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Executor: column '{}' not found in '{}'", name, table))?;
+        values[position] = evaluate(expr, scope, ctx)?;
+    }
+    Ok(values)
+}
+
+// Evaluates a RETURNING list over the given stored rows
+fn returning_rows(
+    database: &dyn StorageEngine,
+    table: &str,
+    row_ids: &[usize],
+    items: &[SelectItem],
+    ctx: &EvalContext,
+) -> Result<ExecutionResult, String> {
+    let relation = Relation {
+        table: table.to_string(),
+        columns: database.schema(table)?.columns,
+        rows: fetch_rows(database, table, Box::new(row_ids.iter().copied().map(Ok))),
+    };
+    project(relation, items, None, ctx).map(ExecutionResult::Rows)
+}
+
+// Lays out the values of one INSERT row in table column order. Columns missing from an explicit
+// column list are filled with NULL.
+fn build_row(
+    schema: &[Column],
+    table: &str,
+    columns: &[String],
+    values: Vec<Value>,
+) -> Result<Vec<Value>, String> {
+    if columns.is_empty() {
+        if values.len() != schema.len() {
+            return Err(format!(
+                "Executor: table '{}' has {} columns but {} values given",
+                table,
+                schema.len(),
+                values.len()
+            ));
+        }
+        return Ok(values);
+    }
+    if columns.len() != values.len() {
+        return Err(format!(
+            "Executor: {} columns but {} values given",
+            columns.len(),
+            values.len()
+        ));
+    }
+
+    let mut row = vec![Value::Null; schema.len()];
+    for (name, value) in columns.iter().zip(values) {
+        let position = schema
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Executor: column '{}' not found in '{}'", name, table))?;
+        row[position] = value;
+    }
+    Ok(row)
+}
+
+// Rows handed from one operator of a plan to the next, with the columns they have
+struct Relation<'a> {
+    table: String,
+    columns: Vec<Column>,
+    rows: RowIter<'a>,
+}
+
+fn execute_plan<'a>(
+    database: &'a dyn StorageEngine,
+    plan: &PhysicalPlan,
+    schema: Option<&TableSchema>,
+    ranking: Option<&'a Ranking>,
+    profile: Option<&'a Profile>,
+    settings: &Settings,
+    ctx: &'a EvalContext,
+) -> Result<QueryResult, String> {
+    let Operator::Project { input, items } = &plan.operator else {
+        unreachable!("a SELECT plan ends in its projection")
+    };
+    let actual = profile.map(profiled);
+    let start = Instant::now();
+    let relation = rows(database, input, schema, ranking, profile, settings, ctx)?;
+    let result = project(relation, items, ranking, ctx)?;
+    if let Some(actual) = actual {
+        let mut actual = actual.borrow_mut();
+        actual.rows = result.rows.len();
+        actual.elapsed = start.elapsed();
+    }
+    Ok(result)
+}
+
+// What EXPLAIN ANALYZE found each operator of a plan to do, in the order `PhysicalPlan::explain`
+// lists them
+type Profile = RefCell<Vec<Rc<RefCell<Actual>>>>;
+
+// The rows an operator handed on and the time spent getting them, the operators below it
+// included, and the runs a sort wrote to disk
+#[derive(Debug, Default, Clone, Copy)]
+struct Actual {
+    rows: usize,
+    elapsed: Duration,
+    spilled: usize,
+}
+
+fn profiled(profile: &Profile) -> Rc<RefCell<Actual>> {
+    let actual = Rc::new(RefCell::new(Actual::default()));
+    profile.borrow_mut().push(actual.clone());
+    actual
+}
+
+// Counts the rows and times each one, adding to the time it took to set the operator up
+fn timed<'a>(relation: Relation<'a>, actual: Rc<RefCell<Actual>>, setup: Duration) -> Relation<'a> {
+    actual.borrow_mut().elapsed = setup;
+    let mut rows = relation.rows;
+    let rows = std::iter::from_fn(move || {
+        let start = Instant::now();
+        let row = rows.next();
+        let mut actual = actual.borrow_mut();
+        actual.elapsed += start.elapsed();
+        if let Some(Ok(_)) = row {
+            actual.rows += 1;
+        }
+        row
+    });
+    Relation {
+        rows: Box::new(rows),
+        ..relation
+    }
+}
+
+// The rows an operator hands on. Scans and filters pass them along one at a time, sorting reads
+// all of them first.
+fn rows<'a>(
+    database: &'a dyn StorageEngine,
+    plan: &PhysicalPlan,
+    schema: Option<&TableSchema>,
+    ranking: Option<&'a Ranking>,
+    profile: Option<&'a Profile>,
+    settings: &Settings,
+    ctx: &'a EvalContext,
+) -> Result<Relation<'a>, String> {
+    let actual = profile.map(profiled);
+    let start = Instant::now();
+    let table_columns = || {
+        schema
+            .expect("table scans are planned with the table's schema")
+            .columns
+            .clone()
+    };
+    let relation = match &plan.operator {
+        Operator::SeqScan {
+            table,
+            filter,
+            columns,
+        } => {
+            let relation = Relation {
+                table: table.clone(),
+                columns: table_columns(),
+                rows: database.scan(table, ALL_ROWS)?,
+            };
+            narrow(
+                filtered(relation, filter.as_ref(), ranking, ctx),
+                columns.as_deref(),
+            )
+        }
+        Operator::IndexScan {
+            table,
+            plan,
+            ordered,
+            filter,
+            columns,
+        } => {
+            // In key order the ids are read as the rows are asked for, so LIMIT stops the scan
+            let row_ids: RowIdIter<'_> = match ordered {
+                true => database.index_scan(table, &plan.index, &plan.ranges[0])?,
+                false => Box::new(planned_row_ids(database, table, plan)?.into_iter().map(Ok)),
+            };
+            let relation = Relation {
+                table: table.clone(),
+                columns: table_columns(),
+                rows: fetch_rows(database, table, row_ids),
+            };
+            narrow(
+                filtered(relation, filter.as_ref(), ranking, ctx),
+                columns.as_deref(),
+            )
+        }
+        Operator::FunctionScan { name, args, filter } => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, &Scope::empty(), ctx))
+                .collect::<Result<Vec<Value>, String>>()?;
+            let (columns, rows) = table_function(name, args)?;
+            let relation = Relation {
+                table: name.clone(),
+                columns,
+                rows: Box::new(rows.into_iter().enumerate().map(Ok)),
+            };
+            filtered(relation, filter.as_ref(), None, ctx)
+        }
+        Operator::Empty { table } => Relation {
+            table: table.clone(),
+            columns: table_columns(),
+            rows: Box::new(std::iter::empty()),
+        },
+        Operator::Filter { input, predicate } => filtered(
+            rows(database, input, schema, ranking, profile, settings, ctx)?,
+            Some(predicate),
+            ranking,
+            ctx,
+        ),
+        Operator::Sort { input, order_by } => {
+            let input = rows(database, input, schema, ranking, profile, settings, ctx)?;
+            let (relation, spilled) = sort(input, order_by, ranking, settings, ctx)?;
+            if let Some(actual) = &actual {
+                actual.borrow_mut().spilled = spilled;
+            }
+            relation
+        }
+        Operator::Limit { input, count } => {
+            let relation = rows(database, input, schema, ranking, profile, settings, ctx)?;
+            Relation {
+                rows: Box::new(relation.rows.take(*count)),
+                ..relation
+            }
+        }
+        Operator::Project { .. } => unreachable!("projections only come last"),
+    };
+    Ok(match actual {
+        Some(actual) => timed(relation, actual, start.elapsed()),
+        None => relation,
+    })
+}
+
+fn filtered<'a>(
+    relation: Relation<'a>,
+    predicate: Option<&Expr>,
+    ranking: Option<&'a Ranking>,
+    ctx: &'a EvalContext,
+) -> Relation<'a> {
+    let Some(predicate) = predicate.cloned() else {
+        return relation;
+    };
+    let (table, columns) = (relation.table.clone(), relation.columns.clone());
+    let rows = relation.rows.filter_map(move |row| {
+        let (row_id, row) = match row {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e)),
+        };
+        let scope = Scope::new(&columns, &row.values)
+            .with_table(&table)
+            .with_row_id(row_id)
+            .with_ranking(ranking);
+        match evaluate(&predicate, &scope, ctx) {
+            Ok(value) if is_truthy(&value) => Some(Ok((row_id, row))),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }
+    });
+    Relation {
+        rows: Box::new(rows),
+        ..relation
+    }
+}
+
+// Keeps the values of the named columns, for plans that read only some of them
+fn narrow<'a>(relation: Relation<'a>, columns: Option<&[String]>) -> Relation<'a> {
+    let Some(columns) = columns else {
+        return relation;
+    };
+    let positions: Vec<usize> = relation
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| columns.contains(&column.name))
+        .map(|(position, _)| position)
+        .collect();
+    let kept = positions
+        .iter()
+        .map(|position| relation.columns[*position].clone())
+        .collect();
+    let rows = relation.rows.map(move |row| {
+        let (row_id, row) = row?;
+        let values = positions
+            .iter()
+            .map(|position| row.values[*position].clone())
+            .collect();
+        Ok((row_id, Row { values }))
+    });
+    Relation {
+        table: relation.table,
+        columns: kept,
+        rows: Box::new(rows),
+    }
+}
+
+// Reads every row of the relation and hands them on in order, with the number of runs that
+// didn't fit in `settings.sort_memory` and went to disk
+fn sort<'a>(
+    relation: Relation<'a>,
+    order_by: &[OrderBy],
+    ranking: Option<&Ranking>,
+    settings: &Settings,
+    ctx: &EvalContext,
+) -> Result<(Relation<'a>, usize), String> {
+    let descending: Vec<bool> = order_by.iter().map(|item| item.descending).collect();
+    let compare = move |a: &[Value], b: &[Value]| {
+        descending
+            .iter()
+            .zip(a.iter().zip(b))
+            .map(|(descending, (a, b))| {
+                let ordering = sort_order(a, b);
+                if *descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    };
+    // Stable, so rows with equal keys stay in the order they were read in
+    let mut sorted = ExternalSort::new(settings.sort_memory, compare);
+    for row in relation.rows {
+        let (row_id, row) = row?;
+        let scope = Scope::new(&relation.columns, &row.values)
+            .with_table(&relation.table)
+            .with_row_id(row_id)
+            .with_ranking(ranking);
+        let keys = order_by
+            .iter()
+            .map(|item| evaluate(&item.expr, &scope, ctx))
+            .collect::<Result<Vec<Value>, String>>()?;
+        sorted.push(keys, row_id, row)?;
+    }
+    let spilled = sorted.spilled();
+    let relation = Relation {
+        table: relation.table,
+        columns: relation.columns,
+        rows: Box::new(sorted.finish()?),
+    };
+    Ok((relation, spilled))
+}
+
+fn project(
+    relation: Relation,
+    items: &[SelectItem],
+    ranking: Option<&Ranking>,
+    ctx: &EvalContext,
+) -> Result<QueryResult, String> {
+    let columns = items
+        .iter()
+        .flat_map(|item| match item {
+            SelectItem::Wildcard => relation
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect(),
+            SelectItem::Expr {
+                alias: Some(alias), ..
+            } => vec![alias.clone()],
+            SelectItem::Expr { expr, alias: None } => vec![expr.to_string()],
+        })
+        .collect();
+
+    let mut result = Vec::new();
+    for row in relation.rows {
+        let (row_id, row) = row?;
+        let scope = Scope::new(&relation.columns, &row.values)
+            .with_table(&relation.table)
+            .with_row_id(row_id)
+            .with_ranking(ranking);
+        let mut values = Vec::new();
+        for item in items {
+            match item {
+                SelectItem::Wildcard => values.extend(row.values.iter().cloned()),
+                SelectItem::Expr { expr, .. } => values.push(evaluate(expr, &scope, ctx)?),
+            }
+        }
+        result.push(Row { values });
+    }
+    Ok(QueryResult {
+        columns,
+        rows: result,
+    })
+}
+
+// NULLs come first, as they do in indexes. Values that can't be compared fall back to the
+// order indexes keep them in.
+fn sort_order(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        (a, b) => compare_values(a, b).unwrap_or_else(|_| a.cmp(b)),
+    }
+}
+
+impl fmt::Display for QueryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pretty_table = PrettyTable::new();
+        pretty_table.add_row(PrettyRow::new(
+            self.columns.iter().map(|name| Cell::new(name)).collect(),
+        ));
+        for row in &self.rows {
+            pretty_table.add_row(PrettyRow::new(
+                row.values
+                    .iter()
+                    .map(|value| Cell::new(&value.to_string()))
+                    .collect(),
+            ));
+        }
+        write!(f, "{}", pretty_table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::db::btreemap_database::Database;
+    use crate::db::datetime::{FixedClock, Timestamp};
+    use crate::db::pager::DEFAULT_MEMORY_BUDGET;
+    use crate::db::sled_database::SledDatabase;
+    use crate::sql_engine::process_sql;
+
+    fn run(database: &mut dyn StorageEngine, sql: &str) -> Result<ExecutionResult, String> {
+        execute(database, process_sql(sql)?)
+    }
+
+    fn rows(result: ExecutionResult) -> Vec<Vec<String>> {
+        match result {
+            ExecutionResult::Rows(result) => result
+                .rows
+                .iter()
+                .map(|row| row.values.iter().map(|value| value.to_string()).collect())
+                .collect(),
+            other => panic!("Executor: expected rows, got {:?}", other),
+        }
+    }
+
+    fn query(database: &mut dyn StorageEngine, sql: &str) -> Vec<Vec<String>> {
+        rows(run(database, sql).unwrap())
+    }
+
+    fn database_at(now: &str) -> Database {
+        let mut database = Database::new();
+        database.set_clock(Arc::new(FixedClock(Timestamp::parse(now).unwrap())));
+        database
+    }
+
+    #[test]
+    fn test_insert_and_select_dates() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE events (id INTEGER, day DATE, at TIMESTAMP);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO events (id, day, at) VALUES (1, '2024-01-31', '2024-01-31T08:00:00Z');",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO events (id, day, at) VALUES (2, CURRENT_DATE, CURRENT_TIMESTAMP);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT * FROM events;"),
+            vec![
+                vec!["1", "2024-01-31", "2024-01-31 08:00:00"],
+                vec!["2", "2024-03-15", "2024-03-15 10:30:00"],
+            ]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM events WHERE day < DATE '2024-02-01';"
+            ),
+            vec![vec!["1"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM events WHERE at >= CURRENT_DATE - INTERVAL '1' DAY;"
+            ),
+            vec![vec!["2"]]
+        );
+    }
+
+    #[test]
+    fn test_date_arithmetic_and_functions() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE t (d DATE);").unwrap();
+        run(&mut database, "INSERT INTO t (d) VALUES ('2024-01-31');").unwrap();
+
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT d + INTERVAL '1' MONTH, d + INTERVAL '2 days 03:00:00', CURRENT_DATE - d FROM t;"
+            ),
+            vec![vec!["2024-02-29", "2024-02-02 03:00:00", "44"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT EXTRACT(YEAR FROM d), EXTRACT(DOW FROM d), EXTRACT(HOUR FROM CURRENT_TIMESTAMP) FROM t;"
+            ),
+            vec![vec!["2024", "3", "10"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT strftime('%d/%m/%Y', d) AS formatted, strftime('%H:%M', 'now') FROM t;"
+            ),
+            vec![vec!["31/01/2024", "10:30"]]
+        );
+        // Out of range arithmetic is an error rather than a panic
+        for sql in [
+            "SELECT INTERVAL '99999999999999:00:00' FROM t;",
+            "SELECT CURRENT_TIMESTAMP + INTERVAL '2000000000 days' FROM t;",
+            "SELECT d + INTERVAL '2000000000 days 01:00:00' FROM t;",
+        ] {
+            let error = run(&mut database, sql).unwrap_err();
+            assert!(error.contains("out of range"), "{}: {}", sql, error);
+        }
+    }
+
+    #[test]
+    fn test_invalid_date_is_rejected() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE t (d DATE);").unwrap();
+        assert!(run(&mut database, "INSERT INTO t (d) VALUES ('2024-02-30');").is_err());
+        assert!(run(&mut database, "INSERT INTO t (d) VALUES (42);").is_err());
+        assert!(query(&mut database, "SELECT * FROM t;").is_empty());
+    }
+
+    #[test]
+    fn test_decimal_columns_are_exact() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE payments (id INTEGER, amount DECIMAL(8, 2));",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO payments (id, amount) VALUES (1, 0.1);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO payments (id, amount) VALUES (2, 0.2);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO payments (id, amount) VALUES (3, 19.999);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO payments (id, amount) VALUES (4, '-5');",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT amount FROM payments;"),
+            vec![vec!["0.10"], vec!["0.20"], vec!["20.00"], vec!["-5.00"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM payments WHERE amount * 3 = 0.3 OR amount = 20;"
+            ),
+            vec![vec!["1"], vec!["3"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT amount / 3, amount + 1, -amount FROM payments WHERE id = 2;"
+            ),
+            vec![vec!["0.06666667", "1.20", "-0.20"]]
+        );
+
+        assert!(run(
+            &mut database,
+            "INSERT INTO payments (id, amount) VALUES (5, 1000000);"
+        )
+        .is_err());
+        assert!(run(
+            &mut database,
+            "INSERT INTO payments (id, amount) VALUES (5, 'ten');"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_varchar_and_char_lengths() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE countries (code CHAR(3), name VARCHAR(8));",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO countries (code, name) VALUES ('NZ', 'Aotearoa');",
+        )
+        .unwrap();
+        // Excess trailing spaces are dropped rather than rejected
+        run(
+            &mut database,
+            "INSERT INTO countries (code, name) VALUES ('USA   ', 'USA  ');",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT code, name FROM countries;"),
+            vec![vec!["NZ ", "Aotearoa"], vec!["USA", "USA  "]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT name FROM countries WHERE code = 'NZ';"
+            ),
+            vec![vec!["Aotearoa"]]
+        );
+
+        assert!(run(
+            &mut database,
+            "INSERT INTO countries (code, name) VALUES ('NZL', 'New Zealand');"
+        )
+        .is_err());
+        assert!(run(
+            &mut database,
+            "INSERT INTO countries (code, name) VALUES ('AUST', 'Oz');"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_json_columns_and_operators() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE users (id INTEGER, doc JSON);").unwrap();
+        run(
+            &mut database,
+            r#"INSERT INTO users (id, doc) VALUES (1, '{"email": "a@example.com", "tags": ["x", "y"], "age": 30}');"#,
+        )
+        .unwrap();
+        run(
+            &mut database,
+            r#"INSERT INTO users (id, doc) VALUES (2, '{"email": "b@example.com", "tags": []}');"#,
+        )
+        .unwrap();
+        assert!(run(
+            &mut database,
+            "INSERT INTO users (id, doc) VALUES (3, '{not json');"
+        )
+        .is_err());
+
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT doc->'email', doc->>'email', doc->'tags'->>0, json_array_length(doc, '$.tags') FROM users;"
+            ),
+            vec![
+                vec![r#""a@example.com""#, "a@example.com", "x", "2"],
+                vec![r#""b@example.com""#, "b@example.com", "NULL", "0"],
+            ]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM users WHERE json_extract(doc, '$.age') > 18;"
+            ),
+            vec![vec!["1"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM users WHERE doc->>'$.tags[#-1]' = 'y';"
+            ),
+            vec![vec!["1"]]
+        );
+    }
+
+    #[test]
+    fn test_json_each() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        assert_eq!(
+            query(
+                &mut database,
+                r#"SELECT * FROM json_each('[1, "a", {"k": 2}]');"#
+            ),
+            vec![
+                vec!["0", "1", "integer"],
+                vec!["1", "a", "text"],
+                vec!["2", r#"{"k":2}"#, "object"],
+            ]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                r#"SELECT key, value FROM json_each('{"o": {"b": true}}', '$.o');"#
+            ),
+            vec![vec!["b", "true"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                r#"SELECT * FROM json_each('{"b": 1, "a": 2}') ORDER BY 1 DESC;"#
+            ),
+            vec![vec!["b", "1", "integer"], vec!["a", "2", "integer"]]
+        );
+    }
+
+    #[test]
+    fn test_json_path_index() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE users (id INTEGER, doc JSON);").unwrap();
+        run(
+            &mut database,
+            r#"INSERT INTO users (id, doc) VALUES (1, '{"email": "a@example.com"}'),
+               (2, '{"email": "b@example.com"}'), (3, '{"name": "c"}'), (4, NULL);"#,
+        )
+        .unwrap();
+        let ids =
+            |database: &mut Database, sql: &str| -> Vec<String> { query(database, sql).concat() };
+        let queries = [
+            "SELECT id FROM users WHERE doc->>'email' = 'b@example.com';",
+            "SELECT id FROM users WHERE doc->>'email' > 'a' ORDER BY id;",
+            "SELECT id FROM users WHERE doc->>'email' IN ('c@example.com', 'a@example.com');",
+            "SELECT id FROM users WHERE doc->>'email' IS NULL ORDER BY id;",
+        ];
+        let scanned: Vec<Vec<String>> = queries.iter().map(|sql| ids(&mut database, sql)).collect();
+        assert_eq!(
+            scanned,
+            vec![vec!["2"], vec!["1", "2"], vec!["1"], vec!["3", "4"]]
+        );
+
+        // Through the index the queries find the same rows, and follow the rows written after it
+        run(&mut database, "CREATE INDEX ON users (doc->>'email');").unwrap();
+        for (sql, expected) in queries.iter().zip(&scanned) {
+            assert_eq!(&ids(&mut database, sql), expected, "{}", sql);
+        }
+        run(
+            &mut database,
+            r#"INSERT INTO users (id, doc) VALUES (5, '{"email": "c@example.com"}');"#,
+        )
+        .unwrap();
+        run(
+            &mut database,
+            r#"UPDATE users SET doc = '{"email": "d@example.com"}' WHERE id = 2;"#,
+        )
+        .unwrap();
+        run(&mut database, "DELETE FROM users WHERE id = 1;").unwrap();
+        let found: Vec<Vec<String>> = queries.iter().map(|sql| ids(&mut database, sql)).collect();
+        assert_eq!(
+            found,
+            vec![vec![], vec!["2", "5"], vec!["5"], vec!["3", "4"]]
+        );
+        assert!(run(&mut database, "CREATE INDEX ON users (nope->>'email');").is_err());
+
+        // A path is an index expression like any other, so it can be kept unique or have an
+        // index of its own rows
+        run(
+            &mut database,
+            "CREATE UNIQUE INDEX emails ON users (doc->>'email');",
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &mut database,
+                r#"INSERT INTO users (id, doc) VALUES (6, '{"email": "c@example.com"}');"#,
+            )
+            .unwrap_err(),
+            "Duplicate key (c@example.com) violates UNIQUE (doc->>'email')"
+        );
+        run(
+            &mut database,
+            "CREATE INDEX d_ids ON users (id) WHERE doc->>'email' >= 'd';",
+        )
+        .unwrap();
+        run(&mut database, "DROP INDEX users_doc_idx;").unwrap();
+        run(&mut database, "DROP INDEX emails;").unwrap();
+        let sql = "SELECT id FROM users WHERE id > 0 AND doc->>'email' >= 'd';";
+        assert_eq!(ids(&mut database, sql), vec!["2"]);
+        let plan = ids(&mut database, &format!("EXPLAIN {}", sql));
+        assert!(plan[1].contains("using d_ids"), "{:?}", plan);
+    }
+
+    #[test]
+    fn test_order_by() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE events (kind TEXT, at INTEGER);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO events VALUES ('b', 3), ('a', 2), ('b', 1), (NULL, 4), ('a', 5), ('b', 2);",
+        )
+        .unwrap();
+        let pairs = |rows: Vec<Vec<String>>| {
+            rows.into_iter()
+                .map(|row| row.join(" "))
+                .collect::<Vec<String>>()
+        };
+
+        // Sorted, stably: rows with the same kind stay in insertion order
+        assert_eq!(
+            pairs(query(&mut database, "SELECT * FROM events ORDER BY kind;")),
+            vec!["NULL 4", "a 2", "a 5", "b 3", "b 1", "b 2"]
+        );
+        assert_eq!(
+            pairs(query(
+                &mut database,
+                "SELECT * FROM events WHERE at > 1 ORDER BY kind DESC, at;"
+            )),
+            vec!["b 2", "b 3", "a 2", "a 5", "NULL 4"]
+        );
+        assert_eq!(
+            pairs(query(
+                &mut database,
+                "SELECT at FROM events ORDER BY 0 - at;"
+            )),
+            vec!["5", "4", "3", "2", "2", "1"]
+        );
+
+        // Numbers are positions in the select list, `*` standing for each column
+        assert_eq!(
+            pairs(query(
+                &mut database,
+                "SELECT at, kind FROM events ORDER BY 2 DESC, 1;"
+            )),
+            vec!["1 b", "2 b", "3 b", "2 a", "5 a", "4 NULL"]
+        );
+        assert_eq!(
+            pairs(query(&mut database, "SELECT * FROM events ORDER BY 2;")),
+            vec!["b 1", "a 2", "b 2", "b 3", "NULL 4", "a 5"]
+        );
+        for sql in [
+            "SELECT at, kind FROM events ORDER BY 3;",
+            "SELECT * FROM events ORDER BY 0;",
+        ] {
+            let error = run(&mut database, sql).unwrap_err();
+            assert!(error.contains("not in the select list"), "{}", error);
+        }
+
+        // Read in index order, which also orders rows by `at` within a kind
+        run(&mut database, "CREATE INDEX ON events (kind, at);").unwrap();
+        assert_eq!(
+            pairs(query(&mut database, "SELECT * FROM events ORDER BY kind;")),
+            vec!["NULL 4", "a 2", "a 5", "b 1", "b 2", "b 3"]
+        );
+        assert_eq!(
+            pairs(query(
+                &mut database,
+                "SELECT * FROM events WHERE kind = 'b' ORDER BY kind, at;"
+            )),
+            vec!["b 1", "b 2", "b 3"]
+        );
+        // Not an index order, so sorted
+        assert_eq!(
+            pairs(query(
+                &mut database,
+                "SELECT * FROM events ORDER BY at, kind;"
+            )),
+            vec!["b 1", "a 2", "b 2", "b 3", "NULL 4", "a 5"]
+        );
+
+        // Columns the WHERE clause fixes to one value come before the ordered ones in the index
+        run(
+            &mut database,
+            "CREATE TABLE scores (player INTEGER, round INTEGER, points INTEGER);",
+        )
+        .unwrap();
+        run(&mut database, "CREATE INDEX ON scores (player, round);").unwrap();
+        run(
+            &mut database,
+            "INSERT INTO scores VALUES (2, 3, 30), (1, 1, 5), (2, 1, 10), (1, 2, 7), (2, 2, 20);",
+        )
+        .unwrap();
+        for sql in [
+            "SELECT round, points FROM scores WHERE player = 2 ORDER BY round;",
+            "SELECT round, points FROM scores WHERE player = 2 ORDER BY player, round;",
+            "SELECT round, points FROM scores WHERE 2 = player AND points > 0 ORDER BY round;",
+        ] {
+            assert_eq!(
+                pairs(query(&mut database, sql)),
+                vec!["1 10", "2 20", "3 30"]
+            );
+            let plan = query(&mut database, &format!("EXPLAIN {}", sql)).concat();
+            assert!(plan.iter().all(|line| !line.contains("Sort")), "{:?}", plan);
+            assert!(plan[1].contains("Index Scan on scores using scores_player_round_idx"));
+        }
+        // A text equality also takes in padded values, which the index orders apart
+        let sql = "EXPLAIN SELECT * FROM events WHERE kind = 'b' ORDER BY at;";
+        assert!(query(&mut database, sql).concat()[1].contains("Sort by at"));
+    }
+
+    #[test]
+    fn test_between_and_in() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE t (n INTEGER);").unwrap();
+        run(&mut database, "INSERT INTO t VALUES (1), (2), (3), (NULL);").unwrap();
+        let values = |database: &mut Database, sql: &str| {
+            query(database, sql)
+                .into_iter()
+                .map(|row| row.join(" "))
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            values(&mut database, "SELECT n FROM t WHERE n BETWEEN 2 AND 3;"),
+            vec!["2", "3"]
+        );
+        assert_eq!(
+            values(
+                &mut database,
+                "SELECT n FROM t WHERE n NOT BETWEEN 2 AND 3;"
+            ),
+            vec!["1"]
+        );
+        assert_eq!(
+            values(&mut database, "SELECT n FROM t WHERE n IN (3, 1);"),
+            vec!["1", "3"]
+        );
+        // NOT IN a list with NULL is never TRUE
+        assert_eq!(
+            values(&mut database, "SELECT n FROM t WHERE n NOT IN (2);"),
+            vec!["1", "3"]
+        );
+        assert!(values(&mut database, "SELECT n FROM t WHERE n NOT IN (2, NULL);").is_empty());
+        assert_eq!(
+            values(
+                &mut database,
+                "SELECT n BETWEEN 1 AND 2, n IN (2, NULL) FROM t;"
+            ),
+            vec!["true NULL", "true true", "false NULL", "NULL NULL"]
+        );
+    }
+
+    #[test]
+    fn test_indexes_find_the_rows_a_scan_does() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE items (id INTEGER PRIMARY KEY, kind VARCHAR(10), code CHAR(3), \
+             price DECIMAL(5, 2), at TIMESTAMP);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO items VALUES \
+             (1, 'a', 'x', 1.50, '2024-03-01 10:00:00'), \
+             (2, 'b ', 'y', 2, '2024-03-02 00:00:00'), \
+             (3, 'b', 'x', NULL, '2024-03-03 12:00:00'), \
+             (4, NULL, 'z', 3.25, NULL), \
+             (5, 'c', 'xy', 2.00, '2024-03-02 00:00:00');",
+        )
+        .unwrap();
+        let queries = [
+            "SELECT id FROM items WHERE id = 3;",
+            "SELECT id FROM items WHERE 2 < id AND id <= 4;",
+            "SELECT id FROM items WHERE id BETWEEN 2 AND 4 AND kind = 'b';",
+            "SELECT id FROM items WHERE id IN (5, 1, 9, NULL);",
+            "SELECT id FROM items WHERE kind = 'b';",
+            "SELECT id FROM items WHERE kind <= 'b' AND kind > 'a';",
+            "SELECT id FROM items WHERE kind IN ('a', 'c  ');",
+            "SELECT id FROM items WHERE code = 'x';",
+            "SELECT id FROM items WHERE code < 'y';",
+            "SELECT id FROM items WHERE price = 2;",
+            "SELECT id FROM items WHERE price >= 1.5 AND price < 3;",
+            "SELECT id FROM items WHERE at = DATE '2024-03-02';",
+            "SELECT id FROM items WHERE at > '2024-03-02' AND kind IS NOT NULL;",
+            "SELECT id FROM items WHERE kind = 'b' AND code = 'x';",
+            "SELECT id FROM items WHERE kind = NULL;",
+        ];
+        let scanned: Vec<Vec<Vec<String>>> = queries
+            .iter()
+            .map(|sql| query(&mut database, sql))
+            .collect();
+
+        for sql in [
+            "CREATE INDEX ON items (kind, code);",
+            "CREATE INDEX ON items (code);",
+            "CREATE INDEX ON items (price);",
+            "CREATE INDEX ON items (at);",
+        ] {
+            run(&mut database, sql).unwrap();
+        }
+        for (sql, expected) in queries.iter().zip(&scanned) {
+            assert_eq!(&query(&mut database, sql), expected, "{}", sql);
+        }
+        assert_eq!(scanned[4], vec![vec!["2"], vec!["3"]]);
+
+        // UPDATE and DELETE find their rows the same way
+        run(
+            &mut database,
+            "UPDATE items SET price = 9 WHERE kind = 'b';",
+        )
+        .unwrap();
+        run(&mut database, "DELETE FROM items WHERE code IN ('z', 'y');").unwrap();
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id, price FROM items WHERE price = 9;"
+            ),
+            vec![vec!["3", "9.00"]]
+        );
+        assert_eq!(
+            query(&mut database, "SELECT id FROM items WHERE id > 0;"),
+            vec![vec!["1"], vec!["3"], vec!["5"]]
+        );
+    }
+
+    #[test]
+    fn test_expression_and_partial_indexes() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT, active INTEGER);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO users VALUES (1, 'Ann@X.com', 1), (2, 'bo@x.com', 0), \
+             (3, 'ANN@x.COM', 0), (4, NULL, 1);",
+        )
+        .unwrap();
+        let queries = [
+            "SELECT id FROM users WHERE lower(email) = 'ann@x.com';",
+            "SELECT id FROM users WHERE LOWER(Email) > 'b';",
+            "SELECT id FROM users WHERE email = 'bo@x.com' AND active = 1;",
+            "SELECT id FROM users WHERE email > 'a' AND active = 1;",
+            "SELECT id FROM users WHERE email > 'a';",
+        ];
+        let scanned: Vec<Vec<Vec<String>>> = queries
+            .iter()
+            .map(|sql| query(&mut database, sql))
+            .collect();
+
+        assert_eq!(
+            run(&mut database, "CREATE INDEX ON users (lower(email));").unwrap(),
+            ExecutionResult::Message(
+                "Main: index 'users_lower_idx' created successfully on table 'users'.".to_string()
+            )
+        );
+        run(
+            &mut database,
+            "CREATE INDEX active_email ON users (email) WHERE ACTIVE = 1;",
+        )
+        .unwrap();
+        for (sql, expected) in queries.iter().zip(&scanned) {
+            assert_eq!(&query(&mut database, sql), expected, "{}", sql);
+        }
+        assert_eq!(scanned[0], vec![vec!["1"], vec!["3"]]);
+
+        // Entries follow the rows in and out of the partial index
+        let lookup = |database: &Database, index: &str, email: &str| {
+            database
+                .get_table("users")
+                .unwrap()
+                .index_lookup(index, &[Value::Text(email.to_string())])
+        };
+        assert_eq!(
+            lookup(&database, "active_email", "bo@x.com"),
+            Vec::<usize>::new()
+        );
+        run(&mut database, "UPDATE users SET active = 1 WHERE id = 2;").unwrap();
+        run(&mut database, "UPDATE users SET active = 0 WHERE id = 1;").unwrap();
+        run(&mut database, "DELETE FROM users WHERE id = 3;").unwrap();
+        assert_eq!(lookup(&database, "active_email", "bo@x.com"), vec![1]);
+        assert_eq!(
+            lookup(&database, "active_email", "Ann@X.com"),
+            Vec::<usize>::new()
+        );
+        assert_eq!(lookup(&database, "users_lower_idx", "ann@x.com"), vec![0]);
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM users WHERE email = 'bo@x.com' AND active = 1;"
+            ),
+            vec![vec!["2"]]
+        );
+
+        let schema = database.schema("users").unwrap();
+        assert_eq!(schema.column_indexes[0].columns, vec!["lower(email)"]);
+        assert_eq!(
+            schema.column_indexes[1].predicate.as_deref(),
+            Some("(active = 1)")
+        );
+        assert!(run(&mut database, "CREATE INDEX ON users (lower(mail));").is_err());
+        assert_eq!(
+            run(
+                &mut database,
+                "CREATE INDEX ON users (id) WHERE id < CURRENT_DATE;"
+            )
+            .unwrap_err(),
+            "Executor: index expressions cannot depend on the current time"
+        );
+        assert!(run(
+            &mut database,
+            "CREATE UNIQUE INDEX ON users (email) WHERE active = 1;"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_full_text_search() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE tickets (id INTEGER PRIMARY KEY, body TEXT, priority INTEGER);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO tickets VALUES \
+             (1, 'Printer jammed again', 1), \
+             (2, 'Printer offline', 2), \
+             (3, 'The printer is out of toner and the printer jammed', 1), \
+             (4, 'Password reset', 3), \
+             (5, NULL, 1);",
+        )
+        .unwrap();
+        let queries = [
+            "SELECT id FROM tickets WHERE body MATCH 'printer';",
+            "SELECT id FROM tickets WHERE body MATCH 'Printer Jammed';",
+            "SELECT id FROM tickets WHERE body MATCH 'toner OR offline';",
+            "SELECT id FROM tickets WHERE body MATCH '\"printer jammed\"';",
+            "SELECT id FROM tickets WHERE body MATCH 'printer (reset OR jammed)' AND priority = 1;",
+            "SELECT id, rank() FROM tickets WHERE body MATCH 'printer jammed' ORDER BY rank() DESC;",
+        ];
+        let scanned: Vec<Vec<Vec<String>>> = queries
+            .iter()
+            .map(|sql| query(&mut database, sql))
+            .collect();
+
+        assert_eq!(
+            run(&mut database, "CREATE FULLTEXT INDEX ON tickets (body);").unwrap(),
+            ExecutionResult::Message(
+                "Main: index 'tickets_body_fts' created successfully on table 'tickets'."
+                    .to_string()
+            )
+        );
+        for (sql, expected) in queries.iter().zip(&scanned) {
+            assert_eq!(&query(&mut database, sql), expected, "{}", sql);
+        }
+        assert_eq!(scanned[0], vec![vec!["1"], vec!["2"], vec!["3"]]);
+        assert_eq!(scanned[1], vec![vec!["1"], vec!["3"]]);
+        assert_eq!(scanned[2], vec![vec!["2"], vec!["3"]]);
+        assert_eq!(scanned[3], vec![vec!["1"], vec!["3"]]);
+        assert_eq!(scanned[4], vec![vec!["1"], vec!["3"]]);
+        // The short ticket holding both words ranks first
+        assert_eq!(scanned[5][0][0], "1");
+        assert_eq!(scanned[5][1][0], "3");
+        let score = |row: &Vec<String>| row[1].parse::<f64>().unwrap();
+        assert!(score(&scanned[5][0]) > score(&scanned[5][1]));
+
+        run(
+            &mut database,
+            "UPDATE tickets SET body = 'Toner low' WHERE id = 2;",
+        )
+        .unwrap();
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM tickets WHERE body MATCH 'toner';"
+            ),
+            vec![vec!["2"], vec!["3"]]
+        );
+
+        assert_eq!(
+            run(&mut database, "SELECT rank() FROM tickets;").unwrap_err(),
+            "Evaluator: rank() needs a 'column MATCH query' condition in the WHERE clause"
+        );
+        assert!(run(
+            &mut database,
+            "SELECT id FROM tickets WHERE body MATCH 'a AND';"
+        )
+        .is_err());
+        assert!(run(
+            &mut database,
+            "SELECT id FROM tickets WHERE priority MATCH 'a';"
+        )
+        .is_err());
+        assert_eq!(
+            run(
+                &mut database,
+                "CREATE FULLTEXT INDEX ON tickets (priority);"
+            )
+            .unwrap_err(),
+            "Column 'priority' is not of type TEXT"
+        );
+        assert_eq!(
+            run(
+                &mut database,
+                "CREATE FULLTEXT INDEX ON tickets (body, id);"
+            )
+            .unwrap_err(),
+            "Executor: a FULLTEXT index is over a single column, without WHERE"
+        );
+        run(&mut database, "DROP INDEX tickets_body_fts;").unwrap();
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM tickets WHERE body MATCH 'toner';"
+            ),
+            vec![vec!["2"], vec!["3"]]
+        );
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, status TEXT, total DECIMAL(10, 2));",
+        )
+        .unwrap();
+        run(&mut database, "CREATE TABLE empty (id INTEGER);").unwrap();
+        run(
+            &mut database,
+            "INSERT INTO orders VALUES (1, 'paid', 10.50), (2, 'paid', NULL), \
+             (3, 'open', 3.00), (4, NULL, 7.25);",
+        )
+        .unwrap();
+        run(&mut database, "CREATE INDEX ON orders (status);").unwrap();
+
+        assert_eq!(
+            run(&mut database, "ANALYZE orders;").unwrap(),
+            ExecutionResult::Message("Main: 1 table(s) analyzed.".to_string())
+        );
+        let statistics = database.schema("orders").unwrap().statistics.unwrap();
+        assert_eq!(statistics.rows, 4);
+        // Counted through their indexes
+        assert_eq!(statistics.column("id").unwrap().distinct, 4);
+        let status = statistics.column("status").unwrap();
+        assert_eq!(status.distinct, 2);
+        assert_eq!(status.null_fraction, 0.25);
+        assert_eq!(status.min, Some(Value::Text("open".to_string())));
+        let total = statistics.column("total").unwrap();
+        assert_eq!(total.max.as_ref().unwrap().to_string(), "10.50");
+        assert_eq!(total.histogram.len(), 3);
+        assert_eq!(database.schema("empty").unwrap().statistics, None);
+
+        // Statistics stay as they were until the next ANALYZE
+        run(&mut database, "DELETE FROM orders WHERE id > 1;").unwrap();
+        assert_eq!(
+            database.schema("orders").unwrap().statistics.unwrap().rows,
+            4
+        );
+        run(&mut database, "BEGIN;").unwrap();
+        assert_eq!(
+            run(&mut database, "ANALYZE;").unwrap(),
+            ExecutionResult::Message("Main: 2 table(s) analyzed.".to_string())
+        );
+        assert_eq!(
+            database.schema("orders").unwrap().statistics.unwrap().rows,
+            1
+        );
+        assert_eq!(
+            database.schema("empty").unwrap().statistics.unwrap().rows,
+            0
+        );
+        run(&mut database, "ROLLBACK;").unwrap();
+        assert_eq!(
+            database.schema("orders").unwrap().statistics.unwrap().rows,
+            4
+        );
+        assert!(run(&mut database, "ANALYZE missing;").is_err());
+    }
+
+    #[test]
+    fn test_plans_give_the_same_rows_with_statistics() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, status TEXT, total INTEGER, notes TEXT);",
+        )
+        .unwrap();
+        run(&mut database, "CREATE INDEX ON orders (status);").unwrap();
+        run(&mut database, "CREATE INDEX ON orders (total);").unwrap();
+        run(&mut database, "CREATE FULLTEXT INDEX ON orders (notes);").unwrap();
+        for id in 0..200 {
+            let status = if id % 50 == 0 { "open" } else { "closed" };
+            let notes = if id % 7 == 0 { "late delivery" } else { "fine" };
+            run(
+                &mut database,
+                &format!(
+                    "INSERT INTO orders VALUES ({}, '{}', {}, '{}');",
+                    id,
+                    status,
+                    id % 20,
+                    notes
+                ),
+            )
+            .unwrap();
+        }
+
+        let queries = [
+            "SELECT id FROM orders WHERE status = 'open';",
+            "SELECT id, total FROM orders WHERE status = 'closed' AND total = 3 ORDER BY id DESC;",
+            "SELECT * FROM orders WHERE total BETWEEN 2 AND 4 AND id > 150 ORDER BY total, id;",
+            "SELECT rowid, total * 2 FROM orders WHERE 1 = 1 AND total > 17 ORDER BY id;",
+            "SELECT id, rank() FROM orders WHERE notes MATCH 'late' AND id < 30 ORDER BY rank();",
+            "SELECT 1 + 1 AS two, id FROM orders WHERE 1 = 0 ORDER BY id;",
+        ];
+        let before: Vec<Vec<Vec<String>>> = queries
+            .iter()
+            .map(|sql| query(&mut database, sql))
+            .collect();
+        assert_eq!(
+            before[0],
+            vec![vec!["0"], vec!["50"], vec!["100"], vec!["150"]]
+        );
+        assert_eq!(before[3][0], vec!["18", "36"]);
+        assert_eq!(before[4].len(), 5);
+        assert!(before[5].is_empty());
+
+        run(&mut database, "ANALYZE;").unwrap();
+        for (sql, before) in queries.iter().zip(before) {
+            assert_eq!(query(&mut database, sql), before, "{}", sql);
+        }
+        // Columns are still named after the expressions as written
+        let ExecutionResult::Rows(result) =
+            run(&mut database, "SELECT 2 * 3, id FROM orders WHERE id = 1;").unwrap()
+        else {
+            panic!("expected rows");
+        };
+        assert_eq!(result.columns, vec!["(2 * 3)", "id"]);
+        assert_eq!(result.rows[0].values[0], Value::Integer(6));
+        assert!(run(&mut database, "SELECT missing FROM orders WHERE id = 1;").is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE orders (id INTEGER PRIMARY KEY, status TEXT, total INTEGER);",
+        )
+        .unwrap();
+        run(&mut database, "CREATE INDEX ON orders (status);").unwrap();
+        for id in 0..100 {
+            let status = if id % 10 == 0 { "open" } else { "closed" };
+            run(
+                &mut database,
+                &format!("INSERT INTO orders VALUES ({}, '{}', {});", id, status, id),
+            )
+            .unwrap();
+        }
+        let plan = |database: &mut Database, sql: &str| -> Vec<String> {
+            query(database, sql).into_iter().flatten().collect()
+        };
+
+        let sql = "EXPLAIN SELECT id FROM orders WHERE status = 'open' ORDER BY total DESC;";
+        assert_eq!(
+            plan(&mut database, sql),
+            vec![
+                "Project id (rows=10 cost=36.64)",
+                "  Sort by total DESC (rows=10 cost=36.64)",
+                "    Index Scan on orders using orders_status_idx filter (status = 'open') \
+                 (rows=10 cost=30.00)",
+            ]
+        );
+        run(&mut database, "ANALYZE;").unwrap();
+        // Most orders are closed, so the index isn't worth it
+        let sql = "EXPLAIN SELECT id FROM orders WHERE status = 'closed' AND 2 > 1;";
+        assert_eq!(
+            plan(&mut database, sql),
+            vec![
+                "Project id (rows=91 cost=100.00)",
+                "  Seq Scan on orders filter (status = 'closed') reading id, status \
+                 (rows=91 cost=100.00)",
+            ]
+        );
+
+        // The query runs, each operator with the rows it really handed on
+        let sql = "EXPLAIN ANALYZE SELECT id FROM orders WHERE status = 'open' ORDER BY id;";
+        let lines = plan(&mut database, sql);
+        assert_eq!(lines.len(), 4, "{:?}", lines);
+        for (line, rows) in lines.iter().zip(["10", "10", "10"]) {
+            assert!(
+                line.contains(&format!("(actual rows={} time=", rows)),
+                "{}",
+                line
+            );
+        }
+        assert!(lines[3].starts_with("Execution time: "));
+        assert!(run(&mut database, "EXPLAIN ANALYZE SELECT * FROM missing;").is_err());
+
+        // Paths into JSON documents are matched like any other index expression
+        run(&mut database, "CREATE TABLE people (id INTEGER, doc JSON);").unwrap();
+        run(&mut database, "CREATE INDEX ON people (doc->>'email');").unwrap();
+        for id in 0..20 {
+            let doc = format!(r#"{{"email": "p{}@x.com"}}"#, id);
+            run(
+                &mut database,
+                &format!("INSERT INTO people VALUES ({}, '{}');", id, doc),
+            )
+            .unwrap();
+        }
+        for (sql, ids) in [
+            (
+                "SELECT id FROM people WHERE doc->>'email' = 'p7@x.com';",
+                vec!["7"],
+            ),
+            (
+                "SELECT id FROM people WHERE DOC ->> 'email' < 'p11';",
+                vec!["0", "10"],
+            ),
+        ] {
+            let lines = plan(&mut database, &format!("EXPLAIN {}", sql));
+            assert!(
+                lines[1].starts_with("  Index Scan on people using people_doc_idx"),
+                "{:?}",
+                lines
+            );
+            assert_eq!(plan(&mut database, sql), ids);
+        }
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE events (id INTEGER PRIMARY KEY, kind TEXT);",
+        )
+        .unwrap();
+        for id in 0..1000 {
+            let kind = if id % 3 == 0 { "click" } else { "view" };
+            run(
+                &mut database,
+                &format!("INSERT INTO events VALUES ({}, '{}');", id, kind),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            query(&mut database, "SELECT id FROM events LIMIT 3;"),
+            vec![vec!["0"], vec!["1"], vec!["2"]]
+        );
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM events WHERE kind = 'click' ORDER BY id DESC LIMIT 2;"
+            ),
+            vec![vec!["999"], vec!["996"]]
+        );
+        assert!(query(&mut database, "SELECT * FROM events LIMIT 0;").is_empty());
+        assert_eq!(
+            query(
+                &mut database,
+                "SELECT id FROM events WHERE id > 997 LIMIT 10;"
+            )
+            .len(),
+            2
+        );
+
+        // Scans stop after the rows the limit lets through, whichever way they read the table
+        run(&mut database, "ANALYZE;").unwrap();
+        for sql in [
+            "EXPLAIN ANALYZE SELECT * FROM events LIMIT 10;",
+            "EXPLAIN ANALYZE SELECT * FROM events WHERE kind = 'view' LIMIT 10;",
+            "EXPLAIN ANALYZE SELECT * FROM events ORDER BY id LIMIT 10;",
+        ] {
+            let lines: Vec<String> = query(&mut database, sql).into_iter().flatten().collect();
+            let scan = lines
+                .iter()
+                .find(|line| line.contains(" Scan on events"))
+                .unwrap();
+            assert!(scan.contains("(actual rows=10 "), "{}: {:?}", sql, lines);
+        }
+    }
+
+    #[test]
+    fn test_sort_spills_to_disk() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE events (id INTEGER PRIMARY KEY, kind TEXT, score INTEGER);",
+        )
+        .unwrap();
+        for id in 0..300 {
+            run(
+                &mut database,
+                &format!(
+                    "INSERT INTO events VALUES ({}, 'kind {}', {});",
+                    id,
+                    id % 7,
+                    (id * 37) % 11
+                ),
+            )
+            .unwrap();
+        }
+        let small = Settings { sort_memory: 4096 };
+        let sorted = |database: &mut Database, settings: &Settings, sql: &str| {
+            rows(execute_with(database, process_sql(sql).unwrap(), settings).unwrap())
+        };
+
+        for sql in [
+            "SELECT id, score FROM events ORDER BY score DESC, kind;",
+            "SELECT kind FROM events WHERE id > 20 ORDER BY score LIMIT 15;",
+        ] {
+            assert_eq!(
+                sorted(&mut database, &small, sql),
+                sorted(&mut database, &Settings::default(), sql),
+                "{}",
+                sql
+            );
+        }
+
+        let explain = "EXPLAIN ANALYZE SELECT * FROM events ORDER BY score;";
+        let spilled = |lines: Vec<Vec<String>>| {
+            let lines: Vec<String> = lines.into_iter().flatten().collect();
+            let sort = lines.iter().find(|line| line.contains("Sort by")).unwrap();
+            sort.contains(" runs)")
+        };
+        assert!(spilled(sorted(&mut database, &small, explain)));
+        assert!(!spilled(sorted(
+            &mut database,
+            &Settings::default(),
+            explain
+        )));
+    }
+
+    #[test]
+    fn test_create_and_drop_index() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT, name TEXT);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO users VALUES (1, 'a@example.com', 'Ann'), (2, 'b@example.com', 'Bo');",
+        )
+        .unwrap();
+        let name = |s: &str| Value::Text(s.to_string());
+        // Only the primary key is indexed until asked otherwise
+        assert!(database
+            .get_table("users")
+            .unwrap()
+            .index_lookup("name", &[name("Bo")])
+            .is_empty());
+
+        assert_eq!(
+            run(&mut database, "CREATE INDEX ON users (name);").unwrap(),
+            ExecutionResult::Message(
+                "Main: index 'users_name_idx' created successfully on table 'users'.".to_string()
+            )
+        );
+        assert_eq!(
+            database
+                .get_table("users")
+                .unwrap()
+                .index_lookup("users_name_idx", &[name("Bo")]),
+            vec![1]
+        );
+
+        run(
+            &mut database,
+            "CREATE UNIQUE INDEX users_email ON users (email);",
+        )
+        .unwrap();
+        assert!(run(
+            &mut database,
+            "INSERT INTO users VALUES (3, 'a@example.com', 'Al');"
+        )
+        .unwrap_err()
+        .starts_with("Duplicate key"));
+        // A UNIQUE index is a conflict target like a UNIQUE constraint
+        run(
+            &mut database,
+            "INSERT INTO users VALUES (3, 'a@example.com', 'Al') ON CONFLICT (email) DO UPDATE SET name = excluded.name;",
+        )
+        .unwrap();
+        assert_eq!(
+            query(&mut database, "SELECT id, name FROM users;"),
+            vec![vec!["1", "Al"], vec!["2", "Bo"]]
+        );
+        assert!(run(&mut database, "CREATE UNIQUE INDEX ON users (name, id);").is_ok());
+        // Expressions are kept unique like columns
+        run(
+            &mut database,
+            "CREATE UNIQUE INDEX ON users (lower(email));",
+        )
+        .unwrap();
+        assert_eq!(
+            run(
+                &mut database,
+                "INSERT INTO users VALUES (3, 'A@Example.com', 'Cy');"
+            )
+            .unwrap_err(),
+            "Duplicate key (a@example.com) violates UNIQUE (lower(email))"
+        );
+        run(&mut database, "DROP INDEX users_lower_idx;").unwrap();
+
+        run(&mut database, "DROP INDEX users_email;").unwrap();
+        run(
+            &mut database,
+            "INSERT INTO users VALUES (3, 'a@example.com', 'Al');",
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut database, "DROP INDEX users_email;").unwrap_err(),
+            "Executor: index 'users_email' not found"
+        );
+
+        // Index names only need to be unique within a table
+        run(&mut database, "CREATE TABLE teams (name TEXT);").unwrap();
+        run(
+            &mut database,
+            "CREATE INDEX users_name_idx ON teams (name);",
+        )
+        .unwrap();
+        assert!(run(&mut database, "DROP INDEX users_name_idx;")
+            .unwrap_err()
+            .contains("teams, users"));
+        run(&mut database, "DROP INDEX users_name_idx ON teams;").unwrap();
+        run(&mut database, "DROP INDEX users_name_idx;").unwrap();
+        assert_eq!(database.schema("users").unwrap().column_indexes.len(), 1);
+    }
+
+    #[test]
+    fn test_multi_row_insert_is_atomic() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE t (id INTEGER, d DATE);").unwrap();
+        run(
+            &mut database,
+            "INSERT INTO t VALUES (1, '2024-01-01'), (2, '2024-01-02');",
+        )
+        .unwrap();
+        // The second row is invalid, so the first must not be inserted either
+        assert!(run(
+            &mut database,
+            "INSERT INTO t VALUES (3, '2024-01-03'), (4, '2024-02-30');"
+        )
+        .is_err());
+        assert!(run(&mut database, "INSERT INTO t VALUES (5);").is_err());
+        run(&mut database, "INSERT INTO t DEFAULT VALUES;").unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT * FROM t;"),
+            vec![
+                vec!["1", "2024-01-01"],
+                vec!["2", "2024-01-02"],
+                vec!["NULL", "NULL"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_select() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE t (id INTEGER, d DATE);").unwrap();
+        run(
+            &mut database,
+            "INSERT INTO t (id, d) VALUES (1, '2024-01-01'), (2, '2024-06-01');",
+        )
+        .unwrap();
+        // Selecting from the target table only sees the rows from before the statement
+        run(
+            &mut database,
+            "INSERT INTO t (d, id) SELECT d + 1, id + 10 FROM t;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT * FROM t WHERE id > 10;"),
+            vec![vec!["11", "2024-01-02"], vec!["12", "2024-06-02"]]
+        );
+        assert!(run(&mut database, "INSERT INTO t (id) SELECT id, d FROM t;").is_err());
+        assert_eq!(query(&mut database, "SELECT id FROM t;").len(), 4);
+    }
+
+    #[test]
+    fn test_unique_constraints() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, team TEXT, UNIQUE (team, id));",
+        )
+        .unwrap();
+        run(&mut database, "INSERT INTO users VALUES (1, 'a@x', 'red');").unwrap();
+
+        assert!(run(&mut database, "INSERT INTO users VALUES (1, 'b@x', 'red');").is_err());
+        assert!(run(&mut database, "INSERT INTO users VALUES (2, 'a@x', 'red');").is_err());
+        assert!(run(
+            &mut database,
+            "INSERT INTO users VALUES (NULL, 'c@x', 'red');"
+        )
+        .is_err());
+        // Duplicates inside one statement are caught too, and nothing from it is inserted
+        assert!(run(
+            &mut database,
+            "INSERT INTO users VALUES (2, 'b@x', 'red'), (2, 'c@x', 'red');"
+        )
+        .is_err());
+        // NULLs never clash in a UNIQUE column
+        run(
+            &mut database,
+            "INSERT INTO users VALUES (2, NULL, 'red'), (3, NULL, 'red');",
+        )
+        .unwrap();
+
+        assert_eq!(query(&mut database, "SELECT id FROM users;").len(), 3);
+        assert!(run(
+            &mut database,
+            "CREATE TABLE bad (a INTEGER, PRIMARY KEY (b));"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_upsert() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE stock (sku TEXT PRIMARY KEY, qty INTEGER, price DECIMAL(6, 2));",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO stock VALUES ('a', 1, 1.00), ('b', 2, 2.00);",
+        )
+        .unwrap();
+
+        // Re-sent records are skipped, new ones inserted
+        run(
+            &mut database,
+            "INSERT INTO stock VALUES ('a', 9, 9.00), ('c', 3, 3.00) ON CONFLICT DO NOTHING;",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO stock VALUES ('b', 5, 2.50), ('d', 4, 4.00) ON CONFLICT (sku) DO UPDATE SET qty = stock.qty + excluded.qty, price = excluded.price;",
+        )
+        .unwrap();
+        // The WHERE clause can leave the conflicting row as it is
+        run(
+            &mut database,
+            "INSERT INTO stock VALUES ('a', 7, 0.50) ON CONFLICT (sku) DO UPDATE SET price = excluded.price WHERE excluded.price > price;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT * FROM stock;"),
+            vec![
+                vec!["a", "1", "1.00"],
+                vec!["b", "7", "2.50"],
+                vec!["c", "3", "3.00"],
+                vec!["d", "4", "4.00"],
+            ]
+        );
+
+        assert!(run(
+            &mut database,
+            "INSERT INTO stock VALUES ('a', 1, 1), ('a', 2, 2) ON CONFLICT (sku) DO UPDATE SET qty = excluded.qty;"
+        )
+        .is_err());
+        assert!(run(
+            &mut database,
+            "INSERT INTO stock VALUES ('a', 1, 1) ON CONFLICT (qty) DO NOTHING;"
+        )
+        .is_err());
+        assert_eq!(
+            query(&mut database, "SELECT qty FROM stock WHERE sku = 'a';"),
+            vec![vec!["1"]]
+        );
+    }
+
+    #[test]
+    fn test_update_and_delete() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);",
+        )
+        .unwrap();
+        run(
+            &mut database,
+            "INSERT INTO t VALUES (1, 10), (2, 20), (3, 30);",
+        )
+        .unwrap();
+
+        run(&mut database, "UPDATE t SET n = n + id WHERE id >= 2;").unwrap();
+        // Would give two rows the same key, so nothing changes
+        assert!(run(&mut database, "UPDATE t SET id = 1;").is_err());
+        // Keys may be swapped within one statement
+        run(&mut database, "UPDATE t SET id = 4 - id WHERE id <> 2;").unwrap();
+        run(&mut database, "DELETE FROM t WHERE n > 30;").unwrap();
+
+        assert_eq!(
+            query(&mut database, "SELECT * FROM t;"),
+            vec![vec!["3", "10"], vec!["2", "22"]]
+        );
+        run(&mut database, "DELETE FROM t;").unwrap();
+        assert!(query(&mut database, "SELECT * FROM t;").is_empty());
+    }
+
+    #[test]
+    fn test_returning() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE stock (sku TEXT PRIMARY KEY, qty INTEGER);",
+        )
+        .unwrap();
+        run(&mut database, "CREATE TABLE other (a INTEGER);").unwrap();
+        run(&mut database, "INSERT INTO other VALUES (1);").unwrap();
+
+        // Row ids are shared across tables, so the first stock row gets id 1
+        assert_eq!(
+            rows(
+                run(
+                    &mut database,
+                    "INSERT INTO stock VALUES ('a', 1), ('b', 2) RETURNING rowid, *;"
+                )
+                .unwrap()
+            ),
+            vec![vec!["1", "a", "1"], vec!["2", "b", "2"]]
+        );
+        assert_eq!(
+            rows(
+                run(
+                    &mut database,
+                    "INSERT INTO stock VALUES ('b', 5), ('c', 3) ON CONFLICT (sku) DO UPDATE SET qty = stock.qty + excluded.qty RETURNING rowid, sku, qty;"
+                )
+                .unwrap()
+            ),
+            vec![vec!["2", "b", "7"], vec!["3", "c", "3"]]
+        );
+        assert_eq!(
+            rows(
+                run(
+                    &mut database,
+                    "UPDATE stock SET qty = qty * 10 WHERE sku <> 'b' RETURNING sku, qty AS new_qty;"
+                )
+                .unwrap()
+            ),
+            vec![vec!["a", "10"], vec!["c", "30"]]
+        );
+        assert_eq!(
+            rows(
+                run(
+                    &mut database,
+                    "DELETE FROM stock WHERE qty > 20 RETURNING rowid, sku;"
+                )
+                .unwrap()
+            ),
+            vec![vec!["3", "c"]]
+        );
+        assert_eq!(
+            query(&mut database, "SELECT rowid, sku FROM stock;"),
+            vec![vec!["1", "a"], vec!["2", "b"]]
+        );
+    }
+
+    #[test]
+    fn test_transactions() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(&mut database, "CREATE TABLE t (id INTEGER PRIMARY KEY);").unwrap();
+        run(&mut database, "INSERT INTO t VALUES (1);").unwrap();
+
+        run(&mut database, "BEGIN;").unwrap();
+        run(&mut database, "INSERT INTO t VALUES (2);").unwrap();
+        run(&mut database, "SAVEPOINT before_delete;").unwrap();
+        run(&mut database, "DELETE FROM t;").unwrap();
+        assert!(query(&mut database, "SELECT * FROM t;").is_empty());
+        run(&mut database, "ROLLBACK TO SAVEPOINT before_delete;").unwrap();
+        run(&mut database, "RELEASE before_delete;").unwrap();
+        run(&mut database, "COMMIT;").unwrap();
+        assert_eq!(
+            query(&mut database, "SELECT * FROM t;"),
+            vec![vec!["1"], vec!["2"]]
+        );
+
+        run(&mut database, "BEGIN TRANSACTION;").unwrap();
+        run(&mut database, "UPDATE t SET id = id + 10;").unwrap();
+        run(&mut database, "CREATE TABLE u (a INTEGER);").unwrap();
+        run(&mut database, "ROLLBACK;").unwrap();
+        assert_eq!(
+            query(&mut database, "SELECT * FROM t;"),
+            vec![vec!["1"], vec!["2"]]
+        );
+        assert!(run(&mut database, "SELECT * FROM u;").is_err());
+        assert!(run(&mut database, "COMMIT;").is_err());
+    }
+
+    #[test]
+    fn test_failed_statement_changes_nothing() {
+        let mut database = database_at("2024-03-15 10:30:00");
+        run(
+            &mut database,
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, n INTEGER);",
+        )
+        .unwrap();
+        run(&mut database, "CREATE INDEX t_n ON t (n);").unwrap();
+        run(&mut database, "INSERT INTO t VALUES (1, 10), (2, 20);").unwrap();
+        run(&mut database, "BEGIN;").unwrap();
+        run(&mut database, "INSERT INTO t VALUES (3, 30);").unwrap();
+
+        // Interrupted halfway through writing the rows and their index entries
+        crate::db::fault::fail_after(5);
+        let result = run(
+            &mut database,
+            "INSERT INTO t VALUES (1, 0), (4, 40) ON CONFLICT (id) DO UPDATE SET n = excluded.n;",
+        );
+        crate::db::fault::disarm();
+        assert!(result.unwrap_err().starts_with("Fault: "));
+        assert_eq!(
+            query(&mut database, "SELECT * FROM t WHERE n >= 10;"),
+            vec![vec!["1", "10"], vec!["2", "20"], vec!["3", "30"]]
+        );
+
+        // The transaction itself is still intact
+        run(&mut database, "COMMIT;").unwrap();
+        run(&mut database, "INSERT INTO t VALUES (4, 40);").unwrap();
+        assert_eq!(
+            query(&mut database, "SELECT rowid, id FROM t WHERE id = 4;"),
+            vec![vec!["3", "4"]]
+        );
+    }
+
+    #[test]
+    fn test_statements_run_the_same_on_sled() {
+        let path = std::env::temp_dir().join(format!("banditdb-{}-executor", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let mut sled = SledDatabase::open(&path, DEFAULT_MEMORY_BUDGET).unwrap();
+        sled.set_clock(Arc::new(FixedClock(
+            Timestamp::parse("2024-03-15 10:30:00").unwrap(),
+        )));
+        let mut memory = database_at("2024-03-15 10:30:00");
+
+        let statements = [
+            "CREATE TABLE stock (sku TEXT PRIMARY KEY, qty INTEGER, added DATE);",
+            "INSERT INTO stock VALUES ('a', 1, CURRENT_DATE), ('b', 2, CURRENT_DATE);",
+            "BEGIN;",
+            "INSERT INTO stock VALUES ('a', 5, NULL), ('c', 3, NULL) \
+             ON CONFLICT (sku) DO UPDATE SET qty = qty + excluded.qty RETURNING *;",
+            "SAVEPOINT s;",
+            "DELETE FROM stock WHERE qty < 5 RETURNING sku;",
+            "ROLLBACK TO SAVEPOINT s;",
+            "UPDATE stock SET sku = 'z' WHERE sku = 'b' RETURNING rowid, sku;",
+            "COMMIT;",
+            "INSERT INTO stock VALUES ('a', 0, NULL);",
+            "SELECT rowid, * FROM stock WHERE qty > 1;",
+            "SELECT sku FROM stock WHERE qty >= 0 ORDER BY sku LIMIT 2;",
+            "ANALYZE;",
+        ];
+        for sql in statements {
+            assert_eq!(run(&mut sled, sql), run(&mut memory, sql), "{}", sql);
+        }
+        assert_eq!(sled.schema("stock"), memory.schema("stock"));
+        drop(sled);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{self, AtomicUsize};
+
+use crate::db::data_types::Value;
+use crate::db::spill::heap_size;
+use crate::db::table::Row;
+
+// Used when no limit is given
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+
+// The most runs merged at once, each with a file open. More runs are merged in passes.
+const MERGE_FAN_IN: usize = 64;
+
+// A row with its sort keys and row id
+type Keyed = (Vec<Value>, usize, Row);
+
+// Tells the spill files of the sorts running in one process apart
+static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+
+// Sorts rows that may not fit in memory. Rows are gathered until they take up about `memory`
+// bytes, then sorted and written to a temporary file as a run; the runs are merged back as the
+// rows are read. The sort is stable: of rows with equal keys, the one pushed first comes first.
+pub struct ExternalSort<C> {
+    compare: Rc<C>,
+    memory: usize,
+    buffer: Vec<Keyed>,
+    buffered: usize,
+    runs: Vec<Run>,
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> ExternalSort<C> {
+    pub fn new(memory: usize, compare: C) -> Self {
+        ExternalSort {
+            compare: Rc::new(compare),
+            memory,
+            buffer: Vec::new(),
+            buffered: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, keys: Vec<Value>, row_id: usize, row: Row) -> Result<(), String> {
+        self.buffered += mem::size_of::<Keyed>() + heap_size(&keys) + heap_size(&row.values);
+        self.buffer.push((keys, row_id, row));
+        if self.buffered > self.memory {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    // How many runs went to disk
+    pub fn spilled(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn finish(mut self) -> Result<Sorted<C>, String> {
+        if self.runs.is_empty() {
+            self.sort_buffer();
+            return Ok(Sorted {
+                memory: self.buffer.into_iter(),
+                merge: None,
+            });
+        }
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        // Each pass merges neighbouring runs into one that takes their place, so rows with equal
+        // keys stay in the order they were pushed
+        let mut runs = self.runs;
+        while runs.len() > MERGE_FAN_IN {
+            let mut merged = Vec::new();
+            let mut remaining = runs.into_iter().peekable();
+            while remaining.peek().is_some() {
+                let mut group: Vec<Run> = remaining.by_ref().take(MERGE_FAN_IN).collect();
+                merged.push(match group.len() {
+                    1 => group.remove(0),
+                    _ => Run::write(Merge::new(self.compare.clone(), group)?)?,
+                });
+            }
+            runs = merged;
+        }
+        Ok(Sorted {
+            memory: Vec::new().into_iter(),
+            merge: Some(Merge::new(self.compare, runs)?),
+        })
+    }
+
+    fn sort_buffer(&mut self) {
+        let compare = &self.compare;
+        self.buffer.sort_by(|(a, _, _), (b, _, _)| compare(a, b));
+    }
+
+    fn spill(&mut self) -> Result<(), String> {
+        self.sort_buffer();
+        let rows = mem::take(&mut self.buffer);
+        self.buffered = 0;
+        self.runs.push(Run::write(rows.into_iter().map(Ok))?);
+        Ok(())
+    }
+}
+
+// Sorted rows written to a temporary file, which goes away with the run. The file is only open
+// while the run is being written or merged.
+struct Run {
+    path: PathBuf,
+    rows: usize,
+}
+
+impl Run {
+    fn write(rows: impl Iterator<Item = Result<Keyed, String>>) -> Result<Run, String> {
+        let error =
+            |e: &dyn std::fmt::Display| format!("Executor: could not spill sorted rows: {}", e);
+        let path = std::env::temp_dir().join(format!(
+            "banditdb-{}-sort-{}",
+            std::process::id(),
+            NEXT_RUN.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| error(&e))?;
+        // From here on the run removes the file when it is dropped, whatever fails
+        let mut run = Run { path, rows: 0 };
+        let mut writer = BufWriter::new(file);
+        for row in rows {
+            bincode::serialize_into(&mut writer, &row?).map_err(|e| error(&e))?;
+            run.rows += 1;
+        }
+        writer.flush().map_err(|e| error(&e))?;
+        Ok(run)
+    }
+
+    fn open(self) -> Result<RunReader, String> {
+        let file = File::open(&self.path)
+            .map_err(|e| format!("Executor: could not read spilled rows back: {}", e))?;
+        Ok(RunReader {
+            reader: BufReader::new(file),
+            run: self,
+        })
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// A run being read back, whose `rows` count down to the end of the file
+struct RunReader {
+    run: Run,
+    reader: BufReader<File>,
+}
+
+impl RunReader {
+    fn next(&mut self) -> Result<Option<Keyed>, String> {
+        if self.run.rows == 0 {
+            return Ok(None);
+        }
+        self.run.rows -= 1;
+        bincode::deserialize_from(&mut self.reader)
+            .map(Some)
+            .map_err(|e| format!("Executor: could not read spilled rows back: {}", e))
+    }
+}
+
+// The next row of one of the runs being merged
+struct Head<C> {
+    compare: Rc<C>,
+    row: Keyed,
+    run: usize,
+}
+
+// Ordered for BinaryHeap, which pops the greatest: the smallest keys are the greatest, and of
+// equal keys the ones from the earliest run
+impl<C: Fn(&[Value], &[Value]) -> Ordering> Ord for Head<C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&other.row.0, &self.row.0).then(other.run.cmp(&self.run))
+    }
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> PartialOrd for Head<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> PartialEq for Head<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> Eq for Head<C> {}
+
+// The rows of several runs in order, with the next row of each run on a heap
+struct Merge<C> {
+    compare: Rc<C>,
+    readers: Vec<RunReader>,
+    heads: BinaryHeap<Head<C>>,
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> Merge<C> {
+    fn new(compare: Rc<C>, runs: Vec<Run>) -> Result<Self, String> {
+        let mut merge = Merge {
+            compare,
+            readers: Vec::new(),
+            heads: BinaryHeap::new(),
+        };
+        for run in runs {
+            merge.readers.push(run.open()?);
+            merge.advance(merge.readers.len() - 1)?;
+        }
+        Ok(merge)
+    }
+
+    // Puts the next row of a run on the heap
+    fn advance(&mut self, run: usize) -> Result<(), String> {
+        if let Some(row) = self.readers[run].next()? {
+            self.heads.push(Head {
+                compare: self.compare.clone(),
+                row,
+                run,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> Iterator for Merge<C> {
+    type Item = Result<Keyed, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let head = self.heads.pop()?;
+        Some(self.advance(head.run).map(|()| head.row))
+    }
+}
+
+// The rows of an `ExternalSort` in order, as (row id, row): the sorted rows in memory when
+// nothing was spilled, or else the merged runs
+pub struct Sorted<C> {
+    memory: std::vec::IntoIter<Keyed>,
+    merge: Option<Merge<C>>,
+}
+
+impl<C: Fn(&[Value], &[Value]) -> Ordering> Iterator for Sorted<C> {
+    type Item = Result<(usize, Row), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match &mut self.merge {
+            Some(merge) => merge.next()?,
+            None => Ok(self.memory.next()?),
+        };
+        Some(row.map(|(_, row_id, row)| (row_id, row)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(memory: usize, rows: &[(i64, &str)]) -> (Vec<(usize, Row)>, usize) {
+        let mut sort = ExternalSort::new(memory, |a: &[Value], b: &[Value]| a.cmp(b));
+        for (row_id, (key, text)) in rows.iter().enumerate() {
+            let row = Row {
+                values: vec![Value::Integer(*key), Value::Text(text.to_string())],
+            };
+            sort.push(vec![Value::Integer(*key)], row_id, row).unwrap();
+        }
+        let spilled = sort.spilled();
+        let sorted = sort.finish().unwrap().collect::<Result<_, _>>().unwrap();
+        (sorted, spilled)
+    }
+
+    #[test]
+    fn test_spilled_runs_merge_in_order() {
+        let rows: Vec<(i64, &str)> = (0..500)
+            .map(|i| ((i * 7919) % 50, if i % 2 == 0 { "even" } else { "odd" }))
+            .collect();
+        let (in_memory, spilled) = sort(DEFAULT_SORT_MEMORY, &rows);
+        assert_eq!(spilled, 0);
+        let (on_disk, spilled) = sort(4096, &rows);
+        assert!(spilled > 5, "{}", spilled);
+        assert_eq!(on_disk, in_memory);
+
+        // Stable: equal keys keep the order the rows were pushed in
+        let keys: Vec<i64> = on_disk.iter().map(|(id, _)| rows[*id].0).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+        for pair in on_disk.windows(2) {
+            if rows[pair[0].0].0 == rows[pair[1].0].0 {
+                assert!(pair[0].0 < pair[1].0);
+            }
+        }
+        assert_eq!(on_disk[0].1.values[1], Value::Text("even".to_string()));
+    }
+
+    fn open_paths<C>(sorted: &Sorted<C>) -> Vec<PathBuf> {
+        let readers = &sorted.merge.as_ref().unwrap().readers;
+        readers
+            .iter()
+            .map(|reader| reader.run.path.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_many_runs_merge_in_passes() {
+        let rows: Vec<(i64, &str)> = (0..300).map(|i| ((i * 31) % 7, "")).collect();
+        let mut sort = ExternalSort::new(1, |a: &[Value], b: &[Value]| a.cmp(b));
+        for (row_id, (key, _)) in rows.iter().enumerate() {
+            let row = Row { values: vec![] };
+            sort.push(vec![Value::Integer(*key)], row_id, row).unwrap();
+        }
+        assert_eq!(sort.spilled(), 300);
+        let spilled: Vec<PathBuf> = sort.runs.iter().map(|run| run.path.clone()).collect();
+        // No file stays open between writing a run and merging it
+        assert!(sort.runs.iter().all(|run| run.rows == 1));
+
+        let sorted = sort.finish().unwrap();
+        // 300 runs merge into 5 of at most 64, whose files are the only ones left
+        let paths = open_paths(&sorted);
+        assert_eq!(paths.len(), 5);
+        assert!(spilled.iter().all(|path| !path.exists()));
+        let ids: Vec<usize> = sorted.map(|row| row.unwrap().0).collect();
+        let mut expected: Vec<usize> = (0..300).collect();
+        expected.sort_by_key(|id| rows[*id].0);
+        assert_eq!(ids, expected);
+        assert!(paths.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn test_spill_files_are_removed() {
+        let mut sort = ExternalSort::new(1, |a: &[Value], b: &[Value]| a.cmp(b));
+        for row_id in 0..3 {
+            sort.push(vec![Value::Integer(0)], row_id, Row { values: vec![] })
+                .unwrap();
+        }
+        let mut sorted = sort.finish().unwrap();
+        let paths = open_paths(&sorted);
+        assert_eq!(paths.len(), 3);
+        assert!(paths.iter().all(|path| path.exists()));
+        sorted.next().unwrap().unwrap();
+        // Dropped halfway through, as under a LIMIT
+        drop(sorted);
+        assert!(paths.iter().all(|path| !path.exists()));
+    }
+}
@@ -1,10 +1,18 @@
+pub mod evaluator;
+pub mod executor;
+pub mod external_sort;
+pub mod optimizer;
 pub mod parser;
+pub mod planner;
 pub mod tokenizer;
 
 // pub use parser::{parse_create_table, parse_insert, parse_select};
 // pub use tokenizer::Token;
 
-use crate::db::data_types::{Column, Value};
+use core::fmt;
+
+use crate::db::data_types::{Column, UniqueConstraint, Value};
+use crate::db::datetime::DateField;
 
 pub fn process_sql(input: &str) -> Result<SqlCommand, String> {
     let tokens = tokenizer::tokenize(input);
@@ -22,29 +30,380 @@ pub enum SqlCommand {
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        constraints: Vec<UniqueConstraint>,
     },
+    // An empty column list means every column, in table order
     Insert {
         table: String,
         columns: Vec<String>,
-        values: Vec<String>,
+        source: InsertSource,
+        on_conflict: Option<OnConflict>,
+        returning: Option<Vec<SelectItem>>,
+    },
+    Update {
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        where_clause: Option<Expr>,
+        returning: Option<Vec<SelectItem>>,
+    },
+    Delete {
+        table: String,
+        where_clause: Option<Expr>,
+        returning: Option<Vec<SelectItem>>,
+    },
+    // `CREATE [UNIQUE | FULLTEXT] INDEX [name] ON table (expr, ...) [WHERE predicate]` over
+    // columns and expressions such as `lower(email)`, over a single JSON path expression such as
+    // `data->>'email'`, or over the words of a TEXT column
+    CreateIndex {
+        name: Option<String>,
+        table: String,
+        unique: bool,
+        full_text: bool,
+        exprs: Vec<Expr>,
+        where_clause: Option<Expr>,
+    },
+    // `DROP INDEX name [ON table]`; without a table every table is searched for the index
+    DropIndex {
+        name: String,
+        table: Option<String>,
     },
     Select {
         table: String,
-        columns: Vec<String>,
-        where_clause: Option<Vec<Condition>>,
+        // Set when FROM names a table-valued function, e.g. `FROM json_each('[1, 2]')`
+        table_args: Option<Vec<Expr>>,
+        columns: Vec<SelectItem>,
+        where_clause: Option<Expr>,
         join_clause: Option<Vec<Join>>,
+        order_by: Vec<OrderBy>,
+        // `LIMIT count`, applied after ORDER BY
+        limit: Option<usize>,
+    },
+    // `ANALYZE [table]` gathers statistics for the table, or else for every table
+    Analyze {
+        table: Option<String>,
+    },
+    // `EXPLAIN [ANALYZE] query` shows the plan of a SELECT; with ANALYZE the query runs and each
+    // step of the plan shows the rows it handed on and the time it took
+    Explain {
+        analyze: bool,
+        query: Box<SqlCommand>,
+    },
+    // Transaction control: BEGIN, COMMIT, ROLLBACK [TO [SAVEPOINT] name], SAVEPOINT name and
+    // RELEASE [SAVEPOINT] name
+    Begin,
+    Commit,
+    Rollback {
+        savepoint: Option<String>,
+    },
+    Savepoint {
+        name: String,
+    },
+    Release {
+        name: String,
     },
     // Add other command types as needed
 }
 
+// Where the rows of an INSERT come from
+#[derive(Debug, PartialEq)]
+pub enum InsertSource {
+    // `VALUES (...), (...)`, one entry per row
+    Values(Vec<Vec<Expr>>),
+    // `INSERT INTO t SELECT ...`
+    Select(Box<SqlCommand>),
+    // `DEFAULT VALUES`: a single row of column defaults
+    DefaultValues,
+}
+
+// `ON CONFLICT [(columns)] DO NOTHING | DO UPDATE SET ... [WHERE ...]`. Without a target any
+// unique constraint counts as a conflict.
+#[derive(Debug, PartialEq)]
+pub struct OnConflict {
+    pub target: Vec<String>,
+    pub action: ConflictAction,
+}
+
 #[derive(Debug, PartialEq)]
-pub enum Condition {
-    Comparison {
-        left: String,
-        operator: String,
-        right: String,
+pub enum ConflictAction {
+    DoNothing,
+    // Expressions see the existing row and, qualified as `excluded`, the row that was proposed
+    DoUpdate {
+        assignments: Vec<(String, Expr)>,
+        where_clause: Option<Expr>,
     },
-    // TODO:Add more variants as needed, e.g., for AND, OR, etc.
+}
+
+// One key of `ORDER BY expr [ASC | DESC], ...`, ascending unless DESC is given
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Wildcard,
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+// Expressions replace the old string based `Condition` so that WHERE clauses, projections and
+// inserted values can all be computed (e.g. `CURRENT_DATE - INTERVAL '7' DAY`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    // `table.column`, e.g. `excluded.price` in ON CONFLICT DO UPDATE
+    QualifiedColumn {
+        table: String,
+        column: String,
+    },
+    Literal(Value),
+    Unary {
+        operator: UnaryOperator,
+        expr: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        operator: BinaryOperator,
+        right: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    // `expr [NOT] BETWEEN low AND high`, both ends included
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    // `expr [NOT] IN (value, ...)`
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    Extract {
+        field: DateField,
+        expr: Box<Expr>,
+    },
+    CurrentDate,
+    CurrentTimestamp,
+}
+
+impl Expr {
+    // Whether the expression, or one inside it, is one `predicate` holds for
+    pub fn contains(&self, predicate: &dyn Fn(&Expr) -> bool) -> bool {
+        if predicate(self) {
+            return true;
+        }
+        match self {
+            Expr::Column(_)
+            | Expr::QualifiedColumn { .. }
+            | Expr::Literal(_)
+            | Expr::CurrentDate
+            | Expr::CurrentTimestamp => false,
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Extract { expr, .. } => {
+                expr.contains(predicate)
+            }
+            Expr::Binary { left, right, .. } => {
+                left.contains(predicate) || right.contains(predicate)
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => [expr, low, high]
+                .iter()
+                .any(|expr| expr.contains(predicate)),
+            Expr::InList { expr, list, .. } => {
+                expr.contains(predicate) || list.iter().any(|item| item.contains(predicate))
+            }
+            Expr::Function { args, .. } => args.iter().any(|arg| arg.contains(predicate)),
+        }
+    }
+
+    // The expression with `f` applied to each of the expressions directly inside it
+    pub fn map_children(&self, f: &dyn Fn(&Expr) -> Expr) -> Expr {
+        let boxed = |expr: &Expr| Box::new(f(expr));
+        match self {
+            Expr::Column(_)
+            | Expr::QualifiedColumn { .. }
+            | Expr::Literal(_)
+            | Expr::CurrentDate
+            | Expr::CurrentTimestamp => self.clone(),
+            Expr::Unary { operator, expr } => Expr::Unary {
+                operator: *operator,
+                expr: boxed(expr),
+            },
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => Expr::Binary {
+                left: boxed(left),
+                operator: *operator,
+                right: boxed(right),
+            },
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: boxed(expr),
+                negated: *negated,
+            },
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => Expr::Between {
+                expr: boxed(expr),
+                low: boxed(low),
+                high: boxed(high),
+                negated: *negated,
+            },
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => Expr::InList {
+                expr: boxed(expr),
+                list: list.iter().map(f).collect(),
+                negated: *negated,
+            },
+            Expr::Function { name, args } => Expr::Function {
+                name: name.clone(),
+                args: args.iter().map(f).collect(),
+            },
+            Expr::Extract { field, expr } => Expr::Extract {
+                field: *field,
+                expr: boxed(expr),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Not,
+    Minus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    JsonGet,
+    JsonGetText,
+    // `text MATCH 'query'`, see full_text::Query
+    Match,
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOperator::Not => write!(f, "NOT "),
+            UnaryOperator::Minus => write!(f, "-"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinaryOperator::Or => "OR",
+            BinaryOperator::And => "AND",
+            BinaryOperator::Equal => "=",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterOrEqual => ">=",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::JsonGet => "->",
+            BinaryOperator::JsonGetText => "->>",
+            BinaryOperator::Match => "MATCH",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+// Renders the expression back as SQL. Used for result column headers when no alias is given.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::QualifiedColumn { table, column } => write!(f, "{}.{}", table, column),
+            Expr::Literal(Value::Text(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(Value::Date(date)) => write!(f, "DATE '{}'", date),
+            Expr::Literal(Value::Timestamp(timestamp)) => write!(f, "TIMESTAMP '{}'", timestamp),
+            Expr::Literal(Value::Interval(interval)) => write!(f, "INTERVAL '{}'", interval),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Unary { operator, expr } => write!(f, "{}{}", operator, expr),
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOperator::JsonGet | BinaryOperator::JsonGetText),
+                right,
+            } => write!(f, "{}{}{}", left, operator, right),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => write!(f, "({} {} {})", left, operator, right),
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => write!(
+                f,
+                "({} {}BETWEEN {} AND {})",
+                expr,
+                if *negated { "NOT " } else { "" },
+                low,
+                high
+            ),
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let list: Vec<String> = list.iter().map(|item| item.to_string()).collect();
+                write!(
+                    f,
+                    "({} {}IN ({}))",
+                    expr,
+                    if *negated { "NOT " } else { "" },
+                    list.join(", ")
+                )
+            }
+            Expr::Function { name, args } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Extract { field, expr } => write!(f, "EXTRACT({} FROM {})", field, expr),
+            Expr::CurrentDate => write!(f, "CURRENT_DATE"),
+            Expr::CurrentTimestamp => write!(f, "CURRENT_TIMESTAMP"),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
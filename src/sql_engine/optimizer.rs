@@ -0,0 +1,1084 @@
+use std::cell::RefCell;
+use std::ops::Bound;
+
+use super::evaluator::{evaluate, is_truthy, EvalContext, Scope};
+use super::planner::{self, IndexPlan};
+use super::{BinaryOperator, Expr, OrderBy, SelectItem};
+use crate::db::data_types::Value;
+use crate::db::statistics::ColumnStatistics;
+use crate::db::table::{IndexRange, TableSchema};
+
+// A SELECT as relational operators: what rows come out, before deciding how to read them
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogicalPlan {
+    // The rows of a table that `filter` keeps, narrowed down to `columns` unless that is None
+    Scan {
+        table: String,
+        filter: Option<Expr>,
+        columns: Option<Vec<String>>,
+    },
+    // The rows of a table-valued function such as json_each
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        filter: Option<Expr>,
+    },
+    // None of the table's rows, e.g. for `WHERE 1 = 0`
+    Empty {
+        table: String,
+    },
+    Filter {
+        input: Box<LogicalPlan>,
+        predicate: Expr,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        order_by: Vec<OrderBy>,
+    },
+    // The first `count` rows
+    Limit {
+        input: Box<LogicalPlan>,
+        count: usize,
+    },
+    Project {
+        input: Box<LogicalPlan>,
+        items: Vec<SelectItem>,
+    },
+}
+
+// How a SELECT runs, with the rows each operator is expected to hand on and the cost of getting
+// them, its inputs included
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhysicalPlan {
+    pub operator: Operator,
+    pub rows: f64,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operator {
+    SeqScan {
+        table: String,
+        filter: Option<Expr>,
+        columns: Option<Vec<String>>,
+    },
+    // The rows an index leads to: in key order when `ordered`, which takes a single range, and
+    // otherwise in row id order
+    IndexScan {
+        table: String,
+        plan: IndexPlan,
+        ordered: bool,
+        filter: Option<Expr>,
+        columns: Option<Vec<String>>,
+    },
+    FunctionScan {
+        name: String,
+        args: Vec<Expr>,
+        filter: Option<Expr>,
+    },
+    Empty {
+        table: String,
+    },
+    Filter {
+        input: Box<PhysicalPlan>,
+        predicate: Expr,
+    },
+    Sort {
+        input: Box<PhysicalPlan>,
+        order_by: Vec<OrderBy>,
+    },
+    Limit {
+        input: Box<PhysicalPlan>,
+        count: usize,
+    },
+    Project {
+        input: Box<PhysicalPlan>,
+        items: Vec<SelectItem>,
+    },
+}
+
+// Relative costs: reading the next row of a scan, fetching a row by its id, looking up a range of
+// index keys and comparing two rows while sorting
+const SCAN_ROW: f64 = 1.0;
+const FETCH_ROW: f64 = 2.0;
+const INDEX_RANGE: f64 = 10.0;
+const SORT_COMPARISON: f64 = 0.2;
+
+// What is assumed without statistics: the rows of a table or function, and the fraction of rows
+// an equality, a range, a full-text word or any other condition keeps
+const DEFAULT_ROWS: f64 = 1000.0;
+const DEFAULT_EQUAL: f64 = 0.01;
+const DEFAULT_RANGE: f64 = 1.0 / 3.0;
+const DEFAULT_WORD: f64 = 0.05;
+const DEFAULT_CONDITION: f64 = 0.25;
+
+pub fn logical_plan(
+    table: String,
+    table_args: Option<Vec<Expr>>,
+    items: Vec<SelectItem>,
+    where_clause: Option<Expr>,
+    order_by: Vec<OrderBy>,
+    limit: Option<usize>,
+) -> LogicalPlan {
+    let mut plan = match table_args {
+        Some(args) => LogicalPlan::Function {
+            name: table,
+            args,
+            filter: None,
+        },
+        None => LogicalPlan::Scan {
+            table,
+            filter: None,
+            columns: None,
+        },
+    };
+    if let Some(predicate) = where_clause {
+        plan = LogicalPlan::Filter {
+            input: Box::new(plan),
+            predicate,
+        };
+    }
+    if !order_by.is_empty() {
+        plan = LogicalPlan::Sort {
+            input: Box::new(plan),
+            order_by,
+        };
+    }
+    if let Some(count) = limit {
+        plan = LogicalPlan::Limit {
+            input: Box::new(plan),
+            count,
+        };
+    }
+    LogicalPlan::Project {
+        input: Box::new(plan),
+        items,
+    }
+}
+
+// Rewrites the plan and picks how to read each table. `schema` is the one of the table the plan
+// scans, if it scans one.
+pub fn optimize(
+    plan: LogicalPlan,
+    schema: Option<&TableSchema>,
+    ctx: &EvalContext,
+) -> PhysicalPlan {
+    let plan = fold_constants(plan, ctx);
+    let plan = push_down_filters(plan);
+    let plan = match schema {
+        Some(schema) => prune_columns(plan, schema, Some(Vec::new())),
+        None => plan,
+    };
+    physical_plan(plan, schema, ctx)
+}
+
+// Computes the parts of expressions that don't depend on the row, so that `id = 1 + 1` can be
+// looked up in an index. Conditions AND-ed into a WHERE clause that always hold are dropped, and
+// one that never does leaves no rows to read.
+fn fold_constants(plan: LogicalPlan, ctx: &EvalContext) -> LogicalPlan {
+    let fold_input = |input: Box<LogicalPlan>| Box::new(fold_constants(*input, ctx));
+    match plan {
+        LogicalPlan::Filter { input, predicate } => {
+            let input = fold_input(input);
+            let mut conditions = Vec::new();
+            planner::conjuncts(&predicate, &mut conditions);
+            let mut kept = Vec::new();
+            for condition in conditions {
+                match fold(condition, ctx) {
+                    Expr::Literal(value) if is_truthy(&value) => {}
+                    Expr::Literal(value) => {
+                        if let LogicalPlan::Scan { table, .. } = *input {
+                            return LogicalPlan::Empty { table };
+                        }
+                        kept = vec![Expr::Literal(value)];
+                        break;
+                    }
+                    condition => kept.push(condition),
+                }
+            }
+            match and_all(kept) {
+                Some(predicate) => LogicalPlan::Filter { input, predicate },
+                None => *input,
+            }
+        }
+        LogicalPlan::Sort { input, order_by } => {
+            // Rows all have the same value for a constant key, so it doesn't order them
+            let order_by: Vec<OrderBy> = order_by
+                .into_iter()
+                .map(|item| OrderBy {
+                    expr: fold(&item.expr, ctx),
+                    descending: item.descending,
+                })
+                .filter(|item| !matches!(item.expr, Expr::Literal(_)))
+                .collect();
+            match fold_constants(*input, ctx) {
+                // No rows need no sorting
+                input @ LogicalPlan::Empty { .. } => input,
+                input if order_by.is_empty() => input,
+                input => LogicalPlan::Sort {
+                    input: Box::new(input),
+                    order_by,
+                },
+            }
+        }
+        LogicalPlan::Project { input, items } => {
+            let items = items
+                .into_iter()
+                .map(|item| match item {
+                    SelectItem::Wildcard => SelectItem::Wildcard,
+                    SelectItem::Expr { expr, alias } => {
+                        let folded = fold(&expr, ctx);
+                        // A column is named after the expression as it was written
+                        let alias = match alias {
+                            None if folded != expr => Some(expr.to_string()),
+                            alias => alias,
+                        };
+                        SelectItem::Expr {
+                            expr: folded,
+                            alias,
+                        }
+                    }
+                })
+                .collect();
+            LogicalPlan::Project {
+                input: fold_input(input),
+                items,
+            }
+        }
+        LogicalPlan::Function { name, args, filter } => LogicalPlan::Function {
+            name,
+            args: args.iter().map(|arg| fold(arg, ctx)).collect(),
+            filter,
+        },
+        LogicalPlan::Limit { input, count } => LogicalPlan::Limit {
+            input: fold_input(input),
+            count,
+        },
+        plan @ (LogicalPlan::Scan { .. } | LogicalPlan::Empty { .. }) => plan,
+    }
+}
+
+// Expressions that fail, such as 1 / 0, are left for the rows to fail on, so that a query that
+// finds no rows still succeeds
+fn fold(expr: &Expr, ctx: &EvalContext) -> Expr {
+    let expr = expr.map_children(&|child| fold(child, ctx));
+    let depends_on_row = expr.contains(&|expr| {
+        matches!(expr, Expr::Column(_) | Expr::QualifiedColumn { .. })
+            || matches!(expr, Expr::Function { name, .. } if name == "rank")
+    });
+    if depends_on_row || matches!(expr, Expr::Literal(_)) {
+        return expr;
+    }
+    match evaluate(&expr, &Scope::empty(), ctx) {
+        Ok(value) => Expr::Literal(value),
+        Err(_) => expr,
+    }
+}
+
+fn and_all(conditions: Vec<Expr>) -> Option<Expr> {
+    conditions.into_iter().reduce(|left, right| Expr::Binary {
+        left: Box::new(left),
+        operator: BinaryOperator::And,
+        right: Box::new(right),
+    })
+}
+
+// Moves filters down into the scans below them, where the conditions can pick an index
+fn push_down_filters(plan: LogicalPlan) -> LogicalPlan {
+    let and = |filter: Option<Expr>, predicate: Expr| {
+        and_all(filter.into_iter().chain([predicate]).collect())
+    };
+    match plan {
+        LogicalPlan::Filter { input, predicate } => match push_down_filters(*input) {
+            LogicalPlan::Scan {
+                table,
+                filter,
+                columns,
+            } => LogicalPlan::Scan {
+                table,
+                filter: and(filter, predicate),
+                columns,
+            },
+            LogicalPlan::Function { name, args, filter } => LogicalPlan::Function {
+                name,
+                args,
+                filter: and(filter, predicate),
+            },
+            empty @ LogicalPlan::Empty { .. } => empty,
+            // Sorting keeps the rows as they are, so filtering first sorts fewer of them
+            LogicalPlan::Sort { input, order_by } => LogicalPlan::Sort {
+                input: Box::new(push_down_filters(LogicalPlan::Filter { input, predicate })),
+                order_by,
+            },
+            input => LogicalPlan::Filter {
+                input: Box::new(input),
+                predicate,
+            },
+        },
+        LogicalPlan::Sort { input, order_by } => LogicalPlan::Sort {
+            input: Box::new(push_down_filters(*input)),
+            order_by,
+        },
+        LogicalPlan::Limit { input, count } => LogicalPlan::Limit {
+            input: Box::new(push_down_filters(*input)),
+            count,
+        },
+        LogicalPlan::Project { input, items } => LogicalPlan::Project {
+            input: Box::new(push_down_filters(*input)),
+            items,
+        },
+        plan => plan,
+    }
+}
+
+// Narrows the rows a scan hands on to the columns the operators above it read, which is all of
+// them for `*` and rank(). `used` holds the names read so far, None meaning every column.
+fn prune_columns(
+    plan: LogicalPlan,
+    schema: &TableSchema,
+    used: Option<Vec<String>>,
+) -> LogicalPlan {
+    let reads = |used: Option<Vec<String>>, exprs: &[&Expr]| {
+        let mut used = used?;
+        for expr in exprs {
+            if expr.contains(&|expr| matches!(expr, Expr::Function { name, .. } if name == "rank"))
+            {
+                return None;
+            }
+            column_names(expr, &mut used);
+        }
+        Some(used)
+    };
+    match plan {
+        LogicalPlan::Project { input, items } => {
+            let exprs: Option<Vec<&Expr>> = items
+                .iter()
+                .map(|item| match item {
+                    SelectItem::Expr { expr, .. } => Some(expr),
+                    SelectItem::Wildcard => None,
+                })
+                .collect();
+            let used = exprs.and_then(|exprs| reads(used, &exprs));
+            LogicalPlan::Project {
+                input: Box::new(prune_columns(*input, schema, used)),
+                items,
+            }
+        }
+        LogicalPlan::Sort { input, order_by } => {
+            let exprs: Vec<&Expr> = order_by.iter().map(|item| &item.expr).collect();
+            let used = reads(used, &exprs);
+            LogicalPlan::Sort {
+                input: Box::new(prune_columns(*input, schema, used)),
+                order_by,
+            }
+        }
+        LogicalPlan::Filter { input, predicate } => {
+            let used = reads(used, &[&predicate]);
+            LogicalPlan::Filter {
+                input: Box::new(prune_columns(*input, schema, used)),
+                predicate,
+            }
+        }
+        LogicalPlan::Limit { input, count } => LogicalPlan::Limit {
+            input: Box::new(prune_columns(*input, schema, used)),
+            count,
+        },
+        LogicalPlan::Scan {
+            table,
+            filter,
+            columns: _,
+        } => {
+            let used = match &filter {
+                Some(filter) => reads(used, &[filter]),
+                None => used,
+            };
+            let columns = used.and_then(|used| {
+                let kept: Vec<String> = schema
+                    .columns
+                    .iter()
+                    .filter(|column| {
+                        used.iter()
+                            .any(|name| column.name.eq_ignore_ascii_case(name))
+                    })
+                    .map(|column| column.name.clone())
+                    .collect();
+                (kept.len() < schema.columns.len()).then_some(kept)
+            });
+            LogicalPlan::Scan {
+                table,
+                filter,
+                columns,
+            }
+        }
+        plan => plan,
+    }
+}
+
+fn column_names(expr: &Expr, names: &mut Vec<String>) {
+    let found = RefCell::new(Vec::new());
+    expr.contains(&|expr| {
+        if let Expr::Column(name) | Expr::QualifiedColumn { column: name, .. } = expr {
+            found.borrow_mut().push(name.clone());
+        }
+        false
+    });
+    names.extend(found.into_inner());
+}
+
+fn physical_plan(
+    plan: LogicalPlan,
+    schema: Option<&TableSchema>,
+    ctx: &EvalContext,
+) -> PhysicalPlan {
+    match plan {
+        LogicalPlan::Project { input, items } => {
+            let input = physical_plan(*input, schema, ctx);
+            PhysicalPlan {
+                rows: input.rows,
+                cost: input.cost,
+                operator: Operator::Project {
+                    input: Box::new(input),
+                    items,
+                },
+            }
+        }
+        LogicalPlan::Sort { input, order_by } => ordered_plan(*input, order_by, None, schema, ctx),
+        LogicalPlan::Limit { input, count } => {
+            let input = match *input {
+                LogicalPlan::Sort { input, order_by } => {
+                    ordered_plan(*input, order_by, Some(count), schema, ctx)
+                }
+                input => ordered_plan(input, Vec::new(), Some(count), schema, ctx),
+            };
+            limited(input, count)
+        }
+        LogicalPlan::Scan {
+            table,
+            filter,
+            columns,
+        } => match schema {
+            Some(schema) => scan_plan(schema, table, filter, columns, &[], None, ctx),
+            None => PhysicalPlan {
+                rows: DEFAULT_ROWS,
+                cost: DEFAULT_ROWS * SCAN_ROW,
+                operator: Operator::SeqScan {
+                    table,
+                    filter,
+                    columns,
+                },
+            },
+        },
+        LogicalPlan::Function { name, args, filter } => PhysicalPlan {
+            rows: DEFAULT_ROWS * filter.as_ref().map_or(1.0, |f| fraction(None, f, ctx)),
+            cost: DEFAULT_ROWS * SCAN_ROW,
+            operator: Operator::FunctionScan { name, args, filter },
+        },
+        LogicalPlan::Empty { table } => PhysicalPlan {
+            rows: 0.0,
+            cost: 0.0,
+            operator: Operator::Empty { table },
+        },
+        LogicalPlan::Filter { input, predicate } => {
+            let input = physical_plan(*input, schema, ctx);
+            PhysicalPlan {
+                rows: input.rows * fraction(schema, &predicate, ctx),
+                cost: input.cost + input.rows * SCAN_ROW,
+                operator: Operator::Filter {
+                    input: Box::new(input),
+                    predicate,
+                },
+            }
+        }
+    }
+}
+
+// The rows of `input` in the order of `order_by`, of which only `limit` may be wanted
+fn ordered_plan(
+    input: LogicalPlan,
+    order_by: Vec<OrderBy>,
+    limit: Option<usize>,
+    schema: Option<&TableSchema>,
+    ctx: &EvalContext,
+) -> PhysicalPlan {
+    match (input, schema) {
+        (
+            LogicalPlan::Scan {
+                table,
+                filter,
+                columns,
+            },
+            Some(schema),
+        ) => scan_plan(schema, table, filter, columns, &order_by, limit, ctx),
+        (input, _) => {
+            let input = physical_plan(input, schema, ctx);
+            match order_by.is_empty() {
+                true => input,
+                false => sorted(input, order_by),
+            }
+        }
+    }
+}
+
+// Operators that hand rows on as they read them stop early, a sort has to read all of its input
+// before the first row comes out
+fn limited(input: PhysicalPlan, count: usize) -> PhysicalPlan {
+    let rows = input.rows.min(count as f64);
+    let cost = match input.operator {
+        Operator::Sort { .. } => input.cost,
+        _ if input.rows > 0.0 => input.cost * rows / input.rows,
+        _ => input.cost,
+    };
+    PhysicalPlan {
+        rows,
+        cost,
+        operator: Operator::Limit {
+            input: Box::new(input),
+            count,
+        },
+    }
+}
+
+fn sorted(input: PhysicalPlan, order_by: Vec<OrderBy>) -> PhysicalPlan {
+    PhysicalPlan {
+        rows: input.rows,
+        cost: input.cost + sort_cost(input.rows),
+        operator: Operator::Sort {
+            input: Box::new(input),
+            order_by,
+        },
+    }
+}
+
+fn sort_cost(rows: f64) -> f64 {
+    rows * rows.max(2.0).log2() * SORT_COMPARISON
+}
+
+// Reads a table and sorts it by `order_by` unless the rows come out in that order. Each index the
+// filter can narrow the rows down with is weighed against reading through an index in the order
+// wanted and against scanning the table, reading only as far as `limit` rows when they come out
+// in order. A table that has never been analyzed keeps to the first of those it can use, in that
+// order.
+pub fn scan_plan(
+    schema: &TableSchema,
+    table: String,
+    filter: Option<Expr>,
+    columns: Option<Vec<String>>,
+    order_by: &[OrderBy],
+    limit: Option<usize>,
+    ctx: &EvalContext,
+) -> PhysicalPlan {
+    let total = schema
+        .statistics
+        .as_ref()
+        .map_or(DEFAULT_ROWS, |statistics| statistics.rows as f64);
+    let rows = match &filter {
+        Some(filter) => (total * fraction(Some(schema), filter, ctx)).min(total),
+        None => total,
+    };
+    let ordering = ordering_index(schema, order_by, filter.as_ref(), ctx);
+
+    // Each way of reading the rows, with its cost and whether it gives them in order
+    let mut candidates: Vec<(Option<(IndexPlan, bool)>, f64)> = Vec::new();
+    for plan in planner::index_plans(schema, filter.as_ref(), ctx) {
+        let fetched = total * index_fraction(schema, &plan);
+        let cost = plan.ranges.len() as f64 * INDEX_RANGE + fetched * FETCH_ROW;
+        let ordered = ordering == Some(plan.index.as_str()) && plan.ranges.len() == 1;
+        candidates.push((Some((plan, ordered)), cost));
+    }
+    if let Some(index) = ordering {
+        let plan = IndexPlan {
+            index: index.to_string(),
+            ranges: vec![IndexRange::all()],
+        };
+        candidates.push((Some((plan, true)), INDEX_RANGE + total * FETCH_ROW));
+    }
+    candidates.push((None, total * SCAN_ROW));
+
+    let total_cost = |(access, cost): &(Option<(IndexPlan, bool)>, f64)| {
+        let ordered = order_by.is_empty() || access.as_ref().is_some_and(|(_, ordered)| *ordered);
+        match (ordered, limit) {
+            (false, _) => cost + sort_cost(rows),
+            (true, Some(limit)) if rows > 0.0 => cost * (limit as f64 / rows).min(1.0),
+            (true, _) => *cost,
+        }
+    };
+    let chosen = match schema.statistics {
+        Some(_) => candidates.into_iter().reduce(|best, candidate| {
+            match total_cost(&candidate) < total_cost(&best) {
+                true => candidate,
+                false => best,
+            }
+        }),
+        None => candidates.into_iter().next(),
+    };
+    let (access, cost) = chosen.expect("a table can always be scanned");
+
+    let (operator, ordered) = match access {
+        Some((plan, ordered)) => {
+            // Only what the index doesn't already see to is checked on the rows
+            let filter = filter.and_then(|filter| {
+                let mut conditions = Vec::new();
+                planner::conjuncts(&filter, &mut conditions);
+                and_all(
+                    conditions
+                        .into_iter()
+                        .filter(|condition| !planner::enforced(schema, condition, &plan, ctx))
+                        .cloned()
+                        .collect(),
+                )
+            });
+            (
+                Operator::IndexScan {
+                    table,
+                    plan,
+                    ordered,
+                    filter,
+                    columns,
+                },
+                ordered,
+            )
+        }
+        None => (
+            Operator::SeqScan {
+                table,
+                filter,
+                columns,
+            },
+            false,
+        ),
+    };
+    let scan = PhysicalPlan {
+        operator,
+        rows,
+        cost,
+    };
+    match order_by.is_empty() || ordered {
+        true => scan,
+        false => sorted(scan, order_by.to_vec()),
+    }
+}
+
+// The index to read rows from for an ORDER BY of ascending columns. Columns the filter holds to
+// one value needn't be ordered by, in the ORDER BY or before its columns in the index.
+fn ordering_index<'a>(
+    schema: &'a TableSchema,
+    order_by: &[OrderBy],
+    filter: Option<&Expr>,
+    ctx: &EvalContext,
+) -> Option<&'a str> {
+    if order_by.is_empty() {
+        return None;
+    }
+    let columns = order_by
+        .iter()
+        .map(|item| match &item.expr {
+            Expr::Column(name) if !item.descending => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<&str>>>()?;
+    schema.ordering_index(&columns, &planner::fixed_keys(schema, filter, ctx))
+}
+
+// The fraction of rows a condition keeps, taking the conditions AND-ed together to be independent
+fn fraction(schema: Option<&TableSchema>, condition: &Expr, ctx: &EvalContext) -> f64 {
+    let mut conditions = Vec::new();
+    planner::conjuncts(condition, &mut conditions);
+    conditions
+        .into_iter()
+        .map(|condition| {
+            let intervals = schema.and_then(|schema| {
+                let (key, intervals) = planner::condition_intervals(schema, condition, ctx)?;
+                Some((key_statistics(schema, &key), intervals))
+            });
+            match (intervals, condition) {
+                (Some((statistics, intervals)), _) => intervals
+                    .iter()
+                    .map(|(lower, upper)| interval_fraction(statistics, lower, upper))
+                    .sum::<f64>()
+                    .min(1.0),
+                (
+                    None,
+                    Expr::Binary {
+                        operator: BinaryOperator::Match,
+                        ..
+                    },
+                ) => DEFAULT_WORD,
+                (None, _) => DEFAULT_CONDITION,
+            }
+        })
+        .product()
+}
+
+// The fraction of rows an index plan leads to
+fn index_fraction(schema: &TableSchema, plan: &IndexPlan) -> f64 {
+    let Some((_, keys, _)) = schema
+        .column_index_keys()
+        .into_iter()
+        .find(|(name, _, _)| *name == plan.index)
+    else {
+        // A full-text index, with a range for each word
+        return (plan.ranges.len() as f64 * DEFAULT_WORD).min(1.0);
+    };
+    plan.ranges
+        .iter()
+        .map(|range| {
+            let mut fraction = 1.0;
+            for (key, value) in keys.iter().zip(&range.prefix) {
+                let point = Bound::Included(value.clone());
+                fraction *= interval_fraction(key_statistics(schema, key), &point, &point);
+            }
+            let bounded = range.lower != Bound::Unbounded || range.upper != Bound::Unbounded;
+            if let (Some(key), true) = (keys.get(range.prefix.len()), bounded) {
+                fraction *=
+                    interval_fraction(key_statistics(schema, key), &range.lower, &range.upper);
+            }
+            fraction
+        })
+        .sum::<f64>()
+        .min(1.0)
+}
+
+// What ANALYZE found about an index key, when the key is a column
+fn key_statistics<'a>(schema: &'a TableSchema, key: &str) -> Option<&'a ColumnStatistics> {
+    schema.statistics.as_ref()?.column(key)
+}
+
+fn interval_fraction(
+    statistics: Option<&ColumnStatistics>,
+    lower: &Bound<Value>,
+    upper: &Bound<Value>,
+) -> f64 {
+    let equality = planner::is_equality(lower, upper);
+    match (statistics, lower) {
+        // Text equalities take in padded values too, which only the histogram knows about
+        (Some(statistics), Bound::Included(value))
+            if equality && !matches!(value, Value::Text(_)) =>
+        {
+            statistics.equal_fraction(value)
+        }
+        (Some(statistics), _) => statistics.range_fraction(lower.as_ref(), upper.as_ref()),
+        (None, _) if equality => DEFAULT_EQUAL,
+        (None, _) => DEFAULT_RANGE,
+    }
+}
+
+impl PhysicalPlan {
+    // One line per operator for EXPLAIN, each indented under the one it hands rows to, in the
+    // order of a walk from the top
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.explain_into(0, &mut lines);
+        lines
+    }
+
+    fn explain_into(&self, depth: usize, lines: &mut Vec<String>) {
+        let list = |items: Vec<String>| items.join(", ");
+        let (mut line, input) = match &self.operator {
+            Operator::SeqScan { table, .. } => (format!("Seq Scan on {}", table), None),
+            Operator::IndexScan {
+                table,
+                plan,
+                ordered,
+                ..
+            } => {
+                let mut line = format!("Index Scan on {} using {}", table, plan.index);
+                if plan.ranges.len() > 1 {
+                    line.push_str(&format!(" ({} ranges)", plan.ranges.len()));
+                }
+                if *ordered {
+                    line.push_str(" in key order");
+                }
+                (line, None)
+            }
+            Operator::FunctionScan { name, args, .. } => {
+                let args = list(args.iter().map(Expr::to_string).collect());
+                (format!("Function Scan on {}({})", name, args), None)
+            }
+            Operator::Empty { table } => (format!("Empty on {}", table), None),
+            Operator::Filter { input, predicate } => (format!("Filter {}", predicate), Some(input)),
+            Operator::Sort { input, order_by } => {
+                let keys = order_by
+                    .iter()
+                    .map(|item| match item.descending {
+                        true => format!("{} DESC", item.expr),
+                        false => item.expr.to_string(),
+                    })
+                    .collect();
+                (format!("Sort by {}", list(keys)), Some(input))
+            }
+            Operator::Limit { input, count } => (format!("Limit {}", count), Some(input)),
+            Operator::Project { input, items } => {
+                let items = items
+                    .iter()
+                    .map(|item| match item {
+                        SelectItem::Wildcard => "*".to_string(),
+                        SelectItem::Expr {
+                            expr,
+                            alias: Some(alias),
+                        } => format!("{} AS {}", expr, alias),
+                        SelectItem::Expr { expr, alias: None } => expr.to_string(),
+                    })
+                    .collect();
+                (format!("Project {}", list(items)), Some(input))
+            }
+        };
+        if let Operator::SeqScan {
+            filter, columns, ..
+        }
+        | Operator::IndexScan {
+            filter, columns, ..
+        } = &self.operator
+        {
+            if let Some(filter) = filter {
+                line.push_str(&format!(" filter {}", filter));
+            }
+            if let Some(columns) = columns {
+                line.push_str(&format!(" reading {}", list(columns.clone())));
+            }
+        }
+        if let Operator::FunctionScan {
+            filter: Some(filter),
+            ..
+        } = &self.operator
+        {
+            line.push_str(&format!(" filter {}", filter));
+        }
+        lines.push(format!(
+            "{}{} (rows={:.0} cost={:.2})",
+            "  ".repeat(depth),
+            line,
+            self.rows,
+            self.cost
+        ));
+        if let Some(input) = input {
+            input.explain_into(depth + 1, lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::data_types::{Column, DataType, UniqueConstraint};
+    use crate::db::datetime::Timestamp;
+    use crate::db::statistics::TableStatistics;
+    use crate::db::table::ColumnIndex;
+    use crate::sql_engine::{process_sql, SqlCommand};
+
+    fn ctx() -> EvalContext {
+        EvalContext {
+            now: Timestamp::parse("2024-03-15 10:30:00").unwrap(),
+        }
+    }
+
+    // 1000 orders: ids 0 to 999, a status that is 'open' for one in a hundred and a total from 0
+    // to 99
+    fn schema(analyzed: bool) -> TableSchema {
+        let columns = vec![
+            Column::new("id".to_string(), DataType::Integer),
+            Column::new("status".to_string(), DataType::Text),
+            Column::new("total".to_string(), DataType::Integer),
+            Column::new("notes".to_string(), DataType::Text),
+        ];
+        let rows = (0..1000).map(|id| {
+            let status = if id % 100 == 0 { "open" } else { "closed" };
+            Ok(vec![
+                Value::Integer(id),
+                Value::Text(status.to_string()),
+                Value::Integer(id % 100),
+                Value::Null,
+            ])
+        });
+        let statistics = analyzed.then(|| TableStatistics::collect(&columns, rows).unwrap());
+        let index = |name: &str, column: &str| ColumnIndex {
+            name: name.to_string(),
+            columns: vec![column.to_string()],
+            unique: false,
+            predicate: None,
+        };
+        TableSchema {
+            columns,
+            constraints: vec![UniqueConstraint {
+                columns: vec!["id".to_string()],
+                primary_key: true,
+            }],
+            column_indexes: vec![index("by_status", "status"), index("by_total", "total")],
+            full_text_indexes: Vec::new(),
+            statistics,
+        }
+    }
+
+    fn plan(sql: &str, analyzed: bool) -> PhysicalPlan {
+        let SqlCommand::Select {
+            table,
+            table_args,
+            columns,
+            where_clause,
+            order_by,
+            limit,
+            ..
+        } = process_sql(sql).unwrap()
+        else {
+            panic!("not a SELECT: {}", sql);
+        };
+        let plan = logical_plan(table, table_args, columns, where_clause, order_by, limit);
+        optimize(plan, Some(&schema(analyzed)), &ctx())
+    }
+
+    // The operator under the projection
+    fn access(plan: &PhysicalPlan) -> &Operator {
+        match &plan.operator {
+            Operator::Project { input, .. } => &input.operator,
+            operator => panic!("no projection: {:?}", operator),
+        }
+    }
+
+    fn index_used(operator: &Operator) -> Option<&str> {
+        match operator {
+            Operator::IndexScan { plan, .. } => Some(&plan.index),
+            Operator::Sort { input, .. } => index_used(&input.operator),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_rewrites() {
+        // The filter moves into the scan, which reads only the columns the query uses
+        let plan = plan("SELECT id FROM orders WHERE total > 2 * 45;", false);
+        let Operator::IndexScan {
+            plan: index,
+            filter,
+            columns,
+            ..
+        } = access(&plan)
+        else {
+            panic!("{:?}", plan);
+        };
+        assert_eq!(index.index, "by_total");
+        // The index only leads to rows it keeps
+        assert_eq!(*filter, None);
+        assert_eq!(
+            columns.as_deref(),
+            Some(&["id".to_string(), "total".to_string()][..])
+        );
+
+        // Constant conditions and ORDER BY keys go away, projections keep their names
+        let plan = self::plan(
+            "SELECT 1 + 1, * FROM orders WHERE 1 = 1 AND status = 'open' ORDER BY 'x';",
+            false,
+        );
+        let Operator::Project { input, items } = &plan.operator else {
+            panic!("{:?}", plan);
+        };
+        assert_eq!(
+            items[0],
+            SelectItem::Expr {
+                expr: Expr::Literal(Value::Integer(2)),
+                alias: Some("(1 + 1)".to_string()),
+            }
+        );
+        let Operator::IndexScan {
+            filter, columns, ..
+        } = &input.operator
+        else {
+            panic!("{:?}", input);
+        };
+        assert_eq!(filter.as_ref().unwrap().to_string(), "(status = 'open')");
+        assert_eq!(*columns, None);
+        assert_eq!(
+            plan.explain(),
+            vec![
+                "Project 2 AS (1 + 1), * (rows=10 cost=30.00)",
+                "  Index Scan on orders using by_status filter (status = 'open') (rows=10 cost=30.00)",
+            ]
+        );
+
+        for sql in [
+            "SELECT * FROM orders WHERE 1 = 0 AND id = 1;",
+            "SELECT id FROM orders WHERE NULL ORDER BY total;",
+        ] {
+            assert!(
+                matches!(access(&self::plan(sql, false)), Operator::Empty { .. }),
+                "{}",
+                sql
+            );
+        }
+        // Errors are left for the rows
+        let plan = self::plan("SELECT * FROM orders WHERE id = 1 / 0;", false);
+        assert!(matches!(access(&plan), Operator::SeqScan { .. }));
+
+        // An index scan checks the conditions its ranges don't already see to
+        let residual = |sql: &str| {
+            let plan = self::plan(sql, true);
+            match access(&plan) {
+                Operator::IndexScan { plan, filter, .. } => {
+                    (plan.index.clone(), filter.as_ref().map(Expr::to_string))
+                }
+                operator => panic!("{:?}", operator),
+            }
+        };
+        let by_total = |filter: Option<&str>| ("by_total".to_string(), filter.map(str::to_string));
+        assert_eq!(
+            residual("SELECT * FROM orders WHERE total = 4 AND id > 3;"),
+            by_total(Some("(id > 3)"))
+        );
+        assert_eq!(
+            residual("SELECT * FROM orders WHERE total BETWEEN 4 AND 5 AND 3 <= total;"),
+            by_total(None)
+        );
+        // NULLs sort below every other key, so a range without a lower bound takes them in
+        assert_eq!(
+            residual("SELECT * FROM orders WHERE total < 2;"),
+            by_total(Some("(total < 2)"))
+        );
+        assert_eq!(
+            residual("SELECT * FROM orders WHERE total = 4 AND total = 5;"),
+            by_total(Some("(total = 5)"))
+        );
+        assert_eq!(
+            residual("SELECT * FROM orders WHERE status = 'open';"),
+            (
+                "by_status".to_string(),
+                Some("(status = 'open')".to_string())
+            )
+        );
+        assert_eq!(
+            self::plan("SELECT id FROM orders WHERE total = 4;", true).explain(),
+            vec![
+                "Project id (rows=10 cost=30.00)",
+                "  Index Scan on orders using by_total reading id, total (rows=10 cost=30.00)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_costs_pick_the_access_path() {
+        // Without statistics the most specific index wins, as it always has
+        let plan = self::plan("SELECT * FROM orders WHERE status = 'closed';", false);
+        assert_eq!(index_used(access(&plan)), Some("by_status"));
+
+        // Most orders are closed, so reading through the index costs more than a scan
+        let plan = self::plan("SELECT * FROM orders WHERE status = 'closed';", true);
+        assert!(matches!(access(&plan), Operator::SeqScan { .. }));
+        assert!(plan.rows > 900.0, "{}", plan.rows);
+        let plan = self::plan("SELECT * FROM orders WHERE status = 'open';", true);
+        assert_eq!(index_used(access(&plan)), Some("by_status"));
+
+        // Of two indexes the one that leads to fewer rows
+        let sql = "SELECT * FROM orders WHERE total < 50 AND id BETWEEN 10 AND 12;";
+        assert_eq!(index_used(access(&self::plan(sql, false))), Some("id"));
+        let sql = "SELECT * FROM orders WHERE total = 5 AND id > 10;";
+        assert_eq!(index_used(access(&self::plan(sql, false))), Some("id"));
+        assert_eq!(index_used(access(&self::plan(sql, true))), Some("by_total"));
+
+        // Reading in index order saves the sort unless the filter keeps few rows
+        let plan = self::plan("SELECT * FROM orders WHERE total > 1 ORDER BY id;", true);
+        assert!(matches!(
+            access(&plan),
+            Operator::IndexScan { ordered: true, .. }
+        ));
+        let plan = self::plan("SELECT * FROM orders WHERE total = 1 ORDER BY id;", true);
+        assert!(matches!(access(&plan), Operator::Sort { .. }));
+        assert_eq!(index_used(access(&plan)), Some("by_total"));
+    }
+}
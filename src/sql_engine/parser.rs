@@ -1,7 +1,12 @@
 use std::{iter::Peekable, slice::Iter};
 
-use super::{tokenizer::Token, Join};
-use crate::db::data_types::{Column, DataType, Keyword, Value};
+use super::{
+    tokenizer::Token, BinaryOperator, ConflictAction, Expr, InsertSource, Join, OnConflict,
+    OrderBy, SelectItem, UnaryOperator,
+};
+use crate::db::data_types::{Column, DataType, Keyword, UniqueConstraint, Value};
+use crate::db::datetime::{Date, DateField, Interval, Timestamp};
+use crate::db::decimal::Decimal;
 // use crate::sql_engine::tokenizer::Token::Keyword;
 // pub struct Repl {}
 //
@@ -67,17 +72,32 @@ use crate::db::data_types::{Column, DataType, Keyword, Value};
 //         Some((table_name, values))
 //     }
 // }
-use super::{Condition, SqlCommand};
+use super::SqlCommand;
 
 type ParseResult<T> = Result<T, String>;
 
 pub fn parse(tokens: &[Token]) -> Result<SqlCommand, String> {
-    match tokens.get(0) {
+    match tokens.first() {
         // TODO: redo this
         Some(Token::Keyword(keyword)) => match keyword {
-            Keyword::Create => parse_create_table(tokens),
+            Keyword::Create => match tokens.get(1) {
+                Some(Token::Keyword(Keyword::Index | Keyword::Unique | Keyword::Fulltext)) => {
+                    parse_create_index(tokens)
+                }
+                _ => parse_create_table(tokens),
+            },
             Keyword::Insert => parse_insert(tokens),
             Keyword::Select => parse_select(tokens),
+            Keyword::Update => parse_update(tokens),
+            Keyword::Delete => parse_delete(tokens),
+            Keyword::Drop => parse_drop_index(tokens),
+            Keyword::Analyze => parse_analyze(tokens),
+            Keyword::Explain => parse_explain(tokens),
+            Keyword::Begin
+            | Keyword::Commit
+            | Keyword::Rollback
+            | Keyword::Savepoint
+            | Keyword::Release => parse_transaction_control(tokens),
             _ => Err("Unable to parse token: unsupported command.".to_string()),
         },
         _ => Err("Invalid SQL command".to_string()),
@@ -113,6 +133,7 @@ fn parse_create_table(tokens: &[Token]) -> Result<SqlCommand, String> {
     }
     let mut columns: Vec<String> = Vec::new();
     let mut data_types: Vec<DataType> = Vec::new();
+    let mut constraints: Vec<UniqueConstraint> = Vec::new();
     loop {
         if iter.peek().is_none() {
             return Err(String::from(
@@ -125,10 +146,28 @@ fn parse_create_table(tokens: &[Token]) -> Result<SqlCommand, String> {
                 columns.push(name.clone());
 
                 if let Some(Token::DataType(type_name)) = iter.next() {
-                    data_types.push(type_name.to_owned());
+                    data_types.push(parse_type_parameters(type_name, &mut iter)?);
                 } else {
                     return Err("Expected a data type after column name".to_string());
                 }
+
+                // Column constraints: `id INTEGER PRIMARY KEY`, `email TEXT UNIQUE`
+                while let Some(primary_key) = parse_constraint_keyword(&mut iter)? {
+                    constraints.push(UniqueConstraint {
+                        columns: vec![name.clone()],
+                        primary_key,
+                    });
+                }
+            }
+            // Table constraints: `PRIMARY KEY (a, b)`, `UNIQUE (a, b)`
+            Some(Token::Keyword(keyword @ (Keyword::Primary | Keyword::Unique))) => {
+                if *keyword == Keyword::Primary {
+                    expect_key(&mut iter)?;
+                }
+                constraints.push(UniqueConstraint {
+                    columns: parse_column_list(&mut iter)?,
+                    primary_key: *keyword == Keyword::Primary,
+                });
             }
             Some(Token::Symbol(',')) => continue,
             Some(Token::Symbol(')')) => break,
@@ -155,75 +194,556 @@ fn parse_create_table(tokens: &[Token]) -> Result<SqlCommand, String> {
     Ok(SqlCommand::CreateTable {
         name: table_name,
         columns: column_vec,
+        constraints,
     })
 }
 
-#[allow(unused_variables)]
-fn parse_insert(tokens: &[Token]) -> Result<SqlCommand, String> {
-    let mut iter = tokens.iter().peekable();
+// Consumes `PRIMARY KEY` or `UNIQUE` after a column definition, returning whether it was a
+// primary key
+fn parse_constraint_keyword(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Option<bool>> {
+    match iter.peek() {
+        Some(Token::Keyword(Keyword::Primary)) => {
+            iter.next();
+            expect_key(iter)?;
+            Ok(Some(true))
+        }
+        Some(Token::Keyword(Keyword::Unique)) => {
+            iter.next();
+            Ok(Some(false))
+        }
+        _ => Ok(None),
+    }
+}
 
-    // Expect INSERT keyword
+// KEY is not a keyword so that it stays usable as a column name (json_each has one)
+fn expect_key(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<()> {
     match iter.next() {
-        Some(Token::Keyword(Keyword::Insert)) => {}
-        _ => return Err("Expected INSERT keyword".to_string()),
+        Some(Token::Identifier(word)) if word.eq_ignore_ascii_case("key") => Ok(()),
+        _ => Err("Parser: expected KEY after PRIMARY".to_string()),
     }
+}
 
-    // Expect INTO keyword
+// `(name, ...)`
+fn parse_column_list(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Vec<String>> {
     match iter.next() {
-        Some(Token::Keyword(Keyword::Into)) => {}
-        _ => return Err("Expected INTO keyword".to_string()),
+        Some(Token::Symbol('(')) => {}
+        _ => return Err("Parser: expected '(' to start a column list".to_string()),
+    }
+    let mut columns = Vec::new();
+    loop {
+        match iter.next() {
+            Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => {
+                columns.push(name.clone())
+            }
+            _ => return Err("Parser: expected column name in column list".to_string()),
+        }
+        match iter.next() {
+            Some(Token::Symbol(',')) => continue,
+            Some(Token::Symbol(')')) => return Ok(columns),
+            _ => return Err("Parser: expected ',' or ')' in column list".to_string()),
+        }
+    }
+}
+
+fn parse_create_index(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter().peekable();
+
+    if iter.next() != Some(&Token::Keyword(Keyword::Create)) {
+        return Err("Parser: expected CREATE INDEX keywords".to_string());
+    }
+    let unique = iter.next_if_eq(&&Token::Keyword(Keyword::Unique)).is_some();
+    let full_text = !unique
+        && iter
+            .next_if_eq(&&Token::Keyword(Keyword::Fulltext))
+            .is_some();
+    if iter.next() != Some(&Token::Keyword(Keyword::Index)) {
+        return Err("Parser: expected CREATE INDEX keywords".to_string());
     }
 
-    let table_name = match iter.next() {
-        Some(Token::Identifier(name)) => name.clone(),
-        _ => return Err("Expected table name".to_string()),
+    let name = match iter.peek() {
+        Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => {
+            iter.next();
+            Some(name.clone())
+        }
+        _ => None,
     };
 
-    // Parse column values
-    let mut columns = Vec::new();
+    match iter.next() {
+        Some(Token::Keyword(Keyword::On)) => {}
+        _ => return Err("Parser: expected ON after CREATE INDEX".to_string()),
+    }
+    let table = match iter.next() {
+        Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
+        _ => return Err("Parser: expected table name in CREATE INDEX".to_string()),
+    };
+
+    match iter.next() {
+        Some(Token::Symbol('(')) => {}
+        _ => return Err("Parser: expected '(' after table name in CREATE INDEX".to_string()),
+    }
+    let mut exprs = Vec::new();
     loop {
+        exprs.push(parse_expression(&mut iter)?);
         match iter.next() {
-            Some(Token::Symbol('(')) => continue,
-            Some(Token::Identifier(name)) => columns.push(name.clone()),
             Some(Token::Symbol(',')) => continue,
             Some(Token::Symbol(')')) => break,
-            _ => return Err("Expected column name or FROM keyword".to_string()),
+            _ => return Err("Parser: expected ',' or ')' after index expression".to_string()),
         }
     }
+    let where_clause = match iter.next_if_eq(&&Token::Keyword(Keyword::Where)) {
+        Some(_) => Some(parse_expression(&mut iter)?),
+        None => None,
+    };
+
+    match iter.next() {
+        Some(Token::Semicolon) | None => {}
+        Some(_) => return Err("Parser: unexpected tokens after CREATE INDEX".to_string()),
+    }
+
+    Ok(SqlCommand::CreateIndex {
+        name,
+        table,
+        unique,
+        full_text,
+        exprs,
+        where_clause,
+    })
+}
+
+fn parse_drop_index(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter();
+
+    match (iter.next(), iter.next()) {
+        (Some(Token::Keyword(Keyword::Drop)), Some(Token::Keyword(Keyword::Index))) => {}
+        _ => return Err("Parser: expected DROP INDEX keywords".to_string()),
+    }
+    let name = match iter.next() {
+        Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
+        _ => return Err("Parser: expected index name after DROP INDEX".to_string()),
+    };
+
+    let table = match iter.next() {
+        Some(Token::Keyword(Keyword::On)) => match iter.next() {
+            Some(Token::Identifier(table)) | Some(Token::QuotedIdentifier(table)) => {
+                Some(table.clone())
+            }
+            _ => return Err("Parser: expected table name after ON in DROP INDEX".to_string()),
+        },
+        Some(Token::Semicolon) | None => return Ok(SqlCommand::DropIndex { name, table: None }),
+        Some(_) => return Err("Parser: unexpected tokens after DROP INDEX".to_string()),
+    };
+    match iter.next() {
+        Some(Token::Semicolon) | None => Ok(SqlCommand::DropIndex { name, table }),
+        Some(_) => Err("Parser: unexpected tokens after DROP INDEX".to_string()),
+    }
+}
 
-    // Expect VALUES keyword
+fn parse_analyze(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter().skip(1);
+    let table = match iter.next() {
+        Some(Token::Identifier(table)) | Some(Token::QuotedIdentifier(table)) => {
+            Some(table.clone())
+        }
+        Some(Token::Semicolon) | None => return Ok(SqlCommand::Analyze { table: None }),
+        Some(_) => return Err("Parser: expected table name after ANALYZE".to_string()),
+    };
     match iter.next() {
-        Some(Token::Keyword(keyword)) => {}
-        _ => return Err("Expected VALUES keyword".to_string()),
+        Some(Token::Semicolon) | None => Ok(SqlCommand::Analyze { table }),
+        Some(_) => Err("Parser: unexpected tokens after ANALYZE".to_string()),
     }
+}
+
+fn parse_explain(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let analyze = matches!(tokens.get(1), Some(Token::Keyword(Keyword::Analyze)));
+    let query = &tokens[1 + usize::from(analyze)..];
+    match query.first() {
+        Some(Token::Keyword(Keyword::Select)) => Ok(SqlCommand::Explain {
+            analyze,
+            query: Box::new(parse_select(query)?),
+        }),
+        _ => Err("Parser: expected a SELECT query after EXPLAIN".to_string()),
+    }
+}
 
-    // Parse values for each column
-    let mut values = Vec::new();
+// Reads the optional parameters after a type name: VARCHAR(255), CHAR(2), DECIMAL(10, 2)
+fn parse_type_parameters(
+    data_type: &DataType,
+    iter: &mut Peekable<Iter<'_, Token>>,
+) -> ParseResult<DataType> {
+    let takes_parameters = matches!(
+        data_type,
+        DataType::Varchar(_) | DataType::Char(_) | DataType::Decimal(_)
+    );
+    if !takes_parameters || !matches!(iter.peek(), Some(Token::Symbol('('))) {
+        return Ok(data_type.clone());
+    }
+    iter.next(); // consume '('
+
+    let mut parameters = Vec::new();
     loop {
         match iter.next() {
-            Some(Token::Symbol('(')) => continue,
-            Some(Token::Symbol('\'')) => continue,
-            Some(Token::Identifier(name)) => values.push(name.clone()),
+            Some(Token::Number(n)) => parameters.push(
+                u32::try_from(*n)
+                    .map_err(|_| format!("Parser: invalid {} parameter {}", data_type, n))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "Parser: expected a number in {} parameters",
+                    data_type
+                ))
+            }
+        }
+        match iter.next() {
             Some(Token::Symbol(',')) => continue,
             Some(Token::Symbol(')')) => break,
-            _ => return Err("Expected column name or FROM keyword".to_string()),
+            _ => {
+                return Err(format!(
+                    "Parser: expected ',' or ')' in {} parameters",
+                    data_type
+                ))
+            }
         }
     }
 
+    let data_type = match (data_type, parameters.as_slice()) {
+        (DataType::Varchar(_), [length]) => DataType::Varchar(Some(*length)),
+        (DataType::Char(_), [length]) => DataType::Char(Some(*length)),
+        (DataType::Decimal(_), [precision]) => DataType::Decimal(Some((*precision, 0))),
+        (DataType::Decimal(_), [precision, scale]) => DataType::Decimal(Some((*precision, *scale))),
+        _ => {
+            return Err(format!(
+                "Parser: wrong number of parameters for {}",
+                data_type
+            ))
+        }
+    };
+    data_type.validate()?;
+    Ok(data_type)
+}
+
+fn parse_insert(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter().peekable();
+
+    // Expect INSERT keyword
+    match iter.next() {
+        Some(Token::Keyword(Keyword::Insert)) => {}
+        _ => return Err("Expected INSERT keyword".to_string()),
+    }
+
+    // Expect INTO keyword
+    match iter.next() {
+        Some(Token::Keyword(Keyword::Into)) => {}
+        _ => return Err("Expected INTO keyword".to_string()),
+    }
+
+    let table_name = match iter.next() {
+        Some(Token::Identifier(name)) => name.clone(),
+        _ => return Err("Expected table name".to_string()),
+    };
+
+    // Optional column list; without one the values are given in table column order
+    let mut columns = Vec::new();
+    if let Some(Token::Symbol('(')) = iter.peek() {
+        columns = parse_column_list(&mut iter)?;
+    }
+
+    let source = match iter.next() {
+        Some(Token::Keyword(Keyword::Values)) => InsertSource::Values(parse_value_rows(&mut iter)?),
+        // The query runs up to ON CONFLICT, RETURNING or the end of the statement; `iter.len()` is
+        // how many tokens are left after SELECT
+        Some(Token::Keyword(Keyword::Select)) => {
+            let rest = &tokens[tokens.len() - iter.len() - 1..];
+            let end = (0..rest.len())
+                .find(|&i| {
+                    matches!(
+                        &rest[i..],
+                        [Token::Keyword(Keyword::Returning), ..]
+                            | [
+                                Token::Keyword(Keyword::On),
+                                Token::Keyword(Keyword::Conflict),
+                                ..
+                            ]
+                    )
+                })
+                .unwrap_or(rest.len());
+            iter = rest[end..].iter().peekable();
+            InsertSource::Select(Box::new(parse_select(&rest[..end])?))
+        }
+        Some(Token::Keyword(Keyword::Default)) if columns.is_empty() => match iter.next() {
+            Some(Token::Keyword(Keyword::Values)) => InsertSource::DefaultValues,
+            _ => return Err("Parser: expected VALUES after DEFAULT".to_string()),
+        },
+        _ => return Err("Parser: expected VALUES, SELECT or DEFAULT VALUES".to_string()),
+    };
+
+    let on_conflict = match iter.peek() {
+        Some(Token::Keyword(Keyword::On)) => {
+            iter.next();
+            Some(parse_on_conflict(&mut iter)?)
+        }
+        _ => None,
+    };
+    let returning = parse_returning(&mut iter)?;
+
     // Ensure we've consumed all tokens except for a possible semicolon
     match iter.next() {
-        Some(Token::Semicolon) => {}
-        Some(_) => return Err("Unexpected tokens after WHERE clause".to_string()),
-        None => {}
+        Some(Token::Semicolon) | None => {}
+        Some(_) => return Err("Parser: unexpected tokens after INSERT values".to_string()),
     }
 
     Ok(SqlCommand::Insert {
         table: table_name,
         columns,
-        values,
+        source,
+        on_conflict,
+        returning,
+    })
+}
+
+// `UPDATE table SET col = expr, ... [WHERE expr] [RETURNING ...]`
+fn parse_update(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter().peekable();
+
+    match iter.next() {
+        Some(Token::Keyword(Keyword::Update)) => {}
+        _ => return Err("Parser: expected UPDATE keyword".to_string()),
+    }
+    let table = match iter.next() {
+        Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
+        _ => return Err("Parser: expected table name after UPDATE".to_string()),
+    };
+    match iter.next() {
+        Some(Token::Keyword(Keyword::Set)) => {}
+        _ => return Err("Parser: expected SET after table name in UPDATE".to_string()),
+    }
+    let assignments = parse_assignments(&mut iter)?;
+    let where_clause = parse_where(&mut iter)?;
+    let returning = parse_returning(&mut iter)?;
+
+    match iter.next() {
+        Some(Token::Semicolon) | None => {}
+        Some(_) => return Err("Parser: unexpected tokens at end of UPDATE statement".to_string()),
+    }
+
+    Ok(SqlCommand::Update {
+        table,
+        assignments,
+        where_clause,
+        returning,
+    })
+}
+
+// `DELETE FROM table [WHERE expr] [RETURNING ...]`
+fn parse_delete(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter().peekable();
+
+    match (iter.next(), iter.next()) {
+        (Some(Token::Keyword(Keyword::Delete)), Some(Token::Keyword(Keyword::From))) => {}
+        _ => return Err("Parser: expected DELETE FROM keywords".to_string()),
+    }
+    let table = match iter.next() {
+        Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
+        _ => return Err("Parser: expected table name after DELETE FROM".to_string()),
+    };
+    let where_clause = parse_where(&mut iter)?;
+    let returning = parse_returning(&mut iter)?;
+
+    match iter.next() {
+        Some(Token::Semicolon) | None => {}
+        Some(_) => return Err("Parser: unexpected tokens at end of DELETE statement".to_string()),
+    }
+
+    Ok(SqlCommand::Delete {
+        table,
+        where_clause,
+        returning,
     })
 }
 
+fn parse_transaction_control(tokens: &[Token]) -> Result<SqlCommand, String> {
+    let mut iter = tokens.iter().peekable();
+    let keyword = match iter.next() {
+        Some(Token::Keyword(keyword)) => keyword,
+        _ => return Err("Parser: expected a transaction control statement".to_string()),
+    };
+    // The optional noise words: BEGIN TRANSACTION, ROLLBACK TO SAVEPOINT name, ...
+    let mut skip = |word: Keyword| {
+        if iter.peek() == Some(&&Token::Keyword(word)) {
+            iter.next();
+        }
+    };
+
+    let command = match keyword {
+        Keyword::Begin | Keyword::Commit => {
+            skip(Keyword::Transaction);
+            if *keyword == Keyword::Begin {
+                SqlCommand::Begin
+            } else {
+                SqlCommand::Commit
+            }
+        }
+        Keyword::Rollback => {
+            skip(Keyword::Transaction);
+            match iter.peek() {
+                Some(Token::Keyword(Keyword::To)) => {
+                    iter.next();
+                    if iter.peek() == Some(&&Token::Keyword(Keyword::Savepoint)) {
+                        iter.next();
+                    }
+                    SqlCommand::Rollback {
+                        savepoint: Some(parse_savepoint_name(&mut iter)?),
+                    }
+                }
+                _ => SqlCommand::Rollback { savepoint: None },
+            }
+        }
+        Keyword::Savepoint => SqlCommand::Savepoint {
+            name: parse_savepoint_name(&mut iter)?,
+        },
+        Keyword::Release => {
+            skip(Keyword::Savepoint);
+            SqlCommand::Release {
+                name: parse_savepoint_name(&mut iter)?,
+            }
+        }
+        _ => return Err("Parser: expected a transaction control statement".to_string()),
+    };
+
+    match iter.next() {
+        Some(Token::Semicolon) | None => Ok(command),
+        Some(unexpected) => Err(format!(
+            "Parser: unexpected token after transaction statement: {:?}",
+            unexpected
+        )),
+    }
+}
+
+fn parse_savepoint_name(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<String> {
+    match iter.next() {
+        Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => Ok(name.clone()),
+        _ => Err("Parser: expected savepoint name".to_string()),
+    }
+}
+
+fn parse_where(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Option<Expr>> {
+    match iter.peek() {
+        Some(Token::Keyword(Keyword::Where)) => {
+            iter.next();
+            Ok(Some(parse_expression(iter)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+// `RETURNING *` / `RETURNING expr [AS alias], ...` at the end of INSERT, UPDATE and DELETE
+fn parse_returning(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Option<Vec<SelectItem>>> {
+    match iter.peek() {
+        Some(Token::Keyword(Keyword::Returning)) => {
+            iter.next();
+        }
+        _ => return Ok(None),
+    }
+    let mut items = vec![parse_select_item(iter)?];
+    while let Some(Token::Symbol(',')) = iter.peek() {
+        iter.next();
+        items.push(parse_select_item(iter)?);
+    }
+    Ok(Some(items))
+}
+
+// `CONFLICT [(columns)] DO NOTHING | DO UPDATE SET col = expr, ... [WHERE expr]`, after ON
+fn parse_on_conflict(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<OnConflict> {
+    match iter.next() {
+        Some(Token::Keyword(Keyword::Conflict)) => {}
+        _ => return Err("Parser: expected CONFLICT after ON".to_string()),
+    }
+    let target = match iter.peek() {
+        Some(Token::Symbol('(')) => parse_column_list(iter)?,
+        _ => Vec::new(),
+    };
+    match iter.next() {
+        Some(Token::Keyword(Keyword::Do)) => {}
+        _ => return Err("Parser: expected DO after ON CONFLICT".to_string()),
+    }
+
+    let action = match iter.next() {
+        Some(Token::Keyword(Keyword::Nothing)) => ConflictAction::DoNothing,
+        Some(Token::Keyword(Keyword::Update)) => {
+            // Which row to update is only well defined for a single constraint
+            if target.is_empty() {
+                return Err(
+                    "Parser: ON CONFLICT DO UPDATE requires a conflict target, e.g. (id)"
+                        .to_string(),
+                );
+            }
+            match iter.next() {
+                Some(Token::Keyword(Keyword::Set)) => {}
+                _ => return Err("Parser: expected SET after DO UPDATE".to_string()),
+            }
+            let assignments = parse_assignments(iter)?;
+            let where_clause = parse_where(iter)?;
+            ConflictAction::DoUpdate {
+                assignments,
+                where_clause,
+            }
+        }
+        _ => return Err("Parser: expected NOTHING or UPDATE after DO".to_string()),
+    };
+
+    Ok(OnConflict { target, action })
+}
+
+// `col = expr, ...` after SET
+fn parse_assignments(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Vec<(String, Expr)>> {
+    let mut assignments = Vec::new();
+    loop {
+        let column = match iter.next() {
+            Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
+            _ => return Err("Parser: expected column name in SET".to_string()),
+        };
+        match iter.next() {
+            Some(Token::Symbol('=')) => {}
+            _ => return Err(format!("Parser: expected '=' after '{}' in SET", column)),
+        }
+        assignments.push((column, parse_expression(iter)?));
+
+        match iter.peek() {
+            Some(Token::Symbol(',')) => {
+                iter.next();
+            }
+            _ => return Ok(assignments),
+        }
+    }
+}
+
+// `(expr, ...), (expr, ...)` after VALUES
+fn parse_value_rows(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Vec<Vec<Expr>>> {
+    let mut rows = Vec::new();
+    loop {
+        match iter.next() {
+            Some(Token::Symbol('(')) => {}
+            _ => return Err("Parser: expected '(' to start a VALUES row".to_string()),
+        }
+        let mut values = Vec::new();
+        loop {
+            values.push(parse_expression(iter)?);
+            match iter.next() {
+                Some(Token::Symbol(',')) => continue,
+                Some(Token::Symbol(')')) => break,
+                _ => return Err("Parser: expected ',' or ')' in VALUES list".to_string()),
+            }
+        }
+        rows.push(values);
+
+        match iter.peek() {
+            Some(Token::Symbol(',')) => {
+                iter.next();
+            }
+            _ => return Ok(rows),
+        }
+    }
+}
+
 fn parse_select(tokens: &[Token]) -> Result<SqlCommand, String> {
     let mut iter = tokens.iter().peekable();
 
@@ -239,16 +759,14 @@ fn parse_select(tokens: &[Token]) -> Result<SqlCommand, String> {
     if iter.peek().is_none() {
         return Err("Parser: expected column names, but found none.".to_string());
     }
-    // Parse column names
+    // Parse the select list
     let mut columns = Vec::new();
     loop {
         if iter.peek().is_none() {
             return Err("Parser: error selecting column names. Expected '*' or column names, but found none.".to_string());
         }
+        columns.push(parse_select_item(&mut iter)?);
         match iter.next() {
-            Some(Token::Identifier(name)) => columns.push(name.clone()),
-            Some(Token::QuotedIdentifier(name)) => columns.push(name.clone()),
-            Some(Token::Symbol('*')) => columns.push("*".to_string()),
             Some(Token::Keyword(Keyword::From)) => break,
             Some(Token::Symbol(',')) => continue,
             _ => return Err("Expected column name or FROM keyword".to_string()),
@@ -265,101 +783,479 @@ fn parse_select(tokens: &[Token]) -> Result<SqlCommand, String> {
         _ => return Err("Expected table name".to_string()),
     };
 
+    // A parenthesised argument list turns the name into a table-valued function call
+    let mut table_args = None;
+    if let Some(Token::Symbol('(')) = iter.peek() {
+        iter.next();
+        table_args = Some(parse_arguments(&mut iter)?);
+    }
+
     let mut where_clause = None;
     let join_clause = None;
 
     match iter.peek() {
         Some(Token::Keyword(Keyword::Where)) => {
             iter.next(); // consume WHERE keyword
-            where_clause = Some(parse_where_clause(&mut iter)?)
+            where_clause = Some(parse_expression(&mut iter)?)
         }
         Some(Token::Keyword(Keyword::Join)) => {
             iter.next();
             //TODO: implement inner join
-            Some(parse_inner_join_clause(&mut iter)?);
+            parse_inner_join_clause(&mut iter)?;
         }
-        Some(Token::Semicolon) => println!("Parser: end of SELECT statement."),
+        Some(Token::Keyword(Keyword::Order | Keyword::Limit)) | Some(Token::Semicolon) | None => {}
         Some(_) => {
             return Err(
-                "Parser: unexpected token after table name. Expected WHERE, JOIN, or SEMICOLON."
+                "Parser: unexpected token after table name. Expected WHERE, JOIN, ORDER BY, LIMIT or SEMICOLON."
                     .to_string(),
             )
         }
-        None => {
-            println!("Parser: end of SELECT statement.");
+    }
+
+    let order_by = parse_order_by(&mut iter)?;
+    let limit = match iter.peek() {
+        Some(Token::Keyword(Keyword::Limit)) => {
+            iter.next();
+            match iter.next() {
+                Some(Token::Number(count)) if *count >= 0 => Some(*count as usize),
+                _ => return Err("Parser: expected a row count after LIMIT".to_string()),
+            }
+        }
+        _ => None,
+    };
+
+    match iter.next() {
+        Some(Token::Semicolon) | None => {}
+        Some(unexpected) => {
+            return Err(format!(
+                "Parser: unexpected token at end of SELECT statement: {:?}",
+                unexpected
+            ))
         }
     }
 
     Ok(SqlCommand::Select {
         table: table_name,
+        table_args,
         columns,
         where_clause,
         join_clause,
+        order_by,
+        limit,
     })
 }
 
+// `ORDER BY expr [ASC | DESC], ...`, if present
+fn parse_order_by(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Vec<OrderBy>> {
+    let mut order_by = Vec::new();
+    if let Some(Token::Keyword(Keyword::Order)) = iter.peek() {
+        iter.next();
+        match iter.next() {
+            Some(Token::Keyword(Keyword::By)) => {}
+            _ => return Err("Parser: expected BY after ORDER".to_string()),
+        }
+        loop {
+            let expr = parse_expression(iter)?;
+            let descending = match iter.peek() {
+                Some(Token::Keyword(Keyword::Asc)) => {
+                    iter.next();
+                    false
+                }
+                Some(Token::Keyword(Keyword::Desc)) => {
+                    iter.next();
+                    true
+                }
+                _ => false,
+            };
+            order_by.push(OrderBy { expr, descending });
+            match iter.peek() {
+                Some(Token::Symbol(',')) => {
+                    iter.next();
+                }
+                _ => break,
+            }
+        }
+    }
+    Ok(order_by)
+}
+
+fn parse_select_item(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<SelectItem> {
+    if let Some(Token::Symbol('*')) = iter.peek() {
+        iter.next();
+        return Ok(SelectItem::Wildcard);
+    }
+
+    let expr = parse_expression(iter)?;
+    let alias = match iter.peek() {
+        Some(Token::Keyword(Keyword::As)) => {
+            iter.next();
+            match iter.next() {
+                Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => {
+                    Some(name.clone())
+                }
+                _ => return Err("Parser: expected alias after AS".to_string()),
+            }
+        }
+        _ => None,
+    };
+    Ok(SelectItem::Expr { expr, alias })
+}
+
+#[allow(unused_variables)]
 fn parse_inner_join_clause(iter: &mut Peekable<Iter<'_, Token>>) -> Result<Join, String> {
     todo!()
 }
 
-fn parse_where_clause(iter: &mut Peekable<Iter<'_, Token>>) -> Result<Vec<Condition>, String> {
-    let mut conditions: Vec<Condition> = Vec::new();
+// Expressions are parsed by precedence climbing, from loosest to tightest binding:
+// OR, AND, NOT, comparisons / IS NULL, + -, * / %, unary minus, -> ->>, primary expressions.
+pub fn parse_expression(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    parse_or(iter)
+}
 
-    loop {
-        let left = match iter.next() {
-            Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
-            Some(Token::Number(n)) => n.to_string(),
-            Some(Token::String(s)) => s.clone(),
-            Some(unexpected) => return Err(format!("Parser: Unexpected token in WHERE clause: expected identifier or value, but got {:?}", unexpected)),
-            None => return Err("Parser: Unexpected end of input in WHERE clause: expected identifier or value".to_string()),
+fn parse_or(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let mut left = parse_and(iter)?;
+    while let Some(Token::Keyword(Keyword::Or)) = iter.peek() {
+        iter.next();
+        let right = parse_and(iter)?;
+        left = binary(left, BinaryOperator::Or, right);
+    }
+    Ok(left)
+}
+
+fn parse_and(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let mut left = parse_not(iter)?;
+    while let Some(Token::Keyword(Keyword::And)) = iter.peek() {
+        iter.next();
+        let right = parse_not(iter)?;
+        left = binary(left, BinaryOperator::And, right);
+    }
+    Ok(left)
+}
+
+fn parse_not(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    if let Some(Token::Keyword(Keyword::Not)) = iter.peek() {
+        iter.next();
+        return Ok(Expr::Unary {
+            operator: UnaryOperator::Not,
+            expr: Box::new(parse_not(iter)?),
+        });
+    }
+    parse_comparison(iter)
+}
+
+fn parse_comparison(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let left = parse_additive(iter)?;
+
+    if let Some(Token::Keyword(Keyword::Is)) = iter.peek() {
+        iter.next();
+        let negated = matches!(iter.peek(), Some(Token::Keyword(Keyword::Not)));
+        if negated {
+            iter.next();
+        }
+        return match iter.next() {
+            Some(Token::DataType(DataType::Null)) => Ok(Expr::IsNull {
+                expr: Box::new(left),
+                negated,
+            }),
+            _ => Err("Parser: expected NULL after IS".to_string()),
         };
+    }
 
-        let operator = match iter.next() {
-            Some(Token::Symbol('=')) => "=".to_string(),
-            Some(Token::Symbol('>')) => ">".to_string(),
-            Some(Token::Symbol('<')) => "<".to_string(),
-            Some(Token::Symbol('!')) => {
-                if let Some(Token::Symbol('=')) = iter.next() {
-                    "!=".to_string()
-                } else {
-                    return Err("Parser: expected '=' after '!' in WHERE clause".to_string());
+    // NOT only belongs to this comparison when BETWEEN or IN follows it
+    let mut lookahead = iter.clone();
+    let negated = matches!(lookahead.next(), Some(Token::Keyword(Keyword::Not)));
+    let next = if negated {
+        lookahead.next()
+    } else {
+        iter.peek().copied()
+    };
+    match next {
+        Some(Token::Keyword(Keyword::Between)) => {
+            if negated {
+                iter.next();
+            }
+            iter.next();
+            let low = parse_additive(iter)?;
+            match iter.next() {
+                Some(Token::Keyword(Keyword::And)) => {}
+                _ => return Err("Parser: expected AND in BETWEEN".to_string()),
+            }
+            let high = parse_additive(iter)?;
+            return Ok(Expr::Between {
+                expr: Box::new(left),
+                low: Box::new(low),
+                high: Box::new(high),
+                negated,
+            });
+        }
+        Some(Token::Keyword(Keyword::In)) => {
+            if negated {
+                iter.next();
+            }
+            iter.next();
+            match iter.next() {
+                Some(Token::Symbol('(')) => {}
+                _ => return Err("Parser: expected '(' after IN".to_string()),
+            }
+            let list = parse_arguments(iter)?;
+            if list.is_empty() {
+                return Err("Parser: IN expects at least one value".to_string());
+            }
+            return Ok(Expr::InList {
+                expr: Box::new(left),
+                list,
+                negated,
+            });
+        }
+        _ => {}
+    }
+
+    let operator = match iter.peek() {
+        Some(Token::Symbol('=')) => {
+            iter.next();
+            BinaryOperator::Equal
+        }
+        Some(Token::Symbol('!')) => {
+            iter.next();
+            if let Some(Token::Symbol('=')) = iter.next() {
+                BinaryOperator::NotEqual
+            } else {
+                return Err("Parser: expected '=' after '!' in WHERE clause".to_string());
+            }
+        }
+        Some(Token::Symbol('<')) => {
+            iter.next();
+            match iter.peek() {
+                Some(Token::Symbol('=')) => {
+                    iter.next();
+                    BinaryOperator::LessOrEqual
                 }
+                Some(Token::Symbol('>')) => {
+                    iter.next();
+                    BinaryOperator::NotEqual
+                }
+                _ => BinaryOperator::Less,
+            }
+        }
+        Some(Token::Symbol('>')) => {
+            iter.next();
+            if let Some(Token::Symbol('=')) = iter.peek() {
+                iter.next();
+                BinaryOperator::GreaterOrEqual
+            } else {
+                BinaryOperator::Greater
             }
-            _ => return Err("Parser: expected comparison operator in WHERE clause".to_string()),
+        }
+        Some(Token::Keyword(Keyword::Match)) => {
+            iter.next();
+            BinaryOperator::Match
+        }
+        _ => return Ok(left),
+    };
+
+    let right = parse_additive(iter)?;
+    Ok(binary(left, operator, right))
+}
+
+fn parse_additive(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let mut left = parse_multiplicative(iter)?;
+    loop {
+        let operator = match iter.peek() {
+            Some(Token::Symbol('+')) => BinaryOperator::Plus,
+            Some(Token::Symbol('-')) => BinaryOperator::Minus,
+            _ => return Ok(left),
         };
+        iter.next();
+        let right = parse_multiplicative(iter)?;
+        left = binary(left, operator, right);
+    }
+}
 
-        let right = match iter.next() {
-            Some(Token::Identifier(name)) | Some(Token::QuotedIdentifier(name)) => name.clone(),
-            Some(Token::Number(n)) => n.to_string(),
-            Some(Token::String(s)) => s.clone(),
-            Some(unexpected) => return Err(format!("Parser: unexpected token in WHERE clause: expected identifier or value, but got {:?}", unexpected)),
-            None => return Err("Parser: unexpected end of input in WHERE clause: expected identifier or value".to_string()),
+fn parse_multiplicative(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let mut left = parse_unary(iter)?;
+    loop {
+        let operator = match iter.peek() {
+            Some(Token::Symbol('*')) => BinaryOperator::Multiply,
+            Some(Token::Symbol('/')) => BinaryOperator::Divide,
+            Some(Token::Symbol('%')) => BinaryOperator::Modulo,
+            _ => return Ok(left),
         };
+        iter.next();
+        let right = parse_unary(iter)?;
+        left = binary(left, operator, right);
+    }
+}
 
-        conditions.push(Condition::Comparison {
-            left,
-            operator,
-            right,
-        });
+fn parse_unary(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    if let Some(Token::Symbol('-')) = iter.peek() {
+        iter.next();
+        return match parse_unary(iter)? {
+            Expr::Literal(Value::Integer(n)) => Ok(Expr::Literal(Value::Integer(-n))),
+            Expr::Literal(Value::Decimal(d)) => Ok(Expr::Literal(Value::Decimal(d.negate()))),
+            expr => Ok(Expr::Unary {
+                operator: UnaryOperator::Minus,
+                expr: Box::new(expr),
+            }),
+        };
+    }
+    parse_json_access(iter)
+}
 
-        match iter.peek() {
-            Some(Token::Keyword(Keyword::And)) | Some(Token::Keyword(Keyword::Or)) => {
-                // TODO: handle AND / OR cases
-                iter.next(); // consume the AND/OR keyword
-            }
-            // TODO: create a function that handles the left and right comparisons
-            Some(unexpected) => {
-                println!(
-                    "Parser Debug: Unexpected token after condition: {:?}",
-                    unexpected
-                );
-                break; // If no AND/OR, we're done with the WHERE clause
+fn parse_json_access(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let mut left = parse_primary(iter)?;
+    loop {
+        let operator = match iter.peek() {
+            Some(Token::Operator(op)) if op == "->" => BinaryOperator::JsonGet,
+            Some(Token::Operator(op)) if op == "->>" => BinaryOperator::JsonGetText,
+            _ => return Ok(left),
+        };
+        iter.next();
+        let right = parse_primary(iter)?;
+        left = binary(left, operator, right);
+    }
+}
+
+fn parse_primary(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    match iter.next() {
+        Some(Token::Number(n)) => Ok(Expr::Literal(Value::Integer(*n))),
+        Some(Token::Decimal(s)) => Ok(Expr::Literal(Value::Decimal(Decimal::parse(s)?))),
+        Some(Token::String(s)) => Ok(Expr::Literal(Value::Text(s.clone()))),
+        Some(Token::Keyword(Keyword::True)) => Ok(Expr::Literal(Value::Boolean(true))),
+        Some(Token::Keyword(Keyword::False)) => Ok(Expr::Literal(Value::Boolean(false))),
+        Some(Token::DataType(DataType::Null)) => Ok(Expr::Literal(Value::Null)),
+        // Typed literals: DATE '2024-01-31', TIMESTAMP '2024-01-31 12:00:00'
+        Some(Token::DataType(DataType::Date)) => match iter.next() {
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::Date(Date::parse(s)?))),
+            _ => Err("Parser: expected string literal after DATE".to_string()),
+        },
+        Some(Token::DataType(DataType::Timestamp)) => match iter.next() {
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::Timestamp(Timestamp::parse(s)?))),
+            _ => Err("Parser: expected string literal after TIMESTAMP".to_string()),
+        },
+        Some(Token::Keyword(Keyword::Interval)) => parse_interval(iter),
+        Some(Token::Keyword(Keyword::Extract)) => parse_extract(iter),
+        Some(Token::Keyword(Keyword::CurrentDate)) => Ok(Expr::CurrentDate),
+        Some(Token::Keyword(Keyword::CurrentTimestamp)) => Ok(Expr::CurrentTimestamp),
+        Some(Token::Symbol('(')) => {
+            let expr = parse_expression(iter)?;
+            match iter.next() {
+                Some(Token::Symbol(')')) => Ok(expr),
+                _ => Err("Parser: expected ')' to close expression".to_string()),
+            }
+        }
+        Some(Token::Identifier(name)) => {
+            if let Some(Token::Symbol('(')) = iter.peek() {
+                iter.next();
+                Ok(Expr::Function {
+                    name: name.to_lowercase(),
+                    args: parse_arguments(iter)?,
+                })
+            } else {
+                parse_column(name, iter)
+            }
+        }
+        Some(Token::QuotedIdentifier(name)) => parse_column(name, iter),
+        Some(unexpected) => Err(format!(
+            "Parser: unexpected token in expression: {:?}",
+            unexpected
+        )),
+        None => Err("Parser: unexpected end of input, expected an expression".to_string()),
+    }
+}
+
+// A column reference, qualified when the name is followed by `.column`
+fn parse_column(name: &str, iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    if let Some(Token::Symbol('.')) = iter.peek() {
+        iter.next();
+        return match iter.next() {
+            Some(Token::Identifier(column)) | Some(Token::QuotedIdentifier(column)) => {
+                Ok(Expr::QualifiedColumn {
+                    table: name.to_string(),
+                    column: column.clone(),
+                })
             }
-            None => break, // End of input, we're done with the WHERE clause
+            _ => Err(format!("Parser: expected column name after '{}.'", name)),
+        };
+    }
+    Ok(Expr::Column(name.to_string()))
+}
+
+// Parses a comma separated argument list, the opening parenthesis has already been consumed
+fn parse_arguments(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Vec<Expr>> {
+    let mut args = Vec::new();
+    if let Some(Token::Symbol(')')) = iter.peek() {
+        iter.next();
+        return Ok(args);
+    }
+    loop {
+        args.push(parse_expression(iter)?);
+        match iter.next() {
+            Some(Token::Symbol(',')) => continue,
+            Some(Token::Symbol(')')) => return Ok(args),
+            _ => return Err("Parser: expected ',' or ')' in argument list".to_string()),
+        }
+    }
+}
+
+// INTERVAL '3' DAY or INTERVAL '1 year 2 months'
+fn parse_interval(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    let body = match iter.next() {
+        Some(Token::String(s)) => s.clone(),
+        _ => return Err("Parser: expected string literal after INTERVAL".to_string()),
+    };
+
+    let unit = match iter.peek() {
+        Some(Token::Identifier(unit)) if Interval::from_unit(0, unit).is_ok() => {
+            iter.next();
+            Some(unit)
+        }
+        _ => None,
+    };
+
+    let interval = match unit {
+        Some(unit) => {
+            let quantity = body
+                .trim()
+                .parse()
+                .map_err(|_| format!("Parser: invalid interval quantity '{}'", body))?;
+            Interval::from_unit(quantity, unit)?
         }
+        None => Interval::parse(&body)?,
+    };
+    Ok(Expr::Literal(Value::Interval(interval)))
+}
+
+// EXTRACT(field FROM expr)
+fn parse_extract(iter: &mut Peekable<Iter<'_, Token>>) -> ParseResult<Expr> {
+    match iter.next() {
+        Some(Token::Symbol('(')) => {}
+        _ => return Err("Parser: expected '(' after EXTRACT".to_string()),
+    }
+    let field = match iter.next() {
+        Some(Token::Identifier(name)) => DateField::parse(name)
+            .ok_or_else(|| format!("Parser: unknown EXTRACT field '{}'", name))?,
+        _ => return Err("Parser: expected field name in EXTRACT".to_string()),
+    };
+    match iter.next() {
+        Some(Token::Keyword(Keyword::From)) => {}
+        _ => return Err("Parser: expected FROM in EXTRACT".to_string()),
     }
+    let expr = parse_expression(iter)?;
+    match iter.next() {
+        Some(Token::Symbol(')')) => Ok(Expr::Extract {
+            field,
+            expr: Box::new(expr),
+        }),
+        _ => Err("Parser: expected ')' to close EXTRACT".to_string()),
+    }
+}
 
-    Ok(conditions)
+fn binary(left: Expr, operator: BinaryOperator, right: Expr) -> Expr {
+    Expr::Binary {
+        left: Box::new(left),
+        operator,
+        right: Box::new(right),
+    }
 }
 
 #[cfg(test)]
@@ -383,9 +1279,21 @@ mod tests {
             result.unwrap(),
             SqlCommand::Select {
                 table: "users".to_string(),
-                columns: vec!["name".to_string(), "age".to_string()],
+                table_args: None,
+                columns: vec![
+                    SelectItem::Expr {
+                        expr: Expr::Column("name".to_string()),
+                        alias: None,
+                    },
+                    SelectItem::Expr {
+                        expr: Expr::Column("age".to_string()),
+                        alias: None,
+                    },
+                ],
                 where_clause: None,
                 join_clause: None,
+                order_by: Vec::new(),
+                limit: None,
             }
         );
     }
@@ -413,23 +1321,28 @@ mod tests {
                 columns,
                 where_clause,
                 join_clause,
+                ..
             } => {
                 assert_eq!(table, "users".to_string());
-                assert_eq!(columns, vec!["name".to_string()]);
+                assert_eq!(
+                    columns,
+                    vec![SelectItem::Expr {
+                        expr: Expr::Column("name".to_string()),
+                        alias: None,
+                    }]
+                );
+                assert!(join_clause.is_none());
                 assert!(where_clause.is_some());
 
-                let conditions = where_clause.unwrap();
-                assert_eq!(conditions.len(), 1);
-
-                match &conditions[0] {
-                    Condition::Comparison {
+                match where_clause.unwrap() {
+                    Expr::Binary {
                         left,
                         operator,
                         right,
                     } => {
-                        assert_eq!(left, "age");
-                        assert_eq!(operator, ">");
-                        assert_eq!(right, "18");
+                        assert_eq!(*left, Expr::Column("age".to_string()));
+                        assert_eq!(operator, BinaryOperator::Greater);
+                        assert_eq!(*right, Expr::Literal(Value::Integer(18)));
                     }
                     _ => panic!("Parser: expected Comparison condition"),
                 }
@@ -438,6 +1351,113 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_select_with_order_by() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        match parse_sql("SELECT a FROM t WHERE a > 1 ORDER BY a DESC, b + 1 ASC, c;").unwrap() {
+            SqlCommand::Select {
+                where_clause,
+                order_by,
+                ..
+            } => {
+                assert!(where_clause.is_some());
+                assert_eq!(
+                    order_by,
+                    vec![
+                        OrderBy {
+                            expr: Expr::Column("a".to_string()),
+                            descending: true,
+                        },
+                        OrderBy {
+                            expr: binary(
+                                Expr::Column("b".to_string()),
+                                BinaryOperator::Plus,
+                                Expr::Literal(Value::Integer(1)),
+                            ),
+                            descending: false,
+                        },
+                        OrderBy {
+                            expr: Expr::Column("c".to_string()),
+                            descending: false,
+                        },
+                    ]
+                );
+            }
+            other => panic!("Parser: expected Select command, got {:?}", other),
+        }
+        assert!(parse_sql("SELECT a FROM t ORDER BY a;").is_ok());
+        assert!(parse_sql("SELECT a FROM t ORDER a;").is_err());
+        assert!(parse_sql("SELECT a FROM t ORDER BY;").is_err());
+        assert!(parse_sql("SELECT a FROM t ORDER BY a,;").is_err());
+
+        for (sql, expected) in [
+            ("SELECT a FROM t LIMIT 10;", Some(10)),
+            ("SELECT a FROM t WHERE a > 1 ORDER BY a LIMIT 0", Some(0)),
+            ("SELECT a FROM t ORDER BY a;", None),
+        ] {
+            match parse_sql(sql).unwrap() {
+                SqlCommand::Select { limit, .. } => assert_eq!(limit, expected, "{}", sql),
+                other => panic!("Parser: expected Select command, got {:?}", other),
+            }
+        }
+        for invalid in [
+            "SELECT a FROM t LIMIT;",
+            "SELECT a FROM t LIMIT -1;",
+            "SELECT a FROM t LIMIT 'a';",
+            "SELECT a FROM t LIMIT 1 ORDER BY a;",
+        ] {
+            assert!(parse_sql(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_between_and_in() {
+        let parse = |sql: &str| {
+            let tokens = crate::sql_engine::tokenizer::tokenize(sql).unwrap();
+            parse_expression(&mut tokens.iter().peekable())
+        };
+        let column = |name: &str| Box::new(Expr::Column(name.to_string()));
+        let number = |n: i64| Expr::Literal(Value::Integer(n));
+
+        assert_eq!(
+            parse("a NOT BETWEEN 1 AND 2 + 1 AND b").unwrap(),
+            binary(
+                Expr::Between {
+                    expr: column("a"),
+                    low: Box::new(number(1)),
+                    high: Box::new(binary(number(2), BinaryOperator::Plus, number(1))),
+                    negated: true,
+                },
+                BinaryOperator::And,
+                Expr::Column("b".to_string()),
+            )
+        );
+        assert_eq!(
+            parse("a IN (1, 2)").unwrap(),
+            Expr::InList {
+                expr: column("a"),
+                list: vec![number(1), number(2)],
+                negated: false,
+            }
+        );
+        assert_eq!(
+            parse("NOT a IN (1)").unwrap(),
+            Expr::Unary {
+                operator: UnaryOperator::Not,
+                expr: Box::new(Expr::InList {
+                    expr: column("a"),
+                    list: vec![number(1)],
+                    negated: false,
+                }),
+            }
+        );
+        assert!(parse("a BETWEEN 1").is_err());
+        assert!(parse("a IN ()").is_err());
+        assert!(parse("a IN 1").is_err());
+        assert!(parse("a NOT 1").is_ok());
+    }
+
     // #[test]
     // fn test_parse_select_with_complex_where() {
     //     let tokens = vec![
@@ -504,6 +1524,42 @@ mod tests {
     //     }
     // }
 
+    #[test]
+    fn test_parse_date_expressions() {
+        let tokens = crate::sql_engine::tokenizer::tokenize(
+            "SELECT EXTRACT(MONTH FROM d) AS m FROM t WHERE d >= CURRENT_DATE - INTERVAL '7' DAY;",
+        )
+        .unwrap();
+
+        let result = parse_select(&tokens).unwrap();
+        assert_eq!(
+            result,
+            SqlCommand::Select {
+                table: "t".to_string(),
+                table_args: None,
+                columns: vec![SelectItem::Expr {
+                    expr: Expr::Extract {
+                        field: DateField::Month,
+                        expr: Box::new(Expr::Column("d".to_string())),
+                    },
+                    alias: Some("m".to_string()),
+                }],
+                where_clause: Some(binary(
+                    Expr::Column("d".to_string()),
+                    BinaryOperator::GreaterOrEqual,
+                    binary(
+                        Expr::CurrentDate,
+                        BinaryOperator::Minus,
+                        Expr::Literal(Value::Interval(Interval::new(0, 7, 0))),
+                    ),
+                )),
+                join_clause: None,
+                order_by: Vec::new(),
+                limit: None,
+            }
+        );
+    }
+
     #[test]
     fn test_parse_select_error() {
         let tokens = vec![
@@ -530,9 +1586,7 @@ mod tests {
             Token::Symbol(')'),
             Token::Keyword(Keyword::Values),
             Token::Symbol('('),
-            Token::Symbol('\''),
-            Token::Identifier("charles".to_string()),
-            Token::Symbol('\''),
+            Token::String("charles".to_string()),
             Token::Symbol(')'),
             Token::Semicolon,
         ];
@@ -544,7 +1598,11 @@ mod tests {
             SqlCommand::Insert {
                 table: "my table".to_string(),
                 columns: vec!["users".to_string()],
-                values: vec!["charles".to_string()],
+                source: InsertSource::Values(vec![vec![Expr::Literal(Value::Text(
+                    "charles".to_string()
+                ))]]),
+                on_conflict: None,
+                returning: None,
             }
         )
     }
@@ -572,7 +1630,7 @@ mod tests {
             Token::Identifier("my_table".to_string()),
             Token::Symbol('('),
             Token::Identifier("users".to_string()),
-            Token::DataType(DataType::Varchar),
+            Token::DataType(DataType::Varchar(None)),
             Token::Symbol(','),
             Token::Identifier("id".to_string()),
             Token::DataType(DataType::Integer),
@@ -581,7 +1639,7 @@ mod tests {
         ];
 
         let columns: Vec<Column> = vec![
-            Column::new(String::from("users"), DataType::Varchar ),
+            Column::new(String::from("users"), DataType::Varchar(None) ),
             Column::new(String::from("id"), DataType::Integer),
         ];
 
@@ -591,6 +1649,7 @@ mod tests {
         assert_eq!(result.unwrap(), SqlCommand::CreateTable {
             name: String::from("my_table"),
             columns,
+            constraints: vec![],
         });
     }
 
@@ -602,7 +1661,7 @@ mod tests {
             Token::Identifier("my_table".to_string()),
             Token::Symbol('('),
             Token::Identifier("users".to_string()),
-            Token::DataType(DataType::Varchar),
+            Token::DataType(DataType::Varchar(None)),
             Token::Symbol(','),
             Token::Identifier("id".to_string()),
             Token::DataType(DataType::Integer),
@@ -613,4 +1672,367 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_create_table_type_parameters() {
+        let tokens = crate::sql_engine::tokenizer::tokenize(
+            "CREATE TABLE accounts (code CHAR(3), name VARCHAR(255), balance DECIMAL(12, 2), rate NUMERIC(5));",
+        )
+        .unwrap();
+
+        let result = parse_create_table(&tokens).unwrap();
+        assert_eq!(
+            result,
+            SqlCommand::CreateTable {
+                name: "accounts".to_string(),
+                columns: vec![
+                    Column::new("code".to_string(), DataType::Char(Some(3))),
+                    Column::new("name".to_string(), DataType::Varchar(Some(255))),
+                    Column::new("balance".to_string(), DataType::Decimal(Some((12, 2)))),
+                    Column::new("rate".to_string(), DataType::Decimal(Some((5, 0)))),
+                ],
+                constraints: vec![],
+            }
+        );
+
+        for invalid in [
+            "CREATE TABLE t (a DECIMAL(2, 3));",
+            "CREATE TABLE t (a VARCHAR(0));",
+            "CREATE TABLE t (a VARCHAR(1, 2));",
+            "CREATE TABLE t (a INTEGER(4));",
+        ] {
+            let tokens = crate::sql_engine::tokenizer::tokenize(invalid).unwrap();
+            assert!(parse_create_table(&tokens).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_insert_sources() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+        let int = |n: i64| Expr::Literal(Value::Integer(n));
+
+        assert_eq!(
+            parse_sql("INSERT INTO t VALUES (1, 2), (3, 4);").unwrap(),
+            SqlCommand::Insert {
+                table: "t".to_string(),
+                columns: vec![],
+                source: InsertSource::Values(vec![vec![int(1), int(2)], vec![int(3), int(4)]]),
+                on_conflict: None,
+                returning: None,
+            }
+        );
+        assert_eq!(
+            parse_sql("INSERT INTO t DEFAULT VALUES;").unwrap(),
+            SqlCommand::Insert {
+                table: "t".to_string(),
+                columns: vec![],
+                source: InsertSource::DefaultValues,
+                on_conflict: None,
+                returning: None,
+            }
+        );
+        assert_eq!(
+            parse_sql("INSERT INTO t (a) SELECT b FROM u;").unwrap(),
+            SqlCommand::Insert {
+                table: "t".to_string(),
+                columns: vec!["a".to_string()],
+                source: InsertSource::Select(Box::new(SqlCommand::Select {
+                    table: "u".to_string(),
+                    table_args: None,
+                    columns: vec![SelectItem::Expr {
+                        expr: Expr::Column("b".to_string()),
+                        alias: None,
+                    }],
+                    where_clause: None,
+                    join_clause: None,
+                    order_by: Vec::new(),
+                    limit: None,
+                })),
+                on_conflict: None,
+                returning: None,
+            }
+        );
+        assert!(parse_sql("INSERT INTO t VALUES (1), ;").is_err());
+        assert!(parse_sql("INSERT INTO t (a) DEFAULT VALUES;").is_err());
+    }
+
+    #[test]
+    fn test_parse_constraints_and_on_conflict() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        match parse_sql(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, a TEXT UNIQUE, b TEXT, UNIQUE (a, b));",
+        )
+        .unwrap()
+        {
+            SqlCommand::CreateTable { constraints, .. } => assert_eq!(
+                constraints,
+                vec![
+                    UniqueConstraint {
+                        columns: vec!["id".to_string()],
+                        primary_key: true,
+                    },
+                    UniqueConstraint {
+                        columns: vec!["a".to_string()],
+                        primary_key: false,
+                    },
+                    UniqueConstraint {
+                        columns: vec!["a".to_string(), "b".to_string()],
+                        primary_key: false,
+                    },
+                ]
+            ),
+            other => panic!("Parser: expected CREATE TABLE, got {:?}", other),
+        }
+
+        match parse_sql(
+            "INSERT INTO t VALUES (1, 'x', 'y') ON CONFLICT (id) DO UPDATE SET a = excluded.a WHERE t.b IS NULL;",
+        )
+        .unwrap()
+        {
+            SqlCommand::Insert {
+                on_conflict: Some(on_conflict),
+                ..
+            } => assert_eq!(
+                on_conflict,
+                OnConflict {
+                    target: vec!["id".to_string()],
+                    action: ConflictAction::DoUpdate {
+                        assignments: vec![(
+                            "a".to_string(),
+                            Expr::QualifiedColumn {
+                                table: "excluded".to_string(),
+                                column: "a".to_string(),
+                            }
+                        )],
+                        where_clause: Some(Expr::IsNull {
+                            expr: Box::new(Expr::QualifiedColumn {
+                                table: "t".to_string(),
+                                column: "b".to_string(),
+                            }),
+                            negated: false,
+                        }),
+                    },
+                }
+            ),
+            other => panic!("Parser: expected INSERT, got {:?}", other),
+        }
+
+        assert!(parse_sql("INSERT INTO t SELECT * FROM u ON CONFLICT DO NOTHING;").is_ok());
+        assert!(parse_sql("INSERT INTO t VALUES (1) ON CONFLICT DO UPDATE SET a = 1;").is_err());
+        assert!(parse_sql("CREATE TABLE t (id INTEGER PRIMARY);").is_err());
+    }
+
+    #[test]
+    fn test_parse_update_delete_returning() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        assert_eq!(
+            parse_sql("UPDATE t SET a = a + 1, b = 'x' WHERE id = 2 RETURNING rowid, *;").unwrap(),
+            SqlCommand::Update {
+                table: "t".to_string(),
+                assignments: vec![
+                    (
+                        "a".to_string(),
+                        binary(
+                            Expr::Column("a".to_string()),
+                            BinaryOperator::Plus,
+                            Expr::Literal(Value::Integer(1)),
+                        ),
+                    ),
+                    ("b".to_string(), Expr::Literal(Value::Text("x".to_string()))),
+                ],
+                where_clause: Some(binary(
+                    Expr::Column("id".to_string()),
+                    BinaryOperator::Equal,
+                    Expr::Literal(Value::Integer(2)),
+                )),
+                returning: Some(vec![
+                    SelectItem::Expr {
+                        expr: Expr::Column("rowid".to_string()),
+                        alias: None,
+                    },
+                    SelectItem::Wildcard,
+                ]),
+            }
+        );
+        assert_eq!(
+            parse_sql("DELETE FROM t;").unwrap(),
+            SqlCommand::Delete {
+                table: "t".to_string(),
+                where_clause: None,
+                returning: None,
+            }
+        );
+        match parse_sql("INSERT INTO t SELECT * FROM u RETURNING a AS b;").unwrap() {
+            SqlCommand::Insert {
+                source: InsertSource::Select(_),
+                returning: Some(items),
+                ..
+            } => assert_eq!(
+                items,
+                vec![SelectItem::Expr {
+                    expr: Expr::Column("a".to_string()),
+                    alias: Some("b".to_string()),
+                }]
+            ),
+            other => panic!("Parser: expected INSERT ... SELECT, got {:?}", other),
+        }
+        assert!(parse_sql("DELETE t;").is_err());
+        assert!(parse_sql("UPDATE t WHERE a = 1;").is_err());
+    }
+
+    #[test]
+    fn test_parse_transaction_control() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        assert_eq!(parse_sql("BEGIN TRANSACTION;").unwrap(), SqlCommand::Begin);
+        assert_eq!(parse_sql("commit").unwrap(), SqlCommand::Commit);
+        assert_eq!(
+            parse_sql("ROLLBACK;").unwrap(),
+            SqlCommand::Rollback { savepoint: None }
+        );
+        assert_eq!(
+            parse_sql("ROLLBACK TO SAVEPOINT sp1;").unwrap(),
+            SqlCommand::Rollback {
+                savepoint: Some("sp1".to_string())
+            }
+        );
+        assert_eq!(
+            parse_sql("SAVEPOINT sp1;").unwrap(),
+            SqlCommand::Savepoint {
+                name: "sp1".to_string()
+            }
+        );
+        assert_eq!(
+            parse_sql("RELEASE SAVEPOINT sp1;").unwrap(),
+            SqlCommand::Release {
+                name: "sp1".to_string()
+            }
+        );
+        assert!(parse_sql("SAVEPOINT;").is_err());
+        assert!(parse_sql("ROLLBACK TO;").is_err());
+        assert!(parse_sql("BEGIN now;").is_err());
+    }
+
+    #[test]
+    fn test_parse_analyze() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        assert_eq!(
+            parse_sql("ANALYZE;").unwrap(),
+            SqlCommand::Analyze { table: None }
+        );
+        assert_eq!(
+            parse_sql("analyze users").unwrap(),
+            SqlCommand::Analyze {
+                table: Some("users".to_string())
+            }
+        );
+        assert!(parse_sql("ANALYZE users users;").is_err());
+        assert!(parse_sql("ANALYZE 1;").is_err());
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        let select = parse_sql("SELECT id FROM users WHERE id = 1;").unwrap();
+        assert_eq!(
+            parse_sql("EXPLAIN SELECT id FROM users WHERE id = 1;").unwrap(),
+            SqlCommand::Explain {
+                analyze: false,
+                query: Box::new(select),
+            }
+        );
+        assert!(matches!(
+            parse_sql("explain analyze SELECT * FROM users").unwrap(),
+            SqlCommand::Explain { analyze: true, .. }
+        ));
+        assert!(parse_sql("EXPLAIN;").is_err());
+        assert!(parse_sql("EXPLAIN ANALYZE users;").is_err());
+        assert!(parse_sql("EXPLAIN DELETE FROM users;").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_and_drop_index() {
+        let parse_sql = |sql: &str| parse(&crate::sql_engine::tokenizer::tokenize(sql).unwrap());
+
+        assert_eq!(
+            parse_sql("CREATE UNIQUE INDEX users_name ON users (last, first);").unwrap(),
+            SqlCommand::CreateIndex {
+                name: Some("users_name".to_string()),
+                table: "users".to_string(),
+                unique: true,
+                full_text: false,
+                exprs: vec![
+                    Expr::Column("last".to_string()),
+                    Expr::Column("first".to_string())
+                ],
+                where_clause: None,
+            }
+        );
+        assert_eq!(
+            parse_sql("create index on users (email)").unwrap(),
+            SqlCommand::CreateIndex {
+                name: None,
+                table: "users".to_string(),
+                unique: false,
+                full_text: false,
+                exprs: vec![Expr::Column("email".to_string())],
+                where_clause: None,
+            }
+        );
+        assert_eq!(
+            parse_sql("CREATE FULLTEXT INDEX ticket_words ON tickets (body);").unwrap(),
+            SqlCommand::CreateIndex {
+                name: Some("ticket_words".to_string()),
+                table: "tickets".to_string(),
+                unique: false,
+                full_text: true,
+                exprs: vec![Expr::Column("body".to_string())],
+                where_clause: None,
+            }
+        );
+        assert!(parse_sql("CREATE UNIQUE FULLTEXT INDEX ON tickets (body);").is_err());
+        assert_eq!(
+            parse_sql("CREATE INDEX active_email ON users (lower(email)) WHERE active = 1;")
+                .unwrap(),
+            SqlCommand::CreateIndex {
+                name: Some("active_email".to_string()),
+                table: "users".to_string(),
+                unique: false,
+                full_text: false,
+                exprs: vec![Expr::Function {
+                    name: "lower".to_string(),
+                    args: vec![Expr::Column("email".to_string())],
+                }],
+                where_clause: Some(Expr::Binary {
+                    left: Box::new(Expr::Column("active".to_string())),
+                    operator: BinaryOperator::Equal,
+                    right: Box::new(Expr::Literal(Value::Integer(1))),
+                }),
+            }
+        );
+        assert!(parse_sql("CREATE INDEX ON users (email) WHERE;").is_err());
+        assert_eq!(
+            parse_sql("DROP INDEX users_name;").unwrap(),
+            SqlCommand::DropIndex {
+                name: "users_name".to_string(),
+                table: None,
+            }
+        );
+        assert_eq!(
+            parse_sql("DROP INDEX users_name ON users").unwrap(),
+            SqlCommand::DropIndex {
+                name: "users_name".to_string(),
+                table: Some("users".to_string()),
+            }
+        );
+        assert!(parse_sql("CREATE UNIQUE INDEX ON users ();").is_err());
+        assert!(parse_sql("CREATE INDEX ON users (a b);").is_err());
+        assert!(parse_sql("DROP INDEX;").is_err());
+        assert!(parse_sql("DROP INDEX a ON;").is_err());
+        assert!(parse_sql("DROP TABLE users;").is_err());
+    }
 }
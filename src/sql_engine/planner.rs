@@ -0,0 +1,810 @@
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+use super::evaluator::{evaluate, EvalContext, Scope};
+use super::{parser, tokenizer, BinaryOperator, Expr};
+use crate::db::data_types::{Column, DataType, Value};
+use crate::db::datetime::Timestamp;
+use crate::db::decimal::Decimal;
+use crate::db::full_text::Query;
+use crate::db::table::{IndexRange, TableSchema};
+
+// Where to look for the rows a WHERE clause may keep: the entries of `index` in `ranges`. The
+// ranges only narrow the search down, the rows they lead to are still checked against the clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexPlan {
+    pub index: String,
+    pub ranges: Vec<IndexRange>,
+}
+
+pub type Interval = (Bound<Value>, Bound<Value>);
+
+// What the conditions say about one column: a value it has to equal, or intervals it has to be in
+#[derive(Debug, Clone)]
+enum Restriction {
+    Point(Value),
+    Intervals(Vec<Interval>),
+}
+
+// Every index the conditions AND-ed together in the WHERE clause can narrow the rows down with,
+// the one they pin down the most keys of first: an equality on each of its leading keys, then a
+// comparison, BETWEEN or IN on the next one. A partial index is only used when the clause has
+// every condition of its predicate. Full-text indexes giving the rows holding the words of a
+// MATCH come last.
+pub fn index_plans(
+    schema: &TableSchema,
+    where_clause: Option<&Expr>,
+    ctx: &EvalContext,
+) -> Vec<IndexPlan> {
+    let mut conditions = Vec::new();
+    if let Some(where_clause) = where_clause {
+        conjuncts(where_clause, &mut conditions);
+    }
+    let mut restrictions: Vec<(String, Restriction)> = Vec::new();
+    for condition in &conditions {
+        let Some((key, restriction)) = restriction(schema, condition, ctx) else {
+            continue;
+        };
+        match restrictions.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, existing)) => *existing = combine(existing.clone(), restriction),
+            None => restrictions.push((key, restriction)),
+        }
+    }
+    let restriction_on = |key: &str| {
+        restrictions
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, restriction)| restriction)
+    };
+    let written: Vec<String> = conditions
+        .iter()
+        .map(|condition| canonical(condition, &schema.columns).to_string())
+        .collect();
+
+    let mut plans: Vec<(usize, IndexPlan)> = Vec::new();
+    for (index, keys, predicate) in schema.column_index_keys() {
+        if predicate.is_some_and(|predicate| !implied(predicate, &written)) {
+            continue;
+        }
+        let mut prefix = Vec::new();
+        let mut intervals = None;
+        for key in keys {
+            match restriction_on(key) {
+                Some(Restriction::Point(value)) => prefix.push(value.clone()),
+                Some(Restriction::Intervals(found)) => {
+                    intervals = Some(found.clone());
+                    break;
+                }
+                None => break,
+            }
+        }
+        let used = prefix.len() + usize::from(intervals.is_some());
+        if used == 0 {
+            continue;
+        }
+        let ranges = match intervals {
+            Some(intervals) => intervals
+                .into_iter()
+                .map(|(lower, upper)| IndexRange {
+                    prefix: prefix.clone(),
+                    lower,
+                    upper,
+                })
+                .collect(),
+            None => vec![IndexRange::prefix(prefix)],
+        };
+        let plan = IndexPlan {
+            index: index.to_string(),
+            ranges,
+        };
+        plans.push((used, plan));
+    }
+    // Stable, so of two indexes with as many keys the one declared first comes first
+    plans.sort_by_key(|(used, _)| std::cmp::Reverse(*used));
+
+    let full_text = schema.full_text_indexes.iter().filter_map(|index| {
+        let query = conditions.iter().find_map(|condition| {
+            full_text_condition(schema, condition, ctx)
+                .filter(|(column, _)| *column == index.column)
+                .map(|(_, query)| query)
+        })?;
+        let ranges = query
+            .covering_words()
+            .into_iter()
+            .map(|word| IndexRange::prefix(vec![Value::Text(word.to_string())]))
+            .collect();
+        Some(IndexPlan {
+            index: index.name.clone(),
+            ranges,
+        })
+    });
+    plans
+        .into_iter()
+        .map(|(_, plan)| plan)
+        .chain(full_text)
+        .collect()
+}
+
+// The key one condition compares with constants and the intervals it keeps the key in, an
+// equality being an interval from the value to itself
+pub fn condition_intervals(
+    schema: &TableSchema,
+    condition: &Expr,
+    ctx: &EvalContext,
+) -> Option<(String, Vec<Interval>)> {
+    let (key, restriction) = restriction(schema, condition, ctx)?;
+    let intervals = match restriction {
+        Restriction::Point(value) => vec![(Bound::Included(value.clone()), Bound::Included(value))],
+        Restriction::Intervals(intervals) => intervals,
+    };
+    Some((key, intervals))
+}
+
+// Whether every row the plan leads to meets the condition, so that it needn't be checked again:
+// the plan only reads keys the condition keeps, and the condition keeps exactly the keys in its
+// intervals. Text comparisons take in padded keys too (see `text_range`) and intervals without a
+// lower bound reach down to NULL, so those still have to be checked.
+pub fn enforced(
+    schema: &TableSchema,
+    condition: &Expr,
+    plan: &IndexPlan,
+    ctx: &EvalContext,
+) -> bool {
+    let Some((_, keys, _)) = schema
+        .column_index_keys()
+        .into_iter()
+        .find(|(name, _, _)| *name == plan.index)
+    else {
+        return false;
+    };
+    let Some((key, intervals)) = condition_intervals(schema, condition, ctx) else {
+        return false;
+    };
+    let Some(position) = keys.iter().position(|name| *name == key) else {
+        return false;
+    };
+    let exact = intervals.iter().all(|(lower, upper)| {
+        *lower != Bound::Unbounded
+            && [lower, upper].into_iter().all(|bound| {
+                !matches!(
+                    bound,
+                    Bound::Included(Value::Text(_)) | Bound::Excluded(Value::Text(_))
+                )
+            })
+    });
+    exact
+        && plan.ranges.iter().all(|range| {
+            let (lower, upper) = match range.prefix.get(position) {
+                Some(value) => (
+                    Bound::Included(value.clone()),
+                    Bound::Included(value.clone()),
+                ),
+                None if position == range.prefix.len() => {
+                    (range.lower.clone(), range.upper.clone())
+                }
+                None => return false,
+            };
+            intervals.iter().any(|(outer_lower, outer_upper)| {
+                tighter(&lower, outer_lower, Ordering::Greater) == lower
+                    && tighter(&upper, outer_upper, Ordering::Less) == upper
+            })
+        })
+}
+
+// The first `column MATCH 'query'` condition AND-ed into the WHERE clause, with the declared name
+// of the column
+pub fn full_text_match(
+    schema: &TableSchema,
+    where_clause: Option<&Expr>,
+    ctx: &EvalContext,
+) -> Option<(String, Query)> {
+    let mut conditions = Vec::new();
+    conjuncts(where_clause?, &mut conditions);
+    conditions
+        .into_iter()
+        .find_map(|condition| full_text_condition(schema, condition, ctx))
+}
+
+fn full_text_condition(
+    schema: &TableSchema,
+    condition: &Expr,
+    ctx: &EvalContext,
+) -> Option<(String, Query)> {
+    let Expr::Binary {
+        left,
+        operator: BinaryOperator::Match,
+        right,
+    } = condition
+    else {
+        return None;
+    };
+    let Expr::Column(_) = left.as_ref() else {
+        return None;
+    };
+    let (column, _) = key(schema, left)?;
+    let Value::Text(query) = evaluate(right, &Scope::empty(), ctx).ok()? else {
+        return None;
+    };
+    Some((column, Query::parse(&query).ok()?))
+}
+
+// Whether the conditions include every one of the predicate's, so that each row they keep has an
+// entry in the partial index
+fn implied(predicate: &str, conditions: &[String]) -> bool {
+    let Ok(tokens) = tokenizer::tokenize(predicate) else {
+        return false;
+    };
+    let Ok(predicate) = parser::parse_expression(&mut tokens.iter().peekable()) else {
+        return false;
+    };
+    let mut required = Vec::new();
+    conjuncts(&predicate, &mut required);
+    required
+        .iter()
+        .all(|condition| conditions.contains(&condition.to_string()))
+}
+
+// The index keys the conditions AND-ed together in the WHERE clause hold to a single value, e.g.
+// `a` for `a = 1`. A text equality takes in padded values too, so it doesn't fix its key.
+pub fn fixed_keys(
+    schema: &TableSchema,
+    where_clause: Option<&Expr>,
+    ctx: &EvalContext,
+) -> Vec<String> {
+    let mut conditions = Vec::new();
+    if let Some(where_clause) = where_clause {
+        conjuncts(where_clause, &mut conditions);
+    }
+    conditions
+        .into_iter()
+        .filter_map(|condition| match restriction(schema, condition, ctx)? {
+            (key, Restriction::Point(_)) => Some(key),
+            (_, Restriction::Intervals(_)) => None,
+        })
+        .collect()
+}
+
+pub fn conjuncts<'a>(expr: &'a Expr, conditions: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::Binary {
+            left,
+            operator: BinaryOperator::And,
+            right,
+        } => {
+            conjuncts(left, conditions);
+            conjuncts(right, conditions);
+        }
+        expr => conditions.push(expr),
+    }
+}
+
+// What one condition says about an index key, when it compares the key with constants
+fn restriction(
+    schema: &TableSchema,
+    condition: &Expr,
+    ctx: &EvalContext,
+) -> Option<(String, Restriction)> {
+    match condition {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let ((name, data_type), operator, other) = match (key(schema, left), key(schema, right))
+            {
+                (Some(key), _) => (key, *operator, right),
+                (None, Some(key)) => (key, flipped(*operator)?, left),
+                (None, None) => return None,
+            };
+            let restriction = compare(operator, constant(other, &data_type, ctx)?)?;
+            Some((name, restriction))
+        }
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated: false,
+        } => {
+            let (name, data_type) = key(schema, expr)?;
+            let low = compare(
+                BinaryOperator::GreaterOrEqual,
+                constant(low, &data_type, ctx)?,
+            )?;
+            let high = compare(
+                BinaryOperator::LessOrEqual,
+                constant(high, &data_type, ctx)?,
+            )?;
+            Some((name, combine(low, high)))
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated: false,
+        } => {
+            let (name, data_type) = key(schema, expr)?;
+            let mut items = Vec::new();
+            for item in list {
+                items.push(compare(
+                    BinaryOperator::Equal,
+                    constant(item, &data_type, ctx)?,
+                )?);
+            }
+            if let [Restriction::Point(_)] = items.as_slice() {
+                return Some((name, items.remove(0)));
+            }
+            let intervals = items
+                .into_iter()
+                .flat_map(|item| match item {
+                    Restriction::Point(value) => {
+                        vec![(Bound::Included(value.clone()), Bound::Included(value))]
+                    }
+                    Restriction::Intervals(intervals) => intervals,
+                })
+                .collect();
+            Some((name, Restriction::Intervals(intervals)))
+        }
+        _ => None,
+    }
+}
+
+// The index key an expression would be stored as, with the type of its values: a column, or an
+// expression that always gives text, such as a path into a JSON document read with `->>`.
+// json_extract() gives whatever type the document holds there, and values of different types
+// don't compare, so its keys are left to a scan.
+fn key(schema: &TableSchema, expr: &Expr) -> Option<(String, DataType)> {
+    match expr {
+        Expr::Column(name) => schema
+            .columns
+            .iter()
+            .find(|column| column.name.eq_ignore_ascii_case(name))
+            .map(|column| (column.name.clone(), column.data_type.clone())),
+        Expr::Function { name, .. } if name == "lower" || name == "upper" => {
+            Some((canonical(expr, &schema.columns).to_string(), DataType::Text))
+        }
+        Expr::Binary {
+            operator: BinaryOperator::JsonGetText,
+            ..
+        } => Some((canonical(expr, &schema.columns).to_string(), DataType::Text)),
+        _ => None,
+    }
+}
+
+// The expression with its columns named as declared, which is how index keys and predicates are
+// stored, so that two spellings of it read the same
+pub fn canonical(expr: &Expr, columns: &[Column]) -> Expr {
+    let boxed = |expr: &Expr| Box::new(canonical(expr, columns));
+    match expr {
+        Expr::Column(name) => Expr::Column(
+            columns
+                .iter()
+                .find(|column| column.name.eq_ignore_ascii_case(name))
+                .map_or_else(|| name.clone(), |column| column.name.clone()),
+        ),
+        Expr::QualifiedColumn { .. }
+        | Expr::Literal(_)
+        | Expr::CurrentDate
+        | Expr::CurrentTimestamp => expr.clone(),
+        Expr::Unary { operator, expr } => Expr::Unary {
+            operator: *operator,
+            expr: boxed(expr),
+        },
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => Expr::Binary {
+            left: boxed(left),
+            operator: *operator,
+            right: boxed(right),
+        },
+        Expr::IsNull { expr, negated } => Expr::IsNull {
+            expr: boxed(expr),
+            negated: *negated,
+        },
+        Expr::Between {
+            expr,
+            low,
+            high,
+            negated,
+        } => Expr::Between {
+            expr: boxed(expr),
+            low: boxed(low),
+            high: boxed(high),
+            negated: *negated,
+        },
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => Expr::InList {
+            expr: boxed(expr),
+            list: list.iter().map(|item| canonical(item, columns)).collect(),
+            negated: *negated,
+        },
+        Expr::Function { name, args } => Expr::Function {
+            name: name.clone(),
+            args: args.iter().map(|arg| canonical(arg, columns)).collect(),
+        },
+        Expr::Extract { field, expr } => Expr::Extract {
+            field: *field,
+            expr: boxed(expr),
+        },
+    }
+}
+
+// `constant op column` as `column op constant`
+fn flipped(operator: BinaryOperator) -> Option<BinaryOperator> {
+    match operator {
+        BinaryOperator::Equal => Some(BinaryOperator::Equal),
+        BinaryOperator::Less => Some(BinaryOperator::Greater),
+        BinaryOperator::LessOrEqual => Some(BinaryOperator::GreaterOrEqual),
+        BinaryOperator::Greater => Some(BinaryOperator::Less),
+        BinaryOperator::GreaterOrEqual => Some(BinaryOperator::LessOrEqual),
+        _ => None,
+    }
+}
+
+// The value of an expression that doesn't depend on the row, converted to what the column's
+// index keys are. Only conversions that keep comparisons the same are made, so that a range of
+// keys holds every value that compares into it; anything else is left to a scan.
+fn constant(expr: &Expr, data_type: &DataType, ctx: &EvalContext) -> Option<Value> {
+    match (data_type, evaluate(expr, &Scope::empty(), ctx).ok()?) {
+        (_, Value::Null) => Some(Value::Null),
+        (DataType::Integer, value @ Value::Integer(_))
+        | (DataType::Boolean, value @ Value::Boolean(_))
+        | (DataType::Date, value @ Value::Date(_))
+        | (DataType::Timestamp, value @ Value::Timestamp(_))
+        | (DataType::Decimal(_), value @ Value::Decimal(_))
+        | (DataType::Json, value @ Value::Json(_)) => Some(value),
+        (DataType::Decimal(_), Value::Integer(n)) => Some(Value::Decimal(Decimal::from_i64(n))),
+        (DataType::Timestamp, Value::Date(date)) => date.to_timestamp().ok().map(Value::Timestamp),
+        (DataType::Timestamp, Value::Text(s)) => Timestamp::parse(&s).ok().map(Value::Timestamp),
+        // Trailing spaces don't count when comparing text, see `text_range`
+        (
+            DataType::Text | DataType::Varchar(_) | DataType::Char(_) | DataType::Blob,
+            Value::Text(s),
+        ) => Some(Value::Text(s.trim_end_matches(' ').to_string())),
+        _ => None,
+    }
+}
+
+// The keys `column op value` holds for
+fn compare(operator: BinaryOperator, value: Value) -> Option<Restriction> {
+    if value == Value::Null {
+        return Some(Restriction::Intervals(Vec::new()));
+    }
+    if let Value::Text(s) = &value {
+        return text_range(operator, s).map(|interval| Restriction::Intervals(vec![interval]));
+    }
+    let interval = match operator {
+        BinaryOperator::Equal => return Some(Restriction::Point(value)),
+        BinaryOperator::Less => (Bound::Unbounded, Bound::Excluded(value)),
+        BinaryOperator::LessOrEqual => (Bound::Unbounded, Bound::Included(value)),
+        BinaryOperator::Greater => (Bound::Excluded(value), Bound::Unbounded),
+        BinaryOperator::GreaterOrEqual => (Bound::Included(value), Bound::Unbounded),
+        _ => return None,
+    };
+    Some(Restriction::Intervals(vec![interval]))
+}
+
+// Text compares with trailing spaces ignored, so 'a' equals the stored 'a  '. Every string with
+// `s` followed by spaces sorts before `s` followed by '!', which bounds them. Those ranges can
+// take in a few more keys than the comparison does, which the rows are checked for anyway; the
+// upper bounds only hold when `s` has no characters below space.
+fn text_range(operator: BinaryOperator, s: &str) -> Option<Interval> {
+    let text = |s: &str| Value::Text(s.to_string());
+    let past_padding = || text(&format!("{}!", s));
+    let upper_bound_holds = !s.chars().any(|c| c < ' ');
+    match operator {
+        BinaryOperator::Equal => Some((Bound::Included(text(s)), Bound::Excluded(past_padding()))),
+        BinaryOperator::Greater => Some((Bound::Excluded(text(s)), Bound::Unbounded)),
+        BinaryOperator::GreaterOrEqual => Some((Bound::Included(text(s)), Bound::Unbounded)),
+        BinaryOperator::Less if upper_bound_holds => {
+            Some((Bound::Unbounded, Bound::Excluded(text(s))))
+        }
+        BinaryOperator::LessOrEqual if upper_bound_holds => {
+            Some((Bound::Unbounded, Bound::Excluded(past_padding())))
+        }
+        _ => None,
+    }
+}
+
+// Whether an interval holds the keys equal to one value: the value itself, or a text and its
+// padded forms as `text_range` bounds them
+pub fn is_equality(lower: &Bound<Value>, upper: &Bound<Value>) -> bool {
+    match (lower, upper) {
+        (Bound::Included(a), Bound::Included(b)) => a == b,
+        (Bound::Included(Value::Text(a)), Bound::Excluded(Value::Text(b))) => {
+            b.strip_suffix('!') == Some(a.as_str())
+        }
+        _ => false,
+    }
+}
+
+// Both conditions hold. An equality is enough to look rows up by, and of two lists of intervals
+// the shorter one is kept, since the rows are checked against the other anyway.
+fn combine(a: Restriction, b: Restriction) -> Restriction {
+    match (a, b) {
+        (point @ Restriction::Point(_), _) | (_, point @ Restriction::Point(_)) => point,
+        (Restriction::Intervals(a), Restriction::Intervals(b)) => match (&a[..], &b[..]) {
+            ([(a_lower, a_upper)], [(b_lower, b_upper)]) => Restriction::Intervals(vec![(
+                tighter(a_lower, b_lower, Ordering::Greater),
+                tighter(a_upper, b_upper, Ordering::Less),
+            )]),
+            _ if a.len() <= b.len() => Restriction::Intervals(a),
+            _ => Restriction::Intervals(b),
+        },
+    }
+}
+
+// The bound that lets fewer values through: the one further in the direction `inward` points
+// for a lower bound (Greater) or an upper bound (Less)
+fn tighter(a: &Bound<Value>, b: &Bound<Value>, inward: Ordering) -> Bound<Value> {
+    match (a, b) {
+        (Bound::Unbounded, bound) | (bound, Bound::Unbounded) => bound.clone(),
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            match x.cmp(y) {
+                Ordering::Equal if matches!(a, Bound::Excluded(_)) => a.clone(),
+                Ordering::Equal => b.clone(),
+                ordering if ordering == inward => a.clone(),
+                _ => b.clone(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::data_types::{Column, UniqueConstraint};
+    use crate::db::full_text::FullTextIndex;
+    use crate::db::table::ColumnIndex;
+    use crate::sql_engine::parser::parse_expression;
+    use crate::sql_engine::tokenizer::tokenize;
+
+    fn schema() -> TableSchema {
+        let index = |name: &str, columns: &[&str]| ColumnIndex {
+            name: name.to_string(),
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            unique: false,
+            predicate: None,
+        };
+        let cheap_by_at = ColumnIndex {
+            predicate: Some("(price < 10)".to_string()),
+            ..index("cheap_by_at", &["at", "id"])
+        };
+        TableSchema {
+            columns: vec![
+                Column::new("id".to_string(), DataType::Integer),
+                Column::new("kind".to_string(), DataType::Text),
+                Column::new("at".to_string(), DataType::Integer),
+                Column::new("price".to_string(), DataType::Decimal(None)),
+                Column::new("notes".to_string(), DataType::Text),
+            ],
+            constraints: vec![UniqueConstraint {
+                columns: vec!["id".to_string()],
+                primary_key: true,
+            }],
+            column_indexes: vec![
+                index("by_kind", &["kind"]),
+                index("by_at_kind", &["at", "kind"]),
+                index("by_price", &["price"]),
+                index("by_lower_kind", &["lower(kind)"]),
+                cheap_by_at,
+            ],
+            full_text_indexes: vec![FullTextIndex {
+                name: "notes_fts".to_string(),
+                column: "notes".to_string(),
+            }],
+            statistics: None,
+        }
+    }
+
+    fn plan(condition: &str) -> Option<IndexPlan> {
+        let tokens = tokenize(condition).unwrap();
+        let condition = parse_expression(&mut tokens.iter().peekable()).unwrap();
+        let ctx = EvalContext {
+            now: Timestamp::parse("2024-03-15 10:30:00").unwrap(),
+        };
+        index_plans(&schema(), Some(&condition), &ctx)
+            .into_iter()
+            .next()
+    }
+
+    fn range(prefix: Vec<Value>, lower: Bound<Value>, upper: Bound<Value>) -> IndexRange {
+        IndexRange {
+            prefix,
+            lower,
+            upper,
+        }
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_equalities_and_ranges() {
+        assert_eq!(
+            plan("id = 1 + 1"),
+            Some(IndexPlan {
+                index: "id".to_string(),
+                ranges: vec![IndexRange::prefix(vec![Value::Integer(2)])],
+            })
+        );
+        assert_eq!(
+            plan("3 < id AND id <= 9 AND id > 4"),
+            Some(IndexPlan {
+                index: "id".to_string(),
+                ranges: vec![range(
+                    vec![],
+                    Bound::Excluded(Value::Integer(4)),
+                    Bound::Included(Value::Integer(9))
+                )],
+            })
+        );
+        assert_eq!(
+            plan("id BETWEEN 2 AND 5").unwrap().ranges,
+            vec![range(
+                vec![],
+                Bound::Included(Value::Integer(2)),
+                Bound::Included(Value::Integer(5))
+            )]
+        );
+        assert_eq!(
+            plan("id IN (1, NULL, 3)").unwrap().ranges,
+            vec![
+                range(
+                    vec![],
+                    Bound::Included(Value::Integer(1)),
+                    Bound::Included(Value::Integer(1))
+                ),
+                range(
+                    vec![],
+                    Bound::Included(Value::Integer(3)),
+                    Bound::Included(Value::Integer(3))
+                ),
+            ]
+        );
+        // Never true, so nothing to look at
+        assert_eq!(plan("id = NULL").unwrap().ranges, vec![]);
+    }
+
+    #[test]
+    fn test_text_keys_take_in_padding() {
+        assert_eq!(
+            plan("kind = 'b  '"),
+            Some(IndexPlan {
+                index: "by_kind".to_string(),
+                ranges: vec![range(
+                    vec![],
+                    Bound::Included(text("b")),
+                    Bound::Excluded(text("b!"))
+                )],
+            })
+        );
+        assert_eq!(
+            plan("kind <= 'b'").unwrap().ranges,
+            vec![range(vec![], Bound::Unbounded, Bound::Excluded(text("b!")))]
+        );
+        assert!(plan("kind < 'b\u{1}'").is_none());
+    }
+
+    #[test]
+    fn test_the_index_covering_most_columns_wins() {
+        assert_eq!(
+            plan("id > 1 AND kind = 'b' AND at = 2"),
+            Some(IndexPlan {
+                index: "by_at_kind".to_string(),
+                ranges: vec![range(
+                    vec![Value::Integer(2)],
+                    Bound::Included(text("b")),
+                    Bound::Excluded(text("b!"))
+                )],
+            })
+        );
+        assert_eq!(
+            plan("at = 2").unwrap().ranges,
+            vec![IndexRange::prefix(vec![Value::Integer(2)])]
+        );
+        assert_eq!(plan("at > 2 AND id = 2").unwrap().index, "id");
+    }
+
+    #[test]
+    fn test_keys_compare_like_the_column() {
+        assert_eq!(
+            plan("price = 2").unwrap().ranges,
+            vec![IndexRange::prefix(vec![Value::Decimal(Decimal::from_i64(
+                2
+            ))])]
+        );
+        // A DECIMAL compares with an INTEGER column, but isn't one of its keys
+        assert!(plan("id = 1.5").is_none());
+        assert!(plan("id = 'x'").is_none());
+    }
+
+    #[test]
+    fn test_conditions_no_index_can_use() {
+        assert!(plan("id = 1 OR id = 2").is_none());
+        assert!(plan("id != 1").is_none());
+        assert!(plan("id NOT IN (1, 2)").is_none());
+        assert!(plan("id NOT BETWEEN 1 AND 2").is_none());
+        assert!(plan("id = at").is_none());
+        assert!(plan("id + 1 = 2").is_none());
+    }
+
+    #[test]
+    fn test_expression_keys() {
+        assert_eq!(
+            plan("lower(KIND) = 'ab'"),
+            Some(IndexPlan {
+                index: "by_lower_kind".to_string(),
+                ranges: vec![range(
+                    vec![],
+                    Bound::Included(text("ab")),
+                    Bound::Excluded(text("ab!"))
+                )],
+            })
+        );
+        assert_eq!(
+            plan("'a' < lower(kind)").unwrap().ranges,
+            vec![range(vec![], Bound::Excluded(text("a")), Bound::Unbounded)]
+        );
+        assert!(plan("upper(kind) = 'AB'").is_none());
+        assert!(plan("lower(kind) = 1").is_none());
+    }
+
+    #[test]
+    fn test_partial_indexes_need_their_predicate() {
+        assert_eq!(
+            plan("at = 2 AND ID > 3 AND PRICE < 10"),
+            Some(IndexPlan {
+                index: "cheap_by_at".to_string(),
+                ranges: vec![range(
+                    vec![Value::Integer(2)],
+                    Bound::Excluded(Value::Integer(3)),
+                    Bound::Unbounded
+                )],
+            })
+        );
+        assert_eq!(plan("at = 2 AND id > 3").unwrap().index, "id");
+        assert_eq!(plan("at = 2 AND id > 3 AND price < 9").unwrap().index, "id");
+        assert_eq!(
+            plan("at = 2 AND id > 3 AND (price < 10 OR kind = 'a')")
+                .unwrap()
+                .index,
+            "id"
+        );
+    }
+
+    #[test]
+    fn test_full_text_matches() {
+        let words = |words: &[&str]| {
+            words
+                .iter()
+                .map(|word| IndexRange::prefix(vec![text(word)]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            plan("Notes MATCH '\"Paper jam\" OR toner'"),
+            Some(IndexPlan {
+                index: "notes_fts".to_string(),
+                ranges: words(&["paper", "toner"]),
+            })
+        );
+        assert_eq!(
+            plan("id <> 1 AND notes MATCH 'printer (jam OR toner)'")
+                .unwrap()
+                .ranges,
+            words(&["printer"])
+        );
+        // Column indexes come first
+        assert_eq!(
+            plan("kind = 'a' AND notes MATCH 'printer'").unwrap().index,
+            "by_kind"
+        );
+        assert_eq!(plan("kind MATCH 'printer'"), None);
+        assert_eq!(plan("notes MATCH 'printer' OR id = 1"), None);
+        assert_eq!(plan("notes MATCH 'printer AND'"), None);
+    }
+}
@@ -6,8 +6,12 @@ pub enum Token {
     Identifier(String),
     QuotedIdentifier(String),
     Number(i64),
+    // Numeric literal with a fractional part, or too large for an i64, kept as written
+    Decimal(String),
     String(String),
     Symbol(char),
+    // Operators spelled with more than one character: -> and ->>
+    Operator(String),
     DataType(DataType),
     Semicolon,
 }
@@ -39,15 +43,38 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
                 }
             }
             '0'..='9' => {
-                let number = consume_while(&mut chars, |c| c.is_numeric());
-                tokens.push(Token::Number(number.parse().unwrap()));
+                let mut number = consume_while(&mut chars, |c| c.is_ascii_digit());
+                let mut lookahead = chars.clone();
+                lookahead.next();
+                if chars.peek() == Some(&'.')
+                    && lookahead.peek().is_some_and(|c| c.is_ascii_digit())
+                {
+                    chars.next(); // consume the decimal point
+                    number.push('.');
+                    number.push_str(&consume_while(&mut chars, |c| c.is_ascii_digit()));
+                    tokens.push(Token::Decimal(number));
+                } else {
+                    match number.parse() {
+                        Ok(n) => tokens.push(Token::Number(n)),
+                        Err(_) => tokens.push(Token::Decimal(number)),
+                    }
+                }
             }
             '\'' => {
                 chars.next(); // consume opening quote
-                let string = consume_while(&mut chars, |c| c != '\'');
+                let mut string = consume_while(&mut chars, |c| c != '\'');
                 if chars.next() != Some('\'') {
                     return Err("Unterminated string literal".to_string());
                 }
+                // A doubled quote ('') inside a literal stands for a single quote
+                while chars.peek() == Some(&'\'') {
+                    chars.next();
+                    string.push('\'');
+                    string.push_str(&consume_while(&mut chars, |c| c != '\''));
+                    if chars.next() != Some('\'') {
+                        return Err("Unterminated string literal".to_string());
+                    }
+                }
                 tokens.push(Token::String(string));
             }
             '"' => {
@@ -58,7 +85,21 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
                 }
                 tokens.push(Token::QuotedIdentifier(identifier));
             }
-            ',' | '(' | ')' | '>' | '<' | '=' | '*' => {
+            '-' => {
+                chars.next();
+                if chars.peek() == Some(&'>') {
+                    chars.next();
+                    if chars.peek() == Some(&'>') {
+                        chars.next();
+                        tokens.push(Token::Operator("->>".to_string()));
+                    } else {
+                        tokens.push(Token::Operator("->".to_string()));
+                    }
+                } else {
+                    tokens.push(Token::Symbol('-'));
+                }
+            }
+            ',' | '(' | ')' | '>' | '<' | '=' | '*' | '!' | '+' | '/' | '%' | '.' => {
                 tokens.push(Token::Symbol(c));
                 chars.next();
             }
@@ -95,11 +136,12 @@ fn str_to_data_type(s: &str) -> Option<DataType> {
         "BOOLEAN" => Some(DataType::Boolean),
         "DATE" => Some(DataType::Date),
         "TIMESTAMP" => Some(DataType::Timestamp),
-        "VARCHAR" => Some(DataType::Varchar),
-        "CHAR" => Some(DataType::Char),
+        "VARCHAR" => Some(DataType::Varchar(None)),
+        "CHAR" => Some(DataType::Char(None)),
         "FLOAT" => Some(DataType::Float),
         "DOUBLE" => Some(DataType::Double),
-        "DECIMAL" => Some(DataType::Decimal),
+        "DECIMAL" | "NUMERIC" => Some(DataType::Decimal(None)),
+        "JSON" => Some(DataType::Json),
         _ => None,
     }
 }
@@ -118,6 +160,45 @@ fn str_to_keyword(s: &str) -> Option<Keyword> {
         "AND" => Some(Keyword::And),
         "JOIN" => Some(Keyword::Join),
         "ON" => Some(Keyword::On),
+        "INDEX" => Some(Keyword::Index),
+        "AS" => Some(Keyword::As),
+        "NOT" => Some(Keyword::Not),
+        "IS" => Some(Keyword::Is),
+        "TRUE" => Some(Keyword::True),
+        "FALSE" => Some(Keyword::False),
+        "INTERVAL" => Some(Keyword::Interval),
+        "EXTRACT" => Some(Keyword::Extract),
+        "CURRENT_DATE" => Some(Keyword::CurrentDate),
+        "CURRENT_TIMESTAMP" => Some(Keyword::CurrentTimestamp),
+        "DEFAULT" => Some(Keyword::Default),
+        "PRIMARY" => Some(Keyword::Primary),
+        "UNIQUE" => Some(Keyword::Unique),
+        "CONFLICT" => Some(Keyword::Conflict),
+        "DO" => Some(Keyword::Do),
+        "NOTHING" => Some(Keyword::Nothing),
+        "UPDATE" => Some(Keyword::Update),
+        "SET" => Some(Keyword::Set),
+        "DELETE" => Some(Keyword::Delete),
+        "RETURNING" => Some(Keyword::Returning),
+        "BEGIN" => Some(Keyword::Begin),
+        "COMMIT" => Some(Keyword::Commit),
+        "ROLLBACK" => Some(Keyword::Rollback),
+        "SAVEPOINT" => Some(Keyword::Savepoint),
+        "RELEASE" => Some(Keyword::Release),
+        "TRANSACTION" => Some(Keyword::Transaction),
+        "TO" => Some(Keyword::To),
+        "DROP" => Some(Keyword::Drop),
+        "ORDER" => Some(Keyword::Order),
+        "BY" => Some(Keyword::By),
+        "ASC" => Some(Keyword::Asc),
+        "DESC" => Some(Keyword::Desc),
+        "BETWEEN" => Some(Keyword::Between),
+        "IN" => Some(Keyword::In),
+        "MATCH" => Some(Keyword::Match),
+        "FULLTEXT" => Some(Keyword::Fulltext),
+        "ANALYZE" => Some(Keyword::Analyze),
+        "EXPLAIN" => Some(Keyword::Explain),
+        "LIMIT" => Some(Keyword::Limit),
         _ => None,
     }
 }
@@ -195,4 +276,37 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_numeric_literals() {
+        let result = tokenize("1.50 42 99999999999999999999 t.x").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::Decimal("1.50".to_string()),
+                Token::Number(42),
+                Token::Decimal("99999999999999999999".to_string()),
+                Token::Identifier("t".to_string()),
+                Token::Symbol('.'),
+                Token::Identifier("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_operators() {
+        let result = tokenize("doc->'a'->>0 - 1").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Token::Identifier("doc".to_string()),
+                Token::Operator("->".to_string()),
+                Token::String("a".to_string()),
+                Token::Operator("->>".to_string()),
+                Token::Number(0),
+                Token::Symbol('-'),
+                Token::Number(1),
+            ]
+        );
+    }
 }